@@ -1,5 +1,19 @@
+use crate::gateway_capture::CaptureSession;
+use crate::gateway_defaults::RequestDefaults;
+use crate::gateway_health::{self, CircuitBreakerConfig, CircuitState, HealthRegistry};
+use crate::gateway_host::GatewayHost;
+use crate::gateway_keys::{self, KeyPool, KeyPoolConfig, KeyPoolRegistry};
+use crate::gateway_log::{ErrorClass, GatewayKind, RequestLog};
+use crate::gateway_metrics::{self, AnthropicUsageScanner, GatewayMetrics};
+use crate::gateway_rate_limit::{RateLimitConfig, RateLimitRejection, RateLimiterRegistry};
+use crate::gateway_reasoning::{self, ReasoningMapping, ReasoningStyle};
+use crate::gateway_retry::RetryPolicy;
+use crate::gateway_transform::TransformRules;
 use crate::provider::Provider;
-use crate::store::AppState;
+use crate::secret_ref;
+use crate::secret_vault;
+use crate::store::{ApiGatewayConfig, AppState};
+use crate::token_estimate;
 use axum::{
     body::{Body, Bytes},
     extract::State,
@@ -12,18 +26,26 @@ use axum::{
     Json,
     Router,
 };
-use futures_util::TryStreamExt;
+use futures_util::{StreamExt, TryStreamExt};
 use reqwest::Url;
 use serde_json::{json, Value};
 use std::sync::Arc;
-use tauri::Emitter;
+use std::time::{Duration, Instant};
 use tokio::sync::{oneshot, RwLock};
 
+const COUNT_TOKENS_PATH: &str = "/v1/messages/count_tokens";
+/// Gateway 模式下写入 Claude 配置的占位令牌，真实凭据由 Gateway 注入
+const GATEWAY_PLACEHOLDER_TOKEN: &str = "switch-cc-gateway";
+
 #[derive(Clone)]
 struct GatewayServerState {
-    app_handle: tauri::AppHandle,
+    host: GatewayHost,
     client: reqwest::Client,
     route_state: Arc<RwLock<RouteState>>,
+    rate_limiter: Arc<RateLimiterRegistry>,
+    health: Arc<HealthRegistry>,
+    keys: Arc<KeyPoolRegistry>,
+    metrics: Arc<GatewayMetrics>,
 }
 
 #[derive(Debug, Clone)]
@@ -32,7 +54,14 @@ struct RouteState {
     provider_id: String,
     provider_name: String,
     target_base_url: String,
-    upstream_auth: UpstreamAuth,
+    upstream_auth: KeyPool<UpstreamAuth>,
+    rate_limit: RateLimitConfig,
+    retry: RetryPolicy,
+    circuit_breaker: CircuitBreakerConfig,
+    count_tokens_locally: bool,
+    defaults: RequestDefaults,
+    reasoning: ReasoningMapping,
+    transform: TransformRules,
     available_models: Vec<GatewayModel>,
     models: Vec<GatewayModel>,
     model_routes: std::collections::HashMap<String, GatewayModelRoute>,
@@ -56,7 +85,12 @@ struct GatewayModelRoute {
     provider_name: String,
     target_base_url: String,
     upstream_model: String,
-    upstream_auth: UpstreamAuth,
+    upstream_auth: KeyPool<UpstreamAuth>,
+    rate_limit: RateLimitConfig,
+    count_tokens_locally: bool,
+    defaults: RequestDefaults,
+    reasoning: ReasoningMapping,
+    transform: TransformRules,
 }
 
 #[derive(Debug, Clone)]
@@ -79,7 +113,7 @@ enum GatewayAuthStrategy {
 
 #[derive(Default)]
 pub struct ApiGatewayRuntime {
-    server_handle: Option<tauri::async_runtime::JoinHandle<()>>,
+    server_handle: Option<tokio::task::JoinHandle<()>>,
     shutdown_tx: Option<oneshot::Sender<()>>,
     route_state: Option<Arc<RwLock<RouteState>>>,
 }
@@ -89,7 +123,12 @@ struct ProviderRouteConfig {
     provider_id: String,
     provider_name: String,
     target_base_url: String,
-    upstream_auth: UpstreamAuth,
+    upstream_auth: KeyPool<UpstreamAuth>,
+    rate_limit: RateLimitConfig,
+    count_tokens_locally: bool,
+    defaults: RequestDefaults,
+    reasoning: ReasoningMapping,
+    transform: TransformRules,
     models: Vec<GatewayModel>,
 }
 
@@ -98,6 +137,7 @@ pub struct GatewayRouteSnapshot {
     pub provider_id: String,
     pub provider_name: String,
     pub target_base_url: String,
+    pub request_defaults: RequestDefaults,
 }
 
 pub fn gateway_base_url(port: u16) -> String {
@@ -243,6 +283,37 @@ fn configured_upstream_auth(provider: &Provider) -> UpstreamAuth {
     UpstreamAuth { strategies }
 }
 
+/// 上游凭据池：主密钥之后依次是 `apiGateway.keyPool` 中的密钥。
+///
+/// 每个密钥替换 `ANTHROPIC_API_KEY`（未设置时为 `ANTHROPIC_AUTH_TOKEN`）后生成认证方式，
+/// 通过 `envVar` 引用该变量的自定义认证也随之轮换。
+fn configured_upstream_auths(provider: &Provider) -> KeyPool<UpstreamAuth> {
+    let env_key = if read_env_value(provider, "ANTHROPIC_API_KEY").is_some() {
+        "ANTHROPIC_API_KEY"
+    } else {
+        "ANTHROPIC_AUTH_TOKEN"
+    };
+    let primary = read_env_value(provider, env_key).unwrap_or_default();
+    let config = provider
+        .settings_config
+        .get("apiGateway")
+        .and_then(|value| value.get("keyPool"))
+        .and_then(|value| serde_json::from_value::<KeyPoolConfig>(value.clone()).ok())
+        .unwrap_or_default();
+
+    KeyPool::new(&primary, &config, |key| {
+        let mut provider = provider.clone();
+        if let Some(env) = provider
+            .settings_config
+            .get_mut("env")
+            .and_then(Value::as_object_mut)
+        {
+            env.insert(env_key.to_string(), Value::String(key.to_string()));
+        }
+        configured_upstream_auth(&provider)
+    })
+}
+
 fn configured_custom_auth_strategies(provider: &Provider) -> Vec<GatewayAuthStrategy> {
     let api_gateway = provider.settings_config.get("apiGateway");
     let mut strategies = api_gateway
@@ -307,12 +378,61 @@ fn configured_custom_auth_strategies(provider: &Provider) -> Vec<GatewayAuthStra
     strategies
 }
 
+fn configured_rate_limit(provider: &Provider) -> RateLimitConfig {
+    RateLimitConfig::from_value(
+        provider
+            .settings_config
+            .get("apiGateway")
+            .and_then(|value| value.get("rateLimit")),
+    )
+}
+
+/// 供应商开启 `apiGateway.localCountTokens` 时，count_tokens 请求不转发上游，直接本地估算。
+fn configured_count_tokens_locally(provider: &Provider) -> bool {
+    provider
+        .settings_config
+        .get("apiGateway")
+        .and_then(|value| value.get("localCountTokens"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// 供应商 `apiGateway.defaults` 中的系统提示词和默认参数。
+pub fn provider_request_defaults(provider: &Provider) -> RequestDefaults {
+    RequestDefaults::from_value(
+        provider
+            .settings_config
+            .get("apiGateway")
+            .and_then(|value| value.get("defaults")),
+    )
+}
+
 fn build_provider_route_config(provider: &Provider) -> Result<ProviderRouteConfig, String> {
+    resolved_provider_route_config(&secret_ref::resolve_claude_provider(provider)?)
+}
+
+/// `provider` 中的密钥引用需已解析。
+fn resolved_provider_route_config(provider: &Provider) -> Result<ProviderRouteConfig, String> {
     Ok(ProviderRouteConfig {
         provider_id: provider.id.clone(),
         provider_name: provider.name.clone(),
         target_base_url: provider_target_base_url(provider)?,
-        upstream_auth: configured_upstream_auth(provider),
+        upstream_auth: configured_upstream_auths(provider),
+        rate_limit: configured_rate_limit(provider),
+        count_tokens_locally: configured_count_tokens_locally(provider),
+        defaults: provider_request_defaults(provider),
+        reasoning: ReasoningMapping::from_value(
+            provider
+                .settings_config
+                .get("apiGateway")
+                .and_then(|value| value.get("reasoning")),
+        ),
+        transform: TransformRules::from_value(
+            provider
+                .settings_config
+                .get("apiGateway")
+                .and_then(|value| value.get("transform")),
+        )?,
         models: configured_provider_models(provider),
     })
 }
@@ -332,6 +452,11 @@ fn build_model_routes(
                         target_base_url: provider.target_base_url.clone(),
                         upstream_model: model.upstream_model.clone(),
                         upstream_auth: provider.upstream_auth.clone(),
+                        rate_limit: provider.rate_limit.clone(),
+                        count_tokens_locally: provider.count_tokens_locally,
+                        defaults: provider.defaults.clone(),
+                        reasoning: provider.reasoning.clone(),
+                        transform: provider.transform.clone(),
                     },
                 )
             })
@@ -339,17 +464,70 @@ fn build_model_routes(
         .collect()
 }
 
-fn collect_route_configs(state: &AppState) -> Result<Vec<ProviderRouteConfig>, String> {
-    let config = state
-        .config
-        .lock()
-        .map_err(|e| format!("获取锁失败: {}", e))?;
+/// 解析各供应商的密钥引用并生成路由配置；`cmd:` 引用会执行命令，因此放在阻塞线程中进行。
+///
+/// `provider_id` 解析失败时返回错误，其他供应商解析失败只记录日志并跳过其模型路由。
+async fn build_route_configs(
+    host: &GatewayHost,
+    providers: Vec<Provider>,
+    provider_id: &str,
+) -> Result<Vec<ProviderRouteConfig>, String> {
+    let results = tokio::task::spawn_blocking(move || {
+        providers
+            .iter()
+            .map(|provider| (provider.id.clone(), provider.name.clone(), build_provider_route_config(provider)))
+            .collect::<Vec<_>>()
+    })
+    .await
+    .map_err(|e| format!("解析供应商密钥失败: {}", e))?;
+
+    let mut route_configs = Vec::new();
+    for (id, name, result) in results {
+        match result {
+            Ok(route_config) => route_configs.push(route_config),
+            Err(error) if id == provider_id => return Err(error),
+            Err(error) => {
+                let message = format!("API Gateway 跳过供应商 {} 的模型路由: {}", name, error);
+                log::warn!("{}", message);
+                emit_log(host, "warn", message);
+            }
+        }
+    }
+    Ok(route_configs)
+}
 
-    config
-        .providers
-        .values()
-        .map(build_provider_route_config)
-        .collect()
+/// 根据各供应商路由配置与 Gateway 配置生成指向 `provider_id` 的路由状态。
+fn build_route_state(
+    route_configs: Vec<ProviderRouteConfig>,
+    provider_id: &str,
+    config: &ApiGatewayConfig,
+) -> Result<RouteState, String> {
+    let current_route = route_configs
+        .iter()
+        .find(|item| item.provider_id == provider_id)
+        .cloned()
+        .ok_or_else(|| format!("未找到供应商 {} 的 Gateway 路由配置", provider_id))?;
+
+    Ok(RouteState {
+        enabled: true,
+        provider_id: current_route.provider_id,
+        provider_name: current_route.provider_name,
+        target_base_url: current_route.target_base_url,
+        upstream_auth: current_route.upstream_auth,
+        rate_limit: current_route.rate_limit,
+        retry: config.retry.clone(),
+        circuit_breaker: config.circuit_breaker.clone(),
+        count_tokens_locally: current_route.count_tokens_locally,
+        defaults: current_route.defaults,
+        reasoning: current_route.reasoning,
+        transform: current_route.transform,
+        available_models: route_configs
+            .iter()
+            .flat_map(|item| item.models.clone())
+            .collect(),
+        models: current_route.models,
+        model_routes: build_model_routes(&route_configs),
+    })
 }
 
 fn build_models_response(state: &RouteState) -> Value {
@@ -389,15 +567,59 @@ fn rewrite_model_aliases(body: Bytes, model_routes: &std::collections::HashMap<S
         .unwrap_or(body)
 }
 
+fn apply_request_defaults(body: Bytes, defaults: &RequestDefaults) -> Bytes {
+    if defaults.is_empty() {
+        return body;
+    }
+    let Ok(mut json_body) = serde_json::from_slice::<Value>(&body) else {
+        return body;
+    };
+    defaults.apply_to_messages(&mut json_body);
+    serde_json::to_vec(&json_body).map(Bytes::from).unwrap_or(body)
+}
+
+/// 按供应商的推理映射改写 Anthropic `thinking`，默认只把思考预算限制在 `max_tokens` 以内。
+fn apply_reasoning_mapping(body: Bytes, reasoning: &ReasoningMapping) -> Bytes {
+    let Ok(mut json_body) = serde_json::from_slice::<Value>(&body) else {
+        return body;
+    };
+    let Some(object) = json_body.as_object_mut() else {
+        return body;
+    };
+    let Some(intent) = gateway_reasoning::take_anthropic_intent(object) else {
+        return body;
+    };
+    let style = reasoning.style.unwrap_or(ReasoningStyle::AnthropicBudget);
+    reasoning.apply(style, &intent, object);
+    serde_json::to_vec(&json_body).map(Bytes::from).unwrap_or(body)
+}
+
 fn extract_request_model(body: &Bytes) -> Option<String> {
     serde_json::from_slice::<Value>(body)
         .ok()
         .and_then(|json_body| json_body.get("model").and_then(|value| value.as_str()).map(str::to_string))
 }
 
-#[cfg(test)]
-pub fn build_gateway_provider_config(provider: &Provider, all_providers: &[Provider], port: u16) -> serde_json::Value {
+/// 写入 `~/.claude/settings.json` 的供应商配置。
+///
+/// 直连时解析密钥引用；Gateway 启用时（`gateway_port` 非空）指向本地 Gateway，不写入任何真实凭据。
+pub fn claude_settings(
+    provider: &Provider,
+    all_providers: &[Provider],
+    gateway_port: Option<u16>,
+) -> Result<serde_json::Value, String> {
+    match gateway_port {
+        Some(port) => secret_ref::resolve_claude_settings(&build_gateway_provider_config(provider, all_providers, port)),
+        None => secret_ref::resolve_claude_settings(&provider.settings_config),
+    }
+}
+
+/// Gateway 模式的 Claude 配置：地址和模型指向本地 Gateway，凭据类变量替换为占位令牌并去掉 `apiGateway`。
+fn build_gateway_provider_config(provider: &Provider, all_providers: &[Provider], port: u16) -> serde_json::Value {
     let mut provider_config = provider.settings_config.clone();
+    if let Some(config) = provider_config.as_object_mut() {
+        config.remove("apiGateway");
+    }
     let models = all_providers
         .iter()
         .flat_map(configured_provider_models)
@@ -424,6 +646,11 @@ pub fn build_gateway_provider_config(provider: &Provider, all_providers: &[Provi
         .and_then(|env| env.as_object_mut());
 
     if let Some(env) = env {
+        env.retain(|name, _| !secret_vault::is_secret_env_name(name));
+        env.insert(
+            "ANTHROPIC_AUTH_TOKEN".to_string(),
+            serde_json::Value::String(GATEWAY_PLACEHOLDER_TOKEN.to_string()),
+        );
         env.insert(
             "ANTHROPIC_BASE_URL".to_string(),
             serde_json::Value::String(gateway_base_url(port)),
@@ -453,6 +680,50 @@ pub fn build_gateway_provider_config(provider: &Provider, all_providers: &[Provi
     provider_config
 }
 
+/// 构造回放用的非流式 `/v1/messages` 请求，沿用网关的模型别名重写和鉴权注入。
+///
+/// 请求中的模型不是该供应商的网关模型别名时，改用供应商配置的默认模型，便于横向对比。
+/// `provider` 中的密钥引用需已由调用方解析。
+pub fn build_replay_request(
+    client: &reqwest::Client,
+    provider: &Provider,
+    body: &Value,
+) -> Result<reqwest::RequestBuilder, String> {
+    let route = resolved_provider_route_config(provider)?;
+    let model_routes = build_model_routes(std::slice::from_ref(&route));
+
+    let mut body = body.clone();
+    let object = body.as_object_mut().ok_or("回放请求体必须是 JSON 对象")?;
+    object.insert("stream".to_string(), Value::Bool(false));
+    let is_gateway_alias = object
+        .get("model")
+        .and_then(Value::as_str)
+        .is_some_and(|model| model_routes.contains_key(model));
+    if !is_gateway_alias {
+        if let Some(model) = route.models.first() {
+            object.insert("model".to_string(), Value::String(model.upstream_model.clone()));
+        }
+    }
+    let body = serde_json::to_vec(&body).map_err(|e| format!("序列化回放请求失败: {}", e))?;
+    let body = apply_request_defaults(rewrite_model_aliases(Bytes::from(body), &model_routes), &route.defaults);
+    let body = apply_reasoning_mapping(body, &route.reasoning);
+    let body = route.transform.apply_bytes(body.to_vec());
+
+    let target_url = apply_upstream_auth_query_params(
+        build_target_url(&route.target_base_url, &Uri::from_static("/v1/messages"))?,
+        route.upstream_auth.primary(),
+    );
+    let request_builder = client
+        .post(target_url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("anthropic-version", "2023-06-01")
+        .body(body);
+
+    Ok(route
+        .transform
+        .apply_headers(apply_upstream_auth_headers(request_builder, route.upstream_auth.primary())))
+}
+
 pub fn is_running(state: &AppState) -> Result<bool, String> {
     let runtime = state
         .api_gateway_runtime
@@ -479,35 +750,25 @@ pub async fn get_route_snapshot(state: &AppState) -> Result<Option<GatewayRouteS
         provider_id: route.provider_id.clone(),
         provider_name: route.provider_name.clone(),
         target_base_url: route.target_base_url.clone(),
+        request_defaults: route.defaults.clone(),
     }))
 }
 
-fn emit_log(app_handle: &tauri::AppHandle, level: &str, message: impl Into<String>) {
-    let message = message.into();
-    let payload = json!({
-        "timestamp": chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
-        "level": level,
-        "message": message,
-    });
-
-    let _ = app_handle.emit("api-gateway-log", payload);
+fn emit_log(host: &GatewayHost, level: &str, message: impl Into<String>) {
+    host.emit_log(GatewayKind::Api, level, &message.into());
 }
 
 pub async fn start_or_update(state: &AppState, provider: &Provider, port: u16) -> Result<(), String> {
-    let route_configs = collect_route_configs(state)?;
-    let current_route = route_configs
-        .iter()
-        .find(|item| item.provider_id == provider.id)
-        .cloned()
-        .ok_or_else(|| format!("未找到供应商 {} 的 Gateway 路由配置", provider.id))?;
-    let available_models = route_configs
-        .iter()
-        .flat_map(|item| item.models.clone())
-        .collect::<Vec<_>>();
-    let model_routes = build_model_routes(&route_configs);
-    let target_base_url = current_route.target_base_url.clone();
-    let upstream_auth = current_route.upstream_auth.clone();
-    let models = current_route.models.clone();
+    let (providers, gateway_config) = {
+        let config = state
+            .config
+            .lock()
+            .map_err(|e| format!("获取锁失败: {}", e))?;
+        (config.providers.values().cloned().collect::<Vec<_>>(), config.api_gateway.clone())
+    };
+    let route_configs = build_route_configs(&state.gateway_host()?, providers, &provider.id).await?;
+    let next_route = build_route_state(route_configs, &provider.id, &gateway_config)?;
+    let target_base_url = next_route.target_base_url.clone();
 
     let (route_state, should_spawn) = {
         let mut runtime = state
@@ -516,33 +777,28 @@ pub async fn start_or_update(state: &AppState, provider: &Provider, port: u16) -
             .map_err(|e| format!("获取 API Gateway 运行时锁失败: {}", e))?;
 
         let route_state = runtime.route_state.clone().unwrap_or_else(|| {
-            let route_state = Arc::new(RwLock::new(RouteState {
-                enabled: true,
-                provider_id: provider.id.clone(),
-                provider_name: provider.name.clone(),
-                target_base_url: target_base_url.clone(),
-                upstream_auth: upstream_auth.clone(),
-                available_models: available_models.clone(),
-                models: models.clone(),
-                model_routes: model_routes.clone(),
-            }));
+            let route_state = Arc::new(RwLock::new(next_route.clone()));
             runtime.route_state = Some(route_state.clone());
             route_state
         });
 
         let should_spawn = runtime.server_handle.is_none();
         if should_spawn {
-            let app_handle = state.app_handle()?.clone();
+            let host = state.gateway_host()?;
             let client = reqwest::Client::builder()
                 .build()
                 .map_err(|e| format!("初始化 API Gateway HTTP 客户端失败: {}", e))?;
             let server_state = GatewayServerState {
-                app_handle,
+                host,
                 client,
                 route_state: route_state.clone(),
+                rate_limiter: Arc::new(RateLimiterRegistry::default()),
+                health: state.gateway_health.clone(),
+                keys: state.gateway_keys.clone(),
+                metrics: Arc::new(GatewayMetrics::new(GatewayKind::Api)),
             };
             let (shutdown_tx, shutdown_rx) = oneshot::channel();
-            let server_handle = tauri::async_runtime::spawn(run_server(port, server_state, shutdown_rx));
+            let server_handle = tokio::spawn(run_server(port, server_state, shutdown_rx));
             runtime.shutdown_tx = Some(shutdown_tx);
             runtime.server_handle = Some(server_handle);
         }
@@ -550,17 +806,7 @@ pub async fn start_or_update(state: &AppState, provider: &Provider, port: u16) -
         Ok::<_, String>((route_state, should_spawn))
     }?;
 
-    {
-        let mut route = route_state.write().await;
-        route.enabled = true;
-        route.provider_id = provider.id.clone();
-        route.provider_name = provider.name.clone();
-        route.target_base_url = target_base_url.clone();
-        route.upstream_auth = upstream_auth;
-        route.available_models = available_models;
-        route.models = models;
-        route.model_routes = model_routes;
-    }
+    *route_state.write().await = next_route;
 
     if should_spawn {
         let message = format!(
@@ -570,11 +816,11 @@ pub async fn start_or_update(state: &AppState, provider: &Provider, port: u16) -
             target_base_url
         );
         log::info!("{}", message);
-        emit_log(state.app_handle()?, "info", message);
+        emit_log(&state.gateway_host()?, "info", message);
     } else {
         let message = format!("API Gateway 路由已更新: {} -> {}", provider.name, target_base_url);
         log::info!("{}", message);
-        emit_log(state.app_handle()?, "info", message);
+        emit_log(&state.gateway_host()?, "info", message);
     }
 
     Ok(())
@@ -609,19 +855,15 @@ pub async fn stop(state: &AppState) -> Result<(), String> {
     }
 
     log::info!("API Gateway 已停止");
-    emit_log(state.app_handle()?, "info", "API Gateway 已停止");
+    emit_log(&state.gateway_host()?, "info", "API Gateway 已停止");
     Ok(())
 }
 
 async fn run_server(
     port: u16,
     server_state: GatewayServerState,
-    mut shutdown_rx: oneshot::Receiver<()>,
+    shutdown_rx: oneshot::Receiver<()>,
 ) {
-    let app = Router::new()
-        .fallback(any(proxy_request))
-        .with_state(server_state);
-
     let listener = match tokio::net::TcpListener::bind(("127.0.0.1", port)).await {
         Ok(listener) => listener,
         Err(error) => {
@@ -630,6 +872,19 @@ async fn run_server(
         }
     };
 
+    serve(listener, server_state, shutdown_rx).await;
+}
+
+async fn serve(
+    listener: tokio::net::TcpListener,
+    server_state: GatewayServerState,
+    mut shutdown_rx: oneshot::Receiver<()>,
+) {
+    let probe_state = server_state.clone();
+    let app = Router::new()
+        .fallback(any(proxy_request))
+        .with_state(server_state);
+
     let server = axum::serve(listener, app);
     tokio::select! {
         result = server => {
@@ -637,10 +892,94 @@ async fn run_server(
                 log::error!("API Gateway 服务异常退出: {}", error);
             }
         }
+        _ = run_health_probe(probe_state) => {}
         _ = &mut shutdown_rx => {}
     }
 }
 
+/// 不依赖 Tauri 窗口，在给定监听器上运行 Gateway，直到 `shutdown_rx` 触发。
+pub async fn serve_headless(
+    listener: tokio::net::TcpListener,
+    host: GatewayHost,
+    providers: &[Provider],
+    provider_id: &str,
+    config: &ApiGatewayConfig,
+    shutdown_rx: oneshot::Receiver<()>,
+) -> Result<(), String> {
+    let route_configs = build_route_configs(&host, providers.to_vec(), provider_id).await?;
+    let route_state = build_route_state(route_configs, provider_id, config)?;
+    let client = reqwest::Client::builder()
+        .build()
+        .map_err(|e| format!("初始化 API Gateway HTTP 客户端失败: {}", e))?;
+    let server_state = GatewayServerState {
+        host,
+        client,
+        route_state: Arc::new(RwLock::new(route_state)),
+        rate_limiter: Arc::new(RateLimiterRegistry::default()),
+        health: Arc::new(HealthRegistry::default()),
+        keys: Arc::new(KeyPoolRegistry::default()),
+        metrics: Arc::new(GatewayMetrics::new(GatewayKind::Api)),
+    };
+
+    serve(listener, server_state, shutdown_rx).await;
+    Ok(())
+}
+
+/// 按 `circuit_breaker.probe_interval_secs` 主动探测已熔断的供应商。
+async fn run_health_probe(state: GatewayServerState) {
+    loop {
+        let (circuit_breaker, targets) = {
+            let route = state.route_state.read().await;
+            let mut targets = vec![(
+                route.provider_id.clone(),
+                route.target_base_url.clone(),
+                route.upstream_auth.primary().clone(),
+            )];
+            for model_route in route.model_routes.values() {
+                if !targets.iter().any(|(provider_id, _, _)| *provider_id == model_route.provider_id) {
+                    targets.push((
+                        model_route.provider_id.clone(),
+                        model_route.target_base_url.clone(),
+                        model_route.upstream_auth.primary().clone(),
+                    ));
+                }
+            }
+            (route.circuit_breaker.clone(), targets)
+        };
+
+        let Some(interval) = circuit_breaker.probe_interval_secs.filter(|secs| *secs > 0) else {
+            // 未开启主动探测时定期重新读取配置
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            continue;
+        };
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+
+        for (provider_id, target_base_url, upstream_auth) in targets {
+            if !state.health.is_open(&provider_id) {
+                continue;
+            }
+
+            let reachable = match Url::parse(&target_base_url) {
+                Ok(url) => {
+                    let request = state
+                        .client
+                        .get(apply_upstream_auth_query_params(url, &upstream_auth))
+                        .timeout(Duration::from_secs(10));
+                    apply_upstream_auth_headers(request, &upstream_auth)
+                        .send()
+                        .await
+                        .is_ok_and(|response| !gateway_health::is_failure_status(response.status().as_u16()))
+                }
+                Err(_) => false,
+            };
+
+            if let Some(circuit_state) = state.health.record_probe(&provider_id, &circuit_breaker, reachable) {
+                log_circuit_transition(&state.host, &provider_id, circuit_state);
+            }
+        }
+    }
+}
+
 async fn proxy_request(
     State(state): State<GatewayServerState>,
     method: Method,
@@ -684,6 +1023,14 @@ async fn proxy_request(
         .into_response();
     }
 
+    if method == Method::GET && uri.path() == "/metrics" {
+        return (
+            [(axum::http::header::CONTENT_TYPE, gateway_metrics::CONTENT_TYPE)],
+            state.metrics.render(),
+        )
+            .into_response();
+    }
+
     if method == Method::GET && uri.path() == "/v1/models" {
         let route = state.route_state.read().await;
         let message = format!(
@@ -693,7 +1040,7 @@ async fn proxy_request(
             route.available_models.len()
         );
         log::info!("{}", message);
-        emit_log(&state.app_handle, "info", message);
+        emit_log(&state.host, "info", message);
         return Json(build_models_response(&route)).into_response();
     }
 
@@ -705,7 +1052,7 @@ async fn proxy_request(
             model
         );
         log::info!("{}", message);
-        emit_log(&state.app_handle, "info", message);
+        emit_log(&state.host, "info", message);
     } else {
         let message = format!(
             "API Gateway 收到请求: method={} path={}",
@@ -713,14 +1060,14 @@ async fn proxy_request(
             request_path
         );
         log::info!("{}", message);
-        emit_log(&state.app_handle, "info", message);
+        emit_log(&state.host, "info", message);
     }
 
     match forward_request(state.clone(), method, uri, headers, body).await {
         Ok(response) => response,
         Err(error) => {
             log::error!("API Gateway 转发失败: {}", error);
-            emit_log(&state.app_handle, "error", format!("API Gateway 转发失败: {}", error));
+            emit_log(&state.host, "error", format!("API Gateway 转发失败: {}", error));
             (
                 StatusCode::BAD_GATEWAY,
                 serde_json::json!({
@@ -741,12 +1088,33 @@ async fn forward_request(
     headers: HeaderMap,
     body: Bytes,
 ) -> Result<Response, String> {
-    let (default_target_base_url, default_upstream_auth, model_routes) = {
+    // 在同一次读锁内取出路由快照，避免并发切换时前后读到不同的路由
+    let (
+        default_provider_id,
+        default_target_base_url,
+        default_upstream_auth,
+        default_rate_limit,
+        model_routes,
+        retry,
+        circuit_breaker,
+        default_count_tokens_locally,
+        default_request_defaults,
+        default_reasoning,
+        default_transform,
+    ) = {
         let route = state.route_state.read().await;
         (
+            route.provider_id.clone(),
             route.target_base_url.clone(),
             route.upstream_auth.clone(),
+            route.rate_limit.clone(),
             route.model_routes.clone(),
+            route.retry.clone(),
+            route.circuit_breaker.clone(),
+            route.count_tokens_locally,
+            route.defaults.clone(),
+            route.reasoning.clone(),
+            route.transform.clone(),
         )
     };
 
@@ -754,30 +1122,124 @@ async fn forward_request(
         .as_deref()
         .and_then(|model| model_routes.get(model));
 
+    let provider_id = selected_route
+        .map(|route| route.provider_id.clone())
+        .unwrap_or(default_provider_id);
+    let mut request_log = RequestLog::start(&state.host, &state.metrics, GatewayKind::Api, method.as_str(), uri.path(), body.len());
+    request_log.set_route(&provider_id, extract_request_model(&body).as_deref());
+    let capture = CaptureSession::start(&state.host, GatewayKind::Api, request_log.request_id());
     let target_base_url = selected_route
         .map(|route| route.target_base_url.clone())
         .unwrap_or(default_target_base_url);
     let upstream_auth = selected_route
         .map(|route| route.upstream_auth.clone())
         .unwrap_or(default_upstream_auth);
+    let rate_limit = selected_route
+        .map(|route| route.rate_limit.clone())
+        .unwrap_or(default_rate_limit);
+    let count_tokens_locally = selected_route
+        .map(|route| route.count_tokens_locally)
+        .unwrap_or(default_count_tokens_locally);
+    let request_defaults = selected_route
+        .map(|route| route.defaults.clone())
+        .unwrap_or(default_request_defaults);
+    let reasoning = selected_route
+        .map(|route| route.reasoning.clone())
+        .unwrap_or(default_reasoning);
+    let transform = selected_route
+        .map(|route| route.transform.clone())
+        .unwrap_or(default_transform);
+    let is_count_tokens = method == Method::POST && uri.path() == COUNT_TOKENS_PATH;
+    if let Some(capture) = &capture {
+        capture.set_route(&provider_id, extract_request_model(&body).as_deref());
+        capture.set_inbound(method.as_str(), &uri.to_string(), &headers, &body);
+        for strategy in upstream_auth.credentials().flat_map(|auth| &auth.strategies) {
+            match strategy {
+                GatewayAuthStrategy::Header(header) => capture.add_credential(Some(&header.name), &header.value),
+                GatewayAuthStrategy::Query(param) => capture.add_credential(Some(&param.name), &param.value),
+            }
+        }
+    }
+
+    if is_count_tokens && count_tokens_locally {
+        let message = format!("API Gateway 本地估算 count_tokens: provider={}", provider_id);
+        log::info!("{}", message);
+        emit_log(&state.host, "info", message);
+        return Ok(local_count_tokens_response(&body, &mut request_log, capture.as_ref()));
+    }
+
+    // 先检查熔断，避免被熔断拒绝的请求占用限流额度
+    if let Err(remaining) = state.health.check(&provider_id, &circuit_breaker) {
+        let message = format!(
+            "API Gateway 供应商 {} 已熔断，{} 秒后重试",
+            provider_id,
+            remaining.as_secs_f64().ceil()
+        );
+        log::warn!("{}", message);
+        emit_log(&state.host, "warn", message);
+        request_log.set_local_status(503, ErrorClass::CircuitOpen, "circuit_open");
+        if let Some(capture) = &capture {
+            capture.fail("circuit_open");
+        }
+        return Ok(circuit_open_response(&provider_id, remaining));
+    }
+
+    let rate_limit_permit = match state
+        .rate_limiter
+        .acquire(&provider_id, &rate_limit, token_estimate::estimate_body_tokens(&body))
+        .await
+    {
+        Ok(permit) => permit,
+        Err(rejection) => {
+            let message = format!(
+                "API Gateway 本地限流: provider={} reason={} retry_after={}s",
+                provider_id,
+                rejection.reason.as_str(),
+                rejection.retry_after_secs()
+            );
+            log::warn!("{}", message);
+            emit_log(&state.host, "warn", message);
+            request_log.set_local_status(429, ErrorClass::RateLimited, rejection.reason.as_str());
+            if let Some(capture) = &capture {
+                capture.fail(rejection.message());
+            }
+            return Ok(rate_limited_response(&rejection));
+        }
+    };
 
-    let target_url = apply_upstream_auth_query_params(build_target_url(&target_base_url, &uri)?, &upstream_auth);
+    let target_url = build_target_url(&target_base_url, &uri)?;
     let reqwest_method = reqwest::Method::from_bytes(method.as_str().as_bytes())
         .map_err(|e| format!("不支持的 HTTP 方法: {}", e))?;
 
-    let mut request_builder = state.client.request(reqwest_method, target_url.clone());
-    for (name, value) in headers.iter() {
-        if should_skip_request_header(name) {
-            continue;
+    let build_request = |upstream_auth: &UpstreamAuth| {
+        let mut request_builder = state.client.request(
+            reqwest_method.clone(),
+            apply_upstream_auth_query_params(target_url.clone(), upstream_auth),
+        );
+        for (name, value) in headers.iter() {
+            if should_skip_request_header(name) {
+                continue;
+            }
+            request_builder = request_builder.header(name, value);
         }
-        request_builder = request_builder.header(name, value);
-    }
 
-    request_builder = apply_upstream_auth_headers(request_builder, &upstream_auth);
+        transform.apply_headers(apply_upstream_auth_headers(request_builder, upstream_auth))
+    };
 
     let original_model = extract_request_model(&body);
-    let rewritten_body = if method == Method::POST && uri.path() == "/v1/messages" {
-        rewrite_model_aliases(body, &model_routes)
+    let rewritten_body = if method == Method::POST && (uri.path() == "/v1/messages" || is_count_tokens) {
+        let rewritten = apply_reasoning_mapping(
+            apply_request_defaults(rewrite_model_aliases(body, &model_routes), &request_defaults),
+            &reasoning,
+        );
+        if transform.is_empty() {
+            rewritten
+        } else {
+            let message = format!("API Gateway 按供应商 {} 的改写规则处理请求体", provider_id);
+            log::info!("{}", message);
+            emit_log(&state.host, "info", message);
+            Bytes::from(transform.apply_bytes(rewritten.to_vec()))
+        }
     } else {
         body
     };
@@ -790,7 +1252,7 @@ async fn forward_request(
             rewritten_model.as_deref().unwrap_or("<none>")
         );
         log::info!("{}", message);
-        emit_log(&state.app_handle, "info", message);
+        emit_log(&state.host, "info", message);
     }
 
     if let Some(route) = selected_route {
@@ -801,7 +1263,7 @@ async fn forward_request(
             route.provider_id
         );
         log::info!("{}", message);
-        emit_log(&state.app_handle, "info", message);
+        emit_log(&state.host, "info", message);
     }
 
     let forward_message = format!(
@@ -811,22 +1273,147 @@ async fn forward_request(
         rewritten_model.as_deref().or(original_model.as_deref()).unwrap_or("<none>")
     );
     log::info!("{}", forward_message);
-    emit_log(&state.app_handle, "info", forward_message);
+    emit_log(&state.host, "info", forward_message);
 
-    let upstream_response = request_builder
-        .body(rewritten_body)
-        .send()
-        .await
-        .map_err(|e| format!("请求上游失败: {}", e))?;
+    if let Some(capture) = &capture {
+        match build_request(upstream_auth.primary()).body(rewritten_body.clone()).build() {
+            Ok(request) => capture.set_upstream_request(&request),
+            Err(error) => capture.fail(format!("构造上游请求失败: {}", error)),
+        }
+    }
+
+    let started_at = Instant::now();
+    let upstream_result = gateway_keys::send_with_keys(
+        &state.keys,
+        &provider_id,
+        &upstream_auth,
+        &retry,
+        |upstream_auth| build_request(upstream_auth).body(rewritten_body.clone()),
+        |attempt| {
+            let message = format!(
+                "API Gateway 上游瞬时失败，{}ms 后第 {}/{} 次重试: {} url={}",
+                attempt.delay.as_millis(),
+                attempt.attempt,
+                attempt.max_retries,
+                attempt.reason,
+                target_url
+            );
+            log::warn!("{}", message);
+            emit_log(&state.host, "warn", message);
+        },
+        |failover| {
+            let message = format!(
+                "API Gateway 上游拒绝密钥 {}（status={}），换用供应商 {} 的下一个密钥",
+                failover.label, failover.status, provider_id
+            );
+            log::warn!("{}", message);
+            emit_log(&state.host, "warn", message);
+        },
+    )
+    .await;
+
+    let upstream_error = match &upstream_result {
+        // 上游不支持 count_tokens 会改为本地估算，不计为供应商故障
+        Ok(response) if is_count_tokens && is_count_tokens_unsupported(response.status()) => None,
+        Ok(response) if gateway_health::is_failure_status(response.status().as_u16()) => {
+            Some(format!("status={}", response.status()))
+        }
+        Ok(_) => None,
+        Err(error) => Some(error.to_string()),
+    };
+    let previous_status = state.health.status(&provider_id);
+    if let Some(circuit_state) =
+        state
+            .health
+            .record(&provider_id, &circuit_breaker, started_at.elapsed(), upstream_error)
+    {
+        log_circuit_transition(&state.host, &provider_id, circuit_state);
+    } else if state.health.status(&provider_id) != previous_status {
+        state.host.health_changed();
+    }
+
+    let upstream_response = match upstream_result {
+        Ok(response) => response,
+        Err(error) => {
+            request_log.fail(ErrorClass::from_reqwest(&error), error.to_string());
+            if let Some(capture) = &capture {
+                capture.fail(error.to_string());
+            }
+            return Err(format!("请求上游失败: {}", error));
+        }
+    };
 
     let status = upstream_response.status();
+    request_log.set_upstream_status(status.as_u16());
     let response_message = format!("API Gateway 上游响应: status={} url={}", status, target_url);
     log::info!("{}", response_message);
-    emit_log(&state.app_handle, "info", response_message);
+    emit_log(&state.host, "info", response_message);
     let response_headers = upstream_response.headers().clone();
+    if let Some(capture) = &capture {
+        capture.set_upstream_response(status.as_u16(), &response_headers);
+    }
+
+    if is_count_tokens && is_count_tokens_unsupported(status) {
+        let body_text = upstream_response.text().await.unwrap_or_default();
+        request_log.add_response_bytes(body_text.len());
+        if let Some(capture) = &capture {
+            capture.append_upstream_body(body_text.as_bytes());
+        }
+        let message = format!(
+            "API Gateway 上游不支持 count_tokens (status={})，改为本地估算: provider={}",
+            status, provider_id
+        );
+        log::warn!("{}", message);
+        emit_log(&state.host, "warn", message);
+        return Ok(local_count_tokens_response(&rewritten_body, &mut request_log, capture.as_ref()));
+    }
+
+    if let Some(pause) = state
+        .rate_limiter
+        .observe_upstream(&provider_id, status.as_u16(), &response_headers)
+    {
+        let message = format!(
+            "API Gateway 上游限流，暂停供应商 {} {} 秒",
+            provider_id,
+            pause.as_secs_f64().ceil()
+        );
+        log::warn!("{}", message);
+        emit_log(&state.host, "warn", message);
+    }
+
+    let is_event_stream = response_headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"));
+    if is_event_stream {
+        request_log.start_stream();
+    }
+    let mut usage_scanner = AnthropicUsageScanner::new(is_event_stream);
+
+    // 流式响应结束（或下游断开）前保持并发流名额，结束后写入请求日志和抓包
     let body_stream = upstream_response
         .bytes_stream()
-        .map_err(std::io::Error::other);
+        .map_err(std::io::Error::other)
+        .map(move |chunk| {
+            let _ = &rate_limit_permit;
+            match &chunk {
+                Ok(bytes) => {
+                    request_log.add_response_bytes(bytes.len());
+                    let (input_tokens, output_tokens) = usage_scanner.push(bytes);
+                    request_log.set_usage(input_tokens, output_tokens);
+                    if let Some(capture) = &capture {
+                        capture.append_upstream_body(bytes);
+                    }
+                }
+                Err(error) => {
+                    request_log.fail(ErrorClass::Stream, error.to_string());
+                    if let Some(capture) = &capture {
+                        capture.fail(error.to_string());
+                    }
+                }
+            }
+            chunk
+        });
 
     let mut response_builder = Response::builder().status(status);
     for (name, value) in response_headers.iter() {
@@ -838,6 +1425,87 @@ async fn forward_request(
         .map_err(|e| format!("构造代理响应失败: {}", e))
 }
 
+/// 上游未实现 count_tokens 时常见的状态码
+fn is_count_tokens_unsupported(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 404 | 405 | 501)
+}
+
+fn local_count_tokens_response(body: &Bytes, request_log: &mut RequestLog, capture: Option<&CaptureSession>) -> Response {
+    let Ok(json_body) = serde_json::from_slice::<Value>(body) else {
+        request_log.set_local_status(400, ErrorClass::InvalidRequest, "count_tokens 请求体不是合法 JSON");
+        if let Some(capture) = capture {
+            capture.fail("count_tokens 请求体不是合法 JSON");
+        }
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "type": "error",
+                "error": {
+                    "type": "invalid_request_error",
+                    "message": "count_tokens 请求体不是合法 JSON",
+                },
+            })),
+        )
+            .into_response();
+    };
+
+    let response = json!({ "input_tokens": token_estimate::estimate_anthropic_input_tokens(&json_body) });
+    request_log.set_local_response(200);
+    if let Some(capture) = capture {
+        capture.record_downstream("count_tokens", &response);
+    }
+    Json(response).into_response()
+}
+
+fn circuit_open_response(provider_id: &str, remaining: Duration) -> Response {
+    let mut response = (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({
+            "type": "error",
+            "error": {
+                "type": "api_error",
+                "message": format!("供应商 {} 暂时不可用（熔断中），请稍后重试", provider_id),
+            }
+        })),
+    )
+        .into_response();
+    response.headers_mut().insert(
+        axum::http::header::RETRY_AFTER,
+        axum::http::HeaderValue::from(remaining.as_secs_f64().ceil() as u64),
+    );
+    response
+}
+
+fn log_circuit_transition(host: &GatewayHost, provider_id: &str, circuit_state: CircuitState) {
+    let message = match circuit_state {
+        CircuitState::Open => format!("API Gateway 供应商 {} 连续失败，已熔断", provider_id),
+        CircuitState::HalfOpen => format!("API Gateway 供应商 {} 探测成功，进入半开状态", provider_id),
+        CircuitState::Closed => format!("API Gateway 供应商 {} 已恢复", provider_id),
+    };
+    log::warn!("{}", message);
+    emit_log(host, "warn", message);
+    host.health_changed();
+}
+
+fn rate_limited_response(rejection: &RateLimitRejection) -> Response {
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(json!({
+            "type": "error",
+            "error": {
+                "type": "rate_limit_error",
+                "message": rejection.message(),
+            }
+        })),
+    )
+        .into_response();
+    response.headers_mut().insert(
+        axum::http::header::RETRY_AFTER,
+        axum::http::HeaderValue::from(rejection.retry_after_secs()),
+    );
+    response
+}
+
 fn apply_upstream_auth_headers(
     mut request_builder: reqwest::RequestBuilder,
     upstream_auth: &UpstreamAuth,
@@ -935,6 +1603,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn gateway_mode_settings_contain_no_resolved_secret() {
+        std::env::set_var("SWITCH_CC_TEST_GATEWAY_TOKEN", "sk-resolved-gateway-secret");
+        let mut provider = test_provider();
+        provider.settings_config["env"]["ANTHROPIC_AUTH_TOKEN"] = json!("env:SWITCH_CC_TEST_GATEWAY_TOKEN");
+        provider.settings_config["apiGateway"] = json!({
+            "auth": [{ "name": "x-token", "value": "env:SWITCH_CC_TEST_GATEWAY_TOKEN" }]
+        });
+        let all_providers = std::slice::from_ref(&provider);
+
+        let settings = claude_settings(&provider, all_providers, Some(3456)).unwrap();
+
+        let text = settings.to_string();
+        assert!(!text.contains("sk-resolved-gateway-secret"));
+        assert!(!text.contains("SWITCH_CC_TEST_GATEWAY_TOKEN"));
+        assert!(settings.get("apiGateway").is_none());
+        assert_eq!(settings["env"]["ANTHROPIC_AUTH_TOKEN"], json!(GATEWAY_PLACEHOLDER_TOKEN));
+        assert_eq!(settings["env"]["ANTHROPIC_BASE_URL"], json!("http://127.0.0.1:3456"));
+
+        let direct = claude_settings(&provider, all_providers, None).unwrap();
+        assert_eq!(direct["env"]["ANTHROPIC_AUTH_TOKEN"], json!("sk-resolved-gateway-secret"));
+    }
+
+    #[tokio::test]
+    async fn unresolvable_secondary_provider_is_skipped() {
+        let current = test_provider();
+        let mut broken = test_provider();
+        broken.id = "broken".to_string();
+        broken.settings_config["env"]["ANTHROPIC_AUTH_TOKEN"] = json!("env:SWITCH_CC_TEST_UNSET_ROUTE_TOKEN");
+        let host = GatewayHost::new(
+            Arc::new(crate::gateway_host::ConsoleSink),
+            Arc::new(std::sync::Mutex::new(crate::store::AppConfig::default())),
+            Arc::default(),
+        );
+
+        let route_configs = build_route_configs(&host, vec![current.clone(), broken.clone()], &current.id)
+            .await
+            .unwrap();
+        assert_eq!(route_configs.len(), 1);
+        assert_eq!(route_configs[0].provider_id, current.id);
+
+        let error = build_route_configs(&host, vec![current, broken], "broken").await.unwrap_err();
+        assert!(error.contains("SWITCH_CC_TEST_UNSET_ROUTE_TOKEN"));
+    }
+
     #[test]
     fn configured_models_embed_provider_name_for_picker() {
         let provider = test_provider();
@@ -962,7 +1675,12 @@ mod tests {
                 provider_name: "MiniMax".to_string(),
                 target_base_url: "https://api.minimaxi.com/anthropic".to_string(),
                 upstream_model: "MiniMax-M2.7".to_string(),
-                upstream_auth: UpstreamAuth::default(),
+                upstream_auth: KeyPool::new("", &KeyPoolConfig::default(), |_| UpstreamAuth::default()),
+                rate_limit: RateLimitConfig::default(),
+                count_tokens_locally: false,
+                defaults: RequestDefaults::default(),
+                reasoning: ReasoningMapping::default(),
+                transform: TransformRules::default(),
             },
         )]);
 
@@ -972,6 +1690,27 @@ mod tests {
         assert_eq!(json_body.get("model").and_then(|value| value.as_str()), Some("MiniMax-M2.7"));
     }
 
+    #[test]
+    fn replay_request_uses_provider_model_and_auth() {
+        let provider = test_provider();
+        let client = reqwest::Client::new();
+
+        let request = build_replay_request(
+            &client,
+            &provider,
+            &json!({ "model": "claude-sonnet-4-6", "stream": true, "max_tokens": 16, "messages": [] }),
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+        let body: Value = serde_json::from_slice(request.body().unwrap().as_bytes().unwrap()).unwrap();
+
+        assert_eq!(request.url().as_str(), "https://api.minimaxi.com/anthropic/v1/messages");
+        assert_eq!(request.headers().get("authorization").unwrap(), "Bearer sk-test");
+        assert_eq!(body["model"], json!("MiniMax-M2.7"));
+        assert_eq!(body["stream"], json!(false));
+    }
+
     #[test]
     fn should_skip_request_header_filters_content_length_and_hop_by_hop_headers() {
         assert!(should_skip_request_header(&CONTENT_LENGTH));
@@ -0,0 +1,6 @@
+fn main() {
+    if let Err(error) = switch_cc_lib::run_gateway_daemon() {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
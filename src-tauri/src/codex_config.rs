@@ -136,6 +136,7 @@ mod tests {
                 upstream_url: "https://token-plan-sgp.xiaomimimo.com/v1".to_string(),
                 api_key: "tp-test".to_string(),
                 model_name: "mimo-v2-pro".to_string(),
+                ..Default::default()
             },
             website_url: None,
             category: None,
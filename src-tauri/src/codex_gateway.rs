@@ -1,32 +1,49 @@
-use crate::provider::CodexProvider;
-use crate::store::AppState;
+use crate::codex_tools::{self, HostedTools};
+use crate::gateway_capture::{self, CaptureSession};
+use crate::gateway_context::ContextWindow;
+use crate::gateway_defaults::RequestDefaults;
+use crate::gateway_health::{self, CircuitBreakerConfig, CircuitState, HealthRegistry};
+use crate::gateway_host::GatewayHost;
+use crate::gateway_keys::{self, KeyFailover, KeyPool, KeyPoolRegistry};
+use crate::gateway_log::{ErrorClass, GatewayKind, RequestLog};
+use crate::gateway_metrics::{self, GatewayMetrics};
+use crate::gateway_rate_limit::{RateLimitConfig, RateLimitPermit, RateLimitRejection, RateLimiterRegistry};
+use crate::gateway_reasoning::{self, ReasoningMapping, ReasoningStyle};
+use crate::gateway_retry::{RetryAttempt, RetryPolicy, StreamPolicy};
+use crate::gateway_structured::{self, StructuredOutput, StructuredOutputMode};
+use crate::gateway_transform::TransformRules;
+use crate::provider::{CodexProvider, InputCapabilities};
+use crate::secret_ref;
+use crate::store::{AppState, CodexGatewayConfig};
+use crate::token_estimate;
 use async_stream::stream;
 use axum::{
     extract::State,
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{sse::Event, sse::KeepAlive, IntoResponse, Response, Sse},
     routing::{get, post},
     Json, Router,
 };
+use base64::Engine;
 use eventsource_stream::Eventsource;
 use futures_util::StreamExt;
 use serde_json::{json, Value};
-use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::convert::Infallible;
-use std::fs::{self, OpenOptions};
-use std::io::Write;
-use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Instant;
-use tauri::{Emitter, Manager};
+use std::time::{Duration, Instant};
 use tokio::sync::{oneshot, RwLock};
 use uuid::Uuid;
 
 #[derive(Clone)]
 struct GatewayServerState {
-    app_handle: tauri::AppHandle,
+    host: GatewayHost,
     client: reqwest::Client,
     route_state: Arc<RwLock<RouteState>>,
+    rate_limiter: Arc<RateLimiterRegistry>,
+    health: Arc<HealthRegistry>,
+    keys: Arc<KeyPoolRegistry>,
+    metrics: Arc<GatewayMetrics>,
 }
 
 /// Provider adapter that normalizes request/response differences across LLM providers.
@@ -38,6 +55,8 @@ enum ProviderAdapter {
     MiniMax,
     /// DeepSeek adapter — normalizes unsupported roles (e.g. developer → system).
     DeepSeek,
+    /// Qwen (DashScope) adapter — normalizes unsupported roles (e.g. developer → system).
+    Qwen,
 }
 
 impl ProviderAdapter {
@@ -48,6 +67,8 @@ impl ProviderAdapter {
             Self::MiniMax
         } else if lower.contains("deepseek") {
             Self::DeepSeek
+        } else if lower.contains("dashscope") || lower.contains("qwen") {
+            Self::Qwen
         } else {
             Self::Default
         }
@@ -61,26 +82,70 @@ impl ProviderAdapter {
                 "developer" => "system",
                 other => other,
             },
-            Self::DeepSeek => match role {
+            Self::DeepSeek | Self::Qwen => match role {
                 "developer" => "system",
                 other => other,
             },
         }
     }
 
+    /// Vendor-native replacement for the hosted `web_search` tool, if any.
+    fn native_web_search(&self) -> Option<(&'static str, Value)> {
+        match self {
+            Self::Qwen => Some(("enable_search", Value::Bool(true))),
+            Self::Default | Self::MiniMax | Self::DeepSeek => None,
+        }
+    }
+
+    /// Multimodal inputs the upstream accepts unless the provider overrides them.
+    fn input_support(&self) -> InputSupport {
+        match self {
+            Self::Default => InputSupport { images: true, files: true },
+            Self::Qwen => InputSupport { images: true, files: false },
+            Self::MiniMax | Self::DeepSeek => InputSupport { images: false, files: false },
+        }
+    }
+
+    /// Default reasoning parameter style when the provider does not configure one.
+    fn reasoning_style(&self) -> ReasoningStyle {
+        match self {
+            Self::Default => ReasoningStyle::Effort,
+            Self::MiniMax => ReasoningStyle::Drop,
+            Self::DeepSeek => ReasoningStyle::ThinkingType,
+            Self::Qwen => ReasoningStyle::EnableThinking,
+        }
+    }
+
+    /// Whether the upstream accepts `stream_options.include_usage` on streaming requests.
+    fn supports_stream_usage(&self) -> bool {
+        match self {
+            Self::Default | Self::DeepSeek | Self::Qwen => true,
+            Self::MiniMax => false,
+        }
+    }
+
+    /// Assistant message that makes the upstream continue from `text`, if supported.
+    fn assistant_prefix_message(&self, text: &str) -> Option<Value> {
+        match self {
+            Self::DeepSeek => Some(json!({ "role": "assistant", "content": text, "prefix": true })),
+            Self::Qwen => Some(json!({ "role": "assistant", "content": text, "partial": true })),
+            Self::Default | Self::MiniMax => None,
+        }
+    }
+
     /// Normalize the full message list for upstream compatibility.
     fn normalize_messages(&self, messages: Vec<Value>) -> Vec<Value> {
         match self {
             Self::Default => messages,
             Self::MiniMax => merge_system_messages(messages),
-            Self::DeepSeek => messages,
+            Self::DeepSeek | Self::Qwen => messages,
         }
     }
 
     /// Normalize an upstream response message into separate reasoning/text channels.
     fn sanitize_response_message(&self, message: &Value) -> ResponseContentParts {
         let mut parts = match self {
-            Self::Default | Self::DeepSeek => ResponseContentParts {
+            Self::Default | Self::DeepSeek | Self::Qwen => ResponseContentParts {
                 text: extract_message_text(message.get("content")),
                 ..Default::default()
             },
@@ -100,14 +165,14 @@ impl ProviderAdapter {
         match self {
             Self::Default => text.to_string(),
             Self::MiniMax => split_minimax_response_text(text).text,
-            Self::DeepSeek => text.to_string(),
+            Self::DeepSeek | Self::Qwen => text.to_string(),
         }
     }
 
     /// Normalize one streaming delta into separate reasoning/text channels.
     fn sanitize_stream_delta(&self, stream_state: &mut StreamState, delta: &Value) -> ResponseContentParts {
         let mut parts = match self {
-            Self::Default | Self::DeepSeek => ResponseContentParts {
+            Self::Default | Self::DeepSeek | Self::Qwen => ResponseContentParts {
                 text: extract_message_text(delta.get("content")),
                 ..Default::default()
             },
@@ -125,6 +190,13 @@ impl ProviderAdapter {
     }
 }
 
+/// 上游接受的多模态输入。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct InputSupport {
+    images: bool,
+    files: bool,
+}
+
 fn merge_system_messages(messages: Vec<Value>) -> Vec<Value> {
     let mut normalized = Vec::with_capacity(messages.len());
     let mut merged_system_index: Option<usize> = None;
@@ -171,13 +243,37 @@ struct RouteState {
     provider_name: String,
     target_base_url: String,
     target_model_name: String,
-    api_key: String,
+    api_keys: KeyPool<String>,
     adapter: ProviderAdapter,
+    rate_limit: RateLimitConfig,
+    retry: RetryPolicy,
+    circuit_breaker: CircuitBreakerConfig,
+    stream: StreamPolicy,
+    defaults: RequestDefaults,
+    reasoning: ReasoningMapping,
+    structured_output: StructuredOutputMode,
+    capabilities: InputCapabilities,
+    context_window: ContextWindow,
+    transform: TransformRules,
+}
+
+impl RouteState {
+    fn input_support(&self) -> InputSupport {
+        let defaults = self.adapter.input_support();
+        InputSupport {
+            images: self.capabilities.images.unwrap_or(defaults.images),
+            files: self.capabilities.files.unwrap_or(defaults.files),
+        }
+    }
+
+    fn reasoning_style(&self) -> ReasoningStyle {
+        self.reasoning.style.unwrap_or_else(|| self.adapter.reasoning_style())
+    }
 }
 
 #[derive(Default)]
 pub struct CodexGatewayRuntime {
-    server_handle: Option<tauri::async_runtime::JoinHandle<()>>,
+    server_handle: Option<tokio::task::JoinHandle<()>>,
     shutdown_tx: Option<oneshot::Sender<()>>,
     route_state: Option<Arc<RwLock<RouteState>>>,
 }
@@ -188,6 +284,7 @@ pub struct GatewayRouteSnapshot {
     pub provider_name: String,
     pub target_base_url: String,
     pub target_model_name: String,
+    pub request_defaults: RequestDefaults,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -195,6 +292,10 @@ struct UsageTotals {
     input_tokens: i64,
     output_tokens: i64,
     total_tokens: i64,
+    /// 命中缓存的输入 token，包含在 `input_tokens` 中
+    cached_input_tokens: i64,
+    /// 推理 token，包含在 `output_tokens` 中
+    reasoning_tokens: i64,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
@@ -206,8 +307,21 @@ struct ResponseContentParts {
 #[derive(Debug, Clone)]
 struct ToolCallState {
     call_id: String,
+    /// 上游是否给出了 `call_id`，未给出时使用合成的 id
+    explicit_id: bool,
+    item_id: String,
     name: String,
     arguments: String,
+    added: bool,
+    done: bool,
+}
+
+/// 流式输出项，按首次出现的顺序排列，位置即 `output_index`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamItem {
+    Reasoning,
+    Message,
+    ToolCall(usize),
 }
 
 #[derive(Debug, Default)]
@@ -223,10 +337,43 @@ struct StreamState {
     message_started: bool,
     reasoning_text: String,
     reasoning_started: bool,
-    tool_calls: BTreeMap<usize, ToolCallState>,
+    /// 按出现顺序排列的工具调用
+    tool_calls: Vec<ToolCallState>,
+    /// 上游 `index` -> 当前占用该 index 的工具调用
+    tool_call_slots: HashMap<usize, usize>,
+    output_order: Vec<StreamItem>,
     minimax_response_text: MiniMaxResponseTextState,
 }
 
+impl StreamState {
+    /// 首次出现 reasoning 时登记输出顺序，返回是否为首次。
+    fn start_reasoning(&mut self) -> bool {
+        if self.reasoning_started {
+            return false;
+        }
+        self.reasoning_started = true;
+        self.output_order.push(StreamItem::Reasoning);
+        true
+    }
+
+    /// 首次出现正文时登记输出顺序，返回是否为首次。
+    fn start_message(&mut self) -> bool {
+        if self.message_started {
+            return false;
+        }
+        self.message_started = true;
+        self.output_order.push(StreamItem::Message);
+        true
+    }
+
+    fn output_index(&self, item: StreamItem) -> usize {
+        self.output_order
+            .iter()
+            .position(|candidate| *candidate == item)
+            .unwrap_or(self.output_order.len())
+    }
+}
+
 pub fn gateway_base_url(port: u16) -> String {
     format!("http://127.0.0.1:{port}/v1")
 }
@@ -266,22 +413,75 @@ pub async fn get_route_snapshot(state: &AppState) -> Result<Option<GatewayRouteS
         provider_name: route.provider_name.clone(),
         target_base_url: route.target_base_url.clone(),
         target_model_name: route.target_model_name.clone(),
+        request_defaults: route.defaults.clone(),
     }))
 }
 
-pub async fn start_or_update(state: &AppState, provider: &CodexProvider, port: u16) -> Result<(), String> {
-    provider.validate()?;
+fn provider_route(provider: &CodexProvider, gateway_config: &CodexGatewayConfig) -> RouteState {
     let target_base_url = provider_target_base_url(provider);
-    let adapter = ProviderAdapter::from_upstream_url(&target_base_url);
-    let next_route = RouteState {
+    RouteState {
         enabled: true,
         provider_id: provider.id.clone(),
         provider_name: provider.name.clone(),
+        adapter: ProviderAdapter::from_upstream_url(&target_base_url),
         target_base_url,
         target_model_name: provider.codex_config.model_name.trim().to_string(),
-        api_key: provider.codex_config.api_key.trim().to_string(),
-        adapter,
-    };
+        api_keys: KeyPool::new(&provider.codex_config.api_key, &provider.codex_config.key_pool, str::to_string),
+        rate_limit: provider.codex_config.rate_limit.clone(),
+        retry: gateway_config.retry.clone(),
+        circuit_breaker: gateway_config.circuit_breaker.clone(),
+        stream: gateway_config.stream.clone(),
+        defaults: provider.codex_config.defaults.clone(),
+        reasoning: provider.codex_config.reasoning.clone(),
+        structured_output: provider.codex_config.structured_output,
+        capabilities: provider.codex_config.capabilities.clone(),
+        context_window: provider.codex_config.context_window.clone(),
+        transform: provider.codex_config.transform.clone(),
+    }
+}
+
+/// 构造回放用的非流式 Chat Completions 请求，转换逻辑与 `/v1/responses` 一致。
+/// `provider` 需已由调用方校验并解析密钥引用。
+pub fn build_replay_request(
+    client: &reqwest::Client,
+    provider: &CodexProvider,
+    body: &Value,
+) -> Result<reqwest::RequestBuilder, String> {
+    let route = provider_route(provider, &CodexGatewayConfig::default());
+    let mut chat_request = build_chat_request(body, &route)?;
+    chat_request["stream"] = Value::Bool(false);
+    if let Some(request) = chat_request.as_object_mut() {
+        request.remove("stream_options");
+    }
+    let upstream_url = build_chat_completions_url(&route.target_base_url)?;
+    Ok(build_upstream_request(client, &route, route.api_keys.primary(), &upstream_url, &chat_request, false))
+}
+
+/// 把回放得到的 Chat Completions 响应转换为 Responses 格式。
+pub fn convert_replay_response(provider: &CodexProvider, upstream_response: &Value) -> Value {
+    let route = provider_route(provider, &CodexGatewayConfig::default());
+    build_non_stream_response(upstream_response, &route, &HostedTools::default())
+}
+
+fn build_http_client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .connect_timeout(std::time::Duration::from_secs(30))
+        .tcp_keepalive(std::time::Duration::from_secs(60))
+        .pool_idle_timeout(std::time::Duration::from_secs(90))
+        .build()
+        .map_err(|error| format!("初始化 Codex Gateway HTTP 客户端失败: {}", error))
+}
+
+pub async fn start_or_update(state: &AppState, provider: &CodexProvider, port: u16) -> Result<(), String> {
+    provider.validate()?;
+    let provider = &secret_ref::resolve_codex_provider(provider)?;
+    let gateway_config = state
+        .config
+        .lock()
+        .map_err(|error| format!("获取配置锁失败: {}", error))?
+        .codex_gateway
+        .clone();
+    let next_route = provider_route(provider, &gateway_config);
 
     let (route_state, should_spawn) = {
         let mut runtime = state
@@ -298,17 +498,16 @@ pub async fn start_or_update(state: &AppState, provider: &CodexProvider, port: u
         let should_spawn = runtime.server_handle.is_none();
         if should_spawn {
             let server_state = GatewayServerState {
-                app_handle: state.app_handle()?.clone(),
-                client: reqwest::Client::builder()
-                    .connect_timeout(std::time::Duration::from_secs(30))
-                    .tcp_keepalive(std::time::Duration::from_secs(60))
-                    .pool_idle_timeout(std::time::Duration::from_secs(90))
-                    .build()
-                    .map_err(|error| format!("初始化 Codex Gateway HTTP 客户端失败: {}", error))?,
+                host: state.gateway_host()?,
+                client: build_http_client()?,
                 route_state: route_state.clone(),
+                rate_limiter: Arc::new(RateLimiterRegistry::default()),
+                health: state.gateway_health.clone(),
+                keys: state.gateway_keys.clone(),
+                metrics: Arc::new(GatewayMetrics::new(GatewayKind::Codex)),
             };
             let (shutdown_tx, shutdown_rx) = oneshot::channel();
-            let server_handle = tauri::async_runtime::spawn(run_server(port, server_state, shutdown_rx));
+            let server_handle = tokio::spawn(run_server(port, server_state, shutdown_rx));
             runtime.shutdown_tx = Some(shutdown_tx);
             runtime.server_handle = Some(server_handle);
         }
@@ -335,7 +534,7 @@ pub async fn start_or_update(state: &AppState, provider: &CodexProvider, port: u
     };
 
     log::info!("{}", message);
-    emit_log(state.app_handle()?, "info", message);
+    emit_log(&state.gateway_host()?, "info", message);
     Ok(())
 }
 
@@ -368,81 +567,25 @@ pub async fn stop(state: &AppState) -> Result<(), String> {
     }
 
     log::info!("Codex Gateway 已停止");
-    emit_log(state.app_handle()?, "info", "Codex Gateway 已停止");
+    emit_log(&state.gateway_host()?, "info", "Codex Gateway 已停止");
     Ok(())
 }
 
-fn emit_log(app_handle: &tauri::AppHandle, level: &str, message: impl Into<String>) {
-    let message = message.into();
-    let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-
-    if disk_logging_enabled(app_handle) {
-        if let Err(error) = append_log_to_disk(&timestamp, level, &message) {
-            log::warn!("Codex Gateway 写入磁盘日志失败: {}", error);
-        }
-    }
-
-    let payload = json!({
-        "timestamp": timestamp,
-        "level": level,
-        "message": message,
-    });
-    let _ = app_handle.emit("codex-gateway-log", payload);
-}
-
-pub fn log_directory() -> Result<PathBuf, String> {
-    let home_dir = dirs::home_dir().ok_or("无法获取用户目录")?;
-    Ok(home_dir.join(".switchcc").join("logs"))
-}
-
-fn disk_logging_enabled(app_handle: &tauri::AppHandle) -> bool {
-    let state = app_handle.state::<AppState>();
-    state
-        .config
-        .lock()
-        .map(|config| config.codex_gateway.disk_logging_enabled)
-        .unwrap_or(false)
-}
-
-fn append_log_to_disk(timestamp: &str, level: &str, message: &str) -> Result<(), String> {
-    let log_dir = log_directory()?;
-    if !log_dir.exists() {
-        fs::create_dir_all(&log_dir).map_err(|error| format!("创建日志目录失败: {}", error))?;
-    }
-
-    let log_file_path = log_dir.join(format!(
-        "codex-gateway-{}.log",
-        chrono::Local::now().format("%Y-%m-%d")
-    ));
-
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&log_file_path)
-        .map_err(|error| format!("打开日志文件失败: {}", error))?;
-
-    writeln!(file, "[{}] [{}] {}", timestamp, level.to_uppercase(), message)
-        .map_err(|error| format!("写入日志文件失败: {}", error))
+fn emit_log(host: &GatewayHost, level: &str, message: impl Into<String>) {
+    host.emit_log(GatewayKind::Codex, level, &message.into());
 }
 
 async fn run_server(
     port: u16,
     server_state: GatewayServerState,
-    mut shutdown_rx: oneshot::Receiver<()>,
+    shutdown_rx: oneshot::Receiver<()>,
 ) {
-    let app_handle = server_state.app_handle.clone();
-    let app = Router::new()
-        .route("/health", get(get_health).head(head_health))
-        .route("/v1/models", get(get_models))
-        .route("/v1/responses", post(post_responses))
-        .with_state(server_state);
-
     let listener = match tokio::net::TcpListener::bind(("127.0.0.1", port)).await {
         Ok(listener) => listener,
         Err(error) => {
             log::error!("Codex Gateway 监听端口 {} 失败: {}", port, error);
             emit_log(
-                &app_handle,
+                &server_state.host,
                 "error",
                 format!("Codex Gateway 监听端口 {} 失败: {}", port, error),
             );
@@ -450,22 +593,99 @@ async fn run_server(
         }
     };
 
+    serve(listener, server_state, shutdown_rx).await;
+}
+
+async fn serve(
+    listener: tokio::net::TcpListener,
+    server_state: GatewayServerState,
+    mut shutdown_rx: oneshot::Receiver<()>,
+) {
+    let host = server_state.host.clone();
+    let probe_state = server_state.clone();
+    let app = Router::new()
+        .route("/health", get(get_health).head(head_health))
+        .route("/metrics", get(get_metrics))
+        .route("/v1/models", get(get_models))
+        .route("/v1/responses", post(post_responses))
+        .route("/v1/chat/completions", post(post_chat_completions))
+        .with_state(server_state);
+
     let server = axum::serve(listener, app);
     tokio::select! {
         result = server => {
             if let Err(error) = result {
                 log::error!("Codex Gateway 服务异常退出: {}", error);
                 emit_log(
-                    &app_handle,
+                    &host,
                     "error",
                     format!("Codex Gateway 服务异常退出: {}", error),
                 );
             }
         }
+        _ = run_health_probe(probe_state) => {}
         _ = &mut shutdown_rx => {}
     }
 }
 
+/// 不依赖 Tauri 窗口，在给定监听器上运行 Gateway，直到 `shutdown_rx` 触发。
+pub async fn serve_headless(
+    listener: tokio::net::TcpListener,
+    host: GatewayHost,
+    provider: &CodexProvider,
+    config: &CodexGatewayConfig,
+    shutdown_rx: oneshot::Receiver<()>,
+) -> Result<(), String> {
+    provider.validate()?;
+    let provider = &secret_ref::resolve_codex_provider(provider)?;
+    let server_state = GatewayServerState {
+        host,
+        client: build_http_client()?,
+        route_state: Arc::new(RwLock::new(provider_route(provider, config))),
+        rate_limiter: Arc::new(RateLimiterRegistry::default()),
+        health: Arc::new(HealthRegistry::default()),
+        keys: Arc::new(KeyPoolRegistry::default()),
+        metrics: Arc::new(GatewayMetrics::new(GatewayKind::Codex)),
+    };
+
+    serve(listener, server_state, shutdown_rx).await;
+    Ok(())
+}
+
+/// 按 `circuit_breaker.probe_interval_secs` 主动探测已熔断的上游（请求 `/models`）。
+async fn run_health_probe(state: GatewayServerState) {
+    loop {
+        let route = state.route_state.read().await.clone();
+        let Some(interval) = route.circuit_breaker.probe_interval_secs.filter(|secs| *secs > 0) else {
+            // 未开启主动探测时定期重新读取配置
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            continue;
+        };
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+
+        if !state.health.is_open(&route.provider_id) {
+            continue;
+        }
+
+        let reachable = state
+            .client
+            .get(format!("{}/models", route.target_base_url.trim_end_matches('/')))
+            .bearer_auth(route.api_keys.primary())
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .is_ok_and(|response| !gateway_health::is_failure_status(response.status().as_u16()));
+
+        if let Some(circuit_state) =
+            state
+                .health
+                .record_probe(&route.provider_id, &route.circuit_breaker, reachable)
+        {
+            log_circuit_transition(&state.host, &route.provider_id, circuit_state);
+        }
+    }
+}
+
 async fn get_health(State(state): State<GatewayServerState>) -> impl IntoResponse {
     let route = state.route_state.read().await;
     Json(json!({
@@ -481,39 +701,66 @@ async fn head_health() -> impl IntoResponse {
     StatusCode::OK
 }
 
+async fn get_metrics(State(state): State<GatewayServerState>) -> impl IntoResponse {
+    (
+        [(axum::http::header::CONTENT_TYPE, gateway_metrics::CONTENT_TYPE)],
+        state.metrics.render(),
+    )
+}
+
 async fn get_models(State(state): State<GatewayServerState>) -> impl IntoResponse {
     let route = state.route_state.read().await;
+    let mut model = json!({
+        "id": route.target_model_name,
+        "object": "model",
+        "created": chrono::Utc::now().timestamp(),
+        "owned_by": route.provider_name,
+    });
+    if let Some(length) = route.context_window.length {
+        model["context_window"] = Value::from(length);
+    }
     Json(json!({
         "object": "list",
-        "data": [{
-            "id": route.target_model_name,
-            "object": "model",
-            "created": chrono::Utc::now().timestamp(),
-            "owned_by": route.provider_name,
-        }]
+        "data": [model]
     }))
 }
 
 async fn post_responses(
     State(state): State<GatewayServerState>,
+    headers: HeaderMap,
     Json(body): Json<Value>,
 ) -> Response {
     let route = state.route_state.read().await.clone();
     if !route.enabled {
-        return (
-            StatusCode::SERVICE_UNAVAILABLE,
-            Json(json!({
-                "error": "codex_gateway_disabled",
-                "message": "Codex Gateway 已关闭",
-            })),
-        )
-            .into_response();
+        return gateway_disabled_response();
+    }
+
+    let mut request_log = RequestLog::start(
+        &state.host,
+        &state.metrics,
+        GatewayKind::Codex,
+        "POST",
+        "/v1/responses",
+        body.to_string().len(),
+    );
+    request_log.set_route(&route.provider_id, Some(&route.target_model_name));
+    let capture = CaptureSession::start(&state.host, GatewayKind::Codex, request_log.request_id());
+    if let Some(capture) = &capture {
+        capture.set_route(&route.provider_id, Some(&route.target_model_name));
+        for api_key in route.api_keys.secrets() {
+            capture.add_credential(None, api_key);
+        }
+        capture.set_inbound("POST", "/v1/responses", &headers, body.to_string().as_bytes());
     }
 
     let chat_request = match build_chat_request(&body, &route) {
         Ok(request) => request,
         Err(error) => {
-            emit_log(&state.app_handle, "error", format!("Codex Gateway 请求转换失败: {}", error));
+            emit_log(&state.host, "error", format!("Codex Gateway 请求转换失败: {}", error));
+            request_log.set_local_status(400, ErrorClass::InvalidRequest, error.clone());
+            if let Some(capture) = &capture {
+                capture.fail(error.clone());
+            }
             return (
                 StatusCode::BAD_REQUEST,
                 Json(json!({
@@ -529,9 +776,16 @@ async fn post_responses(
         .get("stream")
         .and_then(Value::as_bool)
         .unwrap_or(true);
+    let hosted_tools = HostedTools::from_request(&body);
+    // 上游不支持 json_schema 时由 Gateway 在本地校验输出
+    let structured_output = body
+        .get("text")
+        .and_then(|text| text.get("format"))
+        .filter(|_| route.structured_output != StructuredOutputMode::JsonSchema)
+        .and_then(StructuredOutput::from_responses_format);
 
     emit_log(
-        &state.app_handle,
+        &state.host,
         "info",
         format!(
             "Codex Gateway 收到请求: model={} stream={} upstream={}",
@@ -542,6 +796,7 @@ async fn post_responses(
     let upstream_url = match build_chat_completions_url(&route.target_base_url) {
         Ok(url) => url,
         Err(error) => {
+            request_log.set_local_status(502, ErrorClass::Gateway, error.clone());
             return (
                 StatusCode::BAD_GATEWAY,
                 Json(json!({
@@ -553,20 +808,42 @@ async fn post_responses(
         }
     };
 
+    let rate_limit_permit =
+        match acquire_upstream_slot(&state, &route, &chat_request, &mut request_log, capture.as_ref()).await {
+            Ok(permit) => permit,
+            Err(response) => return response,
+        };
+
+    if let Some(capture) = &capture {
+        let api_key = route.api_keys.primary();
+        match build_upstream_request(&state.client, &route, api_key, &upstream_url, &chat_request, stream_requested).build() {
+            Ok(request) => capture.set_upstream_request(&request),
+            Err(error) => capture.fail(format!("构造上游请求失败: {}", error)),
+        }
+    }
+
     if !stream_requested {
-        let upstream_response = match build_upstream_request(
-            &state.client,
-            &route,
-            &upstream_url,
-            &chat_request,
-            false,
+        let started_at = Instant::now();
+        let upstream_result = gateway_keys::send_with_keys(
+            &state.keys,
+            &route.provider_id,
+            &route.api_keys,
+            &route.retry,
+            |api_key| build_upstream_request(&state.client, &route, api_key, &upstream_url, &chat_request, false),
+            |attempt| log_retry_attempt(&state.host, attempt, &upstream_url),
+            |failover| log_key_failover(&state.host, &route.provider_id, failover),
         )
-        .send()
-        .await
-        {
+        .await;
+        record_upstream_health(&state.health, &state.host, &route, started_at, &upstream_result);
+
+        let upstream_response = match upstream_result {
             Ok(response) => response,
             Err(error) => {
-                emit_log(&state.app_handle, "error", format!("Codex Gateway 上游请求失败: {}", error));
+                emit_log(&state.host, "error", format!("Codex Gateway 上游请求失败: {}", error));
+                request_log.fail(ErrorClass::from_reqwest(&error), error.to_string());
+                if let Some(capture) = &capture {
+                    capture.fail(error.to_string());
+                }
                 return (
                     StatusCode::BAD_GATEWAY,
                     Json(json!({
@@ -579,10 +856,25 @@ async fn post_responses(
         };
 
         let status = upstream_response.status();
+        request_log.set_upstream_status(status.as_u16());
+        observe_upstream_rate_limit(
+            &state.rate_limiter,
+            &state.host,
+            &route.provider_id,
+            status,
+            upstream_response.headers(),
+        );
+        if let Some(capture) = &capture {
+            capture.set_upstream_response(status.as_u16(), upstream_response.headers());
+        }
         if !status.is_success() {
             let body_text = upstream_response.text().await.unwrap_or_default();
+            request_log.add_response_bytes(body_text.len());
+            if let Some(capture) = &capture {
+                capture.append_upstream_body(body_text.as_bytes());
+            }
             emit_log(
-                &state.app_handle,
+                &state.host,
                 "error",
                 format!("Codex Gateway 上游返回错误: status={} body={}", status, body_text),
             );
@@ -590,14 +882,33 @@ async fn post_responses(
         }
 
         emit_log(
-            &state.app_handle,
+            &state.host,
             "info",
             format!("Codex Gateway 转发上游成功: {}", upstream_url),
         );
 
-        let upstream_json = match upstream_response.json::<Value>().await {
+        let upstream_bytes = match upstream_response.bytes().await {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                request_log.fail(ErrorClass::Stream, error.to_string());
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    Json(json!({
+                        "error": "invalid_upstream_response",
+                        "message": error.to_string(),
+                    })),
+                )
+                    .into_response();
+            }
+        };
+        request_log.add_response_bytes(upstream_bytes.len());
+        if let Some(capture) = &capture {
+            capture.append_upstream_body(&upstream_bytes);
+        }
+        let upstream_json = match serde_json::from_slice::<Value>(&upstream_bytes) {
             Ok(json) => json,
             Err(error) => {
+                request_log.fail(ErrorClass::UpstreamServer, error.to_string());
                 return (
                     StatusCode::BAD_GATEWAY,
                     Json(json!({
@@ -609,27 +920,60 @@ async fn post_responses(
             }
         };
 
-        return Json(build_non_stream_response(&upstream_json, &route)).into_response();
+        let (input_tokens, output_tokens) = UsageTotals::from_value(upstream_json.get("usage")).token_counts();
+        request_log.set_usage(input_tokens, output_tokens);
+        let response = build_non_stream_response(&upstream_json, &route, &hosted_tools);
+        if let Some(error) = check_structured_output(&state.host, structured_output.as_ref(), &response_output_text(&response)) {
+            request_log.fail(ErrorClass::UpstreamServer, error.clone());
+            if let Some(capture) = &capture {
+                capture.fail(error.clone());
+            }
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({
+                    "error": "structured_output_invalid",
+                    "message": error,
+                })),
+            )
+                .into_response();
+        }
+        if let Some(capture) = &capture {
+            capture.record_downstream("response", &response);
+        }
+        return Json(response).into_response();
     }
 
     let response_id = format!("resp_{}", Uuid::new_v4().simple());
     let reasoning_item_id = format!("rsn_{}", Uuid::new_v4().simple());
     let message_item_id = format!("msg_{}", Uuid::new_v4().simple());
-    let app_handle = state.app_handle.clone();
+    let host = state.host.clone();
     let target_model_name = route.target_model_name.clone();
     let client = state.client.clone();
+    let rate_limiter = state.rate_limiter.clone();
+    let health = state.health.clone();
+    let keys = state.keys.clone();
 
     let event_stream = stream! {
+        // 整个 SSE 流结束前保持并发流名额，结束后写入请求日志和抓包
+        let _rate_limit_permit = rate_limit_permit;
+        let mut request_log = request_log;
+        request_log.start_stream();
+        let capture = capture;
+        let downstream_event = |kind: &str, payload: Value| {
+            if let Some(capture) = &capture {
+                capture.record_downstream(kind, &payload);
+            }
+            json_event(kind, payload)
+        };
         let stream_started_at = Instant::now();
         let mut raw_event_count = 0usize;
         let mut parsed_chunk_count = 0usize;
         let mut forwarded_text_event_count = 0usize;
         let mut forwarded_text_chars = 0usize;
         let mut forwarded_tool_call_delta_count = 0usize;
-        let mut first_upstream_event_logged = false;
 
         emit_log(
-            &app_handle,
+            &host,
             "info",
             format!(
                 "Codex Gateway SSE 下游流已创建: response_id={} model={} upstream={}",
@@ -638,7 +982,7 @@ async fn post_responses(
         );
 
         emit_log(
-            &app_handle,
+            &host,
             "info",
             format!(
                 "Codex Gateway SSE 向下游发送 response.created: response_id={} elapsed_ms={}",
@@ -646,7 +990,7 @@ async fn post_responses(
                 stream_started_at.elapsed().as_millis()
             ),
         );
-        yield Ok::<Event, Infallible>(json_event(
+        yield Ok::<Event, Infallible>(downstream_event(
             "response.created",
             json!({
                 "type": "response.created",
@@ -655,7 +999,7 @@ async fn post_responses(
         ));
 
         emit_log(
-            &app_handle,
+            &host,
             "info",
             format!(
                 "Codex Gateway SSE 开始请求上游: response_id={} url={} elapsed_ms={}",
@@ -665,483 +1009,550 @@ async fn post_responses(
             ),
         );
 
-        let upstream_response = match build_upstream_request(
-            &client,
-            &route,
-            &upstream_url,
-            &chat_request,
-            true,
-        )
-        .send()
-        .await
-        {
-            Ok(response) => response,
-            Err(error) => {
-                emit_log(&app_handle, "error", format!("Codex Gateway 上游请求失败: {}", error));
-                yield Ok(json_event(
+        let mut usage_totals = UsageTotals::default();
+        let mut stream_state = StreamState::default();
+        let mut received_done_event = false;
+        let mut saw_terminal_finish_reason = false;
+        let max_consecutive_errors = 5usize;
+        let mut upstream_request = chat_request.clone();
+        let mut resume_count = 0u32;
+
+        // 每轮发起一次上游请求；断流且允许续传时带着已输出的正文重新请求
+        loop {
+            let started_at = Instant::now();
+            let upstream_result = gateway_keys::send_with_keys(
+                &keys,
+                &route.provider_id,
+                &route.api_keys,
+                &route.retry,
+                |api_key| build_upstream_request(&client, &route, api_key, &upstream_url, &upstream_request, true),
+                |attempt| log_retry_attempt(&host, attempt, &upstream_url),
+                |failover| log_key_failover(&host, &route.provider_id, failover),
+            )
+            .await;
+            record_upstream_health(&health, &host, &route, started_at, &upstream_result);
+
+            let upstream_response = match upstream_result {
+                Ok(response) => response,
+                Err(error) => {
+                    emit_log(&host, "error", format!("Codex Gateway 上游请求失败: {}", error));
+                    request_log.fail(ErrorClass::from_reqwest(&error), error.to_string());
+                    if let Some(capture) = &capture {
+                        capture.fail(error.to_string());
+                    }
+                    yield Ok(downstream_event(
+                        "response.failed",
+                        response_failed_event(&response_id, error.to_string()),
+                    ));
+                    return;
+                }
+            };
+
+            let status = upstream_response.status();
+            request_log.set_upstream_status(status.as_u16());
+            observe_upstream_rate_limit(
+                &rate_limiter,
+                &host,
+                &route.provider_id,
+                status,
+                upstream_response.headers(),
+            );
+            if let Some(capture) = &capture {
+                capture.set_upstream_response(status.as_u16(), upstream_response.headers());
+            }
+            if !status.is_success() {
+                let body_text = upstream_response.text().await.unwrap_or_default();
+                request_log.add_response_bytes(body_text.len());
+                if let Some(capture) = &capture {
+                    capture.append_upstream_body(body_text.as_bytes());
+                }
+                let error_message = format!("上游返回错误: status={} body={}", status, body_text);
+                emit_log(&host, "error", format!("Codex Gateway {}", error_message));
+                emit_log(
+                    &host,
+                    "error",
+                    format!(
+                        "Codex Gateway SSE 向下游发送 response.failed: response_id={} reason={} elapsed_ms={}",
+                        response_id,
+                        error_message,
+                        stream_started_at.elapsed().as_millis()
+                    ),
+                );
+                yield Ok(downstream_event(
                     "response.failed",
-                    response_failed_event(&response_id, error.to_string()),
+                    response_failed_event(&response_id, error_message),
                 ));
                 return;
             }
-        };
 
-        let status = upstream_response.status();
-        if !status.is_success() {
-            let body_text = upstream_response.text().await.unwrap_or_default();
-            let error_message = format!("上游返回错误: status={} body={}", status, body_text);
-            emit_log(&app_handle, "error", format!("Codex Gateway {}", error_message));
             emit_log(
-                &app_handle,
-                "error",
+                &host,
+                "info",
                 format!(
-                    "Codex Gateway SSE 向下游发送 response.failed: response_id={} reason={} elapsed_ms={}",
+                    "Codex Gateway SSE 上游 HTTP 已连接: response_id={} status={} url={} elapsed_ms={}",
                     response_id,
-                    error_message,
+                    status,
+                    upstream_url,
                     stream_started_at.elapsed().as_millis()
                 ),
             );
-            yield Ok(json_event(
-                "response.failed",
-                response_failed_event(&response_id, error_message),
-            ));
-            return;
-        }
-
-        emit_log(
-            &app_handle,
-            "info",
-            format!(
-                "Codex Gateway SSE 上游 HTTP 已连接: response_id={} status={} url={} elapsed_ms={}",
-                response_id,
-                status,
-                upstream_url,
-                stream_started_at.elapsed().as_millis()
-            ),
-        );
-
-        let mut usage_totals = UsageTotals::default();
-        let mut stream_state = StreamState::default();
-        let mut upstream_stream = upstream_response.bytes_stream().eventsource();
-        let mut received_done_event = false;
-        let mut saw_terminal_finish_reason = false;
 
-        let max_consecutive_errors = 5usize;
-        let read_timeout = std::time::Duration::from_secs(300);
-        let mut consecutive_errors = 0usize;
+            let mut upstream_stream = upstream_response.bytes_stream().eventsource();
+            let mut consecutive_errors = 0usize;
+            let mut interruption: Option<(ErrorClass, String)> = None;
+            let mut first_upstream_event_logged = false;
 
-        loop {
-            let next_event = match tokio::time::timeout(read_timeout, upstream_stream.next()).await {
-                Ok(Some(next_event)) => next_event,
-                Ok(None) => {
-                    emit_log(
-                        &app_handle,
-                        "warn",
-                        format!(
-                            "Codex Gateway SSE 上游流自然结束: response_id={} raw_events={} parsed_chunks={} forwarded_text_events={} forwarded_text_chars={} tool_call_deltas={} elapsed_ms={}",
-                            response_id,
-                            raw_event_count,
-                            parsed_chunk_count,
-                            forwarded_text_event_count,
-                            forwarded_text_chars,
-                            forwarded_tool_call_delta_count,
-                            stream_started_at.elapsed().as_millis()
-                        ),
-                    );
-                    break;
-                }
-                Err(_) => {
-                    consecutive_errors += 1;
-                    emit_log(&app_handle, "warn", format!(
-                        "Codex Gateway SSE 读取超时 ({}/{}): response_id={} 超过 {} 秒未收到上游事件 raw_events={} parsed_chunks={} elapsed_ms={}",
-                        consecutive_errors,
-                        max_consecutive_errors,
-                        response_id,
-                        read_timeout.as_secs()
-                        ,raw_event_count
-                        ,parsed_chunk_count
-                        ,stream_started_at.elapsed().as_millis()
-                    ));
-                    if consecutive_errors >= max_consecutive_errors {
-                        emit_log(&app_handle, "error", format!(
-                            "Codex Gateway 连续 {} 次读取超时，中断流: response_id={} raw_events={} parsed_chunks={} forwarded_text_events={} forwarded_text_chars={} tool_call_deltas={} elapsed_ms={}",
-                            max_consecutive_errors,
-                            response_id,
-                            raw_event_count,
-                            parsed_chunk_count,
-                            forwarded_text_event_count,
-                            forwarded_text_chars,
-                            forwarded_tool_call_delta_count,
-                            stream_started_at.elapsed().as_millis()
-                        ));
+            loop {
+                let read_timeout = route.stream.read_timeout(first_upstream_event_logged);
+                let next_event = match tokio::time::timeout(read_timeout, upstream_stream.next()).await {
+                    Ok(Some(next_event)) => next_event,
+                    Ok(None) => {
                         emit_log(
-                            &app_handle,
-                            "error",
+                            &host,
+                            "warn",
                             format!(
-                                "Codex Gateway SSE 向下游发送 response.failed: response_id={} reason=连续 {} 次 SSE 读取超时 elapsed_ms={}",
+                                "Codex Gateway SSE 上游流自然结束: response_id={} raw_events={} parsed_chunks={} forwarded_text_events={} forwarded_text_chars={} tool_call_deltas={} elapsed_ms={}",
                                 response_id,
-                                max_consecutive_errors,
+                                raw_event_count,
+                                parsed_chunk_count,
+                                forwarded_text_event_count,
+                                forwarded_text_chars,
+                                forwarded_tool_call_delta_count,
                                 stream_started_at.elapsed().as_millis()
                             ),
                         );
-                        yield Ok(json_event(
-                            "response.failed",
-                            response_failed_event(
-                                &response_id,
-                                format!(
-                                    "连续 {} 次 SSE 读取超时: 超过 {} 秒未收到上游事件",
-                                    max_consecutive_errors,
-                                    read_timeout.as_secs()
-                                ),
-                            ),
+                        break;
+                    }
+                    Err(_) => {
+                        let reason = format!("超过 {} 秒未收到上游事件", read_timeout.as_secs());
+                        emit_log(&host, "warn", format!(
+                            "Codex Gateway SSE 读取超时，中断流: response_id={} {} raw_events={} parsed_chunks={} elapsed_ms={}",
+                            response_id,
+                            reason,
+                            raw_event_count,
+                            parsed_chunk_count,
+                            stream_started_at.elapsed().as_millis()
                         ));
-                        return;
+                        interruption = Some((ErrorClass::Timeout, format!("SSE 读取超时: {}", reason)));
+                        break;
                     }
-                    continue;
-                }
-            };
-
-            match next_event {
-                Ok(event) => {
-                    consecutive_errors = 0;
-                    raw_event_count += 1;
-                    let data = event.data.trim();
+                };
 
-                    if !first_upstream_event_logged {
-                        first_upstream_event_logged = true;
-                        emit_log(
-                            &app_handle,
-                            "info",
-                            format!(
-                                "Codex Gateway SSE 收到上游首包: response_id={} raw_event={} data_len={} elapsed_ms={}",
-                                response_id,
-                                raw_event_count,
-                                data.len(),
-                                stream_started_at.elapsed().as_millis()
-                            ),
-                        );
-                    }
+                match next_event {
+                    Ok(event) => {
+                        consecutive_errors = 0;
+                        raw_event_count += 1;
+                        request_log.add_response_bytes(event.data.len());
+                        if let Some(capture) = &capture {
+                            capture.append_upstream_body(gateway_capture::sse_text(&event.event, &event.data).as_bytes());
+                        }
+                        let data = event.data.trim();
 
-                    if data.is_empty() {
-                        emit_log(
-                            &app_handle,
-                            "info",
-                            format!(
-                                "Codex Gateway SSE 收到空事件: response_id={} raw_event={} elapsed_ms={}",
-                                response_id,
-                                raw_event_count,
-                                stream_started_at.elapsed().as_millis()
-                            ),
-                        );
-                        continue;
-                    }
+                        if !first_upstream_event_logged {
+                            first_upstream_event_logged = true;
+                            emit_log(
+                                &host,
+                                "info",
+                                format!(
+                                    "Codex Gateway SSE 收到上游首包: response_id={} raw_event={} data_len={} elapsed_ms={}",
+                                    response_id,
+                                    raw_event_count,
+                                    data.len(),
+                                    stream_started_at.elapsed().as_millis()
+                                ),
+                            );
+                        }
 
-                    if data == "[DONE]" {
-                        received_done_event = true;
-                        emit_log(
-                            &app_handle,
-                            "info",
-                            format!(
-                                "Codex Gateway SSE 收到上游 DONE: response_id={} raw_event={} parsed_chunks={} forwarded_text_events={} forwarded_text_chars={} tool_call_deltas={} elapsed_ms={}",
-                                response_id,
-                                raw_event_count,
-                                parsed_chunk_count,
-                                forwarded_text_event_count,
-                                forwarded_text_chars,
-                                forwarded_tool_call_delta_count,
-                                stream_started_at.elapsed().as_millis()
-                            ),
-                        );
-                        continue;
-                    }
+                        if data.is_empty() {
+                            emit_log(
+                                &host,
+                                "info",
+                                format!(
+                                    "Codex Gateway SSE 收到空事件: response_id={} raw_event={} elapsed_ms={}",
+                                    response_id,
+                                    raw_event_count,
+                                    stream_started_at.elapsed().as_millis()
+                                ),
+                            );
+                            continue;
+                        }
 
-                    let chunk = match serde_json::from_str::<Value>(data) {
-                        Ok(chunk) => chunk,
-                        Err(error) => {
+                        if data == "[DONE]" {
+                            received_done_event = true;
                             emit_log(
-                                &app_handle,
-                                "warn",
+                                &host,
+                                "info",
                                 format!(
-                                    "Codex Gateway SSE 单条解析失败 (跳过): response_id={} raw_event={} data_len={} preview={} error={}",
+                                    "Codex Gateway SSE 收到上游 DONE: response_id={} raw_event={} parsed_chunks={} forwarded_text_events={} forwarded_text_chars={} tool_call_deltas={} elapsed_ms={}",
                                     response_id,
                                     raw_event_count,
-                                    data.len(),
-                                    preview_text_for_log(data, 120),
-                                    error
+                                    parsed_chunk_count,
+                                    forwarded_text_event_count,
+                                    forwarded_text_chars,
+                                    forwarded_tool_call_delta_count,
+                                    stream_started_at.elapsed().as_millis()
                                 ),
                             );
                             continue;
                         }
-                    };
-
-                    parsed_chunk_count += 1;
-                    let mut chunk_delta_chars = 0usize;
-                    let mut chunk_message_chars = 0usize;
-                    let mut chunk_tool_call_deltas = 0usize;
-                    let mut chunk_finish_reasons = Vec::new();
-                    let usage_present = chunk.get("usage").is_some();
-
-                    usage_totals.merge(chunk.get("usage"));
-
-                    if let Some(choices) = chunk.get("choices").and_then(Value::as_array) {
-                        for choice in choices {
-                            if let Some(finish_reason) = choice_finish_reason(choice) {
-                                saw_terminal_finish_reason = true;
-                                chunk_finish_reasons.push(finish_reason.to_string());
+
+                        let chunk = match serde_json::from_str::<Value>(data) {
+                            Ok(chunk) => chunk,
+                            Err(error) => {
+                                emit_log(
+                                    &host,
+                                    "warn",
+                                    format!(
+                                        "Codex Gateway SSE 单条解析失败 (跳过): response_id={} raw_event={} data_len={} preview={} error={}",
+                                        response_id,
+                                        raw_event_count,
+                                        data.len(),
+                                        preview_text_for_log(data, 120),
+                                        error
+                                    ),
+                                );
+                                continue;
                             }
+                        };
+
+                        parsed_chunk_count += 1;
+                        let mut chunk_delta_chars = 0usize;
+                        let mut chunk_message_chars = 0usize;
+                        let mut chunk_tool_call_deltas = 0usize;
+                        let mut chunk_finish_reasons = Vec::new();
+                        let usage_present = chunk.get("usage").is_some();
+
+                        usage_totals.merge(chunk.get("usage"));
+                        if usage_present {
+                            let (input_tokens, output_tokens) = usage_totals.token_counts();
+                            request_log.set_usage(input_tokens, output_tokens);
+                        }
 
-                            if let Some(delta) = choice.get("delta") {
-                                let delta_parts = route.adapter.sanitize_stream_delta(&mut stream_state, delta);
+                        if let Some(choices) = chunk.get("choices").and_then(Value::as_array) {
+                            for choice in choices {
+                                if let Some(finish_reason) = choice_finish_reason(choice) {
+                                    saw_terminal_finish_reason = true;
+                                    chunk_finish_reasons.push(finish_reason.to_string());
+                                }
 
-                                if !delta_parts.reasoning.is_empty() {
-                                    if !stream_state.reasoning_started {
-                                        stream_state.reasoning_started = true;
+                                if let Some(delta) = choice.get("delta") {
+                                    let delta_parts = route.adapter.sanitize_stream_delta(&mut stream_state, delta);
+
+                                    if !delta_parts.reasoning.is_empty() {
+                                        if stream_state.start_reasoning() {
+                                            emit_log(
+                                                &host,
+                                                "info",
+                                                format!(
+                                                    "Codex Gateway SSE 向下游发送 reasoning output_item.added: response_id={} raw_event={} parsed_chunk={} elapsed_ms={}",
+                                                    response_id,
+                                                    raw_event_count,
+                                                    parsed_chunk_count,
+                                                    stream_started_at.elapsed().as_millis()
+                                                ),
+                                            );
+                                            yield Ok(downstream_event(
+                                                "response.output_item.added",
+                                                reasoning_item_added_event(&reasoning_item_id),
+                                            ));
+                                            emit_log(
+                                                &host,
+                                                "info",
+                                                format!(
+                                                    "Codex Gateway SSE 向下游发送 response.reasoning_summary_part.added: response_id={} item_id={} elapsed_ms={}",
+                                                    response_id,
+                                                    reasoning_item_id,
+                                                    stream_started_at.elapsed().as_millis()
+                                                ),
+                                            );
+                                            yield Ok(downstream_event(
+                                                "response.reasoning_summary_part.added",
+                                                reasoning_summary_part_added_event(&reasoning_item_id),
+                                            ));
+                                        }
+
+                                        stream_state.reasoning_text.push_str(&delta_parts.reasoning);
                                         emit_log(
-                                            &app_handle,
+                                            &host,
                                             "info",
                                             format!(
-                                                "Codex Gateway SSE 向下游发送 reasoning output_item.added: response_id={} raw_event={} parsed_chunk={} elapsed_ms={}",
+                                                "Codex Gateway SSE 向下游转发 reasoning 增量: response_id={} raw_event={} parsed_chunk={} delta_chars={} preview={} elapsed_ms={}",
                                                 response_id,
                                                 raw_event_count,
                                                 parsed_chunk_count,
+                                                delta_parts.reasoning.chars().count(),
+                                                preview_text_for_log(&delta_parts.reasoning, 80),
                                                 stream_started_at.elapsed().as_millis()
                                             ),
                                         );
-                                        yield Ok(json_event(
-                                            "response.output_item.added",
-                                            reasoning_item_added_event(&reasoning_item_id),
+                                        yield Ok(downstream_event(
+                                            "response.reasoning_summary_text.delta",
+                                            reasoning_summary_text_delta_event(&reasoning_item_id, &delta_parts.reasoning),
                                         ));
+                                    }
+
+                                    let delta_text = delta_parts.text;
+                                    if !delta_text.is_empty() {
+                                        let delta_chars = delta_text.chars().count();
+                                        chunk_delta_chars += delta_chars;
+                                        if stream_state.start_message() {
+                                            emit_log(
+                                                &host,
+                                                "info",
+                                                format!(
+                                                    "Codex Gateway SSE 向下游发送 response.output_item.added: response_id={} raw_event={} parsed_chunk={} elapsed_ms={}",
+                                                    response_id,
+                                                    raw_event_count,
+                                                    parsed_chunk_count,
+                                                    stream_started_at.elapsed().as_millis()
+                                                ),
+                                            );
+                                            yield Ok(downstream_event(
+                                                "response.output_item.added",
+                                                assistant_message_added_event(&message_item_id, ""),
+                                            ));
+                                        }
+
+                                        stream_state.message_text.push_str(&delta_text);
+                                        forwarded_text_event_count += 1;
+                                        forwarded_text_chars += delta_chars;
                                         emit_log(
-                                            &app_handle,
+                                            &host,
                                             "info",
                                             format!(
-                                                "Codex Gateway SSE 向下游发送 response.reasoning_summary_part.added: response_id={} item_id={} elapsed_ms={}",
+                                                "Codex Gateway SSE 向下游转发文本增量: response_id={} raw_event={} parsed_chunk={} delta_chars={} total_chars={} preview={} elapsed_ms={}",
                                                 response_id,
-                                                reasoning_item_id,
+                                                raw_event_count,
+                                                parsed_chunk_count,
+                                                delta_chars,
+                                                forwarded_text_chars,
+                                                preview_text_for_log(&delta_text, 80),
                                                 stream_started_at.elapsed().as_millis()
                                             ),
                                         );
-                                        yield Ok(json_event(
-                                            "response.reasoning_summary_part.added",
-                                            reasoning_summary_part_added_event(&reasoning_item_id),
+                                        yield Ok(downstream_event(
+                                            "response.output_text.delta",
+                                            json!({
+                                                "type": "response.output_text.delta",
+                                                "delta": delta_text,
+                                            }),
                                         ));
                                     }
 
-                                    stream_state.reasoning_text.push_str(&delta_parts.reasoning);
-                                    emit_log(
-                                        &app_handle,
-                                        "info",
-                                        format!(
-                                            "Codex Gateway SSE 向下游转发 reasoning 增量: response_id={} raw_event={} parsed_chunk={} delta_chars={} preview={} elapsed_ms={}",
-                                            response_id,
-                                            raw_event_count,
-                                            parsed_chunk_count,
-                                            delta_parts.reasoning.chars().count(),
-                                            preview_text_for_log(&delta_parts.reasoning, 80),
-                                            stream_started_at.elapsed().as_millis()
-                                        ),
-                                    );
-                                    yield Ok(json_event(
-                                        "response.reasoning_summary_text.delta",
-                                        reasoning_summary_text_delta_event(&reasoning_item_id, &delta_parts.reasoning),
-                                    ));
-                                }
-
-                                let delta_text = delta_parts.text;
-                                if !delta_text.is_empty() {
-                                    let delta_chars = delta_text.chars().count();
-                                    chunk_delta_chars += delta_chars;
-                                    if !stream_state.message_started {
-                                        stream_state.message_started = true;
+                                    if let Some(tool_calls) = delta.get("tool_calls").and_then(Value::as_array) {
+                                        chunk_tool_call_deltas += tool_calls.len();
+                                        forwarded_tool_call_delta_count += tool_calls.len();
                                         emit_log(
-                                            &app_handle,
+                                            &host,
                                             "info",
                                             format!(
-                                                "Codex Gateway SSE 向下游发送 response.output_item.added: response_id={} raw_event={} parsed_chunk={} elapsed_ms={}",
+                                                "Codex Gateway SSE 收到工具调用增量: response_id={} raw_event={} parsed_chunk={} delta_count={} total_delta_count={} elapsed_ms={}",
                                                 response_id,
                                                 raw_event_count,
                                                 parsed_chunk_count,
+                                                tool_calls.len(),
+                                                forwarded_tool_call_delta_count,
                                                 stream_started_at.elapsed().as_millis()
                                             ),
                                         );
-                                        yield Ok(json_event(
-                                            "response.output_item.added",
-                                            assistant_message_added_event(&message_item_id, ""),
-                                        ));
+                                        for tool_event in apply_tool_call_deltas(&mut stream_state, tool_calls, &response_id, &hosted_tools) {
+                                            let kind = tool_event["type"].as_str().unwrap_or_default().to_string();
+                                            if kind != "response.function_call_arguments.delta" {
+                                                emit_log(
+                                                    &host,
+                                                    "info",
+                                                    format!(
+                                                        "Codex Gateway SSE 向下游发送 {}: response_id={} item_id={} elapsed_ms={}",
+                                                        kind,
+                                                        response_id,
+                                                        tool_event["item"]["id"].as_str().or_else(|| tool_event["item_id"].as_str()).unwrap_or_default(),
+                                                        stream_started_at.elapsed().as_millis()
+                                                    ),
+                                                );
+                                            }
+                                            yield Ok(downstream_event(&kind, tool_event));
+                                        }
                                     }
-
-                                    stream_state.message_text.push_str(&delta_text);
-                                    forwarded_text_event_count += 1;
-                                    forwarded_text_chars += delta_chars;
-                                    emit_log(
-                                        &app_handle,
-                                        "info",
-                                        format!(
-                                            "Codex Gateway SSE 向下游转发文本增量: response_id={} raw_event={} parsed_chunk={} delta_chars={} total_chars={} preview={} elapsed_ms={}",
-                                            response_id,
-                                            raw_event_count,
-                                            parsed_chunk_count,
-                                            delta_chars,
-                                            forwarded_text_chars,
-                                            preview_text_for_log(&delta_text, 80),
-                                            stream_started_at.elapsed().as_millis()
-                                        ),
-                                    );
-                                    yield Ok(json_event(
-                                        "response.output_text.delta",
-                                        json!({
-                                            "type": "response.output_text.delta",
-                                            "delta": delta_text,
-                                        }),
-                                    ));
                                 }
 
-                                if let Some(tool_calls) = delta.get("tool_calls").and_then(Value::as_array) {
-                                    chunk_tool_call_deltas += tool_calls.len();
-                                    forwarded_tool_call_delta_count += tool_calls.len();
-                                    emit_log(
-                                        &app_handle,
-                                        "info",
-                                        format!(
-                                            "Codex Gateway SSE 收到工具调用增量: response_id={} raw_event={} parsed_chunk={} delta_count={} total_delta_count={} elapsed_ms={}",
-                                            response_id,
-                                            raw_event_count,
-                                            parsed_chunk_count,
-                                            tool_calls.len(),
-                                            forwarded_tool_call_delta_count,
-                                            stream_started_at.elapsed().as_millis()
-                                        ),
-                                    );
-                                    apply_tool_call_deltas(&mut stream_state.tool_calls, tool_calls, &response_id);
-                                }
-                            }
-
-                            if let Some(message) = choice.get("message") {
-                                let message_parts = route.adapter.sanitize_response_message(message);
-                                let message_text = message_parts.text;
-                                if !message_parts.reasoning.is_empty() {
-                                    stream_state.reasoning_text = message_parts.reasoning;
-                                    stream_state.reasoning_started = true;
-                                    emit_log(
-                                        &app_handle,
-                                        "info",
-                                        format!(
-                                            "Codex Gateway SSE 收到完整 reasoning 覆盖: response_id={} raw_event={} parsed_chunk={} reasoning_chars={} preview={} elapsed_ms={}",
-                                            response_id,
-                                            raw_event_count,
-                                            parsed_chunk_count,
-                                            stream_state.reasoning_text.chars().count(),
-                                            preview_text_for_log(&stream_state.reasoning_text, 80),
-                                            stream_started_at.elapsed().as_millis()
-                                        ),
-                                    );
-                                }
-                                if !message_text.is_empty() {
-                                    chunk_message_chars += message_text.chars().count();
-                                    stream_state.message_text = message_text;
-                                    stream_state.message_started = true;
-                                    stream_state.minimax_response_text = MiniMaxResponseTextState::default();
-                                    emit_log(
-                                        &app_handle,
-                                        "info",
-                                        format!(
-                                            "Codex Gateway SSE 收到完整 message 覆盖: response_id={} raw_event={} parsed_chunk={} message_chars={} preview={} elapsed_ms={}",
-                                            response_id,
-                                            raw_event_count,
-                                            parsed_chunk_count,
-                                            chunk_message_chars,
-                                            preview_text_for_log(&stream_state.message_text, 80),
-                                            stream_started_at.elapsed().as_millis()
-                                        ),
-                                    );
-                                }
+                                if let Some(message) = choice.get("message") {
+                                    let message_parts = route.adapter.sanitize_response_message(message);
+                                    let message_text = message_parts.text;
+                                    if !message_parts.reasoning.is_empty() {
+                                        stream_state.reasoning_text = message_parts.reasoning;
+                                        stream_state.start_reasoning();
+                                        emit_log(
+                                            &host,
+                                            "info",
+                                            format!(
+                                                "Codex Gateway SSE 收到完整 reasoning 覆盖: response_id={} raw_event={} parsed_chunk={} reasoning_chars={} preview={} elapsed_ms={}",
+                                                response_id,
+                                                raw_event_count,
+                                                parsed_chunk_count,
+                                                stream_state.reasoning_text.chars().count(),
+                                                preview_text_for_log(&stream_state.reasoning_text, 80),
+                                                stream_started_at.elapsed().as_millis()
+                                            ),
+                                        );
+                                    }
+                                    if !message_text.is_empty() {
+                                        chunk_message_chars += message_text.chars().count();
+                                        stream_state.message_text = message_text;
+                                        stream_state.start_message();
+                                        stream_state.minimax_response_text = MiniMaxResponseTextState::default();
+                                        emit_log(
+                                            &host,
+                                            "info",
+                                            format!(
+                                                "Codex Gateway SSE 收到完整 message 覆盖: response_id={} raw_event={} parsed_chunk={} message_chars={} preview={} elapsed_ms={}",
+                                                response_id,
+                                                raw_event_count,
+                                                parsed_chunk_count,
+                                                chunk_message_chars,
+                                                preview_text_for_log(&stream_state.message_text, 80),
+                                                stream_started_at.elapsed().as_millis()
+                                            ),
+                                        );
+                                    }
 
-                                if let Some(tool_calls) = message.get("tool_calls").and_then(Value::as_array) {
-                                    chunk_tool_call_deltas += tool_calls.len();
-                                    forwarded_tool_call_delta_count += tool_calls.len();
-                                    emit_log(
-                                        &app_handle,
-                                        "info",
-                                        format!(
-                                            "Codex Gateway SSE 收到完整 message 工具调用: response_id={} raw_event={} parsed_chunk={} delta_count={} total_delta_count={} elapsed_ms={}",
-                                            response_id,
-                                            raw_event_count,
-                                            parsed_chunk_count,
-                                            tool_calls.len(),
-                                            forwarded_tool_call_delta_count,
-                                            stream_started_at.elapsed().as_millis()
-                                        ),
-                                    );
-                                    apply_tool_call_deltas(&mut stream_state.tool_calls, tool_calls, &response_id);
+                                    if let Some(tool_calls) = message.get("tool_calls").and_then(Value::as_array) {
+                                        chunk_tool_call_deltas += tool_calls.len();
+                                        forwarded_tool_call_delta_count += tool_calls.len();
+                                        emit_log(
+                                            &host,
+                                            "info",
+                                            format!(
+                                                "Codex Gateway SSE 收到完整 message 工具调用: response_id={} raw_event={} parsed_chunk={} delta_count={} total_delta_count={} elapsed_ms={}",
+                                                response_id,
+                                                raw_event_count,
+                                                parsed_chunk_count,
+                                                tool_calls.len(),
+                                                forwarded_tool_call_delta_count,
+                                                stream_started_at.elapsed().as_millis()
+                                            ),
+                                        );
+                                        for tool_event in apply_tool_call_deltas(&mut stream_state, tool_calls, &response_id, &hosted_tools) {
+                                            let kind = tool_event["type"].as_str().unwrap_or_default().to_string();
+                                            if kind != "response.function_call_arguments.delta" {
+                                                emit_log(
+                                                    &host,
+                                                    "info",
+                                                    format!(
+                                                        "Codex Gateway SSE 向下游发送 {}: response_id={} item_id={} elapsed_ms={}",
+                                                        kind,
+                                                        response_id,
+                                                        tool_event["item"]["id"].as_str().or_else(|| tool_event["item_id"].as_str()).unwrap_or_default(),
+                                                        stream_started_at.elapsed().as_millis()
+                                                    ),
+                                                );
+                                            }
+                                            yield Ok(downstream_event(&kind, tool_event));
+                                        }
+                                    }
                                 }
                             }
                         }
-                    }
 
-                    emit_log(
-                        &app_handle,
-                        "info",
-                        format!(
-                            "Codex Gateway SSE 已处理 chunk: response_id={} raw_event={} parsed_chunk={} usage_present={} delta_chars={} message_chars={} tool_call_deltas={} finish_reasons={} elapsed_ms={}",
-                            response_id,
-                            raw_event_count,
-                            parsed_chunk_count,
-                            usage_present,
-                            chunk_delta_chars,
-                            chunk_message_chars,
-                            chunk_tool_call_deltas,
-                            if chunk_finish_reasons.is_empty() {
-                                "none".to_string()
-                            } else {
-                                chunk_finish_reasons.join("|")
-                            },
-                            stream_started_at.elapsed().as_millis()
-                        ),
-                    );
-                }
-                Err(error) => {
-                    consecutive_errors += 1;
-                    emit_log(&app_handle, "warn", format!(
-                        "Codex Gateway SSE 读取错误 ({}/{}): response_id={} raw_events={} parsed_chunks={} elapsed_ms={} error={}",
-                        consecutive_errors,
-                        max_consecutive_errors,
-                        response_id,
-                        raw_event_count,
-                        parsed_chunk_count,
-                        stream_started_at.elapsed().as_millis(),
-                        error
-                    ));
-                    if consecutive_errors >= max_consecutive_errors {
-                        emit_log(&app_handle, "error", format!(
-                            "Codex Gateway 连续 {} 次读取失败，中断流: response_id={} raw_events={} parsed_chunks={} forwarded_text_events={} forwarded_text_chars={} tool_call_deltas={} elapsed_ms={}",
-                            max_consecutive_errors,
-                            response_id,
-                            raw_event_count,
-                            parsed_chunk_count,
-                            forwarded_text_event_count,
-                            forwarded_text_chars,
-                            forwarded_tool_call_delta_count,
-                            stream_started_at.elapsed().as_millis()
-                        ));
                         emit_log(
-                            &app_handle,
-                            "error",
+                            &host,
+                            "info",
                             format!(
-                                "Codex Gateway SSE 向下游发送 response.failed: response_id={} reason=连续 {} 次 SSE 读取失败 elapsed_ms={}",
+                                "Codex Gateway SSE 已处理 chunk: response_id={} raw_event={} parsed_chunk={} usage_present={} delta_chars={} message_chars={} tool_call_deltas={} finish_reasons={} elapsed_ms={}",
                                 response_id,
-                                max_consecutive_errors,
+                                raw_event_count,
+                                parsed_chunk_count,
+                                usage_present,
+                                chunk_delta_chars,
+                                chunk_message_chars,
+                                chunk_tool_call_deltas,
+                                if chunk_finish_reasons.is_empty() {
+                                    "none".to_string()
+                                } else {
+                                    chunk_finish_reasons.join("|")
+                                },
                                 stream_started_at.elapsed().as_millis()
                             ),
                         );
-                        yield Ok(json_event(
-                            "response.failed",
-                            response_failed_event(&response_id, format!("连续 {} 次 SSE 读取失败: {}", max_consecutive_errors, error)),
+                    }
+                    Err(error) => {
+                        consecutive_errors += 1;
+                        emit_log(&host, "warn", format!(
+                            "Codex Gateway SSE 读取错误 ({}/{}): response_id={} raw_events={} parsed_chunks={} elapsed_ms={} error={}",
+                            consecutive_errors,
+                            max_consecutive_errors,
+                            response_id,
+                            raw_event_count,
+                            parsed_chunk_count,
+                            stream_started_at.elapsed().as_millis(),
+                            error
                         ));
-                        return;
+                        if consecutive_errors >= max_consecutive_errors {
+                            emit_log(&host, "error", format!(
+                                "Codex Gateway 连续 {} 次读取失败，中断流: response_id={} raw_events={} parsed_chunks={} forwarded_text_events={} forwarded_text_chars={} tool_call_deltas={} elapsed_ms={}",
+                                max_consecutive_errors,
+                                response_id,
+                                raw_event_count,
+                                parsed_chunk_count,
+                                forwarded_text_event_count,
+                                forwarded_text_chars,
+                                forwarded_tool_call_delta_count,
+                                stream_started_at.elapsed().as_millis()
+                            ));
+                            interruption = Some((
+                                ErrorClass::Stream,
+                                format!("连续 {} 次 SSE 读取失败: {}", max_consecutive_errors, error),
+                            ));
+                            break;
+                        }
+                        continue;
                     }
+                }
+            }
+
+            if received_done_event || saw_terminal_finish_reason {
+                break;
+            }
+            // 工具调用无法可靠地接续，只续传推理和正文
+            if resume_count < route.stream.max_resumes && stream_state.tool_calls.is_empty() {
+                if let Some(request) = resume_chat_request(&chat_request, &route.adapter, &stream_state.message_text) {
+                    resume_count += 1;
+                    emit_log(
+                        &host,
+                        "warn",
+                        format!(
+                            "Codex Gateway 上游 SSE 中断，重新发起请求续传 ({}/{}): response_id={} reason={} emitted_text_chars={} elapsed_ms={}",
+                            resume_count,
+                            route.stream.max_resumes,
+                            response_id,
+                            interruption.as_ref().map(|(_, reason)| reason.as_str()).unwrap_or("上游流提前结束"),
+                            stream_state.message_text.chars().count(),
+                            stream_started_at.elapsed().as_millis()
+                        ),
+                    );
+                    upstream_request = request;
                     continue;
                 }
             }
+            if let Some((error_class, reason)) = interruption {
+                emit_log(
+                    &host,
+                    "error",
+                    format!(
+                        "Codex Gateway SSE 向下游发送 response.failed: response_id={} reason={} elapsed_ms={}",
+                        response_id,
+                        reason,
+                        stream_started_at.elapsed().as_millis()
+                    ),
+                );
+                request_log.fail(error_class, reason.clone());
+                yield Ok(downstream_event(
+                    "response.failed",
+                    response_failed_event(&response_id, reason),
+                ));
+                return;
+            }
+            break;
+
         }
 
         if !received_done_event
@@ -1152,7 +1563,7 @@ async fn post_responses(
         {
             let error_message = "上游 SSE 在返回任何内容前中断";
             emit_log(
-                &app_handle,
+                &host,
                 "error",
                 format!(
                     "Codex Gateway {}: response_id={} raw_events={} parsed_chunks={} elapsed_ms={}",
@@ -1164,7 +1575,7 @@ async fn post_responses(
                 ),
             );
             emit_log(
-                &app_handle,
+                &host,
                 "error",
                 format!(
                     "Codex Gateway SSE 向下游发送 response.failed: response_id={} reason={} elapsed_ms={}",
@@ -1173,7 +1584,8 @@ async fn post_responses(
                     stream_started_at.elapsed().as_millis()
                 ),
             );
-            yield Ok(json_event(
+            request_log.fail(ErrorClass::Stream, error_message);
+            yield Ok(downstream_event(
                 "response.failed",
                 response_failed_event(&response_id, error_message),
             ));
@@ -1182,7 +1594,7 @@ async fn post_responses(
 
         if !received_done_event && !saw_terminal_finish_reason {
             emit_log(
-                &app_handle,
+                &host,
                 "warn",
                 format!(
                     "Codex Gateway 上游 SSE 未返回完成标记，但已有内容，继续完成响应: response_id={} raw_events={} parsed_chunks={} forwarded_text_events={} forwarded_text_chars={} tool_call_deltas={} elapsed_ms={}",
@@ -1197,65 +1609,80 @@ async fn post_responses(
             );
         }
 
-        let output_items = stream_output_items(&reasoning_item_id, &message_item_id, &stream_state);
-
-        if stream_state.reasoning_started {
-            emit_log(
-                &app_handle,
-                "info",
-                format!(
-                    "Codex Gateway SSE 向下游发送 reasoning output_item.done: response_id={} item_id={} reasoning_chars={} elapsed_ms={}",
-                    response_id,
-                    reasoning_item_id,
-                    stream_state.reasoning_text.chars().count(),
-                    stream_started_at.elapsed().as_millis()
-                ),
-            );
-            yield Ok(json_event(
-                "response.output_item.done",
-                reasoning_item_done_event(&reasoning_item_id, &stream_state.reasoning_text),
+        if let Some(error) = check_structured_output(&host, structured_output.as_ref(), &stream_state.message_text) {
+            request_log.fail(ErrorClass::UpstreamServer, error.clone());
+            yield Ok(downstream_event(
+                "response.failed",
+                response_failed_event(&response_id, error),
             ));
+            return;
         }
 
-        if stream_state.message_started {
-            emit_log(
-                &app_handle,
-                "info",
-                format!(
-                    "Codex Gateway SSE 向下游发送 response.output_item.done: response_id={} item_id={} text_chars={} elapsed_ms={}",
-                    response_id,
-                    message_item_id,
-                    stream_state.message_text.chars().count(),
-                    stream_started_at.elapsed().as_millis()
-                ),
-            );
-            yield Ok(json_event(
-                "response.output_item.done",
-                assistant_message_done_event(&message_item_id, &stream_state.message_text),
-            ));
-        }
+        let output_items = stream_output_items(&reasoning_item_id, &message_item_id, &stream_state, &hosted_tools);
 
-        for tool_call in stream_state.tool_calls.into_values() {
-            emit_log(
-                &app_handle,
-                "info",
-                format!(
-                    "Codex Gateway SSE 向下游发送 function_call done: response_id={} call_id={} name={} argument_chars={} elapsed_ms={}",
-                    response_id,
-                    tool_call.call_id,
-                    tool_call.name,
-                    tool_call.arguments.chars().count(),
-                    stream_started_at.elapsed().as_millis()
-                ),
-            );
-            yield Ok(json_event(
-                "response.output_item.done",
-                function_call_done_event(&tool_call),
-            ));
+        for item in stream_state.output_order.clone() {
+            match item {
+                StreamItem::Reasoning => {
+                    emit_log(
+                        &host,
+                        "info",
+                        format!(
+                            "Codex Gateway SSE 向下游发送 reasoning output_item.done: response_id={} item_id={} reasoning_chars={} elapsed_ms={}",
+                            response_id,
+                            reasoning_item_id,
+                            stream_state.reasoning_text.chars().count(),
+                            stream_started_at.elapsed().as_millis()
+                        ),
+                    );
+                    yield Ok(downstream_event(
+                        "response.output_item.done",
+                        reasoning_item_done_event(&reasoning_item_id, &stream_state.reasoning_text),
+                    ));
+                }
+                StreamItem::Message => {
+                    emit_log(
+                        &host,
+                        "info",
+                        format!(
+                            "Codex Gateway SSE 向下游发送 response.output_item.done: response_id={} item_id={} text_chars={} elapsed_ms={}",
+                            response_id,
+                            message_item_id,
+                            stream_state.message_text.chars().count(),
+                            stream_started_at.elapsed().as_millis()
+                        ),
+                    );
+                    yield Ok(downstream_event(
+                        "response.output_item.done",
+                        assistant_message_done_event(&message_item_id, &stream_state.message_text),
+                    ));
+                }
+                StreamItem::ToolCall(position) => {
+                    let tool_call = &stream_state.tool_calls[position];
+                    if tool_call.done {
+                        continue;
+                    }
+                    emit_log(
+                        &host,
+                        "info",
+                        format!(
+                            "Codex Gateway SSE 向下游发送 function_call done: response_id={} call_id={} name={} argument_chars={} elapsed_ms={}",
+                            response_id,
+                            tool_call.call_id,
+                            tool_call.name,
+                            tool_call.arguments.chars().count(),
+                            stream_started_at.elapsed().as_millis()
+                        ),
+                    );
+                    for tool_event in finish_tool_call(&mut stream_state, position, &hosted_tools) {
+                        let kind = tool_event["type"].as_str().unwrap_or_default().to_string();
+                        yield Ok(downstream_event(&kind, tool_event));
+                    }
+                }
+            }
         }
 
         emit_log(
-            &app_handle,
+            &host,
             "info",
             format!(
                 "Codex Gateway SSE 向下游发送 response.completed: response_id={} raw_events={} parsed_chunks={} forwarded_text_events={} forwarded_text_chars={} tool_call_deltas={} done_event={} terminal_finish_reason={} elapsed_ms={}",
@@ -1270,7 +1697,7 @@ async fn post_responses(
                 stream_started_at.elapsed().as_millis()
             ),
         );
-        yield Ok(json_event(
+        yield Ok(downstream_event(
             "response.completed",
             response_completed_event(&response_id, &target_model_name, output_items, &usage_totals),
         ));
@@ -1285,18 +1712,513 @@ async fn post_responses(
         .into_response()
 }
 
+/// 断流续传的请求：尚未输出正文时原样重发，否则把已输出的正文作为 assistant 前缀让上游接着生成。
+///
+/// 上游不支持前缀续写时返回 `None`，避免下游收到重复的正文。
+fn resume_chat_request(chat_request: &Value, adapter: &ProviderAdapter, emitted_text: &str) -> Option<Value> {
+    if emitted_text.is_empty() {
+        return Some(chat_request.clone());
+    }
+    let prefix = adapter.assistant_prefix_message(emitted_text)?;
+    let mut request = chat_request.clone();
+    request.get_mut("messages")?.as_array_mut()?.push(prefix);
+    Some(request)
+}
+
+/// 本地校验结构化输出；strict 请求校验失败时返回错误，否则只记录警告。
+fn check_structured_output(host: &GatewayHost, output: Option<&StructuredOutput>, text: &str) -> Option<String> {
+    let output = output?;
+    let error = output.validate(text).err()?;
+    let message = format!("Codex Gateway 输出不符合 JSON Schema {}: {}", output.name, error);
+    if output.strict {
+        emit_log(host, "error", message.clone());
+        Some(message)
+    } else {
+        emit_log(host, "warn", message);
+        None
+    }
+}
+
+fn response_output_text(response: &Value) -> String {
+    response
+        .get("output")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|item| item.get("type").and_then(Value::as_str) == Some("message"))
+        .flat_map(|item| item.get("content").and_then(Value::as_array).into_iter().flatten())
+        .filter_map(|part| part.get("text").and_then(Value::as_str))
+        .collect()
+}
+
+fn gateway_disabled_response() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({
+            "error": "codex_gateway_disabled",
+            "message": "Codex Gateway 已关闭",
+        })),
+    )
+        .into_response()
+}
+
+/// 转发前依次检查熔断状态和本地限流，拒绝时直接返回下游响应。
+async fn acquire_upstream_slot(
+    state: &GatewayServerState,
+    route: &RouteState,
+    chat_request: &Value,
+    request_log: &mut RequestLog,
+    capture: Option<&CaptureSession>,
+) -> Result<RateLimitPermit, Response> {
+    // 先检查熔断，避免被熔断拒绝的请求占用限流额度
+    if let Err(remaining) = state.health.check(&route.provider_id, &route.circuit_breaker) {
+        emit_log(
+            &state.host,
+            "warn",
+            format!(
+                "Codex Gateway 供应商 {} 已熔断，{} 秒后重试",
+                route.provider_id,
+                remaining.as_secs_f64().ceil()
+            ),
+        );
+        request_log.set_local_status(503, ErrorClass::CircuitOpen, "circuit_open");
+        if let Some(capture) = capture {
+            capture.fail("circuit_open");
+        }
+        return Err(circuit_open_response(&route.provider_id, remaining));
+    }
+
+    let rate_limit_permit = match state
+        .rate_limiter
+        .acquire(
+            &route.provider_id,
+            &route.rate_limit,
+            token_estimate::estimate_json_tokens(chat_request),
+        )
+        .await
+    {
+        Ok(permit) => permit,
+        Err(rejection) => {
+            emit_log(
+                &state.host,
+                "warn",
+                format!(
+                    "Codex Gateway 本地限流: provider={} reason={} retry_after={}s",
+                    route.provider_id,
+                    rejection.reason.as_str(),
+                    rejection.retry_after_secs()
+                ),
+            );
+            request_log.set_local_status(429, ErrorClass::RateLimited, rejection.reason.as_str());
+            if let Some(capture) = capture {
+                capture.fail(rejection.message());
+            }
+            return Err(rate_limited_response(&rejection));
+        }
+    };
+
+    Ok(rate_limit_permit)
+}
+
+/// `/v1/chat/completions`：Chat Completions 请求原样转发给当前供应商，只替换模型并做适配器归一化。
+async fn post_chat_completions(
+    State(state): State<GatewayServerState>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Response {
+    let route = state.route_state.read().await.clone();
+    if !route.enabled {
+        return gateway_disabled_response();
+    }
+
+    let mut request_log = RequestLog::start(
+        &state.host,
+        &state.metrics,
+        GatewayKind::Codex,
+        "POST",
+        "/v1/chat/completions",
+        body.to_string().len(),
+    );
+    request_log.set_route(&route.provider_id, Some(&route.target_model_name));
+    let capture = CaptureSession::start(&state.host, GatewayKind::Codex, request_log.request_id());
+    if let Some(capture) = &capture {
+        capture.set_route(&route.provider_id, Some(&route.target_model_name));
+        for api_key in route.api_keys.secrets() {
+            capture.add_credential(None, api_key);
+        }
+        capture.set_inbound("POST", "/v1/chat/completions", &headers, body.to_string().as_bytes());
+    }
+
+    let chat_request = match build_passthrough_chat_request(&body, &route) {
+        Ok(request) => request,
+        Err(error) => {
+            emit_log(&state.host, "error", format!("Codex Gateway Chat 请求转换失败: {}", error));
+            request_log.set_local_status(400, ErrorClass::InvalidRequest, error.clone());
+            if let Some(capture) = &capture {
+                capture.fail(error.clone());
+            }
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({
+                    "error": "invalid_chat_request",
+                    "message": error,
+                })),
+            )
+                .into_response();
+        }
+    };
+
+    let stream_requested = body
+        .get("stream")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+
+    emit_log(
+        &state.host,
+        "info",
+        format!(
+            "Codex Gateway 收到 Chat 请求: model={} stream={} upstream={}",
+            route.target_model_name, stream_requested, route.target_base_url
+        ),
+    );
+
+    let upstream_url = match build_chat_completions_url(&route.target_base_url) {
+        Ok(url) => url,
+        Err(error) => {
+            request_log.set_local_status(502, ErrorClass::Gateway, error.clone());
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({
+                    "error": "invalid_upstream_url",
+                    "message": error,
+                })),
+            )
+                .into_response();
+        }
+    };
+
+    let rate_limit_permit =
+        match acquire_upstream_slot(&state, &route, &chat_request, &mut request_log, capture.as_ref()).await {
+            Ok(permit) => permit,
+            Err(response) => return response,
+        };
+
+    if let Some(capture) = &capture {
+        let api_key = route.api_keys.primary();
+        match build_upstream_request(&state.client, &route, api_key, &upstream_url, &chat_request, stream_requested).build() {
+            Ok(request) => capture.set_upstream_request(&request),
+            Err(error) => capture.fail(format!("构造上游请求失败: {}", error)),
+        }
+    }
+
+    let started_at = Instant::now();
+    let upstream_result = gateway_keys::send_with_keys(
+        &state.keys,
+        &route.provider_id,
+        &route.api_keys,
+        &route.retry,
+        |api_key| build_upstream_request(&state.client, &route, api_key, &upstream_url, &chat_request, stream_requested),
+        |attempt| log_retry_attempt(&state.host, attempt, &upstream_url),
+        |failover| log_key_failover(&state.host, &route.provider_id, failover),
+    )
+    .await;
+    record_upstream_health(&state.health, &state.host, &route, started_at, &upstream_result);
+
+    let upstream_response = match upstream_result {
+        Ok(response) => response,
+        Err(error) => {
+            emit_log(&state.host, "error", format!("Codex Gateway 上游请求失败: {}", error));
+            request_log.fail(ErrorClass::from_reqwest(&error), error.to_string());
+            if let Some(capture) = &capture {
+                capture.fail(error.to_string());
+            }
+            return (
+                StatusCode::BAD_GATEWAY,
+                Json(json!({
+                    "error": "upstream_request_failed",
+                    "message": error.to_string(),
+                })),
+            )
+                .into_response();
+        }
+    };
+
+    let status = upstream_response.status();
+    request_log.set_upstream_status(status.as_u16());
+    observe_upstream_rate_limit(
+        &state.rate_limiter,
+        &state.host,
+        &route.provider_id,
+        status,
+        upstream_response.headers(),
+    );
+    if let Some(capture) = &capture {
+        capture.set_upstream_response(status.as_u16(), upstream_response.headers());
+    }
+    if !status.is_success() {
+        let body_text = upstream_response.text().await.unwrap_or_default();
+        request_log.add_response_bytes(body_text.len());
+        if let Some(capture) = &capture {
+            capture.append_upstream_body(body_text.as_bytes());
+        }
+        emit_log(
+            &state.host,
+            "error",
+            format!("Codex Gateway 上游返回错误: status={} body={}", status, body_text),
+        );
+        return (status, body_text).into_response();
+    }
+
+    if !stream_requested {
+        let upstream_bytes = match upstream_response.bytes().await {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                request_log.fail(ErrorClass::Stream, error.to_string());
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    Json(json!({
+                        "error": "invalid_upstream_response",
+                        "message": error.to_string(),
+                    })),
+                )
+                    .into_response();
+            }
+        };
+        request_log.add_response_bytes(upstream_bytes.len());
+        if let Some(capture) = &capture {
+            capture.append_upstream_body(&upstream_bytes);
+        }
+        let upstream_json = match serde_json::from_slice::<Value>(&upstream_bytes) {
+            Ok(json) => json,
+            Err(error) => {
+                request_log.fail(ErrorClass::UpstreamServer, error.to_string());
+                return (
+                    StatusCode::BAD_GATEWAY,
+                    Json(json!({
+                        "error": "invalid_upstream_response",
+                        "message": error.to_string(),
+                    })),
+                )
+                    .into_response();
+            }
+        };
+
+        let (input_tokens, output_tokens) = UsageTotals::from_value(upstream_json.get("usage")).token_counts();
+        request_log.set_usage(input_tokens, output_tokens);
+        let response = sanitize_chat_completion(&upstream_json, &route.adapter);
+        if let Some(capture) = &capture {
+            capture.record_downstream("chat.completion", &response);
+        }
+        return Json(response).into_response();
+    }
+
+    let host = state.host.clone();
+    let adapter = route.adapter.clone();
+    let stream_policy = route.stream.clone();
+    let event_stream = stream! {
+        // 整个 SSE 流结束前保持并发流名额，结束后写入请求日志和抓包
+        let _rate_limit_permit = rate_limit_permit;
+        let mut request_log = request_log;
+        request_log.start_stream();
+        let capture = capture;
+        let mut usage_totals = UsageTotals::default();
+        let mut stream_state = StreamState::default();
+        let mut upstream_stream = upstream_response.bytes_stream().eventsource();
+        let mut received_first_event = false;
+
+        loop {
+            let read_timeout = stream_policy.read_timeout(received_first_event);
+            let event = match tokio::time::timeout(read_timeout, upstream_stream.next()).await {
+                Ok(Some(Ok(event))) => {
+                    received_first_event = true;
+                    event
+                }
+                Ok(Some(Err(error))) => {
+                    emit_log(&host, "warn", format!("Codex Gateway Chat SSE 读取失败 (跳过): {}", error));
+                    continue;
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    let message = format!("超过 {} 秒未收到上游事件", read_timeout.as_secs());
+                    emit_log(&host, "error", format!("Codex Gateway Chat SSE 读取超时，中断流: {}", message));
+                    request_log.fail(ErrorClass::Timeout, "SSE 读取超时");
+                    let error = json!({ "error": { "type": "timeout", "message": message } });
+                    yield Ok::<Event, Infallible>(Event::default().data(error.to_string()));
+                    return;
+                }
+            };
+
+            request_log.add_response_bytes(event.data.len());
+            if let Some(capture) = &capture {
+                capture.append_upstream_body(gateway_capture::sse_text(&event.event, &event.data).as_bytes());
+            }
+            let data = event.data.trim();
+            if data.is_empty() {
+                continue;
+            }
+            if data == "[DONE]" {
+                break;
+            }
+
+            let mut chunk = match serde_json::from_str::<Value>(data) {
+                Ok(chunk) => chunk,
+                Err(error) => {
+                    emit_log(
+                        &host,
+                        "warn",
+                        format!(
+                            "Codex Gateway Chat SSE 单条解析失败 (跳过): preview={} error={}",
+                            preview_text_for_log(data, 120),
+                            error
+                        ),
+                    );
+                    continue;
+                }
+            };
+
+            if chunk.get("usage").is_some_and(|usage| !usage.is_null()) {
+                usage_totals.merge(chunk.get("usage"));
+                let (input_tokens, output_tokens) = usage_totals.token_counts();
+                request_log.set_usage(input_tokens, output_tokens);
+            }
+            sanitize_chat_chunk(&mut chunk, &adapter, &mut stream_state);
+            if let Some(capture) = &capture {
+                capture.record_downstream("chat.completion.chunk", &chunk);
+            }
+            yield Ok(Event::default().data(chunk.to_string()));
+        }
+
+        yield Ok(Event::default().data("[DONE]"));
+    };
+
+    Sse::new(event_stream)
+        .keep_alive(
+            KeepAlive::new()
+                .interval(std::time::Duration::from_secs(3))
+                .text("ping"),
+        )
+        .into_response()
+}
+
+/// 在客户端的 Chat Completions 请求上替换模型、归一化角色，其余字段原样透传。
+fn build_passthrough_chat_request(body: &Value, route: &RouteState) -> Result<Value, String> {
+    if !body.is_object() {
+        return Err("请求体必须是 JSON 对象".to_string());
+    }
+    if body
+        .get("messages")
+        .and_then(Value::as_array)
+        .map_or(true, |messages| messages.is_empty())
+    {
+        return Err("请求缺少 messages".to_string());
+    }
+
+    if !route.input_support().images && chat_request_has_images(body) {
+        return Err("当前上游不支持图片输入，无法转发请求中的图片（可在 codexConfig.capabilities.images 中开启）".to_string());
+    }
+
+    let mut request = body.clone();
+    route.defaults.apply_to_chat(&mut request);
+    let messages = request["messages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .cloned()
+        .map(|mut message| {
+            let role = message
+                .get("role")
+                .and_then(Value::as_str)
+                .map(|role| route.adapter.normalize_role(role).to_string());
+            if let (Some(role), Some(object)) = (role, message.as_object_mut()) {
+                object.insert("role".to_string(), Value::String(role));
+            }
+            message
+        })
+        .collect::<Vec<_>>();
+
+    request["model"] = Value::String(route.target_model_name.clone());
+    request["messages"] = Value::Array(route.adapter.normalize_messages(messages));
+    if let Some(object) = request.as_object_mut() {
+        if let Some(intent) = gateway_reasoning::take_chat_intent(object) {
+            route.reasoning.apply(route.reasoning_style(), &intent, object);
+        }
+        if let Some(output) = object
+            .get("response_format")
+            .and_then(StructuredOutput::from_chat_format)
+        {
+            output.apply_to_chat_request(route.structured_output, object);
+        }
+    }
+    route.transform.apply(&mut request);
+    Ok(request)
+}
+
+fn chat_request_has_images(body: &Value) -> bool {
+    body.get("messages")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|message| message.get("content").and_then(Value::as_array))
+        .flatten()
+        .any(|part| part.get("type").and_then(Value::as_str) == Some("image_url"))
+}
+
+/// 拆分非流式响应中每个 choice 的推理内容。
+fn sanitize_chat_completion(upstream_response: &Value, adapter: &ProviderAdapter) -> Value {
+    let mut response = upstream_response.clone();
+    if let Some(choices) = response.get_mut("choices").and_then(Value::as_array_mut) {
+        for message in choices.iter_mut().filter_map(|choice| choice.get_mut("message")) {
+            let parts = adapter.sanitize_response_message(message);
+            apply_content_parts(message, parts);
+        }
+    }
+    response
+}
+
+/// 拆分流式分块中每个 choice 增量的推理内容；跨分块的 `<think>` 标签由 `stream_state` 缓冲。
+fn sanitize_chat_chunk(chunk: &mut Value, adapter: &ProviderAdapter, stream_state: &mut StreamState) {
+    let Some(choices) = chunk.get_mut("choices").and_then(Value::as_array_mut) else {
+        return;
+    };
+    for delta in choices.iter_mut().filter_map(|choice| choice.get_mut("delta")) {
+        let parts = adapter.sanitize_stream_delta(stream_state, delta);
+        if !parts.text.is_empty() {
+            stream_state.message_started = true;
+        }
+        apply_content_parts(delta, parts);
+    }
+}
+
+fn apply_content_parts(target: &mut Value, parts: ResponseContentParts) {
+    let Some(object) = target.as_object_mut() else {
+        return;
+    };
+    if !parts.text.is_empty() || object.get("content").is_some_and(|content| !content.is_null()) {
+        object.insert("content".to_string(), Value::String(parts.text));
+    }
+    if parts.reasoning.is_empty() {
+        object.remove("reasoning_content");
+    } else {
+        object.insert("reasoning_content".to_string(), Value::String(parts.reasoning));
+    }
+}
+
 fn build_chat_request(body: &Value, route: &RouteState) -> Result<Value, String> {
-    let messages = build_chat_messages(body, &route.adapter)?;
+    let mut body = body.clone();
+    route.defaults.apply_to_responses(&mut body);
+    let body = &body;
+    let messages = build_chat_messages(body, &route.adapter, route.input_support())?;
     if messages.is_empty() {
         return Err("Responses input 为空，无法转换为 Chat messages".to_string());
     }
 
     let mut request = serde_json::Map::new();
     request.insert("model".to_string(), Value::String(route.target_model_name.clone()));
-    request.insert(
-        "stream".to_string(),
-        Value::Bool(body.get("stream").and_then(Value::as_bool).unwrap_or(true)),
-    );
+    let stream = body.get("stream").and_then(Value::as_bool).unwrap_or(true);
+    request.insert("stream".to_string(), Value::Bool(stream));
+    if stream && route.adapter.supports_stream_usage() {
+        request.insert("stream_options".to_string(), json!({ "include_usage": true }));
+    }
     request.insert("messages".to_string(), Value::Array(messages));
 
     copy_value(body, &mut request, "temperature");
@@ -1312,12 +2234,15 @@ fn build_chat_request(body: &Value, route: &RouteState) -> Result<Value, String>
         request.insert("max_tokens".to_string(), max_tokens.clone());
     }
 
-    if let Some(response_format) = body
-        .get("text")
-        .and_then(|text| text.get("format"))
-        .cloned()
-    {
-        request.insert("response_format".to_string(), response_format);
+    if let Some(format) = body.get("text").and_then(|text| text.get("format")) {
+        match StructuredOutput::from_responses_format(format) {
+            Some(output) => output.apply_to_chat_request(route.structured_output, &mut request),
+            None => {
+                if let Some(response_format) = gateway_structured::responses_format_to_chat(format) {
+                    request.insert("response_format".to_string(), response_format);
+                }
+            }
+        }
     }
 
     if let Some(tool_choice) = map_tool_choice(body.get("tool_choice")) {
@@ -1327,17 +2252,31 @@ fn build_chat_request(body: &Value, route: &RouteState) -> Result<Value, String>
     if let Some(tools) = body.get("tools").and_then(Value::as_array) {
         let mapped_tools = tools
             .iter()
-            .filter_map(map_tool_definition)
+            .filter_map(|tool| codex_tools::hosted_tool_definition(tool).or_else(|| map_tool_definition(tool)))
             .collect::<Vec<_>>();
         if !mapped_tools.is_empty() {
             request.insert("tools".to_string(), Value::Array(mapped_tools));
         }
+        // web_search 由服务端执行，只能换成上游自带的联网搜索
+        if tools.iter().any(codex_tools::is_web_search_tool) {
+            if let Some((key, value)) = route.adapter.native_web_search() {
+                request.insert(key.to_string(), value);
+            }
+        }
     }
 
-    Ok(Value::Object(request))
+    if let Some(intent) = gateway_reasoning::responses_intent(body) {
+        route.reasoning.apply(route.reasoning_style(), &intent, &mut request);
+    }
+
+    route.context_window.fit_chat_request(&mut request)?;
+
+    let mut request = Value::Object(request);
+    route.transform.apply(&mut request);
+    Ok(request)
 }
 
-fn build_chat_messages(body: &Value, adapter: &ProviderAdapter) -> Result<Vec<Value>, String> {
+fn build_chat_messages(body: &Value, adapter: &ProviderAdapter, support: InputSupport) -> Result<Vec<Value>, String> {
     let mut messages = Vec::new();
 
     if let Some(instructions) = body.get("instructions").and_then(Value::as_str) {
@@ -1361,11 +2300,21 @@ fn build_chat_messages(body: &Value, adapter: &ProviderAdapter) -> Result<Vec<Va
             }
         }
         Some(Value::Array(items)) => {
+            // 工具输出中的图片不能放进 tool 消息，等同一批工具输出结束后作为 user 消息补发
+            let mut tool_media = Vec::new();
             for item in items {
-                append_input_item_as_chat_message(&mut messages, item, adapter)?;
+                if !is_tool_output_item(item) {
+                    flush_tool_media(&mut messages, &mut tool_media);
+                }
+                append_input_item_as_chat_message(&mut messages, &mut tool_media, item, adapter, support)?;
             }
+            flush_tool_media(&mut messages, &mut tool_media);
+        }
+        Some(Value::Object(_)) => {
+            let mut tool_media = Vec::new();
+            append_input_item_as_chat_message(&mut messages, &mut tool_media, body.get("input").unwrap(), adapter, support)?;
+            flush_tool_media(&mut messages, &mut tool_media);
         }
-        Some(Value::Object(_)) => append_input_item_as_chat_message(&mut messages, body.get("input").unwrap(), adapter)?,
         Some(_) => return Err("Responses input 类型不受支持".to_string()),
         None => {}
     }
@@ -1373,7 +2322,32 @@ fn build_chat_messages(body: &Value, adapter: &ProviderAdapter) -> Result<Vec<Va
     Ok(adapter.normalize_messages(messages))
 }
 
-fn append_input_item_as_chat_message(messages: &mut Vec<Value>, item: &Value, adapter: &ProviderAdapter) -> Result<(), String> {
+fn is_tool_output_item(item: &Value) -> bool {
+    matches!(
+        item.get("type").and_then(Value::as_str),
+        Some("function_call_output")
+            | Some("custom_tool_call_output")
+            | Some("tool_search_output")
+            | Some("local_shell_call_output")
+    )
+}
+
+fn flush_tool_media(messages: &mut Vec<Value>, tool_media: &mut Vec<Value>) {
+    if tool_media.is_empty() {
+        return;
+    }
+    let mut content = vec![json!({ "type": "text", "text": "Images returned by the tool calls above:" })];
+    content.append(tool_media);
+    messages.push(json!({ "role": "user", "content": content }));
+}
+
+fn append_input_item_as_chat_message(
+    messages: &mut Vec<Value>,
+    tool_media: &mut Vec<Value>,
+    item: &Value,
+    adapter: &ProviderAdapter,
+    support: InputSupport,
+) -> Result<(), String> {
     let item_type = item.get("type").and_then(Value::as_str);
     let raw_role = item
         .get("role")
@@ -1383,7 +2357,7 @@ fn append_input_item_as_chat_message(messages: &mut Vec<Value>, item: &Value, ad
 
     match item_type {
         Some("message") | None if item.get("role").is_some() => {
-            let content = map_message_content(item.get("content"));
+            let content = map_message_content(item.get("content"), support)?;
             messages.push(json!({
                 "role": role.to_string(),
                 "content": content,
@@ -1462,17 +2436,41 @@ fn append_input_item_as_chat_message(messages: &mut Vec<Value>, item: &Value, ad
                 }]
             }));
         }
-        Some("function_call_output") | Some("custom_tool_call_output") | Some("tool_search_output") => {
+        Some("local_shell_call") => {
+            let call_id = item
+                .get("call_id")
+                .or_else(|| item.get("id"))
+                .and_then(Value::as_str)
+                .unwrap_or("local_shell_call");
+            messages.push(json!({
+                "role": "assistant",
+                "content": "",
+                "tool_calls": [{
+                    "id": call_id,
+                    "type": "function",
+                    "function": {
+                        "name": codex_tools::LOCAL_SHELL_FUNCTION,
+                        "arguments": codex_tools::local_shell_call_arguments(item),
+                    }
+                }]
+            }));
+        }
+        Some("function_call_output")
+        | Some("custom_tool_call_output")
+        | Some("tool_search_output")
+        | Some("local_shell_call_output") => {
             let call_id = item
                 .get("call_id")
                 .or_else(|| item.get("id"))
                 .and_then(Value::as_str)
                 .ok_or_else(|| "tool output 缺少 call_id".to_string())?;
+            let (text, media) = map_tool_output(item, support)?;
             messages.push(json!({
                 "role": "tool",
                 "tool_call_id": call_id,
-                "content": extract_tool_output_text(item),
+                "content": text,
             }));
+            tool_media.extend(media);
         }
         Some(_) => {
             let content = extract_message_text(item.get("content").or_else(|| item.get("output")));
@@ -1497,12 +2495,12 @@ fn append_input_item_as_chat_message(messages: &mut Vec<Value>, item: &Value, ad
     Ok(())
 }
 
-fn map_message_content(content: Option<&Value>) -> Value {
+fn map_message_content(content: Option<&Value>, support: InputSupport) -> Result<Value, String> {
     let Some(content) = content else {
-        return Value::String(String::new());
+        return Ok(Value::String(String::new()));
     };
 
-    match content {
+    let content = match content {
         Value::String(text) => Value::String(text.clone()),
         Value::Array(items) => {
             let mut structured = Vec::new();
@@ -1510,40 +2508,117 @@ fn map_message_content(content: Option<&Value>) -> Value {
 
             for item in items {
                 let item_type = item.get("type").and_then(Value::as_str).unwrap_or("text");
-                match item_type {
-                    "input_text" | "output_text" | "text" | "summary_text" | "reasoning_text" => {
-                        if let Some(text) = item.get("text").and_then(Value::as_str) {
-                            plain_text.push_str(text);
-                            structured.push(json!({
-                                "type": "text",
-                                "text": text,
-                            }));
-                        }
-                    }
-                    "input_image" => {
-                        if let Some(url) = item.get("image_url").and_then(Value::as_str).or_else(|| item.get("url").and_then(Value::as_str)) {
-                            structured.push(json!({
-                                "type": "image_url",
-                                "image_url": { "url": url },
-                            }));
-                        }
+                let part = match item_type {
+                    "input_text" | "output_text" | "text" | "summary_text" | "reasoning_text" => item
+                        .get("text")
+                        .and_then(Value::as_str)
+                        .map(|text| json!({ "type": "text", "text": text })),
+                    "input_image" | "image_url" => map_input_image(item, support)?,
+                    "input_file" | "file" => Some(map_input_file(item, support)?),
+                    _ => None,
+                };
+                if let Some(part) = part {
+                    if let Some(text) = part.get("text").and_then(Value::as_str) {
+                        plain_text.push_str(text);
                     }
-                    _ => {}
+                    structured.push(part);
                 }
             }
 
-            if structured.is_empty() {
-                Value::String(plain_text)
-            } else if structured.iter().all(|item| item.get("type").and_then(Value::as_str) == Some("text")) {
+            if structured.iter().all(|item| item.get("type").and_then(Value::as_str) == Some("text")) {
                 Value::String(plain_text)
             } else {
                 Value::Array(structured)
             }
         }
         _ => Value::String(extract_message_text(Some(content))),
+    };
+    Ok(content)
+}
+
+/// Responses `input_image` 转为 Chat `image_url`，保留 data URL 和 `detail`。
+fn map_input_image(item: &Value, support: InputSupport) -> Result<Option<Value>, String> {
+    let image_url = item.get("image_url");
+    let url = image_url
+        .and_then(Value::as_str)
+        .or_else(|| image_url.and_then(|image_url| image_url.get("url")).and_then(Value::as_str))
+        .or_else(|| item.get("url").and_then(Value::as_str));
+    let Some(url) = url else {
+        if item.get("file_id").is_some() {
+            return Err("图片通过 file_id 引用，Gateway 无法转发到 Chat Completions 上游，请改用图片 URL 或 data URL".to_string());
+        }
+        return Ok(None);
+    };
+    if !support.images {
+        return Err("当前上游不支持图片输入，无法转发请求中的图片（可在 codexConfig.capabilities.images 中开启）".to_string());
+    }
+
+    let mut mapped = json!({ "url": url });
+    let detail = item
+        .get("detail")
+        .or_else(|| image_url.and_then(|image_url| image_url.get("detail")))
+        .and_then(Value::as_str);
+    if let Some(detail) = detail {
+        mapped["detail"] = Value::String(detail.to_string());
+    }
+    Ok(Some(json!({ "type": "image_url", "image_url": mapped })))
+}
+
+/// Responses `input_file` 转为文本块（文本类文件）或 Chat `file` 内容块。
+fn map_input_file(item: &Value, support: InputSupport) -> Result<Value, String> {
+    let file = item.get("file").unwrap_or(item);
+    let filename = file.get("filename").and_then(Value::as_str).unwrap_or("file");
+    let Some(file_data) = file.get("file_data").and_then(Value::as_str) else {
+        return Err(format!(
+            "文件 {} 通过 file_id 或 file_url 引用，Gateway 无法转发到 Chat Completions 上游，请改用 file_data",
+            filename
+        ));
+    };
+
+    if let Some(text) = decode_text_file(file_data, filename) {
+        return Ok(json!({
+            "type": "text",
+            "text": format!("<file name=\"{}\">\n{}\n</file>", filename, text),
+        }));
+    }
+    if !support.files {
+        return Err(format!(
+            "当前上游不支持文件输入，无法转发 {}（可在 codexConfig.capabilities.files 中开启）",
+            filename
+        ));
+    }
+    Ok(json!({
+        "type": "file",
+        "file": { "filename": filename, "file_data": file_data },
+    }))
+}
+
+/// 文本类文件直接解码为 UTF-8 文本，其余返回 `None`。
+fn decode_text_file(file_data: &str, filename: &str) -> Option<String> {
+    let (mime, data) = match file_data
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(','))
+    {
+        Some((meta, data)) => (meta.strip_suffix(";base64")?, data),
+        None => ("", file_data),
+    };
+    let extension = filename.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase());
+    let is_text = mime.starts_with("text/")
+        || matches!(mime, "application/json" | "application/xml" | "application/yaml" | "application/toml")
+        || extension.as_deref().is_some_and(|extension| TEXT_FILE_EXTENSIONS.contains(&extension));
+    if !is_text {
+        return None;
     }
+
+    let bytes = base64::engine::general_purpose::STANDARD.decode(data.trim()).ok()?;
+    String::from_utf8(bytes).ok()
 }
 
+const TEXT_FILE_EXTENSIONS: &[&str] = &[
+    "txt", "md", "csv", "tsv", "json", "jsonl", "yaml", "yml", "toml", "xml", "html", "log", "rs", "py", "js",
+    "ts", "tsx", "jsx", "go", "java", "kt", "c", "h", "cpp", "hpp", "cs", "rb", "php", "sh", "sql", "swift",
+];
+
 fn map_tool_definition(tool: &Value) -> Option<Value> {
     let tool_type = tool.get("type").and_then(Value::as_str).unwrap_or("function");
     let name = tool
@@ -1571,7 +2646,7 @@ fn map_tool_definition(tool: &Value) -> Option<Value> {
         }));
 
     match tool_type {
-        "function" | "custom" | "tool_search" => Some(json!({
+        "function" | "tool_search" => Some(json!({
             "type": "function",
             "function": {
                 "name": name,
@@ -1588,26 +2663,26 @@ fn map_tool_choice(tool_choice: Option<&Value>) -> Option<Value> {
     match tool_choice {
         Value::String(_) => Some(tool_choice.clone()),
         Value::Object(object) => {
-            if object.get("type").and_then(Value::as_str) == Some("function") {
-                let name = object.get("name").and_then(Value::as_str).or_else(|| {
+            let name = match object.get("type").and_then(Value::as_str) {
+                Some("function") | Some("custom") => object.get("name").and_then(Value::as_str).or_else(|| {
                     object
                         .get("function")
                         .and_then(|function| function.get("name"))
                         .and_then(Value::as_str)
-                })?;
-                Some(json!({
-                    "type": "function",
-                    "function": { "name": name }
-                }))
-            } else {
-                None
-            }
+                })?,
+                Some("local_shell") => codex_tools::LOCAL_SHELL_FUNCTION,
+                _ => return None,
+            };
+            Some(json!({
+                "type": "function",
+                "function": { "name": name }
+            }))
         }
         _ => None,
     }
 }
 
-fn build_non_stream_response(upstream_response: &Value, route: &RouteState) -> Value {
+fn build_non_stream_response(upstream_response: &Value, route: &RouteState, hosted_tools: &HostedTools) -> Value {
     let response_id = format!("resp_{}", Uuid::new_v4().simple());
     let choice = upstream_response
         .get("choices")
@@ -1652,12 +2727,7 @@ fn build_non_stream_response(upstream_response: &Value, route: &RouteState) -> V
                 .and_then(|function| function.get("arguments"))
                 .and_then(Value::as_str)
                 .unwrap_or("{}");
-            output_items.push(json!({
-                "type": "function_call",
-                "call_id": call_id,
-                "name": name,
-                "arguments": arguments,
-            }));
+            output_items.push(hosted_tools.output_item(call_id, name, arguments));
         }
     }
 
@@ -1688,20 +2758,21 @@ fn build_chat_completions_url(base_url: &str) -> Result<String, String> {
 fn build_upstream_request(
     client: &reqwest::Client,
     route: &RouteState,
+    api_key: &str,
     upstream_url: &str,
     chat_request: &Value,
     stream_requested: bool,
 ) -> reqwest::RequestBuilder {
     let mut request_builder = client
         .post(upstream_url.to_string())
-        .bearer_auth(route.api_key.clone())
+        .bearer_auth(api_key)
         .header(reqwest::header::CONTENT_TYPE, "application/json");
 
     if stream_requested {
         request_builder = request_builder.header(reqwest::header::ACCEPT, "text/event-stream");
     }
 
-    request_builder.json(chat_request)
+    route.transform.apply_headers(request_builder).json(chat_request)
 }
 
 fn copy_value(source: &Value, target: &mut serde_json::Map<String, Value>, key: &str) {
@@ -1841,16 +2912,30 @@ fn extract_message_text(value: Option<&Value>) -> String {
     }
 }
 
-fn extract_tool_output_text(item: &Value) -> String {
-    if let Some(output) = item.get("output") {
-        return extract_message_text(Some(output));
-    }
+/// 拆分工具输出中的文本和图片/文件，后者需要放到 user 消息中发送。
+fn map_tool_output(item: &Value, support: InputSupport) -> Result<(String, Vec<Value>), String> {
+    let Some(output) = item.get("output").or_else(|| item.get("content")) else {
+        return Ok((String::new(), Vec::new()));
+    };
 
-    if let Some(content) = item.get("content") {
-        return extract_message_text(Some(content));
+    let mut text = extract_message_text(Some(output));
+    let mut media = Vec::new();
+    for part in output.as_array().into_iter().flatten() {
+        let mapped = match part.get("type").and_then(Value::as_str) {
+            Some("input_image") => map_input_image(part, support)?,
+            Some("input_file") => Some(map_input_file(part, support)?),
+            _ => None,
+        };
+        match mapped {
+            Some(part) if part.get("type").and_then(Value::as_str) == Some("text") => {
+                text.push_str(part.get("text").and_then(Value::as_str).unwrap_or_default());
+            }
+            Some(part) => media.push(part),
+            None => {}
+        }
     }
 
-    String::new()
+    Ok((text, media))
 }
 
 fn normalize_custom_tool_arguments(input: Option<&Value>) -> String {
@@ -1868,40 +2953,142 @@ fn normalize_custom_tool_arguments(input: Option<&Value>) -> String {
     }
 }
 
+/// 合并工具调用增量，返回需要立即发给下游的 added / 参数增量 / done 事件。
+///
+/// 部分厂商所有调用都复用 index 0 或不带 id：同一 index 出现不同的 id，
+/// 或上一个调用的参数已是完整 JSON 后又收到函数名时，视为新的调用。
 fn apply_tool_call_deltas(
-    tool_calls: &mut BTreeMap<usize, ToolCallState>,
+    stream_state: &mut StreamState,
     deltas: &[Value],
     response_id: &str,
-) {
+    hosted_tools: &HostedTools,
+) -> Vec<Value> {
+    let mut events = Vec::new();
     for (fallback_index, delta) in deltas.iter().enumerate() {
         let index = delta
             .get("index")
             .and_then(Value::as_u64)
             .unwrap_or(fallback_index as u64) as usize;
-        let entry = tool_calls.entry(index).or_insert_with(|| ToolCallState {
-            call_id: format!("call_{}_{}", response_id, index),
-            name: String::new(),
-            arguments: String::new(),
-        });
-
-        if let Some(id) = delta.get("id").and_then(Value::as_str) {
-            entry.call_id = id.to_string();
-        }
-
-        if let Some(function) = delta.get("function") {
-            if let Some(name) = function.get("name").and_then(Value::as_str) {
-                if entry.name.is_empty() {
-                    entry.name = name.to_string();
-                } else if entry.name != name {
-                    entry.name.push_str(name);
+        let id = delta.get("id").and_then(Value::as_str).filter(|id| !id.is_empty());
+        let function = delta.get("function");
+        let name = function
+            .and_then(|function| function.get("name"))
+            .and_then(Value::as_str)
+            .filter(|name| !name.is_empty());
+        let arguments = function
+            .and_then(|function| function.get("arguments"))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+
+        let current = stream_state.tool_call_slots.get(&index).copied();
+        let position = match current {
+            Some(position) if !starts_new_tool_call(&stream_state.tool_calls[position], id, name) => position,
+            _ => {
+                if let Some(previous) = current {
+                    events.extend(finish_tool_call(stream_state, previous, hosted_tools));
                 }
+                let position = stream_state.tool_calls.len();
+                stream_state.tool_calls.push(ToolCallState {
+                    call_id: format!("call_{}_{}", response_id, position),
+                    explicit_id: false,
+                    item_id: format!("fc_{}", Uuid::new_v4().simple()),
+                    name: String::new(),
+                    arguments: String::new(),
+                    added: false,
+                    done: false,
+                });
+                stream_state.output_order.push(StreamItem::ToolCall(position));
+                stream_state.tool_call_slots.insert(index, position);
+                position
             }
+        };
 
-            if let Some(arguments) = function.get("arguments").and_then(Value::as_str) {
-                entry.arguments.push_str(arguments);
+        let output_index = stream_state.output_index(StreamItem::ToolCall(position));
+        let tool_call = &mut stream_state.tool_calls[position];
+        if let Some(id) = id {
+            tool_call.call_id = id.to_string();
+            tool_call.explicit_id = true;
+        }
+        if let Some(name) = name {
+            if tool_call.name.is_empty() {
+                tool_call.name = name.to_string();
+            } else if tool_call.name != name {
+                tool_call.name.push_str(name);
             }
         }
+        tool_call.arguments.push_str(arguments);
+
+        // 拿到函数名后才能发 added，之前收到的参数一并补发
+        if tool_call.name.is_empty() {
+            continue;
+        }
+        let pending_arguments = if tool_call.added {
+            arguments.to_string()
+        } else {
+            tool_call.added = true;
+            events.push(json!({
+                "type": "response.output_item.added",
+                "output_index": output_index,
+                "item": function_call_item(tool_call, hosted_tools, false),
+            }));
+            tool_call.arguments.clone()
+        };
+        // custom / local_shell 的参数需要整体转换，只在 done 时给出
+        if !pending_arguments.is_empty() && !hosted_tools.is_hosted(&tool_call.name) {
+            events.push(json!({
+                "type": "response.function_call_arguments.delta",
+                "item_id": tool_call.item_id,
+                "output_index": output_index,
+                "delta": pending_arguments,
+            }));
+        }
+    }
+    events
+}
+
+fn starts_new_tool_call(tool_call: &ToolCallState, id: Option<&str>, name: Option<&str>) -> bool {
+    match id {
+        Some(id) if tool_call.explicit_id => id != tool_call.call_id,
+        _ => {
+            name.is_some()
+                && !tool_call.arguments.is_empty()
+                && serde_json::from_str::<Value>(&tool_call.arguments).is_ok()
+        }
+    }
+}
+
+/// 结束一个工具调用；重复调用不会再次产生事件。
+fn finish_tool_call(stream_state: &mut StreamState, position: usize, hosted_tools: &HostedTools) -> Vec<Value> {
+    let output_index = stream_state.output_index(StreamItem::ToolCall(position));
+    let tool_call = &mut stream_state.tool_calls[position];
+    if tool_call.done {
+        return Vec::new();
+    }
+    tool_call.done = true;
+
+    let mut events = Vec::new();
+    if !tool_call.added {
+        tool_call.added = true;
+        events.push(json!({
+            "type": "response.output_item.added",
+            "output_index": output_index,
+            "item": function_call_item(tool_call, hosted_tools, false),
+        }));
     }
+    if !hosted_tools.is_hosted(&tool_call.name) {
+        events.push(json!({
+            "type": "response.function_call_arguments.done",
+            "item_id": tool_call.item_id,
+            "output_index": output_index,
+            "arguments": tool_call.arguments,
+        }));
+    }
+    events.push(json!({
+        "type": "response.output_item.done",
+        "output_index": output_index,
+        "item": function_call_item(tool_call, hosted_tools, true),
+    }));
+    events
 }
 
 fn assistant_message_item(message_id: &str, text: &str) -> Value {
@@ -1983,20 +3170,135 @@ fn assistant_message_done_event(message_id: &str, text: &str) -> Value {
     })
 }
 
-fn function_call_item(tool_call: &ToolCallState) -> Value {
-    json!({
-        "type": "function_call",
-        "call_id": tool_call.call_id,
-        "name": tool_call.name,
-        "arguments": tool_call.arguments,
-    })
+/// 流式工具调用的输出项；added 时参数为空，id 在 added 与 done 之间保持一致。
+fn function_call_item(tool_call: &ToolCallState, hosted_tools: &HostedTools, completed: bool) -> Value {
+    let arguments = if completed { tool_call.arguments.as_str() } else { "" };
+    let mut item = hosted_tools.output_item(&tool_call.call_id, &tool_call.name, arguments);
+    item["id"] = Value::String(tool_call.item_id.clone());
+    item["status"] = Value::String(if completed { "completed" } else { "in_progress" }.to_string());
+    item
 }
 
-fn function_call_done_event(tool_call: &ToolCallState) -> Value {
-    json!({
-        "type": "response.output_item.done",
-        "item": function_call_item(tool_call)
-    })
+fn rate_limited_response(rejection: &RateLimitRejection) -> Response {
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(json!({
+            "error": {
+                "type": "rate_limit_exceeded",
+                "code": "rate_limit_exceeded",
+                "message": rejection.message(),
+            }
+        })),
+    )
+        .into_response();
+    response.headers_mut().insert(
+        axum::http::header::RETRY_AFTER,
+        axum::http::HeaderValue::from(rejection.retry_after_secs()),
+    );
+    response
+}
+
+fn observe_upstream_rate_limit(
+    rate_limiter: &RateLimiterRegistry,
+    host: &GatewayHost,
+    provider_id: &str,
+    status: reqwest::StatusCode,
+    headers: &reqwest::header::HeaderMap,
+) {
+    if let Some(pause) = rate_limiter.observe_upstream(provider_id, status.as_u16(), headers) {
+        emit_log(
+            host,
+            "warn",
+            format!(
+                "Codex Gateway 上游限流，暂停供应商 {} {} 秒",
+                provider_id,
+                pause.as_secs_f64().ceil()
+            ),
+        );
+    }
+}
+
+fn circuit_open_response(provider_id: &str, remaining: Duration) -> Response {
+    let mut response = (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(json!({
+            "error": {
+                "type": "provider_unavailable",
+                "code": "circuit_open",
+                "message": format!("供应商 {} 暂时不可用（熔断中），请稍后重试", provider_id),
+            }
+        })),
+    )
+        .into_response();
+    response.headers_mut().insert(
+        axum::http::header::RETRY_AFTER,
+        axum::http::HeaderValue::from(remaining.as_secs_f64().ceil() as u64),
+    );
+    response
+}
+
+fn record_upstream_health(
+    health: &HealthRegistry,
+    host: &GatewayHost,
+    route: &RouteState,
+    started_at: Instant,
+    upstream_result: &Result<reqwest::Response, reqwest::Error>,
+) {
+    let upstream_error = match upstream_result {
+        Ok(response) if gateway_health::is_failure_status(response.status().as_u16()) => {
+            Some(format!("status={}", response.status()))
+        }
+        Ok(_) => None,
+        Err(error) => Some(error.to_string()),
+    };
+
+    let previous_status = health.status(&route.provider_id);
+    if let Some(circuit_state) = health.record(
+        &route.provider_id,
+        &route.circuit_breaker,
+        started_at.elapsed(),
+        upstream_error,
+    ) {
+        log_circuit_transition(host, &route.provider_id, circuit_state);
+    } else if health.status(&route.provider_id) != previous_status {
+        host.health_changed();
+    }
+}
+
+fn log_circuit_transition(host: &GatewayHost, provider_id: &str, circuit_state: CircuitState) {
+    let message = match circuit_state {
+        CircuitState::Open => format!("Codex Gateway 供应商 {} 连续失败，已熔断", provider_id),
+        CircuitState::HalfOpen => format!("Codex Gateway 供应商 {} 探测成功，进入半开状态", provider_id),
+        CircuitState::Closed => format!("Codex Gateway 供应商 {} 已恢复", provider_id),
+    };
+    emit_log(host, "warn", message);
+    host.health_changed();
+}
+
+fn log_retry_attempt(host: &GatewayHost, attempt: &RetryAttempt, upstream_url: &str) {
+    emit_log(
+        host,
+        "warn",
+        format!(
+            "Codex Gateway 上游瞬时失败，{}ms 后第 {}/{} 次重试: {} url={}",
+            attempt.delay.as_millis(),
+            attempt.attempt,
+            attempt.max_retries,
+            attempt.reason,
+            upstream_url
+        ),
+    );
+}
+
+fn log_key_failover(host: &GatewayHost, provider_id: &str, failover: &KeyFailover) {
+    emit_log(
+        host,
+        "warn",
+        format!(
+            "Codex Gateway 上游拒绝密钥 {}（status={}），换用供应商 {} 的下一个密钥",
+            failover.label, failover.status, provider_id
+        ),
+    );
 }
 
 fn response_failed_event(response_id: &str, message: impl Into<String>) -> Value {
@@ -2010,22 +3312,21 @@ fn response_failed_event(response_id: &str, message: impl Into<String>) -> Value
     })
 }
 
-fn stream_output_items(reasoning_id: &str, message_id: &str, stream_state: &StreamState) -> Vec<Value> {
-    let mut output_items = Vec::new();
-
-    if stream_state.reasoning_started {
-        output_items.push(reasoning_item(reasoning_id, &stream_state.reasoning_text, "completed"));
-    }
-
-    if stream_state.message_started {
-        output_items.push(assistant_message_item(message_id, &stream_state.message_text));
-    }
-
-    for tool_call in stream_state.tool_calls.values() {
-        output_items.push(function_call_item(tool_call));
-    }
-
-    output_items
+fn stream_output_items(
+    reasoning_id: &str,
+    message_id: &str,
+    stream_state: &StreamState,
+    hosted_tools: &HostedTools,
+) -> Vec<Value> {
+    stream_state
+        .output_order
+        .iter()
+        .map(|item| match item {
+            StreamItem::Reasoning => reasoning_item(reasoning_id, &stream_state.reasoning_text, "completed"),
+            StreamItem::Message => assistant_message_item(message_id, &stream_state.message_text),
+            StreamItem::ToolCall(position) => function_call_item(&stream_state.tool_calls[*position], hosted_tools, true),
+        })
+        .collect()
 }
 
 fn response_completed_event(
@@ -2097,19 +3398,41 @@ impl UsageTotals {
                 }
             });
 
+        // OpenAI / Qwen 放在 *_tokens_details 中，DeepSeek 用 prompt_cache_hit_tokens，Kimi 直接给 cached_tokens
+        let cached_input_tokens = usage
+            .and_then(|value| {
+                usage_detail(value, &["prompt_tokens_details", "input_tokens_details"], "cached_tokens")
+                    .or_else(|| usage_value(value, &["prompt_cache_hit_tokens", "cached_tokens", "cache_read_input_tokens"]))
+            })
+            .unwrap_or(0);
+        let reasoning_tokens = usage
+            .and_then(|value| {
+                usage_detail(value, &["completion_tokens_details", "output_tokens_details"], "reasoning_tokens")
+                    .or_else(|| usage_value(value, &["reasoning_tokens"]))
+            })
+            .unwrap_or(0);
+
         Self {
             input_tokens,
             output_tokens,
             total_tokens,
+            cached_input_tokens,
+            reasoning_tokens,
         }
     }
 
+    /// 上游未报告的 token 数为 None
+    fn token_counts(&self) -> (Option<u64>, Option<u64>) {
+        let positive = |tokens: i64| u64::try_from(tokens).ok().filter(|tokens| *tokens > 0);
+        (positive(self.input_tokens), positive(self.output_tokens))
+    }
+
     fn as_json(&self) -> Value {
         json!({
             "input_tokens": self.input_tokens,
-            "input_tokens_details": null,
+            "input_tokens_details": { "cached_tokens": self.cached_input_tokens },
             "output_tokens": self.output_tokens,
-            "output_tokens_details": null,
+            "output_tokens_details": { "reasoning_tokens": self.reasoning_tokens },
             "total_tokens": self.total_tokens,
         })
     }
@@ -2120,6 +3443,12 @@ fn usage_value(usage: &Value, keys: &[&str]) -> Option<i64> {
         .find_map(|key| usage.get(*key).and_then(Value::as_i64))
 }
 
+fn usage_detail(usage: &Value, parents: &[&str], key: &str) -> Option<i64> {
+    parents
+        .iter()
+        .find_map(|parent| usage.get(*parent)?.get(key)?.as_i64())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2131,8 +3460,18 @@ mod tests {
             provider_name: "MiMo".to_string(),
             target_base_url: "https://token-plan-sgp.xiaomimimo.com/v1".to_string(),
             target_model_name: "mimo-v2-pro".to_string(),
-            api_key: "tp-test".to_string(),
+            api_keys: KeyPool::new("tp-test", &Default::default(), str::to_string),
             adapter: ProviderAdapter::Default,
+            rate_limit: RateLimitConfig::default(),
+            retry: RetryPolicy::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            stream: StreamPolicy::default(),
+            defaults: RequestDefaults::default(),
+            reasoning: ReasoningMapping::default(),
+            structured_output: StructuredOutputMode::default(),
+            capabilities: InputCapabilities::default(),
+            context_window: ContextWindow::default(),
+            transform: TransformRules::default(),
         }
     }
 
@@ -2143,8 +3482,18 @@ mod tests {
             provider_name: "MiniMax".to_string(),
             target_base_url: "https://api.minimaxi.com/v1".to_string(),
             target_model_name: "MiniMax-Text-01".to_string(),
-            api_key: "mm-test".to_string(),
+            api_keys: KeyPool::new("mm-test", &Default::default(), str::to_string),
             adapter: ProviderAdapter::MiniMax,
+            rate_limit: RateLimitConfig::default(),
+            retry: RetryPolicy::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            stream: StreamPolicy::default(),
+            defaults: RequestDefaults::default(),
+            reasoning: ReasoningMapping::default(),
+            structured_output: StructuredOutputMode::default(),
+            capabilities: InputCapabilities::default(),
+            context_window: ContextWindow::default(),
+            transform: TransformRules::default(),
         }
     }
 
@@ -2155,8 +3504,18 @@ mod tests {
             provider_name: "DeepSeek".to_string(),
             target_base_url: "https://api.deepseek.com/v1".to_string(),
             target_model_name: "deepseek-chat".to_string(),
-            api_key: "ds-test".to_string(),
+            api_keys: KeyPool::new("ds-test", &Default::default(), str::to_string),
             adapter: ProviderAdapter::DeepSeek,
+            rate_limit: RateLimitConfig::default(),
+            retry: RetryPolicy::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            stream: StreamPolicy::default(),
+            defaults: RequestDefaults::default(),
+            reasoning: ReasoningMapping::default(),
+            structured_output: StructuredOutputMode::default(),
+            capabilities: InputCapabilities::default(),
+            context_window: ContextWindow::default(),
+            transform: TransformRules::default(),
         }
     }
 
@@ -2234,7 +3593,7 @@ mod tests {
             }]
         });
 
-        let response = build_non_stream_response(&upstream, &test_route());
+        let response = build_non_stream_response(&upstream, &test_route(), &HostedTools::default());
         let output = response.get("output").and_then(Value::as_array).unwrap();
 
         assert_eq!(response.get("status").and_then(Value::as_str), Some("completed"));
@@ -2255,7 +3614,7 @@ mod tests {
             }]
         });
 
-        let response = build_non_stream_response(&upstream, &test_route());
+        let response = build_non_stream_response(&upstream, &test_route(), &HostedTools::default());
         let output = response.get("output").and_then(Value::as_array).unwrap();
 
         assert_eq!(output.len(), 2);
@@ -2275,7 +3634,7 @@ mod tests {
             }]
         });
 
-        let response = build_non_stream_response(&upstream, &minimax_test_route());
+        let response = build_non_stream_response(&upstream, &minimax_test_route(), &HostedTools::default());
         let output = response.get("output").and_then(Value::as_array).unwrap();
 
         assert_eq!(output.len(), 2);
@@ -2284,11 +3643,59 @@ mod tests {
         assert_eq!(output[1]["content"][0]["text"].as_str(), Some("Hello there"));
     }
 
+    #[test]
+    fn passthrough_chat_request_with_minimax_adapter_replaces_model_and_merges_system() {
+        let request = json!({
+            "model": "gpt-4o",
+            "stream": true,
+            "temperature": 0.2,
+            "messages": [
+                { "role": "system", "content": "base prompt" },
+                { "role": "developer", "content": "be brief" },
+                { "role": "user", "content": "hello" }
+            ]
+        });
+
+        let chat_request = build_passthrough_chat_request(&request, &minimax_test_route()).unwrap();
+
+        assert_eq!(chat_request["model"], "MiniMax-Text-01");
+        assert_eq!(chat_request["temperature"], 0.2);
+        assert_eq!(chat_request["messages"].as_array().unwrap().len(), 2);
+        assert_eq!(chat_request["messages"][0]["content"], "base prompt\n\nbe brief");
+        assert_eq!(chat_request["messages"][1]["role"], "user");
+        assert!(build_passthrough_chat_request(&json!({ "messages": [] }), &test_route()).is_err());
+    }
+
+    #[test]
+    fn sanitize_chat_chunks_with_minimax_adapter_moves_think_block_to_reasoning_content() {
+        let adapter = ProviderAdapter::MiniMax;
+        let mut stream_state = StreamState::default();
+        let mut chunks = [
+            json!({ "choices": [{ "index": 0, "delta": { "content": "<think>plan</th" } }] }),
+            json!({ "choices": [{ "index": 0, "delta": { "content": "ink>\n\nAnswer" } }] }),
+        ];
+        for chunk in &mut chunks {
+            sanitize_chat_chunk(chunk, &adapter, &mut stream_state);
+        }
+
+        assert_eq!(chunks[0]["choices"][0]["delta"]["content"], "");
+        assert_eq!(chunks[0]["choices"][0]["delta"]["reasoning_content"], "plan");
+        assert_eq!(chunks[1]["choices"][0]["delta"]["content"], "Answer");
+        assert!(chunks[1]["choices"][0]["delta"].get("reasoning_content").is_none());
+
+        let response = sanitize_chat_completion(
+            &json!({ "choices": [{ "message": { "role": "assistant", "content": "<think>plan</think>Answer" } }] }),
+            &adapter,
+        );
+        assert_eq!(response["choices"][0]["message"]["content"], "Answer");
+        assert_eq!(response["choices"][0]["message"]["reasoning_content"], "plan");
+    }
+
     #[test]
     fn apply_tool_call_deltas_accumulates_arguments() {
-        let mut tool_calls = BTreeMap::new();
-        apply_tool_call_deltas(
-            &mut tool_calls,
+        let mut stream_state = StreamState::default();
+        let events = apply_tool_call_deltas(
+            &mut stream_state,
             &[
                 json!({
                     "index": 0,
@@ -2301,12 +3708,87 @@ mod tests {
                 }),
             ],
             "resp-1",
+            &HostedTools::default(),
         );
 
-        let tool_call = tool_calls.get(&0).unwrap();
+        let tool_call = &stream_state.tool_calls[0];
         assert_eq!(tool_call.call_id, "call-1");
         assert_eq!(tool_call.name, "search");
         assert_eq!(tool_call.arguments, "{\"q\":\"hello\"}");
+        let kinds: Vec<_> = events.iter().map(|event| event["type"].as_str().unwrap()).collect();
+        assert_eq!(
+            kinds,
+            [
+                "response.output_item.added",
+                "response.function_call_arguments.delta",
+                "response.function_call_arguments.delta"
+            ]
+        );
+        assert_eq!(events[0]["item"]["id"], json!(tool_call.item_id));
+        assert_eq!(events[2]["delta"], json!("\"hello\"}"));
+    }
+
+    #[test]
+    fn apply_tool_call_deltas_splits_calls_that_reuse_index_zero() {
+        let mut stream_state = StreamState::default();
+        stream_state.start_message();
+        let hosted_tools = HostedTools::default();
+        let mut events = Vec::new();
+        for deltas in [
+            json!([{ "index": 0, "function": { "name": "read", "arguments": "{\"path\":\"a\"}" } }]),
+            json!([{ "index": 0, "function": { "name": "read", "arguments": "{\"path\":\"b\"}" } }]),
+            json!([{ "index": 0, "id": "call-x", "function": { "name": "list", "arguments": "{}" } }]),
+            json!([{ "index": 0, "id": "call-y", "function": { "name": "list", "arguments": "{}" } }]),
+        ] {
+            events.extend(apply_tool_call_deltas(
+                &mut stream_state,
+                deltas.as_array().unwrap(),
+                "resp-1",
+                &hosted_tools,
+            ));
+        }
+
+        let calls: Vec<_> = stream_state
+            .tool_calls
+            .iter()
+            .map(|tool_call| (tool_call.call_id.as_str(), tool_call.arguments.as_str()))
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                ("call_resp-1_0", "{\"path\":\"a\"}"),
+                ("call_resp-1_1", "{\"path\":\"b\"}"),
+                ("call-x", "{}"),
+                ("call-y", "{}")
+            ]
+        );
+        let done_indexes: Vec<_> = events
+            .iter()
+            .filter(|event| event["type"] == "response.output_item.done")
+            .map(|event| event["output_index"].as_u64().unwrap())
+            .collect();
+        assert_eq!(done_indexes, [1, 2, 3]);
+
+        let output = stream_output_items("rsn-1", "msg-1", &stream_state, &hosted_tools);
+        assert_eq!(output.len(), 5);
+        assert_eq!(output[0]["type"], json!("message"));
+        assert_eq!(output[4]["call_id"], json!("call-y"));
+    }
+
+    #[test]
+    fn resume_chat_request_continues_from_emitted_text_when_supported() {
+        let chat_request = json!({ "messages": [{ "role": "user", "content": "hi" }] });
+
+        let resumed = resume_chat_request(&chat_request, &ProviderAdapter::DeepSeek, "Hello").unwrap();
+        assert_eq!(
+            resumed["messages"][1],
+            json!({ "role": "assistant", "content": "Hello", "prefix": true })
+        );
+        assert_eq!(
+            resume_chat_request(&chat_request, &ProviderAdapter::Default, ""),
+            Some(chat_request.clone())
+        );
+        assert_eq!(resume_chat_request(&chat_request, &ProviderAdapter::Default, "Hello"), None);
     }
 
     #[test]
@@ -2327,30 +3809,28 @@ mod tests {
     fn stream_completed_event_contains_terminal_response_shape() {
         let mut stream_state = StreamState {
             reasoning_text: "thinking".to_string(),
-            reasoning_started: true,
             message_text: "done".to_string(),
-            message_started: true,
-            tool_calls: BTreeMap::new(),
             ..Default::default()
         };
-        stream_state.tool_calls.insert(
-            0,
-            ToolCallState {
-                call_id: "call-1".to_string(),
-                name: "search".to_string(),
-                arguments: "{\"q\":\"hello\"}".to_string(),
-            },
+        stream_state.start_reasoning();
+        stream_state.start_message();
+        apply_tool_call_deltas(
+            &mut stream_state,
+            &[json!({ "index": 0, "id": "call-1", "function": { "name": "search", "arguments": "{\"q\":\"hello\"}" } })],
+            "resp-1",
+            &HostedTools::default(),
         );
 
         let usage = UsageTotals {
             input_tokens: 11,
             output_tokens: 7,
             total_tokens: 18,
+            ..Default::default()
         };
         let event = response_completed_event(
             "resp-1",
             "mimo-v2-pro",
-            stream_output_items("rsn-1", "msg-1", &stream_state),
+            stream_output_items("rsn-1", "msg-1", &stream_state, &HostedTools::default()),
             &usage,
         );
         let response = &event["response"];
@@ -2422,15 +3902,15 @@ mod tests {
 
     #[test]
     fn stream_output_items_include_reasoning_before_message() {
-        let stream_state = StreamState {
+        let mut stream_state = StreamState {
             reasoning_text: "internal reasoning".to_string(),
-            reasoning_started: true,
             message_text: "Hello there".to_string(),
-            message_started: true,
             ..Default::default()
         };
+        stream_state.start_reasoning();
+        stream_state.start_message();
 
-        let output = stream_output_items("rsn-1", "msg-1", &stream_state);
+        let output = stream_output_items("rsn-1", "msg-1", &stream_state, &HostedTools::default());
 
         assert_eq!(output.len(), 2);
         assert_eq!(output[0]["type"].as_str(), Some("reasoning"));
@@ -2458,6 +3938,35 @@ mod tests {
         assert_eq!(usage.total_tokens, 18);
     }
 
+    #[test]
+    fn usage_totals_report_cached_and_reasoning_tokens_from_vendor_fields() {
+        let openai = UsageTotals::from_value(Some(&json!({
+            "prompt_tokens": 100,
+            "completion_tokens": 40,
+            "prompt_tokens_details": { "cached_tokens": 64 },
+            "completion_tokens_details": { "reasoning_tokens": 25 },
+        })));
+        assert_eq!(
+            openai.as_json(),
+            json!({
+                "input_tokens": 100,
+                "input_tokens_details": { "cached_tokens": 64 },
+                "output_tokens": 40,
+                "output_tokens_details": { "reasoning_tokens": 25 },
+                "total_tokens": 140,
+            })
+        );
+
+        let deepseek = UsageTotals::from_value(Some(&json!({
+            "prompt_tokens": 100,
+            "completion_tokens": 40,
+            "prompt_cache_hit_tokens": 80,
+            "prompt_cache_miss_tokens": 20,
+        })));
+        assert_eq!(deepseek.cached_input_tokens, 80);
+        assert_eq!(deepseek.reasoning_tokens, 0);
+    }
+
     #[test]
     fn provider_adapter_detects_minimax_from_url() {
         assert_eq!(
@@ -2480,6 +3989,76 @@ mod tests {
             ProviderAdapter::from_upstream_url("https://api.deepseek.com/v1"),
             ProviderAdapter::DeepSeek
         );
+        assert_eq!(
+            ProviderAdapter::from_upstream_url("https://dashscope.aliyuncs.com/compatible-mode/v1"),
+            ProviderAdapter::Qwen
+        );
+    }
+
+    #[test]
+    fn build_chat_request_maps_images_files_and_tool_screenshots() {
+        let body = json!({
+            "input": [
+                {
+                    "type": "message",
+                    "role": "user",
+                    "content": [
+                        { "type": "input_text", "text": "what is this?" },
+                        { "type": "input_image", "image_url": "data:image/png;base64,iVBORw0K", "detail": "high" },
+                        { "type": "input_file", "filename": "notes.md", "file_data": "data:text/markdown;base64,IyBUb2Rv" },
+                        { "type": "input_file", "filename": "spec.pdf", "file_data": "data:application/pdf;base64,JVBERi0x" }
+                    ]
+                },
+                { "type": "function_call", "call_id": "call_1", "name": "screenshot", "arguments": "{}" },
+                {
+                    "type": "function_call_output",
+                    "call_id": "call_1",
+                    "output": [
+                        { "type": "input_text", "text": "captured" },
+                        { "type": "input_image", "image_url": "https://example.com/shot.png" }
+                    ]
+                }
+            ]
+        });
+
+        let request = build_chat_request(&body, &test_route()).unwrap();
+        let messages = request["messages"].as_array().unwrap();
+
+        let content = messages[0]["content"].as_array().unwrap();
+        assert_eq!(
+            content[1],
+            json!({ "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBORw0K", "detail": "high" } })
+        );
+        assert_eq!(content[2]["text"], json!("<file name=\"notes.md\">\n# Todo\n</file>"));
+        assert_eq!(content[3]["file"]["filename"], json!("spec.pdf"));
+        assert_eq!(messages[2]["role"], json!("tool"));
+        assert_eq!(messages[2]["content"], json!("captured"));
+        assert_eq!(messages[3]["role"], json!("user"));
+        assert_eq!(messages[3]["content"][1]["image_url"]["url"], json!("https://example.com/shot.png"));
+
+        let error = build_chat_request(&body, &deepseek_test_route()).unwrap_err();
+        assert!(error.contains("不支持图片输入"));
+    }
+
+    #[test]
+    fn build_chat_request_maps_reasoning_effort_per_adapter() {
+        let body = json!({
+            "input": "hi",
+            "reasoning": { "effort": "medium", "summary": "auto" }
+        });
+
+        let request = build_chat_request(&body, &test_route()).unwrap();
+        assert_eq!(request["reasoning_effort"], json!("medium"));
+
+        let request = build_chat_request(&body, &deepseek_test_route()).unwrap();
+        assert_eq!(request["thinking"], json!({ "type": "enabled" }));
+        assert!(request.get("reasoning_effort").is_none());
+
+        let mut route = test_route();
+        route.reasoning.style = Some(ReasoningStyle::EnableThinking);
+        let request = build_chat_request(&body, &route).unwrap();
+        assert_eq!(request["enable_thinking"], json!(true));
+        assert_eq!(request["thinking_budget"], json!(8192));
     }
 
     #[test]
@@ -0,0 +1,229 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Chat 上游中代表 Codex `local_shell` 的函数名
+pub const LOCAL_SHELL_FUNCTION: &str = "local_shell";
+
+/// Codex 内置工具在 Chat 上游中以函数工具模拟，返回时需要还原的类型。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostedTool {
+    /// 自由格式的 `custom` 工具（如 `apply_patch`），参数包装为 `{"input": "..."}`
+    Custom,
+    LocalShell,
+}
+
+/// 请求中声明的模拟工具，按函数名查找。
+#[derive(Debug, Clone, Default)]
+pub struct HostedTools {
+    tools: HashMap<String, HostedTool>,
+}
+
+impl HostedTools {
+    pub fn from_request(body: &Value) -> Self {
+        let tools = body
+            .get("tools")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|tool| match tool.get("type").and_then(Value::as_str)? {
+                "custom" => Some((tool.get("name")?.as_str()?.to_string(), HostedTool::Custom)),
+                "local_shell" => Some((LOCAL_SHELL_FUNCTION.to_string(), HostedTool::LocalShell)),
+                _ => None,
+            })
+            .collect();
+        Self { tools }
+    }
+
+    pub fn is_hosted(&self, name: &str) -> bool {
+        self.tools.contains_key(name)
+    }
+
+    /// 把上游的函数调用还原为 Responses 输出项：`function_call`、`custom_tool_call` 或 `local_shell_call`。
+    pub fn output_item(&self, call_id: &str, name: &str, arguments: &str) -> Value {
+        match self.tools.get(name) {
+            Some(HostedTool::Custom) => json!({
+                "type": "custom_tool_call",
+                "id": format!("ctc_{}", Uuid::new_v4().simple()),
+                "call_id": call_id,
+                "name": name,
+                "input": custom_tool_input(arguments),
+                "status": "completed",
+            }),
+            Some(HostedTool::LocalShell) => {
+                let arguments = serde_json::from_str::<Value>(arguments).unwrap_or_else(|_| json!({}));
+                let mut action = json!({
+                    "type": "exec",
+                    "command": arguments.get("command").cloned().unwrap_or_else(|| json!([])),
+                    "env": {},
+                });
+                for (from, to) in [("workdir", "working_directory"), ("timeout_ms", "timeout_ms")] {
+                    if let Some(value) = arguments.get(from).filter(|value| !value.is_null()) {
+                        action[to] = value.clone();
+                    }
+                }
+                json!({
+                    "type": "local_shell_call",
+                    "id": format!("lsh_{}", Uuid::new_v4().simple()),
+                    "call_id": call_id,
+                    "status": "completed",
+                    "action": action,
+                })
+            }
+            None => json!({
+                "type": "function_call",
+                "call_id": call_id,
+                "name": name,
+                "arguments": arguments,
+            }),
+        }
+    }
+}
+
+/// `custom` 与 `local_shell` 工具转为等价的 Chat 函数工具，其他类型返回 `None`。
+pub fn hosted_tool_definition(tool: &Value) -> Option<Value> {
+    match tool.get("type").and_then(Value::as_str)? {
+        "custom" => {
+            let name = tool.get("name").and_then(Value::as_str)?;
+            let mut description = tool
+                .get("description")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            let format = tool.get("format");
+            if format.and_then(|format| format.get("type")).and_then(Value::as_str) == Some("grammar") {
+                let syntax = format
+                    .and_then(|format| format.get("syntax"))
+                    .and_then(Value::as_str)
+                    .unwrap_or("lark");
+                let definition = format
+                    .and_then(|format| format.get("definition"))
+                    .and_then(Value::as_str)
+                    .unwrap_or_default();
+                description.push_str(&format!(
+                    "\n\nThe `input` argument must be raw text matching this {syntax} grammar:\n{definition}"
+                ));
+            }
+            Some(json!({
+                "type": "function",
+                "function": {
+                    "name": name,
+                    "description": description.trim(),
+                    "parameters": {
+                        "type": "object",
+                        "properties": {
+                            "input": { "type": "string", "description": "Raw freeform input for the tool." }
+                        },
+                        "required": ["input"],
+                        "additionalProperties": false,
+                    }
+                }
+            }))
+        }
+        "local_shell" => Some(json!({
+            "type": "function",
+            "function": {
+                "name": LOCAL_SHELL_FUNCTION,
+                "description": "Runs a shell command on the user's machine and returns its output.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "command": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "The command and its arguments, e.g. [\"bash\", \"-lc\", \"ls\"]."
+                        },
+                        "workdir": { "type": "string", "description": "Working directory for the command." },
+                        "timeout_ms": { "type": "integer", "description": "Timeout in milliseconds." }
+                    },
+                    "required": ["command"],
+                    "additionalProperties": false,
+                }
+            }
+        })),
+        _ => None,
+    }
+}
+
+pub fn is_web_search_tool(tool: &Value) -> bool {
+    tool.get("type")
+        .and_then(Value::as_str)
+        .is_some_and(|tool_type| tool_type.starts_with("web_search"))
+}
+
+/// 历史中的 `local_shell_call` 转回函数调用参数。
+pub fn local_shell_call_arguments(item: &Value) -> String {
+    let action = item.get("action").cloned().unwrap_or_else(|| json!({}));
+    let mut arguments = json!({ "command": action.get("command").cloned().unwrap_or_else(|| json!([])) });
+    for (from, to) in [("working_directory", "workdir"), ("timeout_ms", "timeout_ms")] {
+        if let Some(value) = action.get(from).filter(|value| !value.is_null()) {
+            arguments[to] = value.clone();
+        }
+    }
+    arguments.to_string()
+}
+
+/// 模型可能直接返回原始文本而不是 `{"input": ...}`，两种都接受。
+fn custom_tool_input(arguments: &str) -> String {
+    match serde_json::from_str::<Value>(arguments) {
+        Ok(Value::Object(object)) => match object.get("input") {
+            Some(Value::String(input)) => input.clone(),
+            Some(input) => input.to_string(),
+            None => arguments.to_string(),
+        },
+        _ => arguments.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> Value {
+        json!({
+            "tools": [
+                { "type": "custom", "name": "apply_patch", "description": "Edit files.",
+                  "format": { "type": "grammar", "syntax": "lark", "definition": "start: \"*** Begin Patch\"" } },
+                { "type": "local_shell" },
+                { "type": "function", "name": "read_file", "parameters": { "type": "object" } }
+            ]
+        })
+    }
+
+    #[test]
+    fn custom_and_local_shell_become_function_tools() {
+        let tools = request()["tools"].as_array().unwrap().clone();
+
+        let apply_patch = hosted_tool_definition(&tools[0]).unwrap();
+        assert_eq!(apply_patch["function"]["name"], json!("apply_patch"));
+        assert_eq!(apply_patch["function"]["parameters"]["required"], json!(["input"]));
+        assert!(apply_patch["function"]["description"]
+            .as_str()
+            .unwrap()
+            .contains("*** Begin Patch"));
+
+        let shell = hosted_tool_definition(&tools[1]).unwrap();
+        assert_eq!(shell["function"]["name"], json!(LOCAL_SHELL_FUNCTION));
+        assert_eq!(hosted_tool_definition(&tools[2]), None);
+    }
+
+    #[test]
+    fn function_calls_map_back_to_hosted_items() {
+        let hosted = HostedTools::from_request(&request());
+
+        let patch = hosted.output_item("call_1", "apply_patch", r#"{"input":"*** Begin Patch\n*** End Patch"}"#);
+        assert_eq!(patch["type"], json!("custom_tool_call"));
+        assert_eq!(patch["input"], json!("*** Begin Patch\n*** End Patch"));
+
+        let shell = hosted.output_item("call_2", "local_shell", r#"{"command":["ls","-la"],"workdir":"/tmp"}"#);
+        assert_eq!(shell["type"], json!("local_shell_call"));
+        assert_eq!(shell["action"]["command"], json!(["ls", "-la"]));
+        assert_eq!(shell["action"]["working_directory"], json!("/tmp"));
+        assert_eq!(
+            serde_json::from_str::<Value>(&local_shell_call_arguments(&shell)).unwrap(),
+            json!({ "command": ["ls", "-la"], "workdir": "/tmp" })
+        );
+
+        assert_eq!(hosted.output_item("call_3", "read_file", "{}")["type"], json!("function_call"));
+    }
+}
@@ -1,5 +1,10 @@
 use crate::api_gateway;
 use crate::config;
+use crate::gateway_capture::{self, GatewayCaptureSummary};
+use crate::gateway_health::HealthSnapshot;
+use crate::gateway_keys::KeySnapshot;
+use crate::gateway_log::{self, GatewayKind, GatewayLogPage, GatewayLogQuery};
+use crate::gateway_replay::{self, ReplayPlan, ReplayPreview, ReplayRequest, ReplayResult};
 use crate::codex_config;
 use crate::codex_gateway;
 use crate::provider::{CodexProvider, Provider, ProviderPayload, ProviderType};
@@ -8,12 +13,23 @@ use crate::store::{AppConfig, AppMode, AppState};
 use std::collections::HashMap;
 use tauri::{AppHandle, Emitter, Manager, State};
 
-async fn sync_runtime_provider(_state: &AppState, provider: &Provider) -> Result<(), String> {
-    config::merge_claude_config(&provider.settings_config)?;
+async fn sync_runtime_provider(state: &AppState, provider: &Provider) -> Result<(), String> {
+    let (all_providers, gateway_port) = {
+        let config = state
+            .config
+            .lock()
+            .map_err(|e| format!("获取锁失败: {}", e))?;
+        (claude_providers(&config), config.api_gateway.enabled.then_some(config.api_gateway.port))
+    };
+    config::merge_claude_config(&api_gateway::claude_settings(provider, &all_providers, gateway_port)?)?;
 
     Ok(())
 }
 
+fn claude_providers(config: &AppConfig) -> Vec<Provider> {
+    config.providers.values().cloned().collect()
+}
+
 fn configured_gateway_provider(config: &AppConfig) -> Option<Provider> {
     config
         .api_gateway
@@ -64,6 +80,8 @@ fn build_codex_gateway_status_payload(
     config: &AppConfig,
     running: bool,
     active_route: Option<codex_gateway::GatewayRouteSnapshot>,
+    health: &HashMap<String, HealthSnapshot>,
+    keys: &HashMap<String, Vec<KeySnapshot>>,
 ) -> Result<serde_json::Value, String> {
     let configured_provider = configured_codex_gateway_provider(config);
     let target_provider_id = active_route
@@ -90,6 +108,15 @@ fn build_codex_gateway_status_payload(
                 .as_ref()
                 .map(|provider| provider.codex_config.model_name.clone())
         });
+    let request_defaults = active_route
+        .as_ref()
+        .map(|route| route.request_defaults.clone())
+        .or_else(|| {
+            configured_provider
+                .as_ref()
+                .map(|provider| provider.codex_config.defaults.clone())
+        })
+        .unwrap_or_default();
 
     Ok(serde_json::json!({
         "enabled": config.codex_gateway.enabled,
@@ -98,14 +125,18 @@ fn build_codex_gateway_status_payload(
         "localBaseUrl": codex_gateway::gateway_base_url(config.codex_gateway.port),
         "healthUrl": codex_gateway::health_url(config.codex_gateway.port),
         "diskLoggingEnabled": config.codex_gateway.disk_logging_enabled,
-        "logDirectory": codex_gateway::log_directory()?.to_string_lossy(),
+        "logDirectory": gateway_log::log_directory()?.to_string_lossy(),
+        "captureEnabled": config.codex_gateway.capture_enabled,
         "targetProviderId": target_provider_id,
         "targetProviderName": target_provider_name,
         "targetBaseUrl": target_base_url,
         "targetModelName": target_model_name,
+        "requestDefaults": request_defaults,
         "codexConfigPath": codex_config::get_codex_config_path()?.to_string_lossy(),
         "installedInCodexConfig": codex_config::has_local_gateway_provider()?,
         "providerKey": codex_config::LOCAL_GATEWAY_PROVIDER_KEY,
+        "providerHealth": provider_status_payload(health, config.codex_providers.keys()),
+        "providerKeys": provider_status_payload(keys, config.codex_providers.keys()),
     }))
 }
 
@@ -113,6 +144,8 @@ fn build_gateway_status_payload(
     config: &AppConfig,
     running: bool,
     active_route: Option<api_gateway::GatewayRouteSnapshot>,
+    health: &HashMap<String, HealthSnapshot>,
+    keys: &HashMap<String, Vec<KeySnapshot>>,
 ) -> Result<serde_json::Value, String> {
     let configured_provider = configured_gateway_provider(config);
     let target_provider_id = active_route
@@ -130,18 +163,39 @@ fn build_gateway_status_payload(
             .map(api_gateway::provider_target_base_url)
             .transpose()?,
     };
+    let request_defaults = active_route
+        .as_ref()
+        .map(|route| route.request_defaults.clone())
+        .or_else(|| configured_provider.as_ref().map(api_gateway::provider_request_defaults))
+        .unwrap_or_default();
 
     Ok(serde_json::json!({
         "enabled": config.api_gateway.enabled,
         "running": running,
         "port": config.api_gateway.port,
         "localBaseUrl": api_gateway::gateway_base_url(config.api_gateway.port),
+        "diskLoggingEnabled": config.api_gateway.disk_logging_enabled,
+        "logDirectory": gateway_log::log_directory()?.to_string_lossy(),
+        "captureEnabled": config.api_gateway.capture_enabled,
         "targetProviderId": target_provider_id,
         "targetProviderName": target_provider_name,
         "targetBaseUrl": target_base_url,
+        "requestDefaults": request_defaults,
+        "providerHealth": provider_status_payload(health, config.providers.keys()),
+        "providerKeys": provider_status_payload(keys, config.providers.keys()),
     }))
 }
 
+/// 只保留属于当前 Gateway 供应商的状态（健康状态与密钥池由两个 Gateway 共享）。
+fn provider_status_payload<'a, T: Clone>(
+    snapshots: &HashMap<String, T>,
+    provider_ids: impl Iterator<Item = &'a String>,
+) -> HashMap<String, T> {
+    provider_ids
+        .filter_map(|id| snapshots.get(id).map(|snapshot| (id.clone(), snapshot.clone())))
+        .collect()
+}
+
 #[tauri::command]
 pub async fn get_providers(
     state: State<'_, AppState>,
@@ -171,6 +225,12 @@ pub async fn get_current_codex_provider(state: State<'_, AppState>) -> Result<St
     Ok(config.current_codex.clone())
 }
 
+/// 启动时保险库中缺失的密钥引用，对应配置项需要重新填写
+#[tauri::command]
+pub async fn get_missing_secrets(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    Ok(state.missing_secrets.clone())
+}
+
 #[tauri::command]
 pub async fn add_provider(
     state: State<'_, AppState>,
@@ -434,13 +494,7 @@ pub async fn switch_provider(
     state.save()?;
 
     // 更新托盘菜单
-    if let Ok(new_menu) = crate::create_tray_menu(&app, state.inner()) {
-        if let Some(tray) = app.tray_by_id("main") {
-            if let Err(e) = tray.set_menu(Some(new_menu)) {
-                log::error!("更新托盘菜单失败: {}", e);
-            }
-        }
-    }
+    crate::refresh_tray_menu(&app);
 
     // 发射事件到前端（明确发送到主窗口）
     let event_data = serde_json::json!({
@@ -483,6 +537,7 @@ pub async fn switch_codex_provider(
     }
 
     state.save()?;
+    crate::refresh_tray_menu(&app);
 
     let event_data = serde_json::json!({
         "providerId": provider_id,
@@ -590,12 +645,14 @@ pub async fn get_api_gateway_status(
 ) -> Result<serde_json::Value, String> {
     let running = api_gateway::is_running(state.inner())?;
     let active_route = api_gateway::get_route_snapshot(state.inner()).await?;
+    let health = state.gateway_health.snapshots();
+    let keys = state.gateway_keys.snapshots();
     let config = state
         .config
         .lock()
         .map_err(|e| format!("获取锁失败: {}", e))?;
 
-    build_gateway_status_payload(&config, running, active_route)
+    build_gateway_status_payload(&config, running, active_route, &health, &keys)
 }
 
 #[tauri::command]
@@ -604,12 +661,14 @@ pub async fn get_codex_gateway_status(
 ) -> Result<serde_json::Value, String> {
     let running = codex_gateway::is_running(state.inner())?;
     let active_route = codex_gateway::get_route_snapshot(state.inner()).await?;
+    let health = state.gateway_health.snapshots();
+    let keys = state.gateway_keys.snapshots();
     let config = state
         .config
         .lock()
         .map_err(|e| format!("获取锁失败: {}", e))?;
 
-    build_codex_gateway_status_payload(&config, running, active_route)
+    build_codex_gateway_status_payload(&config, running, active_route, &health, &keys)
 }
 
 #[tauri::command]
@@ -617,14 +676,14 @@ pub async fn set_api_gateway_enabled(
     state: State<'_, AppState>,
     enabled: bool,
 ) -> Result<serde_json::Value, String> {
-    let (provider, port) = {
+    let (provider, port, all_providers) = {
         let config = state
             .config
             .lock()
             .map_err(|e| format!("获取锁失败: {}", e))?;
         let provider = config.providers.get(&config.current).cloned();
         let port = config.api_gateway.port;
-        (provider, port)
+        (provider, port, claude_providers(&config))
     };
 
     let provider = provider.ok_or("当前没有可用的供应商")?;
@@ -635,7 +694,11 @@ pub async fn set_api_gateway_enabled(
         api_gateway::stop(state.inner()).await?;
     }
 
-    config::merge_claude_config(&provider.settings_config)?;
+    config::merge_claude_config(&api_gateway::claude_settings(
+        &provider,
+        &all_providers,
+        enabled.then_some(port),
+    )?)?;
 
     {
         let mut config = state
@@ -709,6 +772,116 @@ pub async fn set_codex_gateway_disk_logging_enabled(
     get_codex_gateway_status(state).await
 }
 
+#[tauri::command]
+pub async fn set_api_gateway_disk_logging_enabled(
+    state: State<'_, AppState>,
+    enabled: bool,
+) -> Result<serde_json::Value, String> {
+    {
+        let mut config = state
+            .config
+            .lock()
+            .map_err(|e| format!("获取锁失败: {}", e))?;
+        config.api_gateway.disk_logging_enabled = enabled;
+    }
+
+    state.save()?;
+    get_api_gateway_status(state).await
+}
+
+#[tauri::command]
+pub async fn set_codex_gateway_capture_enabled(
+    state: State<'_, AppState>,
+    enabled: bool,
+) -> Result<serde_json::Value, String> {
+    {
+        let mut config = state
+            .config
+            .lock()
+            .map_err(|e| format!("获取锁失败: {}", e))?;
+        config.codex_gateway.capture_enabled = enabled;
+    }
+
+    state.save()?;
+    get_codex_gateway_status(state).await
+}
+
+#[tauri::command]
+pub async fn set_api_gateway_capture_enabled(
+    state: State<'_, AppState>,
+    enabled: bool,
+) -> Result<serde_json::Value, String> {
+    {
+        let mut config = state
+            .config
+            .lock()
+            .map_err(|e| format!("获取锁失败: {}", e))?;
+        config.api_gateway.capture_enabled = enabled;
+    }
+
+    state.save()?;
+    get_api_gateway_status(state).await
+}
+
+#[tauri::command]
+pub async fn list_gateway_captures(
+    gateway: Option<GatewayKind>,
+) -> Result<Vec<GatewayCaptureSummary>, String> {
+    gateway_capture::list(&gateway_capture::capture_directory()?, gateway)
+}
+
+/// 导出单个抓包到指定目录，返回导出文件路径。
+#[tauri::command]
+pub async fn export_gateway_capture(request_id: String, target_dir: String) -> Result<String, String> {
+    let path = gateway_capture::export(
+        &gateway_capture::capture_directory()?,
+        &request_id,
+        std::path::Path::new(&target_dir),
+    )?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub async fn query_gateway_logs(query: GatewayLogQuery) -> Result<GatewayLogPage, String> {
+    let log_dir = gateway_log::log_directory()?;
+    tokio::task::spawn_blocking(move || gateway_log::query(&log_dir, &query))
+        .await
+        .map_err(|e| format!("查询 Gateway 日志失败: {}", e))?
+}
+
+/// 将同一个请求体回放到多个供应商，返回并排对比的结果。
+#[tauri::command]
+pub async fn replay_gateway_request(
+    state: State<'_, AppState>,
+    request: ReplayRequest,
+) -> Result<Vec<ReplayResult>, String> {
+    let plan = replay_plan(&state, &request).await?;
+    gateway_replay::run(plan).await
+}
+
+/// 预览回放请求经模型重写、协议转换和改写规则后的上游请求，不实际发送。
+#[tauri::command]
+pub async fn preview_gateway_request(
+    state: State<'_, AppState>,
+    request: ReplayRequest,
+) -> Result<Vec<ReplayPreview>, String> {
+    let plan = replay_plan(&state, &request).await?;
+    Ok(gateway_replay::preview(&plan))
+}
+
+/// 先在锁外读取请求体，持有配置锁期间只复制目标供应商，密钥引用在锁外解析。
+async fn replay_plan(state: &AppState, request: &ReplayRequest) -> Result<ReplayPlan, String> {
+    let body = gateway_replay::load_body(request)?;
+    let targets = {
+        let config = state
+            .config
+            .lock()
+            .map_err(|e| format!("获取锁失败: {}", e))?;
+        gateway_replay::resolve_targets(&config, body.format(), &request.provider_ids)?
+    };
+    gateway_replay::prepare(body, targets).await
+}
+
 #[tauri::command]
 pub async fn install_codex_gateway_provider(
     state: State<'_, AppState>,
@@ -767,6 +940,7 @@ pub async fn open_config_folder() -> Result<(), String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::gateway_health::{CircuitBreakerConfig, HealthRegistry};
     use crate::store::ApiGatewayConfig;
     use serde_json::json;
 
@@ -803,11 +977,13 @@ mod tests {
                 enabled: true,
                 port: 3456,
                 target_provider_id: Some(provider_a.id.clone()),
+                ..Default::default()
             },
             codex_gateway: crate::store::CodexGatewayConfig::default(),
+            gateway_log: Default::default(),
         };
 
-        let payload = build_gateway_status_payload(&config, false, None).unwrap();
+        let payload = build_gateway_status_payload(&config, false, None, &HashMap::new(), &HashMap::new()).unwrap();
 
         assert_eq!(payload["targetProviderId"], json!(provider_a.id));
         assert_eq!(payload["targetProviderName"], json!(provider_a.name));
@@ -831,10 +1007,18 @@ mod tests {
                 enabled: true,
                 port: 3456,
                 target_provider_id: Some(provider_a.id.clone()),
+                ..Default::default()
             },
             codex_gateway: crate::store::CodexGatewayConfig::default(),
+            gateway_log: Default::default(),
         };
 
+        let health = HealthRegistry::default();
+        let circuit_breaker = CircuitBreakerConfig::default();
+        let latency = std::time::Duration::from_millis(120);
+        health.record(&provider_b.id, &circuit_breaker, latency, Some("status=529".to_string()));
+        health.record("codex-only", &circuit_breaker, latency, None);
+
         let payload = build_gateway_status_payload(
             &config,
             true,
@@ -842,13 +1026,23 @@ mod tests {
                 provider_id: provider_b.id.clone(),
                 provider_name: provider_b.name.clone(),
                 target_base_url: "https://runtime.example.com".to_string(),
+                request_defaults: crate::gateway_defaults::RequestDefaults {
+                    temperature: Some(0.2),
+                    ..Default::default()
+                },
             }),
+            &health.snapshots(),
+            &HashMap::new(),
         )
         .unwrap();
 
         assert_eq!(payload["targetProviderId"], json!(provider_b.id));
         assert_eq!(payload["targetProviderName"], json!(provider_b.name));
         assert_eq!(payload["targetBaseUrl"], json!("https://runtime.example.com"));
+        assert_eq!(payload["requestDefaults"], json!({ "temperature": 0.2 }));
+        assert_eq!(payload["providerHealth"][&provider_b.id]["status"], json!("degraded"));
+        assert_eq!(payload["providerHealth"][&provider_b.id]["circuitState"], json!("closed"));
+        assert!(payload["providerHealth"].get("codex-only").is_none());
     }
 }
 
@@ -1,7 +1,8 @@
+use crate::secret_vault::{self, SecretVault};
 use crate::store::AppConfig;
 use dirs;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// 获取应用配置目录
 pub fn get_app_config_dir() -> Result<PathBuf, String> {
@@ -51,38 +52,70 @@ pub fn claude_config_exists() -> bool {
     false
 }
 
-/// 加载应用配置
-pub fn load_config() -> Result<AppConfig, String> {
-    let config_path = get_app_config_path()?;
+/// 加载应用配置，同时返回保险库中缺失的密钥引用
+pub fn load_config() -> Result<(AppConfig, Vec<String>), String> {
+    load_config_with_missing(&get_app_config_path()?)
+}
+
+/// 从指定路径加载应用配置，文件不存在时返回默认配置
+pub fn load_config_from(config_path: &Path) -> Result<AppConfig, String> {
+    load_config_with_missing(config_path).map(|(config, _)| config)
+}
 
+/// 配置中的 `vault:` 引用从同目录的加密保险库解密回明文；仍含明文密钥的旧配置会立即迁移到保险库。
+///
+/// 保险库中缺失的引用保持 `vault:` 原文，其名称随配置一起返回。
+fn load_config_with_missing(config_path: &Path) -> Result<(AppConfig, Vec<String>), String> {
     if !config_path.exists() {
-        return Ok(AppConfig::default());
+        return Ok((AppConfig::default(), Vec::new()));
     }
 
     let content =
-        fs::read_to_string(&config_path).map_err(|e| format!("读取配置文件失败: {}", e))?;
+        fs::read_to_string(config_path).map_err(|e| format!("读取配置文件失败: {}", e))?;
 
-    let config: AppConfig =
+    let mut value: serde_json::Value =
         serde_json::from_str(&content).map_err(|e| format!("解析配置文件失败: {}", e))?;
+    let has_plaintext_secrets = !secret_vault::extract_secrets(&mut value.clone()).is_empty();
+    let secrets = SecretVault::for_config(config_path).read()?;
+    let missing = secret_vault::inject_secrets(&mut value, &secrets);
+    if !missing.is_empty() {
+        log::warn!("保险库中缺少以下密钥，请重新填写: {}", missing.join(", "));
+    }
 
-    Ok(config)
+    let config: AppConfig =
+        serde_json::from_value(value).map_err(|e| format!("解析配置文件失败: {}", e))?;
+
+    if has_plaintext_secrets {
+        if let Err(error) = save_config_to(config_path, &config) {
+            log::warn!("迁移明文密钥到保险库失败: {}", error);
+        }
+    }
+
+    Ok((config, missing))
 }
 
 /// 保存应用配置
 pub fn save_config(config: &AppConfig) -> Result<(), String> {
-    let config_path = get_app_config_path()?;
+    save_config_to(&get_app_config_path()?, config)
+}
 
-    // 确保父目录存在
-    if let Some(parent) = config_path.parent() {
-        if !parent.exists() {
-            fs::create_dir_all(parent).map_err(|e| format!("创建配置目录失败: {}", e))?;
-        }
-    }
+/// 密钥加密写入同目录的保险库，`config.json` 中只保留引用；两个文件权限均为 0600
+pub fn save_config_to(config_path: &Path, config: &AppConfig) -> Result<(), String> {
+    let mut value = serde_json::to_value(config).map_err(|e| format!("序列化配置失败: {}", e))?;
+    let secrets = secret_vault::extract_secrets(&mut value);
+
+    let vault = SecretVault::for_config(config_path);
+    // 无法解密现有保险库时（如缺少口令）拒绝覆盖，避免丢失其中的密钥
+    vault
+        .read()
+        .map_err(|e| format!("无法打开现有保险库，已取消保存: {}", e))?;
+    vault.write(&secrets)?;
 
     let content =
-        serde_json::to_string_pretty(config).map_err(|e| format!("序列化配置失败: {}", e))?;
+        serde_json::to_string_pretty(&value).map_err(|e| format!("序列化配置失败: {}", e))?;
 
-    fs::write(&config_path, content).map_err(|e| format!("写入配置文件失败: {}", e))?;
+    secret_vault::write_private_file(config_path, content.as_bytes())
+        .map_err(|e| format!("写入配置文件失败: {}", e))?;
 
     Ok(())
 }
@@ -96,7 +129,7 @@ pub fn read_claude_config() -> Result<serde_json::Value, String> {
     }
 
     let content =
-        fs::read_to_string(&config_path).map_err(|e| format!("读取 Claude 配置文件失败: {}", e))?;
+        fs::read_to_string(config_path).map_err(|e| format!("读取 Claude 配置文件失败: {}", e))?;
 
     let config: serde_json::Value =
         serde_json::from_str(&content).map_err(|e| format!("解析 Claude 配置文件失败: {}", e))?;
@@ -105,6 +138,8 @@ pub fn read_claude_config() -> Result<serde_json::Value, String> {
 }
 
 /// 合并 Claude 配置文件 - 只覆盖 provider 中指定的键
+///
+/// `provider_config` 应来自 `api_gateway::claude_settings`，密钥引用已按模式处理。
 pub fn merge_claude_config(provider_config: &serde_json::Value) -> Result<(), String> {
     let config_path = get_claude_config_path()?;
 
@@ -170,3 +205,47 @@ pub fn write_claude_config(config: &serde_json::Value) -> Result<(), String> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn load_migrates_plaintext_secrets_into_vault() {
+        let dir = std::env::temp_dir().join(format!("switch-cc-config-{}", uuid::Uuid::new_v4().simple()));
+        fs::create_dir_all(&dir).unwrap();
+        let config_path = dir.join("config.json");
+        let plaintext = json!({
+            "providers": {
+                "relay": {
+                    "id": "relay",
+                    "name": "Relay",
+                    "settingsConfig": { "env": { "ANTHROPIC_AUTH_TOKEN": "sk-plaintext-token" } },
+                    "websiteUrl": null,
+                    "category": null,
+                    "createdAt": null
+                }
+            },
+            "current": "relay",
+            "app_mode": "Main"
+        });
+        fs::write(&config_path, plaintext.to_string()).unwrap();
+
+        let config = load_config_from(&config_path).unwrap();
+
+        assert_eq!(
+            config.providers["relay"].settings_config["env"]["ANTHROPIC_AUTH_TOKEN"],
+            json!("sk-plaintext-token")
+        );
+        let stored = fs::read_to_string(&config_path).unwrap();
+        assert!(!stored.contains("sk-plaintext-token"));
+        assert!(stored.contains("vault:providers/relay/env/ANTHROPIC_AUTH_TOKEN"));
+        let reloaded = load_config_from(&config_path).unwrap();
+        assert_eq!(
+            reloaded.providers["relay"].settings_config["env"]["ANTHROPIC_AUTH_TOKEN"],
+            json!("sk-plaintext-token")
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,611 @@
+use crate::gateway_host::GatewayHost;
+use crate::gateway_log::GatewayKind;
+use crate::secret_vault;
+use reqwest::header::HeaderMap;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::SystemTime;
+
+/// 最多保留的抓包文件数，超出后删除最旧的
+const MAX_CAPTURES: usize = 50;
+/// 单个请求/响应体最多保存的字节数
+const MAX_CAPTURED_BODY_BYTES: usize = 2 * 1024 * 1024;
+const REDACTED: &str = "[REDACTED]";
+/// 过短的密钥做子串替换容易误伤正常内容
+const MIN_SECRET_LEN: usize = 6;
+/// 按名称脱敏的请求头与 query 参数（小写并去掉 `-`/`_` 后比较）
+const SENSITIVE_NAMES: &[&str] = &[
+    "authorization",
+    "proxyauthorization",
+    "xapikey",
+    "apikey",
+    "xgoogapikey",
+    "cookie",
+    "setcookie",
+    "key",
+    "accesstoken",
+    "token",
+];
+
+/// 一次转发的完整抓包，用于排查供应商协议兼容问题。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GatewayCapture {
+    pub request_id: String,
+    pub gateway: GatewayKind,
+    /// RFC3339
+    pub started_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// 客户端发给 Gateway 的原始请求
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inbound: Option<CapturedMessage>,
+    /// 经过模型重写/协议转换后发往上游的请求
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_request: Option<CapturedMessage>,
+    /// 上游原始响应，流式响应保存为原始 SSE 文本
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream_response: Option<CapturedMessage>,
+    /// Gateway 发给客户端的事件；API Gateway 原样透传，不单独记录
+    #[serde(default)]
+    pub downstream_events: Vec<CapturedEvent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapturedMessage {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// 能解析为 JSON 时保存为 JSON，否则保存为文本
+    #[serde(default)]
+    pub body: Value,
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapturedEvent {
+    pub event: String,
+    pub data: Value,
+}
+
+/// 抓包列表中的一项。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GatewayCaptureSummary {
+    pub request_id: String,
+    pub gateway: GatewayKind,
+    pub started_at: String,
+    pub provider_id: Option<String>,
+    pub model: Option<String>,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
+
+/// 落盘前的脱敏规则：内置的敏感名称，加上当前路由配置的鉴权头/参数名和密钥值。
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    names: Vec<String>,
+    secrets: Vec<String>,
+}
+
+impl Redactor {
+    pub fn add_name(&mut self, name: &str) {
+        let name = normalize_name(name);
+        if !name.is_empty() && !self.names.contains(&name) {
+            self.names.push(name);
+        }
+    }
+
+    pub fn add_secret(&mut self, secret: &str) {
+        let secret = secret.trim();
+        if secret.len() >= MIN_SECRET_LEN && !self.secrets.iter().any(|known| known == secret) {
+            self.secrets.push(secret.to_string());
+            // 先替换较长的密钥，避免较短的密钥是其子串时留下残片
+            self.secrets.sort_by_key(|known| std::cmp::Reverse(known.len()));
+        }
+    }
+
+    fn is_sensitive_name(&self, name: &str) -> bool {
+        let name = normalize_name(name);
+        SENSITIVE_NAMES.contains(&name.as_str()) || self.names.contains(&name)
+    }
+
+    /// 递归脱敏：敏感键名的值整体替换，字符串中的密钥和 URL 中的敏感参数逐个替换。
+    pub fn redact(&self, value: &mut Value) {
+        match value {
+            Value::Object(object) => {
+                for (key, item) in object.iter_mut() {
+                    if self.is_sensitive_name(key) && !item.is_null() {
+                        *item = Value::String(REDACTED.to_string());
+                    } else {
+                        self.redact(item);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.redact(item)),
+            Value::String(text) => *text = self.redact_text(text),
+            _ => {}
+        }
+    }
+
+    fn redact_text(&self, text: &str) -> String {
+        let mut redacted = if text.starts_with("http://") || text.starts_with("https://") {
+            self.redact_url(text)
+        } else {
+            text.to_string()
+        };
+        for secret in &self.secrets {
+            if redacted.contains(secret.as_str()) {
+                redacted = redacted.replace(secret.as_str(), REDACTED);
+            }
+        }
+        redacted
+    }
+
+    fn redact_url(&self, text: &str) -> String {
+        let Ok(mut url) = Url::parse(text) else {
+            return text.to_string();
+        };
+        if url.query().is_none() {
+            return text.to_string();
+        }
+
+        let pairs: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(name, value)| {
+                let value = if self.is_sensitive_name(&name) {
+                    REDACTED.to_string()
+                } else {
+                    value.into_owned()
+                };
+                (name.into_owned(), value)
+            })
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+        url.to_string()
+    }
+}
+
+/// 单个请求的抓包会话，可在转发流程与响应流之间共享。
+///
+/// 最后一个引用释放时（流结束或下游断开）交给后台写入线程脱敏并写入磁盘。
+#[derive(Clone)]
+pub struct CaptureSession {
+    inner: Arc<CaptureCell>,
+}
+
+/// 会话共享的抓包内容，释放时取出并发送给写入线程。
+struct CaptureCell(Mutex<Option<CaptureState>>);
+
+struct CaptureState {
+    dir: PathBuf,
+    capture: GatewayCapture,
+    redactor: Redactor,
+    response_body: Vec<u8>,
+}
+
+impl CaptureSession {
+    /// 对应 Gateway 开启抓包时创建会话，请求 ID 与请求日志一致。
+    pub fn start(host: &GatewayHost, gateway: GatewayKind, request_id: &str) -> Option<Self> {
+        if !host.capture_enabled(gateway) {
+            return None;
+        }
+
+        let dir = capture_directory().ok()?;
+        Some(Self::new(dir, gateway, request_id))
+    }
+
+    fn new(dir: PathBuf, gateway: GatewayKind, request_id: &str) -> Self {
+        Self {
+            inner: Arc::new(CaptureCell(Mutex::new(Some(CaptureState {
+                dir,
+                capture: GatewayCapture {
+                    request_id: request_id.to_string(),
+                    gateway,
+                    started_at: chrono::Utc::now().to_rfc3339(),
+                    provider_id: None,
+                    model: None,
+                    inbound: None,
+                    upstream_request: None,
+                    upstream_response: None,
+                    downstream_events: Vec::new(),
+                    error: None,
+                },
+                redactor: Redactor::default(),
+                response_body: Vec::new(),
+            })))),
+        }
+    }
+
+    fn with_state(&self, update: impl FnOnce(&mut CaptureState)) {
+        let mut state = self.inner.0.lock().unwrap_or_else(|error| error.into_inner());
+        if let Some(state) = state.as_mut() {
+            update(state);
+        }
+    }
+
+    /// 登记需要脱敏的鉴权头/query 参数名及密钥值。
+    pub fn add_credential(&self, name: Option<&str>, secret: &str) {
+        self.with_state(|state| {
+            if let Some(name) = name {
+                state.redactor.add_name(name);
+            }
+            state.redactor.add_secret(secret);
+        });
+    }
+
+    pub fn set_route(&self, provider_id: &str, model: Option<&str>) {
+        self.with_state(|state| {
+            state.capture.provider_id = Some(provider_id.to_string());
+            state.capture.model = model.map(str::to_string);
+        });
+    }
+
+    pub fn set_inbound(&self, method: &str, url: &str, headers: &HeaderMap, body: &[u8]) {
+        let message = request_message(method, url, headers, Some(body));
+        self.with_state(|state| state.capture.inbound = Some(message));
+    }
+
+    pub fn set_upstream_request(&self, request: &reqwest::Request) {
+        let message = request_message(
+            request.method().as_str(),
+            request.url().as_str(),
+            request.headers(),
+            request.body().and_then(|body| body.as_bytes()),
+        );
+        self.with_state(|state| state.capture.upstream_request = Some(message));
+    }
+
+    pub fn set_upstream_response(&self, status: u16, headers: &HeaderMap) {
+        self.with_state(|state| {
+            state.capture.upstream_response = Some(CapturedMessage {
+                status: Some(status),
+                headers: header_map(headers),
+                ..Default::default()
+            });
+        });
+    }
+
+    pub fn append_upstream_body(&self, chunk: &[u8]) {
+        self.with_state(|state| {
+            let remaining = MAX_CAPTURED_BODY_BYTES.saturating_sub(state.response_body.len());
+            if chunk.len() > remaining {
+                if let Some(response) = state.capture.upstream_response.as_mut() {
+                    response.truncated = true;
+                }
+            }
+            state.response_body.extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+        });
+    }
+
+    pub fn record_downstream(&self, event: &str, data: &Value) {
+        self.with_state(|state| {
+            state.capture.downstream_events.push(CapturedEvent {
+                event: event.to_string(),
+                data: data.clone(),
+            });
+        });
+    }
+
+    pub fn fail(&self, error: impl Into<String>) {
+        let error = error.into();
+        self.with_state(|state| state.capture.error = Some(error));
+    }
+}
+
+impl Drop for CaptureCell {
+    fn drop(&mut self) {
+        let state = self.0.get_mut().unwrap_or_else(|error| error.into_inner()).take();
+        if let Some(state) = state {
+            send_to_writer(CaptureCommand::Save(Box::new(state)));
+        }
+    }
+}
+
+impl CaptureState {
+    fn save(mut self) {
+        if let Some(response) = self.capture.upstream_response.as_mut() {
+            response.body = body_value(&self.response_body);
+        }
+        if let Err(error) = save(&self.dir, &self.capture, &self.redactor) {
+            log::warn!("保存 Gateway 抓包失败: {}", error);
+        }
+    }
+}
+
+enum CaptureCommand {
+    Save(Box<CaptureState>),
+    #[cfg(test)]
+    Flush(mpsc::Sender<()>),
+}
+
+/// 抓包写入线程的发送端：脱敏、序列化和文件 IO 都不在转发路径上执行。
+static CAPTURE_WRITER: OnceLock<mpsc::Sender<CaptureCommand>> = OnceLock::new();
+
+fn send_to_writer(command: CaptureCommand) {
+    let sender = CAPTURE_WRITER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        // 线程启动失败时 receiver 随闭包一起释放，之后的抓包会被丢弃并告警
+        let _ = thread::Builder::new()
+            .name("gateway-capture-writer".to_string())
+            .spawn(move || run_writer(receiver));
+        sender
+    });
+    if sender.send(command).is_err() {
+        log::warn!("保存 Gateway 抓包失败: 抓包写入线程已退出");
+    }
+}
+
+fn run_writer(receiver: mpsc::Receiver<CaptureCommand>) {
+    for command in receiver {
+        match command {
+            CaptureCommand::Save(state) => state.save(),
+            #[cfg(test)]
+            CaptureCommand::Flush(done_tx) => {
+                let _ = done_tx.send(());
+            }
+        }
+    }
+}
+
+/// 等待此前释放的抓包全部写入磁盘。
+#[cfg(test)]
+fn flush_writer() {
+    let (done_tx, done_rx) = mpsc::channel();
+    send_to_writer(CaptureCommand::Flush(done_tx));
+    let _ = done_rx.recv();
+}
+
+pub fn capture_directory() -> Result<PathBuf, String> {
+    let home_dir = dirs::home_dir().ok_or("无法获取用户目录")?;
+    Ok(home_dir.join(".switchcc").join("captures"))
+}
+
+/// 脱敏后写入 `{request_id}.json`（目录 0700、文件 0600），并只保留最近的 [`MAX_CAPTURES`] 个文件。
+pub fn save(dir: &Path, capture: &GatewayCapture, redactor: &Redactor) -> Result<PathBuf, String> {
+    let mut value = serde_json::to_value(capture).map_err(|error| format!("序列化抓包失败: {}", error))?;
+    redactor.redact(&mut value);
+    let content =
+        serde_json::to_string_pretty(&value).map_err(|error| format!("序列化抓包失败: {}", error))?;
+
+    secret_vault::create_private_dir(dir).map_err(|error| format!("创建抓包目录失败: {}", error))?;
+    let path = capture_path(dir, &capture.request_id)?;
+    secret_vault::write_private_file(&path, content.as_bytes())
+        .map_err(|error| format!("写入抓包文件失败: {}", error))?;
+    prune(dir, MAX_CAPTURES)?;
+    Ok(path)
+}
+
+/// 按时间倒序列出抓包。
+pub fn list(dir: &Path, gateway: Option<GatewayKind>) -> Result<Vec<GatewayCaptureSummary>, String> {
+    let mut summaries: Vec<GatewayCaptureSummary> = capture_files(dir)?
+        .into_iter()
+        .filter_map(|(path, _)| load_file(&path).ok())
+        .filter(|capture| gateway.map_or(true, |gateway| capture.gateway == gateway))
+        .map(|capture| GatewayCaptureSummary {
+            status: capture.upstream_response.as_ref().and_then(|response| response.status),
+            request_id: capture.request_id,
+            gateway: capture.gateway,
+            started_at: capture.started_at,
+            provider_id: capture.provider_id,
+            model: capture.model,
+            error: capture.error,
+        })
+        .collect();
+    summaries.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+    Ok(summaries)
+}
+
+pub fn load(dir: &Path, request_id: &str) -> Result<GatewayCapture, String> {
+    load_file(&capture_path(dir, request_id)?)
+}
+
+/// 把单个抓包复制到 `target_dir`，返回导出文件路径。
+pub fn export(dir: &Path, request_id: &str, target_dir: &Path) -> Result<PathBuf, String> {
+    let source = capture_path(dir, request_id)?;
+    if !source.exists() {
+        return Err(format!("抓包不存在: {}", request_id));
+    }
+    let target = target_dir.join(format!("switchcc-capture-{}.json", request_id));
+    fs::copy(&source, &target).map_err(|error| format!("导出抓包失败: {}", error))?;
+    Ok(target)
+}
+
+fn capture_path(dir: &Path, request_id: &str) -> Result<PathBuf, String> {
+    let valid = !request_id.is_empty()
+        && request_id
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-');
+    if !valid {
+        return Err(format!("无效的请求 ID: {}", request_id));
+    }
+    Ok(dir.join(format!("{}.json", request_id)))
+}
+
+fn load_file(path: &Path) -> Result<GatewayCapture, String> {
+    let content = fs::read_to_string(path).map_err(|error| format!("读取抓包文件失败: {}", error))?;
+    serde_json::from_str(&content).map_err(|error| format!("解析抓包文件失败: {}", error))
+}
+
+/// 抓包文件及其修改时间，按时间倒序。
+fn capture_files(dir: &Path) -> Result<Vec<(PathBuf, SystemTime)>, String> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut files: Vec<(PathBuf, SystemTime)> = fs::read_dir(dir)
+        .map_err(|error| format!("读取抓包目录失败: {}", error))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+        .filter_map(|path| {
+            let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).ok()?;
+            Some((path, modified))
+        })
+        .collect();
+    files.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+    Ok(files)
+}
+
+fn prune(dir: &Path, max_captures: usize) -> Result<(), String> {
+    for (path, _) in capture_files(dir)?.into_iter().skip(max_captures) {
+        let _ = fs::remove_file(path);
+    }
+    Ok(())
+}
+
+fn request_message(method: &str, url: &str, headers: &HeaderMap, body: Option<&[u8]>) -> CapturedMessage {
+    let body = body.unwrap_or_default();
+    CapturedMessage {
+        method: Some(method.to_string()),
+        url: Some(url.to_string()),
+        status: None,
+        headers: header_map(headers),
+        body: body_value(&body[..body.len().min(MAX_CAPTURED_BODY_BYTES)]),
+        truncated: body.len() > MAX_CAPTURED_BODY_BYTES,
+    }
+}
+
+fn header_map(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.as_str().to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect()
+}
+
+fn body_value(bytes: &[u8]) -> Value {
+    if bytes.is_empty() {
+        return Value::Null;
+    }
+    serde_json::from_slice(bytes).unwrap_or_else(|_| Value::String(String::from_utf8_lossy(bytes).into_owned()))
+}
+
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|ch| *ch != '-' && *ch != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// 把 SSE 事件序列化为抓包中的原始文本行。
+pub fn sse_text(event: &str, data: &str) -> String {
+    let mut text = String::new();
+    if !event.is_empty() {
+        text.push_str("event: ");
+        text.push_str(event);
+        text.push('\n');
+    }
+    for line in data.split('\n') {
+        text.push_str("data: ");
+        text.push_str(line);
+        text.push('\n');
+    }
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    use serde_json::json;
+
+    #[test]
+    fn redaction_covers_headers_query_auth_body_keys_and_secret_values() {
+        let mut redactor = Redactor::default();
+        redactor.add_name("X-Relay-Token");
+        redactor.add_secret("sk-live-abcdef123456");
+
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Bearer sk-live-abcdef123456"));
+        headers.insert("x-api-key", HeaderValue::from_static("local-key"));
+        headers.insert("x-relay-token", HeaderValue::from_static("relay"));
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        let message = request_message(
+            "POST",
+            "https://relay.example.com/v1/messages?key=abc&beta=true",
+            &headers,
+            Some(br#"{"model":"glm-4.6","api_key":"k","messages":[{"content":"token sk-live-abcdef123456"}]}"#),
+        );
+
+        let mut value = serde_json::to_value(&message).unwrap();
+        redactor.redact(&mut value);
+
+        assert_eq!(value["headers"]["authorization"], json!(REDACTED));
+        assert_eq!(value["headers"]["x-api-key"], json!(REDACTED));
+        assert_eq!(value["headers"]["x-relay-token"], json!(REDACTED));
+        assert_eq!(value["headers"]["content-type"], json!("application/json"));
+        assert_eq!(
+            value["url"],
+            json!("https://relay.example.com/v1/messages?key=%5BREDACTED%5D&beta=true")
+        );
+        assert_eq!(value["body"]["model"], json!("glm-4.6"));
+        assert_eq!(value["body"]["api_key"], json!(REDACTED));
+        assert_eq!(value["body"]["messages"][0]["content"], json!("token [REDACTED]"));
+        assert!(!value.to_string().contains("sk-live-abcdef123456"));
+    }
+
+    #[test]
+    fn session_persists_on_last_drop_and_prunes_old_captures() {
+        let dir = std::env::temp_dir().join(format!("switchcc-capture-test-{}", uuid::Uuid::new_v4()));
+
+        let session = CaptureSession::new(dir.clone(), GatewayKind::Codex, "req_1");
+        session.add_credential(None, "sk-secret-value");
+        session.set_route("relay", Some("gpt-5"));
+        session.set_upstream_response(200, &HeaderMap::new());
+        let stream_handle = session.clone();
+        drop(session);
+        assert!(!dir.join("req_1.json").exists());
+
+        stream_handle.append_upstream_body(sse_text("", r#"{"id":"sk-secret-value"}"#).as_bytes());
+        stream_handle.record_downstream("response.completed", &json!({"type": "response.completed"}));
+        drop(stream_handle);
+        flush_writer();
+
+        let capture = load(&dir, "req_1").unwrap();
+        assert_eq!(capture.provider_id.as_deref(), Some("relay"));
+        assert_eq!(capture.downstream_events[0].event, "response.completed");
+        let body = capture.upstream_response.unwrap().body;
+        assert_eq!(body, json!("data: {\"id\":\"[REDACTED]\"}\n\n"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&dir), 0o700);
+            assert_eq!(mode(&dir.join("req_1.json")), 0o600);
+        }
+
+        for request_id in ["req_2", "req_3"] {
+            drop(CaptureSession::new(dir.clone(), GatewayKind::Api, request_id));
+        }
+        flush_writer();
+        assert_eq!(list(&dir, Some(GatewayKind::Api)).unwrap().len(), 2);
+        prune(&dir, 1).unwrap();
+        assert_eq!(capture_files(&dir).unwrap().len(), 1);
+        assert!(load(&dir, "../etc").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
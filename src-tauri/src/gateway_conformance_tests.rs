@@ -0,0 +1,394 @@
+//! Chat ↔ Responses 协议一致性用例。
+//!
+//! 每个用例是 `tests/fixtures/codex_conformance/<name>/` 下的一个目录：
+//! - `case.json`：`upstreamUrl` 决定适配器（只取 host 与路径拼到模拟上游后面），`model` 为上游模型名
+//! - `request.json`：客户端发给 Gateway 的 Responses 请求
+//! - `upstream.sse`：录制的上游 Chat SSE 原文
+//! - `expected_upstream_request.json`：Gateway 应发往上游的 Chat 请求
+//! - `expected_events.json`：Gateway 应发给客户端的事件序列
+//!
+//! 随机 id 与时间戳在比较前归一化。行为有意变更时用 `UPDATE_CONFORMANCE=1 cargo test conformance`
+//! 重写期望文件；抓包转用例见 `import_capture_as_conformance_case`。
+
+use crate::codex_gateway;
+use crate::gateway_capture::GatewayCapture;
+use crate::gateway_host::{ConsoleSink, GatewayHost};
+use crate::gateway_log::GatewayKind;
+use crate::mock_upstream::{MockResponse, MockUpstream};
+use crate::provider::{CodexProvider, CodexProviderConfig};
+use crate::store::{AppConfig, CodexGatewayConfig};
+use eventsource_stream::Eventsource;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+const CASE_FILE: &str = "case.json";
+const REQUEST_FILE: &str = "request.json";
+const UPSTREAM_FILE: &str = "upstream.sse";
+const EXPECTED_REQUEST_FILE: &str = "expected_upstream_request.json";
+const EXPECTED_EVENTS_FILE: &str = "expected_events.json";
+/// 每个用例最多列出的差异条数
+const MAX_REPORTED_DIFFS: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CaseMeta {
+    #[serde(default)]
+    description: String,
+    upstream_url: String,
+    model: String,
+}
+
+struct ConformanceCase {
+    name: String,
+    dir: PathBuf,
+    meta: CaseMeta,
+    request: Value,
+    transcript: String,
+}
+
+/// 一次回放中观察到的上游请求与下游事件，均已归一化。
+struct Observed {
+    upstream_request: Value,
+    events: Vec<Value>,
+}
+
+fn fixture_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/codex_conformance")
+}
+
+fn read_json(path: &Path) -> Result<Value, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("读取 {} 失败: {}", path.display(), error))?;
+    serde_json::from_str(&text).map_err(|error| format!("解析 {} 失败: {}", path.display(), error))
+}
+
+fn write_json(path: &Path, value: &Value) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(value).map_err(|error| error.to_string())?;
+    fs::write(path, text + "\n").map_err(|error| format!("写入 {} 失败: {}", path.display(), error))
+}
+
+impl ConformanceCase {
+    fn load(dir: &Path) -> Result<Self, String> {
+        let meta = serde_json::from_value(read_json(&dir.join(CASE_FILE))?)
+            .map_err(|error| format!("{} 格式错误: {}", dir.join(CASE_FILE).display(), error))?;
+        let transcript = fs::read_to_string(dir.join(UPSTREAM_FILE))
+            .map_err(|error| format!("读取 {} 失败: {}", dir.join(UPSTREAM_FILE).display(), error))?;
+        Ok(Self {
+            name: dir.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            dir: dir.to_path_buf(),
+            meta,
+            request: read_json(&dir.join(REQUEST_FILE))?,
+            transcript,
+        })
+    }
+
+    /// 把 `upstreamUrl` 的 host 与路径挂到模拟上游下，适配器按与线上相同的规则识别。
+    fn upstream_url(&self, mock_base_url: &str) -> Result<String, String> {
+        let url = reqwest::Url::parse(&self.meta.upstream_url)
+            .map_err(|error| format!("用例 {} 的 upstreamUrl 无效: {}", self.name, error))?;
+        Ok(format!(
+            "{}/{}{}",
+            mock_base_url,
+            url.host_str().unwrap_or("upstream"),
+            url.path().trim_end_matches('/')
+        ))
+    }
+
+    async fn run(&self) -> Result<Observed, String> {
+        let upstream = MockUpstream::start([MockResponse::sse_transcript(&self.transcript)]).await;
+        let provider = CodexProvider {
+            id: "conformance".to_string(),
+            name: "Conformance".to_string(),
+            codex_config: CodexProviderConfig {
+                provider_name: "conformance".to_string(),
+                upstream_url: self.upstream_url(&upstream.base_url)?,
+                model_name: self.meta.model.clone(),
+                api_key: "sk-upstream".to_string(),
+                ..Default::default()
+            },
+            website_url: None,
+            category: None,
+            created_at: None,
+        };
+
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await.map_err(|error| error.to_string())?;
+        let gateway_url = format!("http://{}", listener.local_addr().map_err(|error| error.to_string())?);
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let host = GatewayHost::new(
+                Arc::new(ConsoleSink),
+                Arc::new(Mutex::new(AppConfig::default())),
+                Arc::default(),
+            );
+            let _ = codex_gateway::serve_headless(listener, host, &provider, &CodexGatewayConfig::default(), shutdown_rx)
+                .await;
+        });
+
+        let response = reqwest::Client::new()
+            .post(format!("{}/v1/responses", gateway_url))
+            .json(&self.request)
+            .send()
+            .await
+            .map_err(|error| format!("请求 Gateway 失败: {}", error))?;
+        let mut events = Vec::new();
+        let mut stream = response.bytes_stream().eventsource();
+        loop {
+            let next = tokio::time::timeout(Duration::from_secs(10), stream.next())
+                .await
+                .map_err(|_| "读取 Gateway SSE 超时".to_string())?;
+            let Some(event) = next else { break };
+            let event = event.map_err(|error| format!("解析 Gateway SSE 失败: {}", error))?;
+            let data = serde_json::from_str(&event.data).unwrap_or(Value::String(event.data));
+            events.push(json!({ "event": event.event, "data": data }));
+        }
+        let _ = shutdown_tx.send(());
+
+        let upstream_request = upstream
+            .requests()
+            .first()
+            .map(|request| request.body.clone())
+            .unwrap_or(Value::Null);
+        Ok(Observed::normalized(upstream_request, events))
+    }
+
+    fn compare(&self, observed: &Observed) -> Result<Vec<String>, String> {
+        let mut diffs = Vec::new();
+        diff_json(
+            EXPECTED_REQUEST_FILE,
+            &read_json(&self.dir.join(EXPECTED_REQUEST_FILE))?,
+            &observed.upstream_request,
+            &mut diffs,
+        );
+        let expected_events = read_json(&self.dir.join(EXPECTED_EVENTS_FILE))?;
+        let expected_events = expected_events.as_array().cloned().unwrap_or_default();
+        if expected_events.len() != observed.events.len() {
+            diffs.push(format!(
+                "事件数量不同: 期望 {:?}，实际 {:?}",
+                event_names(&expected_events),
+                event_names(&observed.events)
+            ));
+        }
+        for (index, (expected, actual)) in expected_events.iter().zip(&observed.events).enumerate() {
+            diff_json(&format!("{}[{}]", EXPECTED_EVENTS_FILE, index), expected, actual, &mut diffs);
+        }
+        Ok(diffs)
+    }
+
+    fn update(&self, observed: &Observed) -> Result<(), String> {
+        write_json(&self.dir.join(EXPECTED_REQUEST_FILE), &observed.upstream_request)?;
+        write_json(&self.dir.join(EXPECTED_EVENTS_FILE), &Value::Array(observed.events.clone()))
+    }
+}
+
+impl Observed {
+    fn normalized(mut upstream_request: Value, events: Vec<Value>) -> Self {
+        let mut ids = IdNormalizer::default();
+        ids.normalize(&mut upstream_request);
+        let mut events = Value::Array(events);
+        ids.normalize(&mut events);
+        Self {
+            upstream_request,
+            events: events.as_array().cloned().unwrap_or_default(),
+        }
+    }
+}
+
+fn event_names(events: &[Value]) -> Vec<&str> {
+    events
+        .iter()
+        .map(|event| event.get("event").and_then(Value::as_str).unwrap_or_default())
+        .collect()
+}
+
+/// 把 32 位十六进制的随机 id 按出现顺序替换为 `<id-N>`，时间戳置零。
+#[derive(Default)]
+struct IdNormalizer {
+    ids: HashMap<String, String>,
+}
+
+impl IdNormalizer {
+    fn normalize(&mut self, value: &mut Value) {
+        match value {
+            Value::String(text) => *text = self.replace_ids(text),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.normalize(item)),
+            Value::Object(object) => {
+                for (key, item) in object.iter_mut() {
+                    if matches!(key.as_str(), "created_at" | "created") && item.is_number() {
+                        *item = json!(0);
+                    } else {
+                        self.normalize(item);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn replace_ids(&mut self, text: &str) -> String {
+        let mut output = String::with_capacity(text.len());
+        let mut run = String::new();
+        for ch in text.chars().chain(std::iter::once('\0')) {
+            if ch.is_ascii_hexdigit() && !ch.is_ascii_uppercase() {
+                run.push(ch);
+                continue;
+            }
+            if run.len() == 32 {
+                let next = format!("<id-{}>", self.ids.len() + 1);
+                output.push_str(self.ids.entry(std::mem::take(&mut run)).or_insert(next));
+            } else {
+                output.push_str(&std::mem::take(&mut run));
+            }
+            if ch != '\0' {
+                output.push(ch);
+            }
+        }
+        output
+    }
+}
+
+/// 逐字段比较两个 JSON，差异以 `路径: 期望 … 实际 …` 记录。
+fn diff_json(path: &str, expected: &Value, actual: &Value, diffs: &mut Vec<String>) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            let mut keys = expected.keys().chain(actual.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let child = format!("{}.{}", path, key);
+                match (expected.get(key), actual.get(key)) {
+                    (Some(expected), Some(actual)) => diff_json(&child, expected, actual, diffs),
+                    (Some(expected), None) => diffs.push(format!("{}: 缺少字段，期望 {}", child, expected)),
+                    (None, Some(actual)) => diffs.push(format!("{}: 多出字段 {}", child, actual)),
+                    (None, None) => {}
+                }
+            }
+        }
+        (Value::Array(expected_items), Value::Array(actual_items)) if expected_items.len() == actual_items.len() => {
+            for (index, (expected, actual)) in expected_items.iter().zip(actual_items).enumerate() {
+                diff_json(&format!("{}[{}]", path, index), expected, actual, diffs);
+            }
+        }
+        _ if expected != actual => diffs.push(format!("{}: 期望 {}，实际 {}", path, expected, actual)),
+        _ => {}
+    }
+}
+
+/// 把一次 Codex Gateway 流式抓包转换为用例目录，期望结果取自抓包中的实际输出。
+fn write_case_from_capture(capture: &GatewayCapture, dir: &Path, description: &str) -> Result<(), String> {
+    if !matches!(capture.gateway, GatewayKind::Codex) {
+        return Err("只支持 Codex Gateway 的抓包".to_string());
+    }
+    let inbound = capture.inbound.as_ref().ok_or("抓包缺少客户端请求")?;
+    let upstream_request = capture.upstream_request.as_ref().ok_or("抓包缺少上游请求")?;
+    let upstream_response = capture.upstream_response.as_ref().ok_or("抓包缺少上游响应")?;
+    if inbound.truncated || upstream_request.truncated || upstream_response.truncated {
+        return Err("抓包内容被截断，无法生成用例".to_string());
+    }
+    let transcript = upstream_response
+        .body
+        .as_str()
+        .filter(|body| body.contains("data:"))
+        .ok_or("上游响应不是 SSE，只支持流式抓包")?;
+    let upstream_url = upstream_request
+        .url
+        .as_deref()
+        .map(|url| url.trim_end_matches("/chat/completions").to_string())
+        .ok_or("抓包缺少上游地址")?;
+    let model = upstream_request
+        .body
+        .get("model")
+        .and_then(Value::as_str)
+        .or(capture.model.as_deref())
+        .ok_or("抓包缺少上游模型名")?
+        .to_string();
+
+    fs::create_dir_all(dir).map_err(|error| format!("创建 {} 失败: {}", dir.display(), error))?;
+    let meta = CaseMeta {
+        description: description.to_string(),
+        upstream_url,
+        model,
+    };
+    write_json(&dir.join(CASE_FILE), &serde_json::to_value(&meta).map_err(|error| error.to_string())?)?;
+    write_json(&dir.join(REQUEST_FILE), &inbound.body)?;
+    fs::write(dir.join(UPSTREAM_FILE), transcript).map_err(|error| format!("写入上游 SSE 失败: {}", error))?;
+
+    let events = capture
+        .downstream_events
+        .iter()
+        .map(|event| json!({ "event": event.event, "data": event.data }))
+        .collect();
+    let observed = Observed::normalized(upstream_request.body.clone(), events);
+    write_json(&dir.join(EXPECTED_REQUEST_FILE), &observed.upstream_request)?;
+    write_json(&dir.join(EXPECTED_EVENTS_FILE), &Value::Array(observed.events))
+}
+
+#[tokio::test]
+async fn conformance_fixtures_match_expected_events() {
+    let update = std::env::var("UPDATE_CONFORMANCE").is_ok_and(|value| value == "1");
+    let mut dirs = fs::read_dir(fixture_root())
+        .expect("读取一致性用例目录失败")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    dirs.sort();
+    assert!(!dirs.is_empty(), "没有找到一致性用例");
+
+    let mut failures = Vec::new();
+    for dir in dirs {
+        let case = ConformanceCase::load(&dir).unwrap();
+        let observed = case.run().await.unwrap_or_else(|error| panic!("用例 {} 回放失败: {}", case.name, error));
+        if update {
+            case.update(&observed).unwrap();
+            continue;
+        }
+        let diffs = case.compare(&observed).unwrap();
+        if !diffs.is_empty() {
+            let shown = diffs.iter().take(MAX_REPORTED_DIFFS).cloned().collect::<Vec<_>>().join("\n  ");
+            failures.push(format!("{} ({}):\n  {}", case.name, case.meta.description, shown));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "协议一致性用例不匹配（确认是预期变更后可用 UPDATE_CONFORMANCE=1 重写）:\n{}",
+        failures.join("\n")
+    );
+}
+
+/// `CONFORMANCE_CAPTURE=<抓包 JSON> CONFORMANCE_CASE=<用例名> cargo test import_capture -- --ignored`
+#[test]
+#[ignore = "手动运行：把抓包转换为一致性用例"]
+fn import_capture_as_conformance_case() {
+    let capture_path = std::env::var("CONFORMANCE_CAPTURE").expect("请设置 CONFORMANCE_CAPTURE 为抓包文件路径");
+    let name = std::env::var("CONFORMANCE_CASE").expect("请设置 CONFORMANCE_CASE 为用例名");
+    let capture: GatewayCapture = serde_json::from_value(read_json(Path::new(&capture_path)).unwrap()).unwrap();
+    let description = format!("导入自抓包 {}", capture.request_id);
+    write_case_from_capture(&capture, &fixture_root().join(name), &description).unwrap();
+}
+
+#[test]
+fn id_normalizer_maps_repeated_ids_consistently() {
+    let mut value = json!({
+        "id": "resp_0123456789abcdef0123456789abcdef",
+        "created_at": 1_700_000_000,
+        "output": [{ "call_id": "call_resp_0123456789abcdef0123456789abcdef_0", "id": "fc_fedcba9876543210fedcba9876543210" }],
+        "short": "deadbeef"
+    });
+
+    IdNormalizer::default().normalize(&mut value);
+
+    assert_eq!(
+        value,
+        json!({
+            "id": "resp_<id-1>",
+            "created_at": 0,
+            "output": [{ "call_id": "call_resp_<id-1>_0", "id": "fc_<id-2>" }],
+            "short": "deadbeef"
+        })
+    );
+}
@@ -0,0 +1,201 @@
+use crate::token_estimate;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// 工具输出被截断时保留的开头字符数
+const TRIMMED_TOOL_OUTPUT_CHARS: usize = 400;
+
+/// 请求超出上下文窗口时的处理方式。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OverflowPolicy {
+    /// 直接返回错误，不请求上游
+    #[default]
+    Error,
+    /// 从最早的工具输出开始截断，只保留开头部分
+    TrimToolOutputs,
+    /// 从最早的对话开始整条丢弃，保留 system 消息和最后一条消息
+    DropOldest,
+}
+
+/// 上游模型的上下文窗口，未设置长度时不做检查。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextWindow {
+    /// 模型上下文长度（token）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<u64>,
+    #[serde(default, skip_serializing_if = "is_default_policy")]
+    pub overflow: OverflowPolicy,
+}
+
+fn is_default_policy(policy: &OverflowPolicy) -> bool {
+    *policy == OverflowPolicy::default()
+}
+
+impl ContextWindow {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// 输入可用的 token：上下文长度减去 `max_tokens`，未指定时预留 1/8 给输出。
+    fn input_budget(&self, request: &Map<String, Value>) -> Option<u64> {
+        let length = self.length.filter(|length| *length > 0)?;
+        let reserved = ["max_tokens", "max_completion_tokens"]
+            .iter()
+            .find_map(|key| request.get(*key).and_then(Value::as_u64))
+            .unwrap_or(length / 8);
+        Some(length.saturating_sub(reserved))
+    }
+
+    /// 按策略让 Chat 请求适应上下文窗口；无法适应时返回错误。
+    pub fn fit_chat_request(&self, request: &mut Map<String, Value>) -> Result<(), String> {
+        let tool_tokens = request
+            .get("tools")
+            .and_then(Value::as_array)
+            .map(|tools| token_estimate::estimate_chat_tools_tokens(tools))
+            .unwrap_or(0);
+        let Some(budget) = self.input_budget(request) else {
+            return Ok(());
+        };
+        let Some(messages) = request.get_mut("messages").and_then(Value::as_array_mut) else {
+            return Ok(());
+        };
+
+        let mut message_tokens = messages
+            .iter()
+            .map(token_estimate::estimate_chat_message_tokens)
+            .collect::<Vec<_>>();
+        let total = |message_tokens: &[u64]| tool_tokens + message_tokens.iter().sum::<u64>();
+
+        match self.overflow {
+            OverflowPolicy::Error => {}
+            OverflowPolicy::TrimToolOutputs => {
+                // 最后一条消息是模型当前要处理的内容，不截断
+                let last = messages.len().saturating_sub(1);
+                for index in 0..last {
+                    if total(&message_tokens) <= budget {
+                        break;
+                    }
+                    if let Some(trimmed) = trim_tool_output(&messages[index]) {
+                        messages[index]["content"] = Value::String(trimmed);
+                        message_tokens[index] = token_estimate::estimate_chat_message_tokens(&messages[index]);
+                    }
+                }
+            }
+            OverflowPolicy::DropOldest => {
+                while total(&message_tokens) > budget {
+                    let Some(first) = messages
+                        .iter()
+                        .position(|message| message.get("role").and_then(Value::as_str) != Some("system"))
+                        .filter(|first| *first + 1 < messages.len())
+                    else {
+                        break;
+                    };
+                    // 连同紧随其后的工具结果一起丢弃，避免留下没有对应调用的 tool 消息
+                    let mut end = first + 1;
+                    while end + 1 < messages.len() && messages[end].get("role").and_then(Value::as_str) == Some("tool") {
+                        end += 1;
+                    }
+                    messages.drain(first..end);
+                    message_tokens.drain(first..end);
+                }
+            }
+        }
+
+        let estimated_tokens = total(&message_tokens);
+        if estimated_tokens > budget {
+            return Err(format!(
+                "请求超出模型上下文窗口: 估算输入 {} tokens，上下文长度 {} tokens 中可用于输入的为 {} tokens，请压缩对话历史后重试",
+                estimated_tokens,
+                self.length.unwrap_or_default(),
+                budget
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// 截断 tool 消息的文本输出，已经足够短或不是纯文本时返回 `None`。
+fn trim_tool_output(message: &Value) -> Option<String> {
+    if message.get("role").and_then(Value::as_str) != Some("tool") {
+        return None;
+    }
+    let content = message.get("content")?.as_str()?;
+    let total_chars = content.chars().count();
+    if total_chars <= TRIMMED_TOOL_OUTPUT_CHARS {
+        return None;
+    }
+    let head = content.chars().take(TRIMMED_TOOL_OUTPUT_CHARS).collect::<String>();
+    Some(format!(
+        "{head}\n[... {} characters of this earlier tool output were omitted to fit the context window]",
+        total_chars - TRIMMED_TOOL_OUTPUT_CHARS
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(max_tokens: u64) -> Map<String, Value> {
+        let long_output = "x".repeat(4_000);
+        json!({
+            "max_tokens": max_tokens,
+            "messages": [
+                { "role": "system", "content": "sys" },
+                { "role": "user", "content": "list files" },
+                { "role": "assistant", "content": null, "tool_calls": [
+                    { "id": "call_1", "type": "function", "function": { "name": "ls", "arguments": "{}" } }
+                ] },
+                { "role": "tool", "tool_call_id": "call_1", "content": long_output },
+                { "role": "user", "content": "now summarise" }
+            ]
+        })
+        .as_object()
+        .cloned()
+        .unwrap()
+    }
+
+    #[test]
+    fn trim_tool_outputs_shrinks_old_results_or_errors() {
+        let window = ContextWindow {
+            length: Some(600),
+            overflow: OverflowPolicy::TrimToolOutputs,
+        };
+        let mut body = request(100);
+
+        window.fit_chat_request(&mut body).unwrap();
+
+        assert!(token_estimate::estimate_chat_input_tokens(&Value::Object(body.clone())) <= 500);
+        let content = body["messages"][3]["content"].as_str().unwrap();
+        assert!(content.ends_with("omitted to fit the context window]"));
+
+        let strict = ContextWindow {
+            length: Some(600),
+            overflow: OverflowPolicy::Error,
+        };
+        let error = strict.fit_chat_request(&mut request(100)).unwrap_err();
+        assert!(error.contains("上下文窗口"));
+    }
+
+    #[test]
+    fn drop_oldest_removes_tool_results_with_their_call() {
+        let window = ContextWindow {
+            length: Some(200),
+            overflow: OverflowPolicy::DropOldest,
+        };
+        let mut body = request(100);
+
+        window.fit_chat_request(&mut body).unwrap();
+
+        let roles = body["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|message| message["role"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(roles, ["system", "user"]);
+        assert_eq!(body["messages"][1]["content"], json!("now summarise"));
+    }
+}
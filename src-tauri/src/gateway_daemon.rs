@@ -0,0 +1,159 @@
+use crate::api_gateway;
+use crate::codex_gateway;
+use crate::config;
+use crate::gateway_host::{ConsoleSink, GatewayHost};
+use crate::gateway_log::GatewayKind;
+use crate::store::AppConfig;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+use tokio::task::JoinSet;
+
+const USAGE: &str = "用法: switch-cc-gateway [--config <config.json>]";
+
+/// 独立 Gateway 守护进程入口，读取与桌面应用相同的 `config.json`。
+///
+/// 只启动配置中已启用的 Gateway，收到 Ctrl-C 或 SIGTERM 后退出；修改配置后需重启进程。
+pub fn run(args: impl IntoIterator<Item = String>) -> Result<(), String> {
+    let config_path = parse_config_path(args)?;
+    let config = config::load_config_from(&config_path)?;
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|error| format!("初始化异步运行时失败: {}", error))?
+        .block_on(serve(config))
+}
+
+fn parse_config_path(args: impl IntoIterator<Item = String>) -> Result<PathBuf, String> {
+    let mut args = args.into_iter();
+    let config_path = match args.next().as_deref() {
+        None => return config::get_app_config_path(),
+        Some("--config") => args
+            .next()
+            .map(PathBuf::from)
+            .ok_or_else(|| format!("--config 缺少配置文件路径\n{}", USAGE))?,
+        Some(arg) => return Err(format!("未知参数: {}\n{}", arg, USAGE)),
+    };
+
+    match args.next() {
+        Some(arg) => Err(format!("未知参数: {}\n{}", arg, USAGE)),
+        None => Ok(config_path),
+    }
+}
+
+async fn serve(config: AppConfig) -> Result<(), String> {
+    let api_config = config.api_gateway.clone();
+    let codex_config = config.codex_gateway.clone();
+    let api_provider = config.api_gateway_provider().cloned();
+    let codex_provider = config.codex_gateway_provider().cloned();
+    let providers = config.providers.values().cloned().collect::<Vec<_>>();
+    let host = GatewayHost::new(Arc::new(ConsoleSink), Arc::new(Mutex::new(config)), Arc::default());
+
+    let mut shutdown_senders = Vec::new();
+    let mut servers = JoinSet::new();
+
+    if api_config.enabled {
+        let provider = api_provider.ok_or("API Gateway 已启用，但没有可用的目标供应商")?;
+        let listener = bind(api_config.port, GatewayKind::Api).await?;
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        shutdown_senders.push(shutdown_tx);
+        host.emit_log(
+            GatewayKind::Api,
+            "info",
+            &format!(
+                "API Gateway 已启动，监听 {}，当前上游 {}",
+                api_gateway::gateway_base_url(api_config.port),
+                provider.name
+            ),
+        );
+        let host = host.clone();
+        servers.spawn(async move {
+            api_gateway::serve_headless(listener, host, &providers, &provider.id, &api_config, shutdown_rx).await
+        });
+    }
+
+    if codex_config.enabled {
+        let provider = codex_provider.ok_or("Codex Gateway 已启用，但没有可用的目标供应商")?;
+        let listener = bind(codex_config.port, GatewayKind::Codex).await?;
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        shutdown_senders.push(shutdown_tx);
+        host.emit_log(
+            GatewayKind::Codex,
+            "info",
+            &format!(
+                "Codex Gateway 已启动，监听 {}，当前上游 {}",
+                codex_gateway::gateway_base_url(codex_config.port),
+                provider.name
+            ),
+        );
+        let host = host.clone();
+        servers.spawn(async move {
+            codex_gateway::serve_headless(listener, host, &provider, &codex_config, shutdown_rx).await
+        });
+    }
+
+    if servers.is_empty() {
+        return Err("配置中没有启用的 Gateway，请先在应用中开启 API Gateway 或 Codex Gateway".to_string());
+    }
+
+    let outcome = tokio::select! {
+        _ = shutdown_signal() => Ok(()),
+        Some(result) = servers.join_next() => match result {
+            Ok(Ok(())) => Err("Gateway 服务意外退出".to_string()),
+            Ok(Err(error)) => Err(error),
+            Err(error) => Err(format!("Gateway 任务异常退出: {}", error)),
+        },
+    };
+
+    for shutdown_tx in shutdown_senders {
+        let _ = shutdown_tx.send(());
+    }
+    while servers.join_next().await.is_some() {}
+    outcome
+}
+
+async fn bind(port: u16, gateway: GatewayKind) -> Result<tokio::net::TcpListener, String> {
+    tokio::net::TcpListener::bind(("127.0.0.1", port)).await.map_err(|error| {
+        let name = match gateway {
+            GatewayKind::Api => "API Gateway",
+            GatewayKind::Codex => "Codex Gateway",
+        };
+        format!("{} 监听端口 {} 失败: {}", name, port, error)
+    })
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+    }
+
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_explicit_config_path() {
+        assert_eq!(
+            parse_config_path(args(&["--config", "/etc/switch-cc/config.json"])).unwrap(),
+            PathBuf::from("/etc/switch-cc/config.json")
+        );
+        assert!(parse_config_path(args(&["--config"])).is_err());
+        assert!(parse_config_path(args(&["--port", "8080"])).is_err());
+    }
+}
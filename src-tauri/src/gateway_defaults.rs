@@ -0,0 +1,194 @@
+use crate::gateway_reasoning;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// 供应商级的系统提示词和默认参数，合并到经过 Gateway 的每个请求。
+///
+/// 系统提示词总是前置到客户端的系统提示词之前；其余参数只在客户端未指定时补上。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestDefaults {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u64>,
+    /// `minimal` / `low` / `medium` / `high`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<String>,
+}
+
+impl RequestDefaults {
+    /// 从 `apiGateway.defaults` 等配置读取，缺失或格式错误时视为没有默认值。
+    pub fn from_value(value: Option<&Value>) -> Self {
+        value
+            .cloned()
+            .and_then(|value| serde_json::from_value(value).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn system_prompt(&self) -> Option<&str> {
+        self.system_prompt
+            .as_deref()
+            .map(str::trim)
+            .filter(|prompt| !prompt.is_empty())
+    }
+
+    fn reasoning_effort(&self) -> Option<&str> {
+        self.reasoning_effort
+            .as_deref()
+            .map(str::trim)
+            .filter(|effort| !effort.is_empty())
+    }
+
+    /// 合并到 Anthropic `/v1/messages` 请求；推理强度换算为扩展思考预算。
+    pub fn apply_to_messages(&self, body: &mut Value) {
+        let Some(object) = body.as_object_mut() else {
+            return;
+        };
+
+        if let Some(prompt) = self.system_prompt() {
+            let system = match object.remove("system") {
+                Some(Value::Array(mut blocks)) => {
+                    blocks.insert(0, json!({ "type": "text", "text": prompt }));
+                    Value::Array(blocks)
+                }
+                Some(Value::String(text)) if !text.trim().is_empty() => Value::String(format!("{prompt}\n\n{text}")),
+                _ => Value::String(prompt.to_string()),
+            };
+            object.insert("system".to_string(), system);
+        }
+
+        if let Some(max_tokens) = self.max_output_tokens {
+            object.entry("max_tokens").or_insert_with(|| Value::from(max_tokens));
+        }
+
+        if !object.contains_key("thinking") {
+            let max_tokens = object.get("max_tokens").and_then(Value::as_u64);
+            if let Some(budget) = self.reasoning_effort().and_then(|effort| thinking_budget(effort, max_tokens)) {
+                object.insert(
+                    "thinking".to_string(),
+                    json!({ "type": "enabled", "budget_tokens": budget }),
+                );
+            }
+        }
+
+        // 开启扩展思考时 Anthropic 只接受默认温度
+        let thinking_enabled = object
+            .get("thinking")
+            .and_then(|thinking| thinking.get("type"))
+            .and_then(Value::as_str)
+            == Some("enabled");
+        if let (Some(temperature), false) = (self.temperature, thinking_enabled) {
+            object.entry("temperature").or_insert_with(|| Value::from(temperature));
+        }
+    }
+
+    /// 合并到 OpenAI Responses 请求：系统提示词写入 `instructions`。
+    pub fn apply_to_responses(&self, body: &mut Value) {
+        let Some(object) = body.as_object_mut() else {
+            return;
+        };
+
+        if let Some(prompt) = self.system_prompt() {
+            let instructions = match object.get("instructions").and_then(Value::as_str) {
+                Some(text) if !text.trim().is_empty() => format!("{prompt}\n\n{text}"),
+                _ => prompt.to_string(),
+            };
+            object.insert("instructions".to_string(), Value::String(instructions));
+        }
+        if let Some(temperature) = self.temperature {
+            object.entry("temperature").or_insert_with(|| Value::from(temperature));
+        }
+        if let Some(max_tokens) = self.max_output_tokens {
+            object.entry("max_output_tokens").or_insert_with(|| Value::from(max_tokens));
+        }
+        if let Some(effort) = self.reasoning_effort() {
+            let reasoning = object.entry("reasoning").or_insert_with(|| json!({}));
+            if let Some(reasoning) = reasoning.as_object_mut() {
+                reasoning
+                    .entry("effort")
+                    .or_insert_with(|| Value::String(effort.to_string()));
+            }
+        }
+    }
+
+    /// 合并到 OpenAI Chat Completions 请求：系统提示词作为第一条 system 消息。
+    pub fn apply_to_chat(&self, body: &mut Value) {
+        let Some(object) = body.as_object_mut() else {
+            return;
+        };
+
+        if let Some(prompt) = self.system_prompt() {
+            if let Some(messages) = object.get_mut("messages").and_then(Value::as_array_mut) {
+                messages.insert(0, json!({ "role": "system", "content": prompt }));
+            }
+        }
+        if let Some(temperature) = self.temperature {
+            object.entry("temperature").or_insert_with(|| Value::from(temperature));
+        }
+        if let Some(max_tokens) = self.max_output_tokens {
+            if !object.contains_key("max_tokens") && !object.contains_key("max_completion_tokens") {
+                object.insert("max_tokens".to_string(), Value::from(max_tokens));
+            }
+        }
+        if let Some(effort) = self.reasoning_effort() {
+            object
+                .entry("reasoning_effort")
+                .or_insert_with(|| Value::String(effort.to_string()));
+        }
+    }
+}
+
+/// 推理强度对应的思考预算，需小于 `max_tokens`；`minimal` 等不开启思考。
+fn thinking_budget(effort: &str, max_tokens: Option<u64>) -> Option<u64> {
+    gateway_reasoning::default_budget_tokens(effort)
+        .and_then(|budget| gateway_reasoning::clamp_budget(budget, max_tokens))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> RequestDefaults {
+        RequestDefaults {
+            system_prompt: Some("遵守公司规范".to_string()),
+            temperature: Some(0.3),
+            max_output_tokens: Some(4096),
+            reasoning_effort: Some("medium".to_string()),
+        }
+    }
+
+    #[test]
+    fn messages_get_prepended_system_block_and_thinking_budget() {
+        let mut body = json!({
+            "max_tokens": 4096,
+            "system": [{ "type": "text", "text": "You are Claude Code" }],
+            "messages": [{ "role": "user", "content": "hi" }]
+        });
+
+        defaults().apply_to_messages(&mut body);
+
+        assert_eq!(body["system"][0]["text"], json!("遵守公司规范"));
+        assert_eq!(body["system"][1]["text"], json!("You are Claude Code"));
+        assert_eq!(body["thinking"], json!({ "type": "enabled", "budget_tokens": 4095 }));
+        assert!(body.get("temperature").is_none());
+    }
+
+    #[test]
+    fn responses_keep_client_values_and_prefix_instructions() {
+        let mut body = json!({ "instructions": "base", "temperature": 1.0, "input": "hi" });
+
+        defaults().apply_to_responses(&mut body);
+
+        assert_eq!(body["instructions"], json!("遵守公司规范\n\nbase"));
+        assert_eq!(body["temperature"], json!(1.0));
+        assert_eq!(body["max_output_tokens"], json!(4096));
+        assert_eq!(body["reasoning"]["effort"], json!("medium"));
+    }
+}
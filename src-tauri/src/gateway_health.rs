@@ -0,0 +1,448 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const HEALTH_WINDOW_SIZE: usize = 100;
+
+/// 熔断器配置，保存在 Gateway 配置中。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    pub enabled: bool,
+    /// 连续失败达到该次数后熔断
+    pub failure_threshold: u32,
+    /// 最近窗口内错误率达到该比例（且样本数不少于 `min_samples`）后熔断
+    pub error_rate_threshold: f64,
+    pub min_samples: u32,
+    /// 熔断持续时间，到期后放行一个试探请求
+    pub open_secs: u64,
+    /// 主动探测间隔，为空时不主动探测
+    pub probe_interval_secs: Option<u64>,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            failure_threshold: 5,
+            error_rate_threshold: 0.5,
+            min_samples: 10,
+            open_secs: 30,
+            probe_interval_secs: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+/// 供应商健康状态快照，用于状态接口和托盘菜单展示。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthSnapshot {
+    pub status: &'static str,
+    pub circuit_state: CircuitState,
+    pub sample_count: usize,
+    pub error_rate: f64,
+    pub consecutive_failures: u32,
+    pub p50_latency_ms: Option<u64>,
+    pub p95_latency_ms: Option<u64>,
+    pub total_requests: u64,
+    pub total_failures: u64,
+    pub last_error: Option<String>,
+    pub last_failure_at: Option<String>,
+    /// 熔断剩余秒数
+    pub open_remaining_secs: Option<u64>,
+}
+
+impl HealthSnapshot {
+    /// 托盘菜单中显示在供应商名称后的状态标记。
+    pub fn tray_badge(&self) -> Option<&'static str> {
+        match self.status {
+            "unhealthy" => Some("🔴"),
+            "degraded" => Some("🟡"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    success: bool,
+    latency_ms: u64,
+}
+
+#[derive(Debug)]
+struct ProviderHealth {
+    samples: VecDeque<Sample>,
+    consecutive_failures: u32,
+    circuit_state: CircuitState,
+    open_until: Option<Instant>,
+    /// 半开状态下试探请求的发出时间；试探请求异常中止时超过熔断时长后允许再次试探
+    half_open_trial_started: Option<Instant>,
+    total_requests: u64,
+    total_failures: u64,
+    last_error: Option<String>,
+    last_failure_at: Option<String>,
+}
+
+impl Default for ProviderHealth {
+    fn default() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(HEALTH_WINDOW_SIZE),
+            consecutive_failures: 0,
+            circuit_state: CircuitState::Closed,
+            open_until: None,
+            half_open_trial_started: None,
+            total_requests: 0,
+            total_failures: 0,
+            last_error: None,
+            last_failure_at: None,
+        }
+    }
+}
+
+impl ProviderHealth {
+    fn error_rate(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        let failures = self.samples.iter().filter(|sample| !sample.success).count();
+        failures as f64 / self.samples.len() as f64
+    }
+
+    fn push_sample(&mut self, sample: Sample) {
+        if self.samples.len() == HEALTH_WINDOW_SIZE {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+        self.total_requests += 1;
+    }
+
+    fn open(&mut self, config: &CircuitBreakerConfig, now: Instant) {
+        self.circuit_state = CircuitState::Open;
+        self.open_until = Some(now + Duration::from_secs(config.open_secs));
+        self.half_open_trial_started = None;
+    }
+
+    fn should_open(&self, config: &CircuitBreakerConfig) -> bool {
+        if !config.enabled {
+            return false;
+        }
+        if self.circuit_state == CircuitState::HalfOpen {
+            return true;
+        }
+        if config.failure_threshold > 0 && self.consecutive_failures >= config.failure_threshold {
+            return true;
+        }
+        self.samples.len() >= config.min_samples.max(1) as usize
+            && self.error_rate() >= config.error_rate_threshold
+    }
+
+    fn status(&self) -> &'static str {
+        match self.circuit_state {
+            CircuitState::Open | CircuitState::HalfOpen => "unhealthy",
+            CircuitState::Closed if self.consecutive_failures > 0 || self.error_rate() >= 0.2 => "degraded",
+            CircuitState::Closed if self.samples.is_empty() => "unknown",
+            CircuitState::Closed => "healthy",
+        }
+    }
+
+    fn snapshot(&self, now: Instant) -> HealthSnapshot {
+        let mut latencies = self
+            .samples
+            .iter()
+            .map(|sample| sample.latency_ms)
+            .collect::<Vec<_>>();
+        latencies.sort_unstable();
+
+        HealthSnapshot {
+            status: self.status(),
+            circuit_state: self.circuit_state,
+            sample_count: self.samples.len(),
+            error_rate: self.error_rate(),
+            consecutive_failures: self.consecutive_failures,
+            p50_latency_ms: percentile(&latencies, 50),
+            p95_latency_ms: percentile(&latencies, 95),
+            total_requests: self.total_requests,
+            total_failures: self.total_failures,
+            last_error: self.last_error.clone(),
+            last_failure_at: self.last_failure_at.clone(),
+            open_remaining_secs: self
+                .open_until
+                .filter(|_| self.circuit_state == CircuitState::Open)
+                .map(|until| until.saturating_duration_since(now).as_secs_f64().ceil() as u64),
+        }
+    }
+}
+
+/// 所有供应商的健康状态，由两个 Gateway 共享。
+#[derive(Default)]
+pub struct HealthRegistry {
+    providers: Mutex<HashMap<String, ProviderHealth>>,
+}
+
+impl HealthRegistry {
+    /// 请求前检查熔断状态；熔断中返回剩余时长。
+    ///
+    /// 熔断到期后进入半开状态，只放行一个试探请求。
+    pub fn check(&self, provider_id: &str, config: &CircuitBreakerConfig) -> Result<(), Duration> {
+        if !config.enabled {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut providers = self.providers.lock().unwrap_or_else(|error| error.into_inner());
+        let Some(health) = providers.get_mut(provider_id) else {
+            return Ok(());
+        };
+
+        match health.circuit_state {
+            CircuitState::Closed => Ok(()),
+            CircuitState::Open => {
+                let until = health.open_until.unwrap_or(now);
+                if until > now {
+                    return Err(until - now);
+                }
+                health.circuit_state = CircuitState::HalfOpen;
+                health.half_open_trial_started = Some(now);
+                Ok(())
+            }
+            CircuitState::HalfOpen => {
+                let trial_timeout = Duration::from_secs(config.open_secs);
+                match health.half_open_trial_started {
+                    Some(started) if now.duration_since(started) < trial_timeout => {
+                        Err(trial_timeout - now.duration_since(started))
+                    }
+                    _ => {
+                        health.half_open_trial_started = Some(now);
+                        Ok(())
+                    }
+                }
+            }
+        }
+    }
+
+    /// 记录一次上游请求结果，熔断状态发生变化时返回新状态。
+    pub fn record(
+        &self,
+        provider_id: &str,
+        config: &CircuitBreakerConfig,
+        latency: Duration,
+        error: Option<String>,
+    ) -> Option<CircuitState> {
+        let now = Instant::now();
+        let mut providers = self.providers.lock().unwrap_or_else(|error| error.into_inner());
+        let health = providers.entry(provider_id.to_string()).or_default();
+        let previous_state = health.circuit_state;
+
+        // 熔断恢复后重新统计，避免熔断前的失败样本让下一次失败立即再次熔断
+        if error.is_none() && previous_state != CircuitState::Closed {
+            health.samples.clear();
+        }
+        health.push_sample(Sample {
+            success: error.is_none(),
+            latency_ms: latency.as_millis() as u64,
+        });
+
+        match error {
+            None => {
+                health.consecutive_failures = 0;
+                health.circuit_state = CircuitState::Closed;
+                health.open_until = None;
+                health.half_open_trial_started = None;
+            }
+            Some(error) => {
+                health.consecutive_failures += 1;
+                health.total_failures += 1;
+                health.last_error = Some(error);
+                health.last_failure_at = Some(chrono::Local::now().to_rfc3339());
+                if health.should_open(config) {
+                    health.open(config, now);
+                }
+            }
+        }
+
+        (health.circuit_state != previous_state).then_some(health.circuit_state)
+    }
+
+    /// 主动探测结果：熔断中的供应商探测成功后提前进入半开状态，失败则延长熔断。
+    pub fn record_probe(
+        &self,
+        provider_id: &str,
+        config: &CircuitBreakerConfig,
+        reachable: bool,
+    ) -> Option<CircuitState> {
+        let now = Instant::now();
+        let mut providers = self.providers.lock().unwrap_or_else(|error| error.into_inner());
+        let health = providers.get_mut(provider_id)?;
+        if health.circuit_state != CircuitState::Open {
+            return None;
+        }
+
+        if reachable {
+            health.circuit_state = CircuitState::HalfOpen;
+            health.half_open_trial_started = None;
+            Some(CircuitState::HalfOpen)
+        } else {
+            health.open(config, now);
+            None
+        }
+    }
+
+    pub fn is_open(&self, provider_id: &str) -> bool {
+        let providers = self.providers.lock().unwrap_or_else(|error| error.into_inner());
+        providers
+            .get(provider_id)
+            .is_some_and(|health| health.circuit_state == CircuitState::Open)
+    }
+
+    /// 供应商当前的健康状态（healthy/degraded/unhealthy/unknown），未记录过请求时返回 None。
+    pub fn status(&self, provider_id: &str) -> Option<&'static str> {
+        let providers = self.providers.lock().unwrap_or_else(|error| error.into_inner());
+        providers.get(provider_id).map(ProviderHealth::status)
+    }
+
+    pub fn snapshot(&self, provider_id: &str) -> Option<HealthSnapshot> {
+        let providers = self.providers.lock().unwrap_or_else(|error| error.into_inner());
+        providers
+            .get(provider_id)
+            .map(|health| health.snapshot(Instant::now()))
+    }
+
+    pub fn snapshots(&self) -> HashMap<String, HealthSnapshot> {
+        let now = Instant::now();
+        let providers = self.providers.lock().unwrap_or_else(|error| error.into_inner());
+        providers
+            .iter()
+            .map(|(provider_id, health)| (provider_id.clone(), health.snapshot(now)))
+            .collect()
+    }
+}
+
+/// 上游状态码是否计为供应商故障（4xx 属于请求问题，不计入）。
+pub fn is_failure_status(status: u16) -> bool {
+    status == 408 || status >= 500
+}
+
+fn percentile(sorted: &[u64], percentile: usize) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let index = (sorted.len() * percentile).div_ceil(100).saturating_sub(1);
+    sorted.get(index.min(sorted.len() - 1)).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            failure_threshold: 3,
+            open_secs: 60,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn consecutive_failures_open_circuit_and_success_after_probe_closes_it() {
+        let registry = HealthRegistry::default();
+        let config = config();
+        let latency = Duration::from_millis(100);
+
+        assert_eq!(registry.record("relay", &config, latency, None), None);
+        assert_eq!(registry.record("relay", &config, latency, Some("529".into())), None);
+        assert_eq!(registry.record("relay", &config, latency, Some("529".into())), None);
+        assert_eq!(
+            registry.record("relay", &config, latency, Some("529".into())),
+            Some(CircuitState::Open)
+        );
+        assert!(registry.check("relay", &config).is_err());
+        assert_eq!(registry.snapshot("relay").unwrap().tray_badge(), Some("🔴"));
+
+        assert_eq!(
+            registry.record_probe("relay", &config, true),
+            Some(CircuitState::HalfOpen)
+        );
+        assert!(registry.check("relay", &config).is_ok());
+        assert!(registry.check("relay", &config).is_err());
+        assert_eq!(
+            registry.record("relay", &config, latency, None),
+            Some(CircuitState::Closed)
+        );
+        assert!(registry.check("relay", &config).is_ok());
+    }
+
+    #[test]
+    fn recovered_circuit_does_not_reopen_on_single_failure() {
+        let registry = HealthRegistry::default();
+        let config = CircuitBreakerConfig {
+            failure_threshold: 0,
+            min_samples: 4,
+            open_secs: 60,
+            ..Default::default()
+        };
+        let latency = Duration::from_millis(100);
+
+        for _ in 0..3 {
+            registry.record("relay", &config, latency, Some("502".into()));
+        }
+        assert_eq!(
+            registry.record("relay", &config, latency, Some("502".into())),
+            Some(CircuitState::Open)
+        );
+        registry.record_probe("relay", &config, true);
+        assert!(registry.check("relay", &config).is_ok());
+        assert_eq!(
+            registry.record("relay", &config, latency, None),
+            Some(CircuitState::Closed)
+        );
+
+        assert_eq!(registry.record("relay", &config, latency, Some("502".into())), None);
+        assert_eq!(registry.snapshot("relay").unwrap().sample_count, 2);
+    }
+
+    #[test]
+    fn snapshot_reports_error_rate_and_latency_percentiles() {
+        let registry = HealthRegistry::default();
+        let config = CircuitBreakerConfig {
+            enabled: false,
+            ..Default::default()
+        };
+
+        for latency_ms in 1..=100 {
+            let error = (latency_ms % 4 == 0).then(|| "status=502".to_string());
+            registry.record("relay", &config, Duration::from_millis(latency_ms), error);
+        }
+
+        let snapshot = registry.snapshot("relay").unwrap();
+        assert_eq!(snapshot.circuit_state, CircuitState::Closed);
+        assert_eq!(snapshot.sample_count, 100);
+        assert_eq!(snapshot.error_rate, 0.25);
+        assert_eq!(snapshot.p50_latency_ms, Some(50));
+        assert_eq!(snapshot.p95_latency_ms, Some(95));
+        assert_eq!(snapshot.status, "degraded");
+        assert_eq!(snapshot.consecutive_failures, 1);
+    }
+
+    #[test]
+    fn status_changes_without_circuit_transition() {
+        let registry = HealthRegistry::default();
+        let config = config();
+        let latency = Duration::from_millis(100);
+
+        assert_eq!(registry.status("relay"), None);
+        registry.record("relay", &config, latency, None);
+        assert_eq!(registry.status("relay"), Some("healthy"));
+        assert_eq!(registry.record("relay", &config, latency, Some("502".into())), None);
+        assert_eq!(registry.status("relay"), Some("degraded"));
+        assert_eq!(registry.snapshot("relay").unwrap().tray_badge(), Some("🟡"));
+    }
+}
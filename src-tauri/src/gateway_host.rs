@@ -0,0 +1,104 @@
+use crate::gateway_log::{self, GatewayKind, GatewayLogConfig, GatewayLogRecord, GatewayLogStore};
+use crate::store::AppConfig;
+use std::sync::{Arc, Mutex};
+
+/// Gateway 运行时事件的接收方，由 Tauri 应用、独立守护进程等宿主各自实现。
+pub trait GatewayEventSink: Send + Sync {
+    /// 推送一条实时日志
+    fn emit_log(&self, gateway: GatewayKind, level: &str, message: &str);
+
+    /// 某个供应商的健康状态或熔断状态发生变化
+    fn health_changed(&self) {}
+}
+
+/// 向 Gateway 提供运行期间可能被修改的配置项。
+pub trait GatewayConfigProvider: Send + Sync {
+    /// 对应 Gateway 开启磁盘日志时返回轮转配置
+    fn disk_logging(&self, gateway: GatewayKind) -> Option<GatewayLogConfig>;
+
+    fn capture_enabled(&self, gateway: GatewayKind) -> bool;
+}
+
+impl GatewayConfigProvider for Mutex<AppConfig> {
+    fn disk_logging(&self, gateway: GatewayKind) -> Option<GatewayLogConfig> {
+        let config = self.lock().ok()?;
+        let enabled = match gateway {
+            GatewayKind::Api => config.api_gateway.disk_logging_enabled,
+            GatewayKind::Codex => config.codex_gateway.disk_logging_enabled,
+        };
+        enabled.then(|| config.gateway_log.clone())
+    }
+
+    fn capture_enabled(&self, gateway: GatewayKind) -> bool {
+        self.lock().is_ok_and(|config| match gateway {
+            GatewayKind::Api => config.api_gateway.capture_enabled,
+            GatewayKind::Codex => config.codex_gateway.capture_enabled,
+        })
+    }
+}
+
+/// Gateway 运行所需的宿主环境：事件接收方、配置来源和磁盘日志写入器。
+#[derive(Clone)]
+pub struct GatewayHost {
+    sink: Arc<dyn GatewayEventSink>,
+    config: Arc<dyn GatewayConfigProvider>,
+    log_store: Arc<GatewayLogStore>,
+}
+
+impl GatewayHost {
+    pub fn new(
+        sink: Arc<dyn GatewayEventSink>,
+        config: Arc<dyn GatewayConfigProvider>,
+        log_store: Arc<GatewayLogStore>,
+    ) -> Self {
+        Self { sink, config, log_store }
+    }
+
+    /// 推送实时日志，同时按配置写入磁盘日志。
+    pub fn emit_log(&self, gateway: GatewayKind, level: &str, message: &str) {
+        self.write_record(&GatewayLogRecord::event(gateway, level, message));
+        self.sink.emit_log(gateway, level, message);
+    }
+
+    /// 写入一条结构化日志；对应 Gateway 未开启磁盘日志时忽略。
+    pub fn write_record(&self, record: &GatewayLogRecord) {
+        let Some(config) = self.config.disk_logging(record.gateway) else {
+            return;
+        };
+
+        let result = gateway_log::log_directory().and_then(|dir| self.log_store.append(&dir, record, &config));
+        if let Err(error) = result {
+            log::warn!("写入 Gateway 磁盘日志失败: {}", error);
+        }
+    }
+
+    pub fn capture_enabled(&self, gateway: GatewayKind) -> bool {
+        self.config.capture_enabled(gateway)
+    }
+
+    pub fn health_changed(&self) {
+        self.sink.health_changed();
+    }
+}
+
+/// 把实时日志逐行输出到标准输出，供守护进程（journald 等）和测试使用。
+pub struct ConsoleSink;
+
+impl GatewayEventSink for ConsoleSink {
+    fn emit_log(&self, gateway: GatewayKind, level: &str, message: &str) {
+        println!(
+            "{} [{}] [{}] {}",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            gateway_label(gateway),
+            level,
+            message
+        );
+    }
+}
+
+fn gateway_label(gateway: GatewayKind) -> &'static str {
+    match gateway {
+        GatewayKind::Api => "API Gateway",
+        GatewayKind::Codex => "Codex Gateway",
+    }
+}
@@ -0,0 +1,885 @@
+use crate::api_gateway;
+use crate::codex_gateway;
+use crate::gateway_context::ContextWindow;
+use crate::gateway_host::{ConsoleSink, GatewayHost};
+use crate::gateway_retry::RetryPolicy;
+use crate::gateway_structured::StructuredOutputMode;
+use crate::mock_upstream::{self, MockResponse, MockUpstream};
+use crate::provider::{CodexProvider, CodexProviderConfig, Provider};
+use crate::store::{ApiGatewayConfig, AppConfig, CodexGatewayConfig};
+use eventsource_stream::Eventsource;
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// 在临时端口上以无窗口模式运行的 Gateway，Drop 时关闭。
+struct TestGateway {
+    base_url: String,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+}
+
+impl Drop for TestGateway {
+    fn drop(&mut self) {
+        if let Some(shutdown_tx) = self.shutdown_tx.take() {
+            let _ = shutdown_tx.send(());
+        }
+    }
+}
+
+/// 输出到控制台、不写磁盘日志和抓包的宿主。
+fn test_host() -> GatewayHost {
+    GatewayHost::new(
+        Arc::new(ConsoleSink),
+        Arc::new(Mutex::new(AppConfig::default())),
+        Arc::default(),
+    )
+}
+
+async fn bind_ephemeral() -> (tokio::net::TcpListener, String) {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    (listener, base_url)
+}
+
+async fn start_api_gateway(provider: Provider, config: ApiGatewayConfig) -> TestGateway {
+    let (listener, base_url) = bind_ephemeral().await;
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    tokio::spawn(async move {
+        let provider_id = provider.id.clone();
+        api_gateway::serve_headless(listener, test_host(), &[provider], &provider_id, &config, shutdown_rx)
+            .await
+            .unwrap();
+    });
+    TestGateway {
+        base_url,
+        shutdown_tx: Some(shutdown_tx),
+    }
+}
+
+async fn start_codex_gateway(provider: CodexProvider, config: CodexGatewayConfig) -> TestGateway {
+    let (listener, base_url) = bind_ephemeral().await;
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    tokio::spawn(async move {
+        codex_gateway::serve_headless(listener, test_host(), &provider, &config, shutdown_rx)
+            .await
+            .unwrap();
+    });
+    TestGateway {
+        base_url,
+        shutdown_tx: Some(shutdown_tx),
+    }
+}
+
+fn anthropic_provider(base_url: &str) -> Provider {
+    Provider {
+        id: "mock".to_string(),
+        name: "Mock".to_string(),
+        settings_config: json!({
+            "env": {
+                "ANTHROPIC_AUTH_TOKEN": "sk-upstream",
+                "ANTHROPIC_BASE_URL": base_url,
+                "ANTHROPIC_MODEL": "mock-model"
+            }
+        }),
+        website_url: None,
+        category: None,
+        created_at: None,
+    }
+}
+
+fn codex_provider(base_url: &str) -> CodexProvider {
+    CodexProvider {
+        id: "mock".to_string(),
+        name: "Mock".to_string(),
+        codex_config: CodexProviderConfig {
+            provider_name: "mock".to_string(),
+            upstream_url: format!("{}/v1", base_url),
+            model_name: "mock-chat".to_string(),
+            api_key: "sk-upstream".to_string(),
+            ..Default::default()
+        },
+        website_url: None,
+        category: None,
+        created_at: None,
+    }
+}
+
+/// 重试间隔缩短到毫秒级，避免拖慢测试。
+fn fast_retry(max_retries: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_retries,
+        initial_backoff_ms: 1,
+        max_backoff_ms: 20,
+    }
+}
+
+fn api_config(max_retries: u32) -> ApiGatewayConfig {
+    ApiGatewayConfig {
+        retry: fast_retry(max_retries),
+        ..Default::default()
+    }
+}
+
+fn codex_config(max_retries: u32) -> CodexGatewayConfig {
+    CodexGatewayConfig {
+        retry: fast_retry(max_retries),
+        ..Default::default()
+    }
+}
+
+/// 读取整个 SSE 响应，返回 (事件名, 解析后的数据) 列表。
+async fn collect_sse(response: reqwest::Response) -> Vec<(String, Value)> {
+    let mut events = Vec::new();
+    let mut stream = response.bytes_stream().eventsource();
+    while let Some(event) = tokio::time::timeout(Duration::from_secs(10), stream.next())
+        .await
+        .expect("读取 Gateway SSE 超时")
+    {
+        let event = event.unwrap();
+        events.push((event.event, serde_json::from_str(&event.data).unwrap_or(Value::Null)));
+    }
+    events
+}
+
+fn event_names(events: &[(String, Value)]) -> Vec<&str> {
+    events.iter().map(|(name, _)| name.as_str()).collect()
+}
+
+#[tokio::test]
+async fn api_gateway_rewrites_alias_and_injects_upstream_auth() {
+    let upstream = MockUpstream::start([MockResponse::json(mock_upstream::anthropic_message("mock-model", "hello"))]).await;
+    let gateway = start_api_gateway(anthropic_provider(&upstream.base_url), api_config(0)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/messages", gateway.base_url))
+        .header("authorization", "Bearer client-token")
+        .json(&json!({
+            "model": "claude-mock:default",
+            "max_tokens": 16,
+            "messages": [{ "role": "user", "content": "hi" }]
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["content"][0]["text"], json!("hello"));
+
+    let requests = upstream.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, reqwest::Method::POST);
+    assert_eq!(requests[0].path, "/v1/messages");
+    assert_eq!(requests[0].query, None);
+    assert_eq!(requests[0].body["model"], json!("mock-model"));
+    assert_eq!(requests[0].headers.get("authorization").unwrap(), "Bearer sk-upstream");
+}
+
+#[tokio::test]
+async fn api_gateway_estimates_count_tokens_when_upstream_lacks_endpoint() {
+    let upstream = MockUpstream::start([MockResponse::error(404, json!({ "error": "not found" }))]).await;
+    let gateway = start_api_gateway(anthropic_provider(&upstream.base_url), api_config(0)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/messages/count_tokens", gateway.base_url))
+        .json(&json!({
+            "model": "claude-mock:default",
+            "messages": [{ "role": "user", "content": "abcdabcd" }]
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["input_tokens"].as_u64().unwrap() > 0);
+
+    let requests = upstream.requests();
+    assert_eq!(requests[0].path, "/v1/messages/count_tokens");
+    assert_eq!(requests[0].body["model"], json!("mock-model"));
+}
+
+#[tokio::test]
+async fn api_gateway_answers_count_tokens_locally_when_configured() {
+    let upstream = MockUpstream::start([]).await;
+    let mut provider = anthropic_provider(&upstream.base_url);
+    provider.settings_config["apiGateway"] = json!({ "localCountTokens": true });
+    let gateway = start_api_gateway(provider, api_config(0)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/messages/count_tokens", gateway.base_url))
+        .json(&json!({
+            "model": "mock-model",
+            "messages": [{ "role": "user", "content": "abcdabcd" }]
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["input_tokens"], json!(6));
+    assert!(upstream.requests().is_empty());
+}
+
+/// 请求日志在响应体释放后才计入指标，轮询直到出现期望的行。
+async fn wait_for_metric(base_url: &str, expected: &str) -> String {
+    let mut metrics = String::new();
+    for _ in 0..50 {
+        metrics = reqwest::get(format!("{}/metrics", base_url))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        if metrics.contains(expected) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    metrics
+}
+
+#[tokio::test]
+async fn api_gateway_exposes_prometheus_metrics() {
+    let upstream = MockUpstream::start([MockResponse::sse_events([
+        (
+            "message_start",
+            json!({ "type": "message_start", "message": { "usage": { "input_tokens": 42, "output_tokens": 1 } } }),
+        ),
+        ("message_delta", json!({ "type": "message_delta", "usage": { "output_tokens": 17 } })),
+        ("message_stop", json!({ "type": "message_stop" })),
+    ])])
+    .await;
+    let gateway = start_api_gateway(anthropic_provider(&upstream.base_url), api_config(0)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/messages", gateway.base_url))
+        .json(&json!({ "model": "claude-mock:default", "stream": true, "messages": [] }))
+        .send()
+        .await
+        .unwrap();
+    collect_sse(response).await;
+
+    let labels = "gateway=\"api\",provider=\"mock\",model=\"claude-mock:default\"";
+    let metrics = wait_for_metric(&gateway.base_url, "switchcc_gateway_requests_total{").await;
+    assert!(metrics.contains(&format!("switchcc_gateway_requests_total{{{}}} 1", labels)));
+    assert!(metrics.contains(&format!("switchcc_gateway_upstream_responses_total{{{},status=\"200\"}} 1", labels)));
+    assert!(metrics.contains(&format!("switchcc_gateway_in_flight_streams{{{}}} 0", labels)));
+    assert!(metrics.contains(&format!("switchcc_gateway_tokens_total{{{},direction=\"input\"}} 42", labels)));
+    assert!(metrics.contains(&format!("switchcc_gateway_tokens_total{{{},direction=\"output\"}} 17", labels)));
+}
+
+#[tokio::test]
+async fn api_gateway_passes_through_delayed_sse_chunks() {
+    let upstream = MockUpstream::start([MockResponse::sse_events([
+        ("message_start", json!({ "type": "message_start", "message": { "id": "msg_mock" } })),
+        (
+            "content_block_delta",
+            json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "text_delta", "text": "he" } }),
+        ),
+        (
+            "content_block_delta",
+            json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "text_delta", "text": "llo" } }),
+        ),
+        ("message_stop", json!({ "type": "message_stop" })),
+    ])
+    .with_chunk_delay(Duration::from_millis(20))])
+    .await;
+    let gateway = start_api_gateway(anthropic_provider(&upstream.base_url), api_config(0)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/messages", gateway.base_url))
+        .json(&json!({ "model": "claude-mock:default", "stream": true, "messages": [] }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let events = collect_sse(response).await;
+    assert_eq!(
+        event_names(&events),
+        ["message_start", "content_block_delta", "content_block_delta", "message_stop"]
+    );
+    assert_eq!(events[2].1["delta"]["text"], json!("llo"));
+}
+
+#[tokio::test]
+async fn api_gateway_retries_overloaded_upstream() {
+    let upstream = MockUpstream::start([
+        MockResponse::error(529, json!({ "type": "error", "error": { "type": "overloaded_error" } })),
+        MockResponse::json(mock_upstream::anthropic_message("mock-model", "ok")),
+    ])
+    .await;
+    let gateway = start_api_gateway(anthropic_provider(&upstream.base_url), api_config(2)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/messages", gateway.base_url))
+        .json(&json!({ "model": "claude-mock:default", "messages": [] }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(upstream.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn api_gateway_circuit_rejections_do_not_consume_rate_limit() {
+    let upstream = MockUpstream::start([
+        MockResponse::error(502, json!({ "error": "bad gateway" })),
+        MockResponse::json(mock_upstream::anthropic_message("mock-model", "ok")),
+    ])
+    .await;
+    let mut provider = anthropic_provider(&upstream.base_url);
+    provider.settings_config["apiGateway"] = json!({
+        "rateLimit": { "requestsPerMinute": 2, "maxQueueWaitMs": 0 }
+    });
+    let mut config = api_config(0);
+    config.circuit_breaker.failure_threshold = 1;
+    config.circuit_breaker.open_secs = 1;
+    let gateway = start_api_gateway(provider, config).await;
+
+    let client = reqwest::Client::new();
+    let send = || {
+        client
+            .post(format!("{}/v1/messages", gateway.base_url))
+            .json(&json!({ "model": "claude-mock:default", "messages": [] }))
+            .send()
+    };
+    assert_eq!(send().await.unwrap().status(), 502);
+    assert_eq!(send().await.unwrap().status(), 503);
+
+    // 熔断期间被拒绝的请求不占用每分钟请求数，熔断到期后的试探请求仍可发出
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert_eq!(send().await.unwrap().status(), 200);
+    assert_eq!(upstream.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn api_gateway_rotates_to_next_key_after_rejection() {
+    let upstream = MockUpstream::start([
+        MockResponse::error(401, json!({ "type": "error", "error": { "type": "authentication_error" } })),
+        MockResponse::json(mock_upstream::anthropic_message("mock-model", "ok")),
+    ])
+    .await;
+    let mut provider = anthropic_provider(&upstream.base_url);
+    provider.settings_config["apiGateway"] = json!({
+        "keyPool": { "keys": [{ "key": "sk-second", "label": "second" }] }
+    });
+    let gateway = start_api_gateway(provider, api_config(0)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/messages", gateway.base_url))
+        .json(&json!({ "model": "claude-mock:default", "messages": [] }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let requests = upstream.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].headers.get("authorization").unwrap(), "Bearer sk-upstream");
+    assert_eq!(requests[1].headers.get("authorization").unwrap(), "Bearer sk-second");
+    assert_eq!(requests[1].headers.get("x-api-key").unwrap(), "sk-second");
+}
+
+#[tokio::test]
+async fn api_gateway_reports_unreachable_upstream_as_bad_gateway() {
+    let base_url = mock_upstream::unreachable_base_url().await;
+    let gateway = start_api_gateway(anthropic_provider(&base_url), api_config(0)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/messages", gateway.base_url))
+        .json(&json!({ "model": "claude-mock:default", "messages": [] }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 502);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], json!("api_gateway_proxy_failed"));
+}
+
+#[tokio::test]
+async fn codex_gateway_converts_non_stream_chat_completion() {
+    let upstream = MockUpstream::start([MockResponse::json(mock_upstream::chat_completion("mock-chat", "hello"))]).await;
+    let gateway = start_codex_gateway(codex_provider(&upstream.base_url), codex_config(0)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/responses", gateway.base_url))
+        .json(&json!({ "model": "gpt-5", "input": "hi", "stream": false }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["status"], json!("completed"));
+    assert_eq!(body["output"][0]["content"][0]["text"], json!("hello"));
+
+    let requests = upstream.requests();
+    assert_eq!(requests[0].path, "/v1/chat/completions");
+    assert_eq!(requests[0].body["model"], json!("mock-chat"));
+    assert_eq!(requests[0].headers.get("authorization").unwrap(), "Bearer sk-upstream");
+}
+
+#[tokio::test]
+async fn codex_gateway_round_robins_keys_and_skips_rate_limited_key() {
+    let upstream = MockUpstream::start([
+        MockResponse::json(mock_upstream::chat_completion("mock-chat", "one")),
+        MockResponse::error(429, json!({ "error": { "message": "quota exceeded" } })),
+        MockResponse::json(mock_upstream::chat_completion("mock-chat", "two")),
+        MockResponse::json(mock_upstream::chat_completion("mock-chat", "three")),
+    ])
+    .await;
+    let mut provider = codex_provider(&upstream.base_url);
+    provider.codex_config.key_pool = serde_json::from_value(json!({
+        "keys": [{ "key": "sk-second" }],
+        "rotation": "roundRobin"
+    }))
+    .unwrap();
+    let gateway = start_codex_gateway(provider, codex_config(1)).await;
+
+    let client = reqwest::Client::new();
+    for _ in 0..3 {
+        let response = client
+            .post(format!("{}/v1/responses", gateway.base_url))
+            .json(&json!({ "model": "gpt-5", "input": "hi", "stream": false }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+    }
+
+    // 第二个密钥被限流后进入冷却，重试和之后的请求都改用主密钥
+    let keys = upstream
+        .requests()
+        .iter()
+        .map(|request| request.headers.get("authorization").unwrap().to_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, ["Bearer sk-upstream", "Bearer sk-second", "Bearer sk-upstream", "Bearer sk-upstream"]);
+}
+
+#[tokio::test]
+async fn codex_gateway_rejects_invalid_non_stream_body() {
+    let upstream = MockUpstream::start([
+        MockResponse::raw(200, "application/json", "not json").delayed(Duration::from_millis(50)),
+    ])
+    .await;
+    let gateway = start_codex_gateway(codex_provider(&upstream.base_url), codex_config(0)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/responses", gateway.base_url))
+        .json(&json!({ "model": "gpt-5", "input": "hi", "stream": false }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 502);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], json!("invalid_upstream_response"));
+}
+
+#[tokio::test]
+async fn codex_gateway_streams_text_and_tool_calls_to_completion() {
+    let upstream = MockUpstream::start([MockResponse::sse_data([
+        mock_upstream::chat_chunk(json!({ "role": "assistant", "content": "Checking" }), None),
+        mock_upstream::chat_chunk(
+            json!({ "tool_calls": [{
+                "index": 0,
+                "id": "call_1",
+                "type": "function",
+                "function": { "name": "get_weather", "arguments": "{\"city\"" }
+            }] }),
+            None,
+        ),
+        mock_upstream::chat_chunk(
+            json!({ "tool_calls": [{ "index": 0, "function": { "arguments": ":\"SF\"}" } }] }),
+            None,
+        ),
+        mock_upstream::chat_chunk(json!({}), Some("tool_calls")),
+        json!({ "choices": [], "usage": {
+            "prompt_tokens": 30,
+            "completion_tokens": 12,
+            "prompt_tokens_details": { "cached_tokens": 16 },
+            "completion_tokens_details": { "reasoning_tokens": 4 }
+        } }),
+    ])
+    .with_chunk_delay(Duration::from_millis(10))])
+    .await;
+    let gateway = start_codex_gateway(codex_provider(&upstream.base_url), codex_config(0)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/responses", gateway.base_url))
+        .json(&json!({ "model": "gpt-5", "input": "weather?", "stream": true }))
+        .send()
+        .await
+        .unwrap();
+
+    let events = collect_sse(response).await;
+    let names = event_names(&events);
+    assert_eq!(names.first(), Some(&"response.created"));
+    assert!(names.contains(&"response.output_text.delta"));
+    assert_eq!(names.last(), Some(&"response.completed"));
+
+    let output = events.last().unwrap().1["response"]["output"].as_array().unwrap().clone();
+    let function_call = output.iter().find(|item| item["type"] == "function_call").unwrap();
+    assert_eq!(function_call["name"], json!("get_weather"));
+    assert_eq!(function_call["arguments"], json!("{\"city\":\"SF\"}"));
+
+    let usage = &events.last().unwrap().1["response"]["usage"];
+    assert_eq!(usage["input_tokens_details"]["cached_tokens"], json!(16));
+    assert_eq!(usage["output_tokens_details"]["reasoning_tokens"], json!(4));
+    assert_eq!(
+        upstream.requests()[0].body["stream_options"],
+        json!({ "include_usage": true })
+    );
+}
+
+#[tokio::test]
+async fn codex_gateway_streams_parallel_tool_call_arguments_in_order() {
+    let upstream = MockUpstream::start([MockResponse::sse_data([
+        mock_upstream::chat_chunk(json!({ "reasoning_content": "plan" }), None),
+        mock_upstream::chat_chunk(
+            json!({ "tool_calls": [
+                { "index": 0, "id": "call_a", "function": { "name": "read", "arguments": "{\"path\"" } },
+                { "index": 1, "id": "call_b", "function": { "name": "read", "arguments": "{\"path\"" } }
+            ] }),
+            None,
+        ),
+        mock_upstream::chat_chunk(json!({ "tool_calls": [{ "index": 1, "function": { "arguments": ":\"b\"}" } }] }), None),
+        mock_upstream::chat_chunk(json!({ "tool_calls": [{ "index": 0, "function": { "arguments": ":\"a\"}" } }] }), None),
+        mock_upstream::chat_chunk(json!({ "content": "Reading both." }), None),
+        mock_upstream::chat_chunk(json!({}), Some("tool_calls")),
+    ])])
+    .await;
+    let gateway = start_codex_gateway(codex_provider(&upstream.base_url), codex_config(0)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/responses", gateway.base_url))
+        .json(&json!({ "model": "gpt-5", "input": "read a and b", "stream": true }))
+        .send()
+        .await
+        .unwrap();
+
+    let events = collect_sse(response).await;
+    let argument_deltas = events
+        .iter()
+        .filter(|(name, _)| name == "response.function_call_arguments.delta")
+        .map(|(_, event)| (event["output_index"].as_u64().unwrap(), event["delta"].as_str().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        argument_deltas,
+        [(1, "{\"path\""), (2, "{\"path\""), (2, ":\"b\"}"), (1, ":\"a\"}")]
+    );
+
+    let first_delta = event_names(&events)
+        .iter()
+        .position(|name| *name == "response.function_call_arguments.delta")
+        .unwrap();
+    let added = event_names(&events)
+        .iter()
+        .position(|name| *name == "response.output_item.added")
+        .unwrap();
+    assert!(added < first_delta);
+
+    let output = events.last().unwrap().1["response"]["output"].as_array().unwrap().clone();
+    let kinds = output.iter().map(|item| item["type"].as_str().unwrap()).collect::<Vec<_>>();
+    assert_eq!(kinds, ["reasoning", "function_call", "function_call", "message"]);
+    assert_eq!(output[1]["call_id"], json!("call_a"));
+    assert_eq!(output[1]["arguments"], json!("{\"path\":\"a\"}"));
+    assert_eq!(output[2]["arguments"], json!("{\"path\":\"b\"}"));
+}
+
+#[tokio::test]
+async fn codex_gateway_resumes_stream_dropped_before_text() {
+    let upstream = MockUpstream::start([
+        MockResponse::sse_raw([
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"reasoning_content\":\"plan\"},\"finish_reason\":null}]}\n\n",
+        ]),
+        MockResponse::sse_data([
+            mock_upstream::chat_chunk(json!({ "content": "recovered" }), None),
+            mock_upstream::chat_chunk(json!({}), Some("stop")),
+        ]),
+    ])
+    .await;
+    let mut config = codex_config(0);
+    config.stream.max_resumes = 1;
+    let gateway = start_codex_gateway(codex_provider(&upstream.base_url), config).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/responses", gateway.base_url))
+        .json(&json!({ "model": "gpt-5", "input": "hi", "stream": true }))
+        .send()
+        .await
+        .unwrap();
+
+    let events = collect_sse(response).await;
+    assert!(!event_names(&events).contains(&"response.failed"));
+    let completed = &events.last().unwrap().1;
+    assert_eq!(completed["type"], json!("response.completed"));
+    let message = completed["response"]["output"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|item| item["type"] == "message")
+        .unwrap()
+        .clone();
+    assert_eq!(message["content"][0]["text"], json!("recovered"));
+    assert_eq!(upstream.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn codex_gateway_enforces_and_reports_context_window() {
+    let upstream = MockUpstream::start([]).await;
+    let mut provider = codex_provider(&upstream.base_url);
+    provider.codex_config.context_window = ContextWindow {
+        length: Some(1_000),
+        ..Default::default()
+    };
+    let gateway = start_codex_gateway(provider, codex_config(0)).await;
+    let client = reqwest::Client::new();
+
+    let models: Value = client
+        .get(format!("{}/v1/models", gateway.base_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(models["data"][0]["context_window"], json!(1_000));
+
+    let response = client
+        .post(format!("{}/v1/responses", gateway.base_url))
+        .json(&json!({ "model": "gpt-5", "input": "x".repeat(8_000), "stream": true }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["message"].as_str().unwrap().contains("上下文窗口"));
+    assert!(upstream.requests().is_empty());
+}
+
+#[tokio::test]
+async fn codex_gateway_skips_malformed_sse_chunks() {
+    let upstream = MockUpstream::start([MockResponse::sse_raw([
+        "data: {not json\n\n",
+        "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"ok\"},\"finish_reason\":null}]}\n\n",
+        "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n",
+        "data: [DONE]\n\n",
+    ])])
+    .await;
+    let gateway = start_codex_gateway(codex_provider(&upstream.base_url), codex_config(0)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/responses", gateway.base_url))
+        .json(&json!({ "model": "gpt-5", "input": "hi", "stream": true }))
+        .send()
+        .await
+        .unwrap();
+
+    let events = collect_sse(response).await;
+    let completed = &events.last().unwrap().1;
+    assert_eq!(completed["type"], json!("response.completed"));
+    assert_eq!(completed["response"]["output"][0]["content"][0]["text"], json!("ok"));
+}
+
+#[tokio::test]
+async fn codex_gateway_reports_upstream_error_as_failed_event() {
+    let upstream = MockUpstream::start([MockResponse::error(500, json!({ "error": { "message": "boom" } }))]).await;
+    let gateway = start_codex_gateway(codex_provider(&upstream.base_url), codex_config(0)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/responses", gateway.base_url))
+        .json(&json!({ "model": "gpt-5", "input": "hi", "stream": true }))
+        .send()
+        .await
+        .unwrap();
+
+    let events = collect_sse(response).await;
+    let (name, failed) = events.last().unwrap();
+    assert_eq!(name, "response.failed");
+    assert!(failed["response"]["error"]["message"].as_str().unwrap().contains("boom"));
+}
+
+#[tokio::test]
+async fn codex_gateway_forwards_chat_completions_stream() {
+    let upstream = MockUpstream::start([MockResponse::sse_data([
+        mock_upstream::chat_chunk(json!({ "role": "assistant", "content": "hel" }), None),
+        mock_upstream::chat_chunk(json!({ "content": "lo" }), Some("stop")),
+    ])])
+    .await;
+    let gateway = start_codex_gateway(codex_provider(&upstream.base_url), codex_config(0)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/chat/completions", gateway.base_url))
+        .json(&json!({
+            "model": "gpt-4o",
+            "stream": true,
+            "messages": [{ "role": "user", "content": "hi" }]
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let events = collect_sse(response).await;
+    let text = events
+        .iter()
+        .filter_map(|(_, chunk)| chunk["choices"][0]["delta"]["content"].as_str())
+        .collect::<String>();
+    assert_eq!(text, "hello");
+    assert_eq!(events.last().unwrap().1, Value::Null);
+
+    let requests = upstream.requests();
+    assert_eq!(requests[0].path, "/v1/chat/completions");
+    assert_eq!(requests[0].body["model"], json!("mock-chat"));
+    assert_eq!(requests[0].body["messages"][0]["content"], json!("hi"));
+    assert_eq!(requests[0].headers.get("authorization").unwrap(), "Bearer sk-upstream");
+}
+
+#[tokio::test]
+async fn codex_gateway_emulates_local_shell_and_custom_tools() {
+    let upstream = MockUpstream::start([MockResponse::sse_data([
+        mock_upstream::chat_chunk(
+            json!({ "tool_calls": [{
+                "index": 0,
+                "id": "call_shell",
+                "type": "function",
+                "function": { "name": "local_shell", "arguments": "{\"command\":[\"ls\"]}" }
+            }, {
+                "index": 1,
+                "id": "call_patch",
+                "type": "function",
+                "function": { "name": "apply_patch", "arguments": "{\"input\":\"*** Begin Patch\\n*** End Patch\"}" }
+            }] }),
+            None,
+        ),
+        mock_upstream::chat_chunk(json!({}), Some("tool_calls")),
+    ])])
+    .await;
+    let gateway = start_codex_gateway(codex_provider(&upstream.base_url), codex_config(0)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/responses", gateway.base_url))
+        .json(&json!({
+            "model": "gpt-5",
+            "input": "list files",
+            "stream": true,
+            "tools": [
+                { "type": "local_shell" },
+                { "type": "custom", "name": "apply_patch", "format": { "type": "grammar", "syntax": "lark", "definition": "start: patch" } },
+                { "type": "web_search_preview" }
+            ]
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    let events = collect_sse(response).await;
+    let output = events.last().unwrap().1["response"]["output"].as_array().unwrap().clone();
+    assert_eq!(output[0]["type"], json!("local_shell_call"));
+    assert_eq!(output[0]["call_id"], json!("call_shell"));
+    assert_eq!(output[0]["action"]["command"], json!(["ls"]));
+    assert_eq!(output[1]["type"], json!("custom_tool_call"));
+    assert_eq!(output[1]["input"], json!("*** Begin Patch\n*** End Patch"));
+
+    let tools = upstream.requests()[0].body["tools"].as_array().unwrap().clone();
+    let names = tools
+        .iter()
+        .map(|tool| tool["function"]["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["local_shell", "apply_patch"]);
+}
+
+fn weather_text_format() -> Value {
+    json!({
+        "format": {
+            "type": "json_schema",
+            "name": "weather",
+            "strict": true,
+            "schema": {
+                "type": "object",
+                "properties": { "city": { "type": "string" }, "celsius": { "type": "integer" } },
+                "required": ["city", "celsius"],
+                "additionalProperties": false
+            }
+        }
+    })
+}
+
+#[tokio::test]
+async fn codex_gateway_maps_json_schema_to_chat_response_format() {
+    let upstream = MockUpstream::start([MockResponse::json(mock_upstream::chat_completion(
+        "mock-chat",
+        r#"{"city":"Paris","celsius":12}"#,
+    ))])
+    .await;
+    let gateway = start_codex_gateway(codex_provider(&upstream.base_url), codex_config(0)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/responses", gateway.base_url))
+        .json(&json!({ "model": "gpt-5", "stream": false, "input": "weather?", "text": weather_text_format() }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let request = &upstream.requests()[0].body;
+    assert_eq!(request["response_format"]["type"], json!("json_schema"));
+    assert_eq!(request["response_format"]["json_schema"]["name"], json!("weather"));
+    assert_eq!(request["response_format"]["json_schema"]["strict"], json!(true));
+    assert_eq!(
+        request["response_format"]["json_schema"]["schema"],
+        weather_text_format()["format"]["schema"]
+    );
+}
+
+#[tokio::test]
+async fn codex_gateway_json_object_fallback_validates_output() {
+    let upstream = MockUpstream::start([
+        MockResponse::json(mock_upstream::chat_completion("mock-chat", r#"{"city":"Paris","celsius":12}"#)),
+        MockResponse::json(mock_upstream::chat_completion("mock-chat", r#"{"city":"Paris"}"#)),
+    ])
+    .await;
+    let mut provider = codex_provider(&upstream.base_url);
+    provider.codex_config.structured_output = StructuredOutputMode::JsonObject;
+    let gateway = start_codex_gateway(provider, codex_config(0)).await;
+    let client = reqwest::Client::new();
+    let request_body = json!({
+        "model": "gpt-5",
+        "stream": false,
+        "instructions": "be brief",
+        "input": "weather?",
+        "text": weather_text_format()
+    });
+
+    let valid = client
+        .post(format!("{}/v1/responses", gateway.base_url))
+        .json(&request_body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(valid.status(), 200);
+
+    let invalid = client
+        .post(format!("{}/v1/responses", gateway.base_url))
+        .json(&request_body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(invalid.status(), 502);
+    let error = invalid.json::<Value>().await.unwrap();
+    assert_eq!(error["error"], json!("structured_output_invalid"));
+    assert!(error["message"].as_str().unwrap().contains("celsius"));
+
+    let request = &upstream.requests()[0].body;
+    assert_eq!(request["response_format"], json!({ "type": "json_object" }));
+    let system = request["messages"][0]["content"].as_str().unwrap();
+    assert!(system.starts_with("be brief"));
+    assert!(system.contains("\"celsius\""));
+}
@@ -0,0 +1,584 @@
+use crate::gateway_retry::{self, RetryAttempt, RetryPolicy};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 密钥认证失败（401/403）后暂停使用的时长
+const AUTH_FAILURE_COOLDOWN: Duration = Duration::from_secs(600);
+
+/// 密钥池的轮换方式。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum KeyRotation {
+    /// 始终使用第一个可用密钥，被上游拒绝后才切换
+    #[default]
+    Failover,
+    /// 每个请求依次使用下一个可用密钥
+    RoundRobin,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PooledKey {
+    pub key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+}
+
+/// 供应商的额外 API Key，与主密钥一起组成密钥池。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyPoolConfig {
+    #[serde(default)]
+    pub keys: Vec<PooledKey>,
+    #[serde(default)]
+    pub rotation: KeyRotation,
+    /// 密钥被限流（429）或额度不足（402）后暂停使用的秒数
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+}
+
+fn default_cooldown_secs() -> u64 {
+    60
+}
+
+impl Default for KeyPoolConfig {
+    fn default() -> Self {
+        Self {
+            keys: Vec::new(),
+            rotation: KeyRotation::default(),
+            cooldown_secs: default_cooldown_secs(),
+        }
+    }
+}
+
+impl KeyPoolConfig {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PoolEntry<T> {
+    fingerprint: String,
+    label: String,
+    secret: String,
+    disabled: bool,
+    credential: T,
+}
+
+/// 运行时的密钥池，`T` 为发送请求所需的凭据。
+#[derive(Debug, Clone)]
+pub struct KeyPool<T> {
+    entries: Vec<PoolEntry<T>>,
+    rotation: KeyRotation,
+    cooldown: Duration,
+}
+
+impl<T> KeyPool<T> {
+    /// 主密钥在前，其后依次是 `config.keys`；`credential` 把密钥转换为请求凭据。
+    ///
+    /// 主密钥为空且配置了其他密钥时不加入主密钥；池中至少保留一项。
+    pub fn new(primary: &str, config: &KeyPoolConfig, credential: impl Fn(&str) -> T) -> Self {
+        let mut entries = Vec::new();
+        let mut push = |secret: &str, label: Option<&str>, disabled: bool| {
+            entries.push(PoolEntry {
+                fingerprint: fingerprint(secret),
+                label: label
+                    .map(str::trim)
+                    .filter(|label| !label.is_empty())
+                    .map(str::to_string)
+                    .unwrap_or_else(|| mask_key(secret)),
+                secret: secret.to_string(),
+                disabled,
+                credential: credential(secret),
+            });
+        };
+
+        let primary = primary.trim();
+        let extra_keys = config
+            .keys
+            .iter()
+            .filter(|key| !key.key.trim().is_empty())
+            .collect::<Vec<_>>();
+        if !primary.is_empty() || extra_keys.is_empty() {
+            push(primary, None, false);
+        }
+        for key in extra_keys {
+            push(key.key.trim(), key.label.as_deref(), key.disabled);
+        }
+
+        Self {
+            entries,
+            rotation: config.rotation,
+            cooldown: Duration::from_secs(config.cooldown_secs.max(1)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// 第一个密钥的凭据，用于探测和抓包展示。
+    pub fn primary(&self) -> &T {
+        &self.entries[0].credential
+    }
+
+    pub fn credentials(&self) -> impl Iterator<Item = &T> {
+        self.entries.iter().map(|entry| &entry.credential)
+    }
+
+    pub fn credential(&self, index: usize) -> &T {
+        &self.entries[index].credential
+    }
+
+    pub fn label(&self, index: usize) -> &str {
+        &self.entries[index].label
+    }
+
+    /// 池中所有密钥原文，用于抓包脱敏。
+    pub fn secrets(&self) -> impl Iterator<Item = &str> {
+        self.entries
+            .iter()
+            .map(|entry| entry.secret.as_str())
+            .filter(|secret| !secret.is_empty())
+    }
+
+    fn has_untried(&self, tried: &[usize]) -> bool {
+        self.entries
+            .iter()
+            .enumerate()
+            .any(|(index, entry)| !entry.disabled && !tried.contains(&index))
+    }
+}
+
+/// 单个密钥的使用情况快照，用于状态接口展示。
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeySnapshot {
+    pub label: String,
+    /// `active`、`cooling` 或 `disabled`
+    pub status: &'static str,
+    pub total_requests: u64,
+    pub total_failures: u64,
+    pub last_status: Option<u16>,
+    pub last_error: Option<String>,
+    pub last_used_at: Option<String>,
+    /// 暂停使用的剩余秒数
+    pub cooldown_remaining_secs: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct KeyHealth {
+    fingerprint: String,
+    label: String,
+    disabled: bool,
+    total_requests: u64,
+    total_failures: u64,
+    last_status: Option<u16>,
+    last_error: Option<String>,
+    last_used_at: Option<String>,
+    cooldown_until: Option<Instant>,
+}
+
+impl KeyHealth {
+    fn is_cooling(&self, now: Instant) -> bool {
+        self.cooldown_until.is_some_and(|until| until > now)
+    }
+
+    fn snapshot(&self, now: Instant) -> KeySnapshot {
+        let status = if self.disabled {
+            "disabled"
+        } else if self.is_cooling(now) {
+            "cooling"
+        } else {
+            "active"
+        };
+        KeySnapshot {
+            label: self.label.clone(),
+            status,
+            total_requests: self.total_requests,
+            total_failures: self.total_failures,
+            last_status: self.last_status,
+            last_error: self.last_error.clone(),
+            last_used_at: self.last_used_at.clone(),
+            cooldown_remaining_secs: self
+                .cooldown_until
+                .filter(|until| *until > now)
+                .map(|until| until.saturating_duration_since(now).as_secs_f64().ceil() as u64),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ProviderKeys {
+    keys: Vec<KeyHealth>,
+    next: usize,
+}
+
+impl ProviderKeys {
+    /// 与当前密钥池对齐，保留仍在池中的密钥的统计。
+    fn sync<T>(&mut self, pool: &KeyPool<T>) {
+        let unchanged = self.keys.len() == pool.entries.len()
+            && self.keys.iter().zip(&pool.entries).all(|(health, entry)| {
+                health.fingerprint == entry.fingerprint
+                    && health.label == entry.label
+                    && health.disabled == entry.disabled
+            });
+        if unchanged {
+            return;
+        }
+
+        let mut previous = std::mem::take(&mut self.keys)
+            .into_iter()
+            .map(|health| (health.fingerprint.clone(), health))
+            .collect::<HashMap<_, _>>();
+        self.keys = pool
+            .entries
+            .iter()
+            .map(|entry| {
+                let mut health = previous.remove(&entry.fingerprint).unwrap_or_default();
+                health.fingerprint = entry.fingerprint.clone();
+                health.label = entry.label.clone();
+                health.disabled = entry.disabled;
+                health
+            })
+            .collect();
+    }
+}
+
+/// 所有供应商的密钥使用情况，由两个 Gateway 共享。
+#[derive(Default)]
+pub struct KeyPoolRegistry {
+    providers: Mutex<HashMap<String, ProviderKeys>>,
+}
+
+impl KeyPoolRegistry {
+    /// 选出本次请求使用的密钥，跳过已停用和 `tried` 中的密钥并优先使用不在冷却中的密钥。
+    ///
+    /// 所有密钥都试过时不再排除 `tried`；全部冷却中时选冷却最早结束的一个。
+    pub fn select<T>(&self, provider_id: &str, pool: &KeyPool<T>, tried: &[usize]) -> usize {
+        let now = Instant::now();
+        let mut providers = self.providers.lock().unwrap_or_else(|error| error.into_inner());
+        let keys = providers.entry(provider_id.to_string()).or_default();
+        keys.sync(pool);
+
+        let enabled = (0..pool.len())
+            .filter(|index| !pool.entries[*index].disabled)
+            .collect::<Vec<_>>();
+        let untried = enabled
+            .iter()
+            .copied()
+            .filter(|index| !tried.contains(index))
+            .collect::<Vec<_>>();
+        let candidates = match (untried.is_empty(), enabled.is_empty()) {
+            (false, _) => untried,
+            (true, false) => enabled,
+            (true, true) => (0..pool.len()).collect(),
+        };
+
+        let start = match pool.rotation {
+            KeyRotation::Failover => 0,
+            KeyRotation::RoundRobin => keys.next % pool.len(),
+        };
+        let order = |index: &usize| (index + pool.len() - start) % pool.len();
+        let selected = candidates
+            .iter()
+            .copied()
+            .filter(|index| !keys.keys[*index].is_cooling(now))
+            .min_by_key(order)
+            .or_else(|| {
+                candidates
+                    .iter()
+                    .copied()
+                    .min_by_key(|index| keys.keys[*index].cooldown_until)
+            })
+            .unwrap_or(0);
+
+        if pool.rotation == KeyRotation::RoundRobin {
+            keys.next = selected + 1;
+        }
+        let health = &mut keys.keys[selected];
+        health.total_requests += 1;
+        health.last_used_at = Some(chrono::Local::now().to_rfc3339());
+        selected
+    }
+
+    /// 记录一次上游结果：密钥被拒绝时按状态码进入冷却，其他失败只计数。
+    pub fn record<T>(&self, provider_id: &str, pool: &KeyPool<T>, index: usize, status: Option<u16>, error: Option<String>) {
+        let mut providers = self.providers.lock().unwrap_or_else(|error| error.into_inner());
+        let keys = providers.entry(provider_id.to_string()).or_default();
+        keys.sync(pool);
+        let Some(health) = keys.keys.get_mut(index) else {
+            return;
+        };
+
+        health.last_status = status;
+        match (status, error) {
+            (_, Some(error)) => {
+                health.total_failures += 1;
+                health.last_error = Some(error);
+            }
+            (Some(status), None) if is_key_failure_status(status) => {
+                health.total_failures += 1;
+                health.last_error = Some(format!("status={}", status));
+                let cooldown = if matches!(status, 401 | 403) {
+                    AUTH_FAILURE_COOLDOWN
+                } else {
+                    pool.cooldown
+                };
+                health.cooldown_until = Some(Instant::now() + cooldown);
+            }
+            (Some(status), None) if status >= 500 => {
+                health.total_failures += 1;
+                health.last_error = Some(format!("status={}", status));
+            }
+            _ => health.cooldown_until = None,
+        }
+    }
+
+    pub fn snapshots(&self) -> HashMap<String, Vec<KeySnapshot>> {
+        let now = Instant::now();
+        let providers = self.providers.lock().unwrap_or_else(|error| error.into_inner());
+        providers
+            .iter()
+            .map(|(provider_id, keys)| {
+                let snapshots = keys.keys.iter().map(|health| health.snapshot(now)).collect();
+                (provider_id.clone(), snapshots)
+            })
+            .collect()
+    }
+}
+
+/// 上游状态码是否表示当前密钥不可用（认证失败、限流或额度不足），应换用其他密钥。
+pub fn is_key_failure_status(status: u16) -> bool {
+    matches!(status, 401 | 402 | 403 | 429)
+}
+
+/// 一次因密钥被上游拒绝而换用其他密钥的记录，用于写入 Gateway 日志。
+#[derive(Debug, Clone)]
+pub struct KeyFailover {
+    pub label: String,
+    pub status: u16,
+}
+
+/// 使用密钥池发送上游请求。
+///
+/// 每次发送（包括重试）前选择密钥；当前密钥被拒绝（401/402/403/429）且还有未试过的密钥时
+/// 立即换用下一个，否则把最后一次响应返回给调用方。
+pub async fn send_with_keys<T, B, L, F>(
+    registry: &KeyPoolRegistry,
+    provider_id: &str,
+    pool: &KeyPool<T>,
+    policy: &RetryPolicy,
+    mut build_request: B,
+    mut on_retry: L,
+    mut on_failover: F,
+) -> Result<reqwest::Response, reqwest::Error>
+where
+    B: FnMut(&T) -> reqwest::RequestBuilder,
+    L: FnMut(&RetryAttempt),
+    F: FnMut(&KeyFailover),
+{
+    // 两个回调都要读写已试过的密钥，且请求 future 需要满足 Send
+    let tried = Mutex::new(Vec::new());
+    let current = AtomicUsize::new(0);
+    let tried_keys = || tried.lock().unwrap_or_else(|error| error.into_inner());
+
+    loop {
+        let result = gateway_retry::send_with_retry(
+            policy,
+            || {
+                let index = registry.select(provider_id, pool, &tried_keys());
+                current.store(index, Ordering::Relaxed);
+                build_request(pool.credential(index))
+            },
+            |attempt| {
+                if let Some(status) = attempt.status {
+                    registry.record(provider_id, pool, current.load(Ordering::Relaxed), Some(status), None);
+                }
+                on_retry(attempt)
+            },
+            // 还有未试过的密钥时，429 等密钥失败直接交给下面换密钥，不按 retry-after 退避
+            |response| {
+                if !is_key_failure_status(response.status().as_u16()) {
+                    return false;
+                }
+                let mut tried = tried_keys().clone();
+                tried.push(current.load(Ordering::Relaxed));
+                pool.has_untried(&tried)
+            },
+        )
+        .await;
+
+        let index = current.load(Ordering::Relaxed);
+        match &result {
+            Ok(response) => {
+                let status = response.status().as_u16();
+                registry.record(provider_id, pool, index, Some(status), None);
+                if is_key_failure_status(status) {
+                    let mut tried = tried_keys();
+                    tried.push(index);
+                    if pool.has_untried(&tried) {
+                        on_failover(&KeyFailover {
+                            label: pool.label(index).to_string(),
+                            status,
+                        });
+                        continue;
+                    }
+                }
+            }
+            Err(error) => registry.record(provider_id, pool, index, None, Some(error.to_string())),
+        }
+        return result;
+    }
+}
+
+/// 密钥指纹，只用于在内存中区分密钥，不保存原文。
+fn fingerprint(secret: &str) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, secret.as_bytes());
+    digest.as_ref()[..8].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// 未设置标签时展示的密钥，只保留前后各 4 个字符。
+fn mask_key(secret: &str) -> String {
+    let chars = secret.chars().collect::<Vec<_>>();
+    if chars.len() <= 12 {
+        return "****".to_string();
+    }
+    let head = chars[..4].iter().collect::<String>();
+    let tail = chars[chars.len() - 4..].iter().collect::<String>();
+    format!("{}…{}", head, tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_upstream::{MockResponse, MockUpstream};
+    use serde_json::json;
+
+    fn pool(rotation: KeyRotation) -> KeyPool<String> {
+        let config = KeyPoolConfig {
+            keys: vec![
+                PooledKey {
+                    key: "sk-second-key-0002".to_string(),
+                    label: Some("second".to_string()),
+                    disabled: false,
+                },
+                PooledKey {
+                    key: "sk-disabled-key-03".to_string(),
+                    label: None,
+                    disabled: true,
+                },
+                PooledKey {
+                    key: "sk-third-key-00004".to_string(),
+                    label: Some("third".to_string()),
+                    disabled: false,
+                },
+            ],
+            rotation,
+            cooldown_secs: 60,
+        };
+        KeyPool::new("sk-primary-key-0001", &config, str::to_string)
+    }
+
+    #[test]
+    fn round_robin_skips_disabled_and_cooling_keys() {
+        let registry = KeyPoolRegistry::default();
+        let pool = pool(KeyRotation::RoundRobin);
+
+        let picks = (0..4).map(|_| registry.select("relay", &pool, &[])).collect::<Vec<_>>();
+        assert_eq!(picks, vec![0, 1, 3, 0]);
+
+        registry.record("relay", &pool, 1, Some(429), None);
+        let picks = (0..3).map(|_| registry.select("relay", &pool, &[])).collect::<Vec<_>>();
+        assert_eq!(picks, vec![3, 0, 3]);
+
+        let snapshots = registry.snapshots().remove("relay").unwrap();
+        assert_eq!(snapshots[0].label, "sk-p…0001");
+        assert_eq!(snapshots[1].status, "cooling");
+        assert_eq!(snapshots[1].last_status, Some(429));
+        assert_eq!(snapshots[2].status, "disabled");
+        assert_eq!(snapshots[3].total_requests, 3);
+    }
+
+    #[test]
+    fn failover_sticks_to_first_key_until_rejected() {
+        let registry = KeyPoolRegistry::default();
+        let pool = pool(KeyRotation::Failover);
+
+        assert_eq!(registry.select("relay", &pool, &[]), 0);
+        assert_eq!(registry.select("relay", &pool, &[]), 0);
+        registry.record("relay", &pool, 0, Some(401), None);
+        assert_eq!(registry.select("relay", &pool, &[]), 1);
+        assert_eq!(registry.select("relay", &pool, &[1]), 3);
+        // 全部试过后仍返回一个密钥，由上游响应决定结果
+        assert_eq!(registry.select("relay", &pool, &[0, 1, 3]), 1);
+        assert_eq!(pool.secrets().count(), 4);
+    }
+
+    /// 返回最终状态码、同一密钥的重试次数和换密钥时的状态码
+    async fn send(
+        registry: &KeyPoolRegistry,
+        pool: &KeyPool<String>,
+        policy: &RetryPolicy,
+        base_url: &str,
+    ) -> (u16, u32, Vec<u16>) {
+        let client = reqwest::Client::new();
+        let (mut retries, mut failovers) = (0, Vec::new());
+        let response = send_with_keys(
+            registry,
+            "relay",
+            pool,
+            policy,
+            |key| client.post(format!("{}/v1/messages", base_url)).bearer_auth(key),
+            |_| retries += 1,
+            |failover| failovers.push(failover.status),
+        )
+        .await
+        .unwrap();
+        (response.status().as_u16(), retries, failovers)
+    }
+
+    #[tokio::test]
+    async fn rate_limited_key_fails_over_without_backoff() {
+        let rate_limited = || MockResponse::error(429, json!({ "error": "rate limited" }));
+        let upstream = MockUpstream::start([
+            rate_limited(),
+            MockResponse::json(json!({ "ok": true })),
+            rate_limited(),
+            MockResponse::json(json!({ "ok": true })),
+        ])
+        .await;
+        let policy = RetryPolicy {
+            max_retries: 1,
+            initial_backoff_ms: 5_000,
+            max_backoff_ms: 5_000,
+        };
+
+        // 还有未试过的密钥时立即换用，不等待退避，也不计为同一密钥的重试
+        let started_at = Instant::now();
+        let result = send(&KeyPoolRegistry::default(), &pool(KeyRotation::Failover), &policy, &upstream.base_url).await;
+        assert_eq!(result, (200, 0, vec![429]));
+        assert!(started_at.elapsed() < Duration::from_secs(2));
+        let keys = upstream
+            .requests()
+            .iter()
+            .map(|request| request.headers.get("authorization").unwrap().to_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["Bearer sk-primary-key-0001", "Bearer sk-second-key-0002"]);
+
+        // 只有一个密钥时按策略退避重试，不报告换密钥
+        let single = KeyPool::new("sk-only-key-000005", &KeyPoolConfig::default(), str::to_string);
+        let policy = RetryPolicy {
+            initial_backoff_ms: 10,
+            max_backoff_ms: 10,
+            ..policy
+        };
+        let result = send(&KeyPoolRegistry::default(), &single, &policy, &upstream.base_url).await;
+        assert_eq!(result, (200, 1, vec![]));
+    }
+}
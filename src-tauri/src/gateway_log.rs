@@ -0,0 +1,846 @@
+use crate::gateway_host::GatewayHost;
+use crate::gateway_metrics::GatewayMetrics;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use uuid::Uuid;
+
+const ACTIVE_LOG_FILE: &str = "gateway.jsonl";
+const ROTATED_LOG_PREFIX: &str = "gateway-";
+const ROTATED_LOG_SUFFIX: &str = ".jsonl";
+/// 旧版 Codex Gateway 按天写入的纯文本日志，只参与过期清理
+const LEGACY_CODEX_LOG_PREFIX: &str = "codex-gateway-";
+const DEFAULT_QUERY_LIMIT: usize = 100;
+const MAX_QUERY_LIMIT: usize = 1000;
+/// 倒序读取日志文件时每次读入的字节数
+const REVERSE_READ_CHUNK: u64 = 64 * 1024;
+
+/// 磁盘日志的轮转与保留配置，两个 Gateway 共用。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GatewayLogConfig {
+    /// 单个日志文件超过该大小后轮转
+    pub max_file_size_mb: u64,
+    /// 当前日志文件创建超过该时长后轮转，0 表示不按时间轮转
+    pub max_file_age_hours: u64,
+    /// 最多保留的日志文件数（含当前文件）
+    pub max_files: usize,
+    /// 轮转后的日志文件保留天数
+    pub retention_days: u64,
+}
+
+impl Default for GatewayLogConfig {
+    fn default() -> Self {
+        Self {
+            max_file_size_mb: 10,
+            max_file_age_hours: 24,
+            max_files: 10,
+            retention_days: 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GatewayKind {
+    Api,
+    Codex,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRecordKind {
+    /// 转发过程中的文本事件（与日志窗口中的实时日志一致）
+    Event,
+    /// 单个请求结束后的汇总记录
+    Request,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    RateLimited,
+    CircuitOpen,
+    InvalidRequest,
+    Connect,
+    Timeout,
+    UpstreamClient,
+    UpstreamServer,
+    Stream,
+    Gateway,
+}
+
+impl ErrorClass {
+    pub fn from_status(status: u16) -> Option<Self> {
+        match status {
+            408 => Some(Self::Timeout),
+            400..=499 => Some(Self::UpstreamClient),
+            500..=599 => Some(Self::UpstreamServer),
+            _ => None,
+        }
+    }
+
+    pub fn from_reqwest(error: &reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Self::Timeout
+        } else if error.is_connect() {
+            Self::Connect
+        } else {
+            Self::Gateway
+        }
+    }
+}
+
+/// 写入 `gateway.jsonl` 的一行记录。
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GatewayLogRecord {
+    /// RFC3339 本地时间
+    pub timestamp: String,
+    pub gateway: GatewayKind,
+    pub kind: LogRecordKind,
+    pub level: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// 收到上游响应头的耗时
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    /// 请求从进入 Gateway 到响应结束的总耗时
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_tokens: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_class: Option<ErrorClass>,
+}
+
+impl GatewayLogRecord {
+    pub fn event(gateway: GatewayKind, level: &str, message: &str) -> Self {
+        Self {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            gateway,
+            kind: LogRecordKind::Event,
+            level: level.to_string(),
+            message: message.to_string(),
+            request_id: None,
+            provider_id: None,
+            model: None,
+            method: None,
+            path: None,
+            status: None,
+            latency_ms: None,
+            duration_ms: None,
+            request_bytes: None,
+            response_bytes: None,
+            input_tokens: None,
+            output_tokens: None,
+            error_class: None,
+        }
+    }
+}
+
+/// 一次转发请求的汇总日志，Drop 时写入磁盘。
+///
+/// 流式响应中随响应体一起移动，因此会在流结束（或下游断开）后才落盘。
+pub struct RequestLog {
+    host: GatewayHost,
+    metrics: Arc<GatewayMetrics>,
+    record: GatewayLogRecord,
+    started_at: Instant,
+    error: Option<String>,
+    /// 本地产生的状态码（限流、熔断等）不计入上游状态码指标
+    upstream_status: Option<u16>,
+    streaming: bool,
+}
+
+impl RequestLog {
+    pub fn start(
+        host: &GatewayHost,
+        metrics: &Arc<GatewayMetrics>,
+        gateway: GatewayKind,
+        method: &str,
+        path: &str,
+        request_bytes: usize,
+    ) -> Self {
+        let mut record = GatewayLogRecord::event(gateway, "info", "");
+        record.kind = LogRecordKind::Request;
+        record.request_id = Some(format!("req_{}", Uuid::new_v4().simple()));
+        record.method = Some(method.to_string());
+        record.path = Some(path.to_string());
+        record.request_bytes = Some(request_bytes as u64);
+
+        Self {
+            host: host.clone(),
+            metrics: metrics.clone(),
+            record,
+            started_at: Instant::now(),
+            error: None,
+            upstream_status: None,
+            streaming: false,
+        }
+    }
+
+    pub fn request_id(&self) -> &str {
+        self.record.request_id.as_deref().unwrap_or_default()
+    }
+
+    pub fn set_route(&mut self, provider_id: &str, model: Option<&str>) {
+        self.record.provider_id = Some(provider_id.to_string());
+        self.record.model = model.map(str::to_string);
+    }
+
+    pub fn set_upstream_status(&mut self, status: u16) {
+        self.upstream_status = Some(status);
+        self.record.status = Some(status);
+        self.record.latency_ms = Some(self.started_at.elapsed().as_millis() as u64);
+        if let Some(error_class) = ErrorClass::from_status(status) {
+            self.record.error_class = Some(error_class);
+        }
+    }
+
+    /// 记录本地产生的响应状态（限流、熔断等未到达上游的请求）。
+    pub fn set_local_status(&mut self, status: u16, error_class: ErrorClass, error: impl Into<String>) {
+        self.record.status = Some(status);
+        self.fail(error_class, error);
+    }
+
+    /// 记录由 Gateway 本地应答成功的请求，覆盖此前上游返回的错误状态。
+    pub fn set_local_response(&mut self, status: u16) {
+        self.record.status = Some(status);
+        self.record.error_class = None;
+        self.error = None;
+    }
+
+    pub fn fail(&mut self, error_class: ErrorClass, error: impl Into<String>) {
+        self.record.error_class = Some(error_class);
+        self.error = Some(error.into());
+    }
+
+    pub fn add_response_bytes(&mut self, bytes: usize) {
+        *self.record.response_bytes.get_or_insert(0) += bytes as u64;
+    }
+
+    /// 记录上游 usage 中的 token 数，None 表示保留已有值。
+    pub fn set_usage(&mut self, input_tokens: Option<u64>, output_tokens: Option<u64>) {
+        if input_tokens.is_some() {
+            self.record.input_tokens = input_tokens;
+        }
+        if output_tokens.is_some() {
+            self.record.output_tokens = output_tokens;
+        }
+    }
+
+    /// 标记响应以流式返回，结束前计入进行中的流。
+    pub fn start_stream(&mut self) {
+        if !self.streaming {
+            self.streaming = true;
+            self.metrics
+                .stream_started(self.record.provider_id.as_deref(), self.record.model.as_deref());
+        }
+    }
+}
+
+impl Drop for RequestLog {
+    fn drop(&mut self) {
+        let mut record = self.record.clone();
+        if record.status.is_none() && record.error_class.is_none() {
+            record.error_class = Some(ErrorClass::Gateway);
+        }
+        record.duration_ms = Some(self.started_at.elapsed().as_millis() as u64);
+        record.level = if record.error_class.is_some() { "error" } else { "info" }.to_string();
+        record.message = format!(
+            "{} {} -> {} {}ms{}",
+            record.method.as_deref().unwrap_or("-"),
+            record.path.as_deref().unwrap_or("-"),
+            record
+                .status
+                .map(|status| status.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            record.duration_ms.unwrap_or_default(),
+            self.error
+                .as_deref()
+                .map(|error| format!(" {}", error))
+                .unwrap_or_default()
+        );
+        if self.streaming {
+            self.metrics
+                .stream_finished(record.provider_id.as_deref(), record.model.as_deref());
+        }
+        self.metrics.observe_request(&record, self.upstream_status);
+        self.host.write_record(&record);
+    }
+}
+
+/// Gateway 磁盘日志写入器，由同一进程内的 Gateway 共享。
+///
+/// 记录经通道交给唯一的后台写入线程，转发路径上不做文件 IO；写入线程持有当前日志文件并负责轮转。
+#[derive(Default)]
+pub struct GatewayLogStore {
+    sender: OnceLock<mpsc::Sender<LogCommand>>,
+}
+
+enum LogCommand {
+    Write {
+        dir: PathBuf,
+        line: String,
+        config: GatewayLogConfig,
+    },
+    #[cfg(test)]
+    Flush(mpsc::Sender<()>),
+}
+
+impl GatewayLogStore {
+    /// 把记录交给后台写入线程，首次调用时启动该线程。
+    pub fn append(&self, dir: &Path, record: &GatewayLogRecord, config: &GatewayLogConfig) -> Result<(), String> {
+        let mut line = serde_json::to_string(record).map_err(|error| format!("序列化日志失败: {}", error))?;
+        line.push('\n');
+
+        self.send(LogCommand::Write {
+            dir: dir.to_path_buf(),
+            line,
+            config: config.clone(),
+        })
+    }
+
+    /// 等待此前提交的记录全部落盘。
+    #[cfg(test)]
+    pub fn flush(&self) {
+        let (done_tx, done_rx) = mpsc::channel();
+        if self.send(LogCommand::Flush(done_tx)).is_ok() {
+            let _ = done_rx.recv();
+        }
+    }
+
+    fn send(&self, command: LogCommand) -> Result<(), String> {
+        let sender = self.sender.get_or_init(|| {
+            let (sender, receiver) = mpsc::channel();
+            // 线程启动失败时 receiver 随闭包一起释放，之后的写入会返回错误
+            let _ = thread::Builder::new()
+                .name("gateway-log-writer".to_string())
+                .spawn(move || run_writer(receiver));
+            sender
+        });
+        sender
+            .send(command)
+            .map_err(|_| "Gateway 日志写入线程已退出".to_string())
+    }
+}
+
+fn run_writer(receiver: mpsc::Receiver<LogCommand>) {
+    let mut active = None;
+    for command in receiver {
+        match command {
+            LogCommand::Write { dir, line, config } => {
+                if let Err(error) = write_line(&mut active, &dir, &line, &config, SystemTime::now()) {
+                    log::warn!("写入 Gateway 磁盘日志失败: {}", error);
+                }
+            }
+            #[cfg(test)]
+            LogCommand::Flush(done_tx) => {
+                let _ = done_tx.send(());
+            }
+        }
+    }
+}
+
+/// 写入线程当前打开的日志文件。
+struct ActiveLog {
+    dir: PathBuf,
+    file: fs::File,
+    size: u64,
+    created_at: SystemTime,
+}
+
+impl ActiveLog {
+    fn open(dir: &Path, now: SystemTime) -> Result<Self, String> {
+        fs::create_dir_all(dir).map_err(|error| format!("创建日志目录失败: {}", error))?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(ACTIVE_LOG_FILE))
+            .map_err(|error| format!("打开日志文件失败: {}", error))?;
+        let metadata = file
+            .metadata()
+            .map_err(|error| format!("读取日志文件信息失败: {}", error))?;
+        // 沿用上次运行留下的文件时，按其创建时间计算轮转时长
+        let created_at = if metadata.len() == 0 {
+            now
+        } else {
+            metadata.created().or_else(|_| metadata.modified()).unwrap_or(now)
+        };
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            file,
+            size: metadata.len(),
+            created_at,
+        })
+    }
+
+    fn should_rotate(&self, next_len: usize, config: &GatewayLogConfig, now: SystemTime) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        let max_size = config.max_file_size_mb.max(1) * 1024 * 1024;
+        let max_age = (config.max_file_age_hours > 0).then(|| Duration::from_secs(config.max_file_age_hours * 60 * 60));
+        self.size + next_len as u64 > max_size
+            || max_age.is_some_and(|max_age| {
+                now.duration_since(self.created_at)
+                    .is_ok_and(|age| age >= max_age)
+            })
+    }
+}
+
+/// 追加一行日志，必要时先轮转；出错后丢弃打开的文件，下次写入时重新打开。
+fn write_line(
+    active: &mut Option<ActiveLog>,
+    dir: &Path,
+    line: &str,
+    config: &GatewayLogConfig,
+    now: SystemTime,
+) -> Result<(), String> {
+    let mut log = match active.take() {
+        Some(log) if log.dir == dir => log,
+        _ => ActiveLog::open(dir, now)?,
+    };
+    if log.should_rotate(line.len(), config, now) {
+        drop(log);
+        rotate(dir, config)?;
+        log = ActiveLog::open(dir, now)?;
+    }
+
+    log.file
+        .write_all(line.as_bytes())
+        .map_err(|error| format!("写入日志文件失败: {}", error))?;
+    log.size += line.len() as u64;
+    *active = Some(log);
+    Ok(())
+}
+
+/// 磁盘日志查询条件。
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GatewayLogQuery {
+    pub gateway: Option<GatewayKind>,
+    pub kind: Option<LogRecordKind>,
+    pub provider_id: Option<String>,
+    pub level: Option<String>,
+    pub errors_only: bool,
+    /// 在消息、请求 ID、供应商、模型、路径中做不区分大小写的子串匹配
+    pub search: Option<String>,
+    /// RFC3339 时间范围
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GatewayLogPage {
+    /// 按时间倒序
+    pub entries: Vec<GatewayLogRecord>,
+    pub has_more: bool,
+}
+
+pub fn log_directory() -> Result<PathBuf, String> {
+    let home_dir = dirs::home_dir().ok_or("无法获取用户目录")?;
+    Ok(home_dir.join(".switchcc").join("logs"))
+}
+
+pub fn query(dir: &Path, query: &GatewayLogQuery) -> Result<GatewayLogPage, String> {
+    let limit = query.limit.unwrap_or(DEFAULT_QUERY_LIMIT).clamp(1, MAX_QUERY_LIMIT);
+    let since = parse_time_bound(query.since.as_deref())?;
+    let until = parse_time_bound(query.until.as_deref())?;
+    let search = query
+        .search
+        .as_deref()
+        .map(|search| search.trim().to_lowercase())
+        .filter(|search| !search.is_empty());
+
+    // 从最新的记录开始倒序扫描，凑够当前页（再多一条用于判断 has_more）即停止
+    let wanted = query.offset + limit;
+    let mut entries = Vec::new();
+    let mut matched = 0usize;
+    let mut has_more = false;
+
+    'files: for path in log_files_newest_first(dir)? {
+        let Ok(lines) = ReverseLines::open(&path) else {
+            continue;
+        };
+        for record in lines.filter_map(|line| serde_json::from_str::<GatewayLogRecord>(&line).ok()) {
+            if !matches_query(&record, query, since, until, search.as_deref()) {
+                continue;
+            }
+            if matched >= wanted {
+                has_more = true;
+                break 'files;
+            }
+            if matched >= query.offset {
+                entries.push(record);
+            }
+            matched += 1;
+        }
+    }
+
+    Ok(GatewayLogPage { entries, has_more })
+}
+
+/// 从文件末尾按块向前读取，逐行返回（最后一行在前）。
+struct ReverseLines {
+    file: fs::File,
+    /// 尚未读入的前半部分长度
+    position: u64,
+    /// 已读入但还没有返回的内容
+    pending: Vec<u8>,
+}
+
+impl ReverseLines {
+    fn open(path: &Path) -> std::io::Result<Self> {
+        let file = fs::File::open(path)?;
+        let position = file.metadata()?.len();
+        Ok(Self {
+            file,
+            position,
+            pending: Vec::new(),
+        })
+    }
+
+    fn read_previous_chunk(&mut self) -> std::io::Result<()> {
+        let size = REVERSE_READ_CHUNK.min(self.position);
+        self.position -= size;
+        self.file.seek(SeekFrom::Start(self.position))?;
+        let mut chunk = vec![0; size as usize];
+        self.file.read_exact(&mut chunk)?;
+        chunk.append(&mut self.pending);
+        self.pending = chunk;
+        Ok(())
+    }
+}
+
+impl Iterator for ReverseLines {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        loop {
+            if let Some(index) = self.pending.iter().rposition(|byte| *byte == b'\n') {
+                let line = self.pending.split_off(index + 1);
+                self.pending.truncate(index);
+                if !line.is_empty() {
+                    return Some(String::from_utf8_lossy(&line).into_owned());
+                }
+                continue;
+            }
+            if self.position == 0 {
+                if self.pending.is_empty() {
+                    return None;
+                }
+                let line = std::mem::take(&mut self.pending);
+                return Some(String::from_utf8_lossy(&line).into_owned());
+            }
+            self.read_previous_chunk().ok()?;
+        }
+    }
+}
+
+fn matches_query(
+    record: &GatewayLogRecord,
+    query: &GatewayLogQuery,
+    since: Option<chrono::DateTime<chrono::FixedOffset>>,
+    until: Option<chrono::DateTime<chrono::FixedOffset>>,
+    search: Option<&str>,
+) -> bool {
+    if query.gateway.is_some_and(|gateway| gateway != record.gateway) {
+        return false;
+    }
+    if query.kind.is_some_and(|kind| kind != record.kind) {
+        return false;
+    }
+    if query
+        .provider_id
+        .as_deref()
+        .is_some_and(|provider_id| record.provider_id.as_deref() != Some(provider_id))
+    {
+        return false;
+    }
+    if query
+        .level
+        .as_deref()
+        .is_some_and(|level| !record.level.eq_ignore_ascii_case(level))
+    {
+        return false;
+    }
+    if query.errors_only && record.error_class.is_none() && record.level != "error" {
+        return false;
+    }
+
+    if since.is_some() || until.is_some() {
+        let Ok(timestamp) = chrono::DateTime::parse_from_rfc3339(&record.timestamp) else {
+            return false;
+        };
+        if since.is_some_and(|since| timestamp < since) || until.is_some_and(|until| timestamp > until) {
+            return false;
+        }
+    }
+
+    let Some(search) = search else {
+        return true;
+    };
+    [
+        Some(record.message.as_str()),
+        record.request_id.as_deref(),
+        record.provider_id.as_deref(),
+        record.model.as_deref(),
+        record.path.as_deref(),
+    ]
+    .into_iter()
+    .flatten()
+    .any(|value| value.to_lowercase().contains(search))
+}
+
+fn parse_time_bound(value: Option<&str>) -> Result<Option<chrono::DateTime<chrono::FixedOffset>>, String> {
+    value
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            chrono::DateTime::parse_from_rfc3339(value).map_err(|error| format!("时间格式错误({}): {}", value, error))
+        })
+        .transpose()
+}
+
+/// 当前日志文件在前，轮转文件按文件名（时间戳）倒序。
+fn log_files_newest_first(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    let active_path = dir.join(ACTIVE_LOG_FILE);
+    if active_path.exists() {
+        files.push(active_path);
+    }
+    files.extend(rotated_log_files(dir)?.into_iter().rev());
+    Ok(files)
+}
+
+/// 轮转后的日志文件，按文件名（时间戳）升序。
+fn rotated_log_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = fs::read_dir(dir)
+        .map_err(|error| format!("读取日志目录失败: {}", error))?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(ROTATED_LOG_PREFIX) && name.ends_with(ROTATED_LOG_SUFFIX))
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+fn rotate(dir: &Path, config: &GatewayLogConfig) -> Result<(), String> {
+    let rotated_name = format!(
+        "{}{}{}",
+        ROTATED_LOG_PREFIX,
+        chrono::Local::now().format("%Y%m%d-%H%M%S%.3f"),
+        ROTATED_LOG_SUFFIX
+    );
+    fs::rename(dir.join(ACTIVE_LOG_FILE), dir.join(rotated_name))
+        .map_err(|error| format!("轮转日志文件失败: {}", error))?;
+    prune(dir, config, SystemTime::now())
+}
+
+/// 按数量和保留天数清理轮转后的日志文件以及旧版 Codex 文本日志。
+pub fn prune(dir: &Path, config: &GatewayLogConfig, now: SystemTime) -> Result<(), String> {
+    let retention = Duration::from_secs(config.retention_days * 24 * 60 * 60);
+    let is_expired = |path: &Path| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age > retention)
+    };
+
+    let rotated = rotated_log_files(dir)?;
+    let keep = config.max_files.saturating_sub(1);
+    let overflow = rotated.len().saturating_sub(keep);
+    for (index, path) in rotated.iter().enumerate() {
+        if index < overflow || is_expired(path) {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    if let Ok(entries) = fs::read_dir(dir) {
+        for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+            let is_legacy = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(LEGACY_CODEX_LOG_PREFIX) && name.ends_with(".log"));
+            if is_legacy && is_expired(&path) {
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("switch-cc-{}-{}", name, Uuid::new_v4().simple()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn request_record(gateway: GatewayKind, provider_id: &str, status: u16) -> GatewayLogRecord {
+        let mut record = GatewayLogRecord::event(gateway, "info", &format!("POST /v1/messages -> {}", status));
+        record.kind = LogRecordKind::Request;
+        record.provider_id = Some(provider_id.to_string());
+        record.status = Some(status);
+        record.error_class = ErrorClass::from_status(status);
+        record
+    }
+
+    #[test]
+    fn append_rotates_and_prunes_by_file_count() {
+        let dir = temp_dir("rotate");
+        let store = GatewayLogStore::default();
+        let config = GatewayLogConfig {
+            max_file_size_mb: 1,
+            max_files: 3,
+            ..Default::default()
+        };
+        let mut record = GatewayLogRecord::event(GatewayKind::Codex, "info", "");
+        record.message = "x".repeat(300 * 1024);
+
+        for _ in 0..12 {
+            store.append(&dir, &record, &config).unwrap();
+            store.flush();
+            // 轮转文件名精确到毫秒，避免同名覆盖
+            std::thread::sleep(Duration::from_millis(2));
+        }
+
+        assert!(dir.join(ACTIVE_LOG_FILE).exists());
+        assert_eq!(rotated_log_files(&dir).unwrap().len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn active_file_rotates_once_older_than_max_age() {
+        let dir = temp_dir("rotate-age");
+        let config = GatewayLogConfig {
+            max_file_age_hours: 24,
+            ..Default::default()
+        };
+        let now = SystemTime::now();
+        let mut active = None;
+
+        write_line(&mut active, &dir, "{}\n", &config, now).unwrap();
+        write_line(&mut active, &dir, "{}\n", &config, now + Duration::from_secs(23 * 60 * 60)).unwrap();
+        assert!(rotated_log_files(&dir).unwrap().is_empty());
+
+        write_line(&mut active, &dir, "{}\n", &config, now + Duration::from_secs(24 * 60 * 60)).unwrap();
+        assert_eq!(rotated_log_files(&dir).unwrap().len(), 1);
+        assert_eq!(fs::read_to_string(dir.join(ACTIVE_LOG_FILE)).unwrap(), "{}\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reverse_lines_reads_across_chunk_boundaries() {
+        let dir = temp_dir("reverse");
+        let path = dir.join(ACTIVE_LOG_FILE);
+        let long_line = "长".repeat(REVERSE_READ_CHUNK as usize);
+        fs::write(&path, format!("first\n{}\nlast\n", long_line)).unwrap();
+
+        let lines = ReverseLines::open(&path).unwrap().collect::<Vec<_>>();
+        assert_eq!(lines, vec!["last".to_string(), long_line, "first".to_string()]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn query_filters_and_pages_newest_first() {
+        let dir = temp_dir("query");
+        let store = GatewayLogStore::default();
+        let config = GatewayLogConfig::default();
+
+        store
+            .append(&dir, &request_record(GatewayKind::Api, "relay-a", 200), &config)
+            .unwrap();
+        store
+            .append(&dir, &request_record(GatewayKind::Api, "relay-b", 529), &config)
+            .unwrap();
+        store
+            .append(&dir, &GatewayLogRecord::event(GatewayKind::Codex, "warn", "SSE 读取超时"), &config)
+            .unwrap();
+        store
+            .append(&dir, &request_record(GatewayKind::Api, "relay-a", 502), &config)
+            .unwrap();
+        store.flush();
+
+        let page = query(
+            &dir,
+            &GatewayLogQuery {
+                gateway: Some(GatewayKind::Api),
+                errors_only: true,
+                limit: Some(1),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(page.entries.len(), 1);
+        assert!(page.has_more);
+        assert_eq!(page.entries[0].status, Some(502));
+
+        let page = query(
+            &dir,
+            &GatewayLogQuery {
+                search: Some("超时".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(page.entries.len(), 1);
+        assert!(!page.has_more);
+        assert_eq!(page.entries[0].gateway, GatewayKind::Codex);
+
+        let page = query(
+            &dir,
+            &GatewayLogQuery {
+                provider_id: Some("relay-a".to_string()),
+                offset: 1,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(page.entries.len(), 1);
+        assert!(!page.has_more);
+        assert_eq!(page.entries[0].status, Some(200));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
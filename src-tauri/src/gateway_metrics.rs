@@ -0,0 +1,314 @@
+use crate::gateway_log::{ErrorClass, GatewayKind, GatewayLogRecord};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// 延迟直方图的桶上界（秒）
+const LATENCY_BUCKETS: [f64; 12] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+/// 非流式响应体超过该大小时不再解析 usage
+const MAX_USAGE_BODY_BYTES: usize = 4 * 1024 * 1024;
+const UNKNOWN_LABEL: &str = "unknown";
+/// `/metrics` 响应的 Content-Type
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// (provider, model)
+type SeriesKey = (String, String);
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, upper_bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= upper_bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct MetricsState {
+    requests: BTreeMap<SeriesKey, u64>,
+    errors: BTreeMap<(SeriesKey, String), u64>,
+    upstream_statuses: BTreeMap<(SeriesKey, u16), u64>,
+    durations: BTreeMap<SeriesKey, Histogram>,
+    upstream_latencies: BTreeMap<SeriesKey, Histogram>,
+    in_flight_streams: BTreeMap<SeriesKey, u64>,
+    input_tokens: BTreeMap<SeriesKey, u64>,
+    output_tokens: BTreeMap<SeriesKey, u64>,
+}
+
+/// 单个 Gateway 的 Prometheus 指标，随 Gateway 服务一起创建，进程重启后归零。
+#[derive(Debug)]
+pub struct GatewayMetrics {
+    gateway: GatewayKind,
+    state: Mutex<MetricsState>,
+}
+
+impl GatewayMetrics {
+    pub fn new(gateway: GatewayKind) -> Self {
+        Self {
+            gateway,
+            state: Mutex::default(),
+        }
+    }
+
+    /// 记录一个已结束的请求；`upstream_status` 为 None 表示请求未到达上游。
+    pub fn observe_request(&self, record: &GatewayLogRecord, upstream_status: Option<u16>) {
+        let key = series_key(record.provider_id.as_deref(), record.model.as_deref());
+        let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+
+        *state.requests.entry(key.clone()).or_default() += 1;
+        if let Some(error_class) = record.error_class {
+            *state.errors.entry((key.clone(), error_class_label(error_class))).or_default() += 1;
+        }
+        if let Some(status) = upstream_status {
+            *state.upstream_statuses.entry((key.clone(), status)).or_default() += 1;
+        }
+        if let Some(duration_ms) = record.duration_ms {
+            state.durations.entry(key.clone()).or_default().observe(duration_ms as f64 / 1000.0);
+        }
+        if let (Some(latency_ms), Some(_)) = (record.latency_ms, upstream_status) {
+            state
+                .upstream_latencies
+                .entry(key.clone())
+                .or_default()
+                .observe(latency_ms as f64 / 1000.0);
+        }
+        if let Some(input_tokens) = record.input_tokens {
+            *state.input_tokens.entry(key.clone()).or_default() += input_tokens;
+        }
+        if let Some(output_tokens) = record.output_tokens {
+            *state.output_tokens.entry(key).or_default() += output_tokens;
+        }
+    }
+
+    pub fn stream_started(&self, provider_id: Option<&str>, model: Option<&str>) {
+        let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        *state.in_flight_streams.entry(series_key(provider_id, model)).or_default() += 1;
+    }
+
+    pub fn stream_finished(&self, provider_id: Option<&str>, model: Option<&str>) {
+        let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        if let Some(count) = state.in_flight_streams.get_mut(&series_key(provider_id, model)) {
+            *count = count.saturating_sub(1);
+        }
+    }
+
+    /// 按 Prometheus 文本格式（0.0.4）输出全部指标。
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        let gateway = match self.gateway {
+            GatewayKind::Api => "api",
+            GatewayKind::Codex => "codex",
+        };
+        let labels = |(provider, model): &SeriesKey| {
+            format!(
+                "gateway=\"{}\",provider=\"{}\",model=\"{}\"",
+                gateway,
+                escape_label(provider),
+                escape_label(model)
+            )
+        };
+        let mut output = String::new();
+
+        write_header(&mut output, "switchcc_gateway_requests_total", "counter", "已完成的请求数");
+        for (key, count) in &state.requests {
+            let _ = writeln!(output, "switchcc_gateway_requests_total{{{}}} {}", labels(key), count);
+        }
+
+        write_header(&mut output, "switchcc_gateway_errors_total", "counter", "按错误分类统计的失败请求数");
+        for ((key, error_class), count) in &state.errors {
+            let _ = writeln!(
+                output,
+                "switchcc_gateway_errors_total{{{},class=\"{}\"}} {}",
+                labels(key),
+                error_class,
+                count
+            );
+        }
+
+        write_header(&mut output, "switchcc_gateway_upstream_responses_total", "counter", "按状态码统计的上游响应数");
+        for ((key, status), count) in &state.upstream_statuses {
+            let _ = writeln!(
+                output,
+                "switchcc_gateway_upstream_responses_total{{{},status=\"{}\"}} {}",
+                labels(key),
+                status,
+                count
+            );
+        }
+
+        for (name, help, histograms) in [
+            (
+                "switchcc_gateway_request_duration_seconds",
+                "请求从进入 Gateway 到响应结束的耗时",
+                &state.durations,
+            ),
+            (
+                "switchcc_gateway_upstream_latency_seconds",
+                "收到上游响应头的耗时",
+                &state.upstream_latencies,
+            ),
+        ] {
+            write_header(&mut output, name, "histogram", help);
+            for (key, histogram) in histograms {
+                let labels = labels(key);
+                for (upper_bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                    let _ = writeln!(output, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, upper_bound, count);
+                }
+                let _ = writeln!(output, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, histogram.count);
+                let _ = writeln!(output, "{}_sum{{{}}} {}", name, labels, histogram.sum);
+                let _ = writeln!(output, "{}_count{{{}}} {}", name, labels, histogram.count);
+            }
+        }
+
+        write_header(&mut output, "switchcc_gateway_in_flight_streams", "gauge", "正在进行的流式响应数");
+        for (key, count) in &state.in_flight_streams {
+            let _ = writeln!(output, "switchcc_gateway_in_flight_streams{{{}}} {}", labels(key), count);
+        }
+
+        write_header(&mut output, "switchcc_gateway_tokens_total", "counter", "上游 usage 报告的 token 数");
+        for (direction, tokens) in [("input", &state.input_tokens), ("output", &state.output_tokens)] {
+            for (key, count) in tokens {
+                let _ = writeln!(
+                    output,
+                    "switchcc_gateway_tokens_total{{{},direction=\"{}\"}} {}",
+                    labels(key),
+                    direction,
+                    count
+                );
+            }
+        }
+
+        output
+    }
+}
+
+/// 从透传的 Anthropic 响应（SSE 或 JSON）中提取 usage。
+#[derive(Debug, Default)]
+pub struct AnthropicUsageScanner {
+    buffer: Vec<u8>,
+    is_stream: bool,
+    input_tokens: Option<u64>,
+    output_tokens: Option<u64>,
+}
+
+impl AnthropicUsageScanner {
+    pub fn new(is_stream: bool) -> Self {
+        Self {
+            is_stream,
+            ..Default::default()
+        }
+    }
+
+    /// 追加一段响应体，返回目前已知的 (input_tokens, output_tokens)。
+    pub fn push(&mut self, bytes: &[u8]) -> (Option<u64>, Option<u64>) {
+        if self.is_stream {
+            self.buffer.extend_from_slice(bytes);
+            while let Some(position) = self.buffer.iter().position(|byte| *byte == b'\n') {
+                let line = self.buffer.drain(..=position).collect::<Vec<_>>();
+                let Some(data) = std::str::from_utf8(&line)
+                    .ok()
+                    .and_then(|line| line.trim().strip_prefix("data:"))
+                else {
+                    continue;
+                };
+                if let Ok(event) = serde_json::from_str::<Value>(data.trim()) {
+                    self.merge(event.get("usage").or_else(|| event.pointer("/message/usage")));
+                }
+            }
+        } else if self.buffer.len() + bytes.len() <= MAX_USAGE_BODY_BYTES {
+            self.buffer.extend_from_slice(bytes);
+            if let Ok(body) = serde_json::from_slice::<Value>(&self.buffer) {
+                self.merge(body.get("usage"));
+            }
+        }
+
+        (self.input_tokens, self.output_tokens)
+    }
+
+    fn merge(&mut self, usage: Option<&Value>) {
+        let Some(usage) = usage else {
+            return;
+        };
+        // message_delta 中的 output_tokens 为累计值，直接覆盖
+        if let Some(input_tokens) = usage.get("input_tokens").and_then(Value::as_u64) {
+            self.input_tokens = Some(input_tokens);
+        }
+        if let Some(output_tokens) = usage.get("output_tokens").and_then(Value::as_u64) {
+            self.output_tokens = Some(output_tokens);
+        }
+    }
+}
+
+fn series_key(provider_id: Option<&str>, model: Option<&str>) -> SeriesKey {
+    (
+        provider_id.unwrap_or(UNKNOWN_LABEL).to_string(),
+        model.unwrap_or(UNKNOWN_LABEL).to_string(),
+    )
+}
+
+fn write_header(output: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(output, "# HELP {} {}", name, help);
+    let _ = writeln!(output, "# TYPE {} {}", name, kind);
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn error_class_label(error_class: ErrorClass) -> String {
+    serde_json::to_value(error_class)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_else(|| UNKNOWN_LABEL.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counters_and_histograms_by_provider_and_model() {
+        let metrics = GatewayMetrics::new(GatewayKind::Api);
+        let mut record = GatewayLogRecord::event(GatewayKind::Api, "info", "");
+        record.provider_id = Some("minimax".to_string());
+        record.model = Some("claude-minimax:default".to_string());
+        record.latency_ms = Some(300);
+        record.duration_ms = Some(1_200);
+        record.input_tokens = Some(12);
+        record.output_tokens = Some(5);
+        metrics.observe_request(&record, Some(200));
+        record.error_class = Some(ErrorClass::RateLimited);
+        metrics.observe_request(&record, None);
+
+        let output = metrics.render();
+        let labels = "gateway=\"api\",provider=\"minimax\",model=\"claude-minimax:default\"";
+        assert!(output.contains(&format!("switchcc_gateway_requests_total{{{}}} 2", labels)));
+        assert!(output.contains(&format!("switchcc_gateway_errors_total{{{},class=\"rate_limited\"}} 1", labels)));
+        assert!(output.contains(&format!("switchcc_gateway_upstream_responses_total{{{},status=\"200\"}} 1", labels)));
+        assert!(output.contains(&format!("switchcc_gateway_request_duration_seconds_bucket{{{},le=\"1\"}} 0", labels)));
+        assert!(output.contains(&format!("switchcc_gateway_request_duration_seconds_bucket{{{},le=\"2.5\"}} 2", labels)));
+        assert!(output.contains(&format!("switchcc_gateway_upstream_latency_seconds_count{{{}}} 1", labels)));
+        assert!(output.contains(&format!("switchcc_gateway_tokens_total{{{},direction=\"input\"}} 24", labels)));
+    }
+
+    #[test]
+    fn scans_usage_from_anthropic_sse_split_across_chunks() {
+        let mut scanner = AnthropicUsageScanner::new(true);
+        scanner.push(b"event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":");
+        scanner.push(b"42,\"output_tokens\":1}}}\n\n");
+        let usage = scanner.push(b"event: message_delta\ndata: {\"type\":\"message_delta\",\"usage\":{\"output_tokens\":17}}\n\n");
+
+        assert_eq!(usage, (Some(42), Some(17)));
+    }
+}
@@ -0,0 +1,395 @@
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+const DEFAULT_QUEUE_WAIT: Duration = Duration::from_secs(10);
+const DEFAULT_UPSTREAM_PAUSE: Duration = Duration::from_secs(5);
+const MAX_UPSTREAM_PAUSE: Duration = Duration::from_secs(300);
+
+/// 单个供应商的限流配置，所有字段为空时表示不限流。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requests_per_minute: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokens_per_minute: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_concurrent_streams: Option<u32>,
+    /// 超出限额时最多排队等待的毫秒数，超时后返回 429
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_queue_wait_ms: Option<u64>,
+}
+
+impl RateLimitConfig {
+    /// 从 Claude 供应商的 `apiGateway.rateLimit` 读取配置，缺失或格式错误时视为不限流。
+    pub fn from_value(value: Option<&Value>) -> Self {
+        value
+            .cloned()
+            .and_then(|value| serde_json::from_value(value).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn queue_wait(&self) -> Duration {
+        self.max_queue_wait_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_QUEUE_WAIT)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitReason {
+    RequestsPerMinute,
+    TokensPerMinute,
+    ConcurrentStreams,
+    UpstreamPaused,
+}
+
+impl RateLimitReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::RequestsPerMinute => "requests_per_minute",
+            Self::TokensPerMinute => "tokens_per_minute",
+            Self::ConcurrentStreams => "max_concurrent_streams",
+            Self::UpstreamPaused => "upstream_paused",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitRejection {
+    pub reason: RateLimitReason,
+    pub retry_after: Duration,
+}
+
+impl RateLimitRejection {
+    /// `retry-after` 响应头取值（秒，至少为 1）。
+    pub fn retry_after_secs(&self) -> u64 {
+        self.retry_after.as_secs_f64().ceil().max(1.0) as u64
+    }
+
+    pub fn message(&self) -> String {
+        let reason = match self.reason {
+            RateLimitReason::RequestsPerMinute => "已达到每分钟请求数上限",
+            RateLimitReason::TokensPerMinute => "已达到每分钟 token 上限",
+            RateLimitReason::ConcurrentStreams => "已达到最大并发流数量",
+            RateLimitReason::UpstreamPaused => "上游要求暂停请求",
+        };
+        format!("{}，请在 {} 秒后重试", reason, self.retry_after_secs())
+    }
+}
+
+/// 持有期间占用一个并发流名额，drop 时释放。
+#[derive(Debug)]
+pub struct RateLimitPermit {
+    _stream_permit: Option<OwnedSemaphorePermit>,
+}
+
+#[derive(Default)]
+struct LimiterWindow {
+    requests: VecDeque<(Instant, u64)>,
+    paused_until: Option<Instant>,
+}
+
+impl LimiterWindow {
+    fn prune(&mut self, now: Instant) {
+        while let Some((started_at, _)) = self.requests.front() {
+            if now.duration_since(*started_at) >= RATE_WINDOW {
+                self.requests.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// 尝试占用窗口名额；失败时返回需要等待的时长与原因。
+    fn try_reserve(
+        &mut self,
+        config: &RateLimitConfig,
+        tokens: u64,
+        now: Instant,
+    ) -> Result<(), (Duration, RateLimitReason)> {
+        self.prune(now);
+
+        if let Some(paused_until) = self.paused_until {
+            if paused_until > now {
+                return Err((paused_until - now, RateLimitReason::UpstreamPaused));
+            }
+            self.paused_until = None;
+        }
+
+        if let Some(limit) = config.requests_per_minute.filter(|limit| *limit > 0) {
+            if self.requests.len() >= limit as usize {
+                let oldest = self.requests.front().map(|(started_at, _)| *started_at).unwrap_or(now);
+                return Err((
+                    (oldest + RATE_WINDOW).saturating_duration_since(now),
+                    RateLimitReason::RequestsPerMinute,
+                ));
+            }
+        }
+
+        if let Some(limit) = config.tokens_per_minute.filter(|limit| *limit > 0) {
+            let used = self.requests.iter().map(|(_, tokens)| *tokens).sum::<u64>();
+            // 单个请求超过整个窗口额度时，只要窗口为空就放行，避免永远排队
+            if used + tokens > limit && !self.requests.is_empty() {
+                let mut remaining = used + tokens;
+                let mut available_at = now;
+                for (started_at, request_tokens) in &self.requests {
+                    remaining = remaining.saturating_sub(*request_tokens);
+                    available_at = *started_at + RATE_WINDOW;
+                    if remaining <= limit {
+                        break;
+                    }
+                }
+                return Err((
+                    available_at.saturating_duration_since(now),
+                    RateLimitReason::TokensPerMinute,
+                ));
+            }
+        }
+
+        self.requests.push_back((now, tokens));
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct ProviderLimiter {
+    window: Mutex<LimiterWindow>,
+    streams: Mutex<Option<(u32, Arc<Semaphore>)>>,
+}
+
+impl ProviderLimiter {
+    fn stream_semaphore(&self, max_streams: u32) -> Arc<Semaphore> {
+        let mut streams = self.streams.lock().unwrap_or_else(|error| error.into_inner());
+        match streams.as_ref() {
+            Some((limit, semaphore)) if *limit == max_streams => semaphore.clone(),
+            _ => {
+                let semaphore = Arc::new(Semaphore::new(max_streams as usize));
+                *streams = Some((max_streams, semaphore.clone()));
+                semaphore
+            }
+        }
+    }
+}
+
+/// 按供应商 ID 维护的限流状态，Gateway 生命周期内共享。
+#[derive(Default)]
+pub struct RateLimiterRegistry {
+    limiters: Mutex<HashMap<String, Arc<ProviderLimiter>>>,
+}
+
+impl RateLimiterRegistry {
+    fn limiter(&self, provider_id: &str) -> Arc<ProviderLimiter> {
+        let mut limiters = self.limiters.lock().unwrap_or_else(|error| error.into_inner());
+        limiters
+            .entry(provider_id.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// 获取一次请求的限流许可；超出限额时最多排队 `max_queue_wait_ms`，仍无法获取则返回拒绝信息。
+    pub async fn acquire(
+        &self,
+        provider_id: &str,
+        config: &RateLimitConfig,
+        estimated_tokens: u64,
+    ) -> Result<RateLimitPermit, RateLimitRejection> {
+        let limiter = self.limiter(provider_id);
+        let deadline = Instant::now() + config.queue_wait();
+
+        let stream_permit = match config.max_concurrent_streams.filter(|limit| *limit > 0) {
+            Some(max_streams) => {
+                let semaphore = limiter.stream_semaphore(max_streams);
+                let wait = deadline.saturating_duration_since(Instant::now());
+                match tokio::time::timeout(wait, semaphore.acquire_owned()).await {
+                    Ok(Ok(permit)) => Some(permit),
+                    _ => {
+                        return Err(RateLimitRejection {
+                            reason: RateLimitReason::ConcurrentStreams,
+                            retry_after: Duration::from_secs(1),
+                        })
+                    }
+                }
+            }
+            None => None,
+        };
+
+        loop {
+            let now = Instant::now();
+            let reservation = {
+                let mut window = limiter.window.lock().unwrap_or_else(|error| error.into_inner());
+                window.try_reserve(config, estimated_tokens, now)
+            };
+
+            match reservation {
+                Ok(()) => {
+                    return Ok(RateLimitPermit {
+                        _stream_permit: stream_permit,
+                    })
+                }
+                Err((wait, reason)) => {
+                    if now + wait > deadline {
+                        return Err(RateLimitRejection {
+                            reason,
+                            retry_after: wait,
+                        });
+                    }
+                    tokio::time::sleep(wait).await;
+                }
+            }
+        }
+    }
+
+    /// 根据上游响应的 `retry-after` / `anthropic-ratelimit-*` 头自动暂停供应商，返回暂停时长。
+    pub fn observe_upstream(&self, provider_id: &str, status: u16, headers: &HeaderMap) -> Option<Duration> {
+        let pause = upstream_pause_duration(status, headers, chrono::Utc::now())?;
+        let limiter = self.limiter(provider_id);
+        let mut window = limiter.window.lock().unwrap_or_else(|error| error.into_inner());
+        let paused_until = Instant::now() + pause;
+        if window.paused_until.map_or(true, |current| current < paused_until) {
+            window.paused_until = Some(paused_until);
+        }
+        Some(pause)
+    }
+}
+
+fn upstream_pause_duration(
+    status: u16,
+    headers: &HeaderMap,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Option<Duration> {
+    let retry_after = header_str(headers, "retry-after").and_then(|value| parse_retry_after(value, now));
+
+    let exhausted_reset = ["requests", "tokens", "input-tokens", "output-tokens"]
+        .iter()
+        .filter(|kind| {
+            header_str(headers, &format!("anthropic-ratelimit-{kind}-remaining"))
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                == Some(0)
+        })
+        .filter_map(|kind| {
+            header_str(headers, &format!("anthropic-ratelimit-{kind}-reset"))
+                .and_then(|value| chrono::DateTime::parse_from_rfc3339(value.trim()).ok())
+                .map(|reset_at| {
+                    (reset_at.with_timezone(&chrono::Utc) - now)
+                        .to_std()
+                        .unwrap_or_default()
+                })
+        })
+        .max();
+
+    let pause = match status {
+        429 => Some(retry_after.or(exhausted_reset).unwrap_or(DEFAULT_UPSTREAM_PAUSE)),
+        503 | 529 => retry_after,
+        _ => exhausted_reset,
+    }?;
+
+    if pause.is_zero() {
+        return None;
+    }
+
+    Some(pause.min(MAX_UPSTREAM_PAUSE))
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// 解析 `retry-after`：支持秒数与 HTTP 日期两种格式。
+pub fn parse_retry_after(value: &str, now: chrono::DateTime<chrono::Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<f64>() {
+        return (seconds.is_finite() && seconds >= 0.0).then(|| Duration::from_secs_f64(seconds));
+    }
+
+    chrono::DateTime::parse_from_rfc2822(value)
+        .ok()
+        .map(|retry_at| (retry_at.with_timezone(&chrono::Utc) - now).to_std().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn requests_per_minute_limit_reports_wait_until_oldest_expires() {
+        let config = RateLimitConfig {
+            requests_per_minute: Some(2),
+            ..Default::default()
+        };
+        let mut window = LimiterWindow::default();
+        let start = Instant::now();
+
+        assert!(window.try_reserve(&config, 0, start).is_ok());
+        assert!(window.try_reserve(&config, 0, start + Duration::from_secs(10)).is_ok());
+
+        let (wait, reason) = window
+            .try_reserve(&config, 0, start + Duration::from_secs(20))
+            .unwrap_err();
+        assert_eq!(reason, RateLimitReason::RequestsPerMinute);
+        assert_eq!(wait, Duration::from_secs(40));
+
+        assert!(window.try_reserve(&config, 0, start + Duration::from_secs(61)).is_ok());
+    }
+
+    #[test]
+    fn tokens_per_minute_limit_allows_oversized_request_on_empty_window() {
+        let config = RateLimitConfig {
+            tokens_per_minute: Some(100),
+            ..Default::default()
+        };
+        let mut window = LimiterWindow::default();
+        let start = Instant::now();
+
+        assert!(window.try_reserve(&config, 500, start).is_ok());
+        let (_, reason) = window.try_reserve(&config, 10, start).unwrap_err();
+        assert_eq!(reason, RateLimitReason::TokensPerMinute);
+    }
+
+    #[tokio::test]
+    async fn concurrent_stream_limit_rejects_after_queue_wait() {
+        let registry = RateLimiterRegistry::default();
+        let config = RateLimitConfig {
+            max_concurrent_streams: Some(1),
+            max_queue_wait_ms: Some(20),
+            ..Default::default()
+        };
+
+        let first = registry.acquire("p1", &config, 0).await.unwrap();
+        let rejection = registry.acquire("p1", &config, 0).await.unwrap_err();
+        assert_eq!(rejection.reason, RateLimitReason::ConcurrentStreams);
+
+        drop(first);
+        assert!(registry.acquire("p1", &config, 0).await.is_ok());
+    }
+
+    #[test]
+    fn upstream_pause_honours_retry_after_and_anthropic_reset_headers() {
+        let now = chrono::Utc::now();
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("7"));
+        assert_eq!(upstream_pause_duration(429, &headers, now), Some(Duration::from_secs(7)));
+        assert_eq!(upstream_pause_duration(200, &headers, now), None);
+
+        let reset_at = (now + chrono::Duration::seconds(30)).to_rfc3339();
+        let mut headers = HeaderMap::new();
+        headers.insert("anthropic-ratelimit-tokens-remaining", HeaderValue::from_static("0"));
+        headers.insert(
+            "anthropic-ratelimit-tokens-reset",
+            HeaderValue::from_str(&reset_at).unwrap(),
+        );
+        let pause = upstream_pause_duration(200, &headers, now).unwrap();
+        assert!(pause > Duration::from_secs(29) && pause <= Duration::from_secs(30));
+    }
+}
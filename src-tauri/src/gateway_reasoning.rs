@@ -0,0 +1,241 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// Anthropic 扩展思考的最小预算
+pub const MIN_THINKING_BUDGET_TOKENS: u64 = 1024;
+
+/// 上游接受的推理参数格式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReasoningStyle {
+    /// 不发送推理参数
+    Drop,
+    /// OpenAI `reasoning_effort`
+    Effort,
+    /// Qwen `enable_thinking` + `thinking_budget`
+    EnableThinking,
+    /// DeepSeek / GLM `thinking: { type }`
+    ThinkingType,
+    /// Anthropic `thinking: { type, budget_tokens }`
+    AnthropicBudget,
+}
+
+/// 与协议无关的推理意图。
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReasoningIntent {
+    Disabled,
+    Effort(String),
+    Budget(u64),
+}
+
+/// 单个供应商的推理参数映射，未设置 `style` 时按适配器推断。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReasoningMapping {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<ReasoningStyle>,
+    /// 推理强度改名，例如上游不支持 `minimal` 时映射为 `low`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub effort_map: BTreeMap<String, String>,
+    /// 推理强度 -> 思考预算，覆盖内置的 low 2048 / medium 8192 / high 24576
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub budgets: BTreeMap<String, u64>,
+}
+
+impl ReasoningMapping {
+    /// 从 `apiGateway.reasoning` 等配置读取，缺失或格式错误时使用适配器默认行为。
+    pub fn from_value(value: Option<&Value>) -> Self {
+        value
+            .cloned()
+            .and_then(|value| serde_json::from_value(value).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn effort<'a>(&'a self, effort: &'a str) -> &'a str {
+        self.effort_map.get(effort).map(String::as_str).unwrap_or(effort)
+    }
+
+    fn budget(&self, intent: &ReasoningIntent) -> Option<u64> {
+        match intent {
+            ReasoningIntent::Disabled => None,
+            ReasoningIntent::Budget(budget) => Some(*budget),
+            ReasoningIntent::Effort(effort) => {
+                let effort = self.effort(effort);
+                self.budgets
+                    .get(effort)
+                    .copied()
+                    .or_else(|| default_budget_tokens(effort))
+            }
+        }
+    }
+
+    /// 按 `style` 把推理意图写入上游请求；调用前需已移除来源协议的推理字段。
+    pub fn apply(&self, style: ReasoningStyle, intent: &ReasoningIntent, request: &mut Map<String, Value>) {
+        let budget = self.budget(intent);
+        let enabled = budget.is_some();
+        match style {
+            ReasoningStyle::Drop => {}
+            ReasoningStyle::Effort => {
+                let effort = match intent {
+                    ReasoningIntent::Disabled => return,
+                    ReasoningIntent::Effort(effort) => self.effort(effort).to_string(),
+                    ReasoningIntent::Budget(budget) => self.effort(effort_for_budget(*budget)).to_string(),
+                };
+                request.insert("reasoning_effort".to_string(), Value::String(effort));
+            }
+            ReasoningStyle::EnableThinking => {
+                request.insert("enable_thinking".to_string(), Value::Bool(enabled));
+                if let Some(budget) = budget {
+                    request.insert("thinking_budget".to_string(), Value::from(budget));
+                }
+            }
+            ReasoningStyle::ThinkingType => {
+                let kind = if enabled { "enabled" } else { "disabled" };
+                request.insert("thinking".to_string(), json!({ "type": kind }));
+            }
+            ReasoningStyle::AnthropicBudget => {
+                let max_tokens = MAX_TOKENS_KEYS
+                    .iter()
+                    .find_map(|key| request.get(*key).and_then(Value::as_u64));
+                let thinking = match budget {
+                    None => json!({ "type": "disabled" }),
+                    Some(budget) => match clamp_budget(budget, max_tokens) {
+                        Some(budget) => json!({ "type": "enabled", "budget_tokens": budget }),
+                        None => return,
+                    },
+                };
+                request.insert("thinking".to_string(), thinking);
+            }
+        }
+    }
+}
+
+/// 上游请求中限制输出长度的字段，思考预算必须小于它
+const MAX_TOKENS_KEYS: &[&str] = &["max_tokens", "max_completion_tokens", "max_output_tokens"];
+
+/// 读取 Responses 请求的 `reasoning.effort`。
+pub fn responses_intent(body: &Value) -> Option<ReasoningIntent> {
+    effort_intent(body.get("reasoning")?.get("effort")?.as_str()?)
+}
+
+/// 取出 Chat Completions 请求的 `reasoning_effort`。
+pub fn take_chat_intent(request: &mut Map<String, Value>) -> Option<ReasoningIntent> {
+    let intent = effort_intent(request.get("reasoning_effort")?.as_str()?)?;
+    request.remove("reasoning_effort");
+    Some(intent)
+}
+
+/// 取出 Anthropic 请求的 `thinking`；无法识别的写法保持原样。
+pub fn take_anthropic_intent(request: &mut Map<String, Value>) -> Option<ReasoningIntent> {
+    let thinking = request.get("thinking")?;
+    let intent = match thinking.get("type").and_then(Value::as_str)? {
+        "disabled" => ReasoningIntent::Disabled,
+        "enabled" => ReasoningIntent::Budget(thinking.get("budget_tokens")?.as_u64()?),
+        _ => return None,
+    };
+    request.remove("thinking");
+    Some(intent)
+}
+
+fn effort_intent(effort: &str) -> Option<ReasoningIntent> {
+    match effort.trim() {
+        "" => None,
+        "none" => Some(ReasoningIntent::Disabled),
+        effort => Some(ReasoningIntent::Effort(effort.to_string())),
+    }
+}
+
+/// 内置的推理强度 -> 思考预算；`minimal` 等不开启思考。
+pub fn default_budget_tokens(effort: &str) -> Option<u64> {
+    match effort {
+        "low" => Some(2_048),
+        "medium" => Some(8_192),
+        "high" => Some(24_576),
+        _ => None,
+    }
+}
+
+fn effort_for_budget(budget: u64) -> &'static str {
+    match budget {
+        0..=2_048 => "low",
+        2_049..=8_192 => "medium",
+        _ => "high",
+    }
+}
+
+/// 思考预算需小于输出上限且不低于最小预算，无法满足时返回 `None`。
+pub fn clamp_budget(budget: u64, max_tokens: Option<u64>) -> Option<u64> {
+    let budget = match max_tokens {
+        Some(max_tokens) => budget.min(max_tokens.saturating_sub(1)),
+        None => budget,
+    };
+    (budget >= MIN_THINKING_BUDGET_TOKENS).then_some(budget)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(mapping: &ReasoningMapping, style: ReasoningStyle, intent: ReasoningIntent, request: Value) -> Value {
+        let mut request = request.as_object().cloned().unwrap_or_default();
+        mapping.apply(style, &intent, &mut request);
+        Value::Object(request)
+    }
+
+    #[test]
+    fn responses_effort_maps_to_each_style() {
+        let intent = responses_intent(&json!({ "reasoning": { "effort": "high", "summary": "auto" } })).unwrap();
+        let mapping = ReasoningMapping {
+            budgets: BTreeMap::from([("high".to_string(), 16_000)]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            apply(&mapping, ReasoningStyle::Effort, intent.clone(), json!({})),
+            json!({ "reasoning_effort": "high" })
+        );
+        assert_eq!(
+            apply(&mapping, ReasoningStyle::EnableThinking, intent.clone(), json!({})),
+            json!({ "enable_thinking": true, "thinking_budget": 16000 })
+        );
+        assert_eq!(
+            apply(&mapping, ReasoningStyle::ThinkingType, intent.clone(), json!({})),
+            json!({ "thinking": { "type": "enabled" } })
+        );
+        assert_eq!(
+            apply(&mapping, ReasoningStyle::AnthropicBudget, intent, json!({ "max_tokens": 8000 })),
+            json!({ "max_tokens": 8000, "thinking": { "type": "enabled", "budget_tokens": 7999 } })
+        );
+    }
+
+    #[test]
+    fn anthropic_thinking_is_clamped_or_converted() {
+        let mapping = ReasoningMapping {
+            effort_map: BTreeMap::from([("low".to_string(), "minimal".to_string())]),
+            ..Default::default()
+        };
+        let mut request = json!({ "max_tokens": 1000, "thinking": { "type": "enabled", "budget_tokens": 2000 } })
+            .as_object()
+            .cloned()
+            .unwrap();
+
+        let intent = take_anthropic_intent(&mut request).unwrap();
+        assert_eq!(intent, ReasoningIntent::Budget(2000));
+        mapping.apply(ReasoningStyle::AnthropicBudget, &intent, &mut request);
+        assert_eq!(Value::Object(request), json!({ "max_tokens": 1000 }));
+
+        assert_eq!(
+            apply(&mapping, ReasoningStyle::Effort, ReasoningIntent::Budget(1500), json!({})),
+            json!({ "reasoning_effort": "minimal" })
+        );
+        assert_eq!(
+            apply(&mapping, ReasoningStyle::Effort, ReasoningIntent::Disabled, json!({})),
+            json!({})
+        );
+    }
+}
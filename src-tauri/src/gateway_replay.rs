@@ -0,0 +1,617 @@
+use crate::api_gateway;
+use crate::codex_gateway;
+use crate::gateway_capture::Redactor;
+use crate::secret_ref;
+use crate::store::AppConfig;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+const REPLAY_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// 回放请求体的协议格式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayFormat {
+    /// Anthropic `/v1/messages`，回放到 Claude 供应商
+    Anthropic,
+    /// OpenAI Responses，经 Codex Gateway 转换后回放到 Codex 供应商
+    Responses,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ReplayRequest {
+    /// 为空时根据请求体自动识别（含 `input` 为 Responses，否则为 Anthropic）
+    pub format: Option<ReplayFormat>,
+    /// 粘贴的请求体，与 `file_path` 二选一
+    pub body: Option<String>,
+    pub file_path: Option<String>,
+    /// 为空时回放到当前 Gateway 路由的目标供应商
+    pub provider_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+/// 单个供应商的回放结果。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayResult {
+    pub provider_id: String,
+    pub provider_name: String,
+    pub model: Option<String>,
+    pub status: Option<u16>,
+    pub latency_ms: u64,
+    pub usage: ReplayUsage,
+    /// 拼接后的文本输出
+    pub output_text: String,
+    /// 工具调用（名称与参数）
+    pub tool_calls: Vec<Value>,
+    /// 响应体；Responses 格式下为转换后的 Responses 响应
+    pub response: Value,
+    pub error: Option<String>,
+}
+
+/// 改写预览：回放时将发往上游的请求，鉴权信息已脱敏。
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayPreview {
+    pub provider_id: String,
+    pub provider_name: String,
+    pub url: Option<String>,
+    pub headers: BTreeMap<String, String>,
+    /// 模型重写、协议转换和改写规则之后的请求体
+    pub body: Value,
+    pub error: Option<String>,
+}
+
+/// 回放目标：从配置中复制出的供应商及其请求构造方式。
+pub enum ReplayTarget {
+    Claude(crate::provider::Provider),
+    Codex(Box<crate::provider::CodexProvider>),
+}
+
+impl ReplayTarget {
+    fn id(&self) -> &str {
+        match self {
+            Self::Claude(provider) => &provider.id,
+            Self::Codex(provider) => &provider.id,
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            Self::Claude(provider) => &provider.name,
+            Self::Codex(provider) => &provider.name,
+        }
+    }
+
+    /// 校验并解析密钥引用（`cmd:` 会执行外部命令），需在阻塞线程中调用。
+    fn resolve(&self) -> Result<Self, String> {
+        match self {
+            Self::Claude(provider) => secret_ref::resolve_claude_provider(provider).map(Self::Claude),
+            Self::Codex(provider) => {
+                provider.validate()?;
+                secret_ref::resolve_codex_provider(provider).map(|provider| Self::Codex(Box::new(provider)))
+            }
+        }
+    }
+
+    /// 构造上游请求，`self` 需已解析密钥引用。
+    fn build_request(&self, client: &reqwest::Client, body: &Value) -> Result<reqwest::Request, String> {
+        let request_builder = match self {
+            Self::Claude(provider) => api_gateway::build_replay_request(client, provider, body),
+            Self::Codex(provider) => codex_gateway::build_replay_request(client, provider, body),
+        };
+        request_builder.and_then(|builder| builder.build().map_err(|e| e.to_string()))
+    }
+
+    /// 预览中需要脱敏的密钥，`self` 需已解析密钥引用。
+    fn redactor(&self) -> Redactor {
+        let mut redactor = Redactor::default();
+        match self {
+            Self::Claude(provider) => {
+                let env = provider.settings_config.get("env").and_then(Value::as_object);
+                for (key, value) in env.into_iter().flatten() {
+                    if key.contains("KEY") || key.contains("TOKEN") {
+                        redactor.add_secret(value.as_str().unwrap_or_default());
+                    }
+                }
+            }
+            Self::Codex(provider) => redactor.add_secret(&provider.codex_config.api_key),
+        }
+        redactor
+    }
+}
+
+/// 解析过密钥引用的回放目标，构造请求和脱敏共用同一份解析结果。
+struct ResolvedTarget {
+    id: String,
+    name: String,
+    /// 解析失败时为错误信息，该供应商的结果中直接报错
+    target: Result<ReplayTarget, String>,
+}
+
+impl ResolvedTarget {
+    fn new(target: &ReplayTarget) -> Self {
+        Self {
+            id: target.id().to_string(),
+            name: target.name().to_string(),
+            target: target.resolve(),
+        }
+    }
+
+    fn build_request(&self, client: &reqwest::Client, body: &Value) -> Result<reqwest::Request, String> {
+        self.target.as_ref().map_err(Clone::clone)?.build_request(client, body)
+    }
+
+    fn redactor(&self) -> Redactor {
+        self.target.as_ref().map(ReplayTarget::redactor).unwrap_or_default()
+    }
+}
+
+/// 已读取并识别出协议格式的回放请求体。
+pub struct ReplayBody {
+    body: Value,
+    format: ReplayFormat,
+}
+
+impl ReplayBody {
+    pub fn format(&self) -> ReplayFormat {
+        self.format
+    }
+}
+
+/// 已解析好请求体和目标供应商的回放任务。
+pub struct ReplayPlan {
+    body: Value,
+    targets: Vec<ResolvedTarget>,
+}
+
+/// 组合请求体和目标供应商，并在阻塞线程池中逐个解析密钥引用（每个供应商只解析一次）。
+///
+/// 目标应在配置锁内复制好，这里不再访问配置。
+pub async fn prepare(body: ReplayBody, targets: Vec<ReplayTarget>) -> Result<ReplayPlan, String> {
+    let targets = tokio::task::spawn_blocking(move || targets.iter().map(ResolvedTarget::new).collect())
+        .await
+        .map_err(|e| format!("解析回放供应商密钥失败: {}", e))?;
+    Ok(ReplayPlan {
+        body: body.body,
+        targets,
+    })
+}
+
+/// 并发回放到所有目标供应商，按请求顺序返回结果。
+pub async fn run(plan: ReplayPlan) -> Result<Vec<ReplayResult>, String> {
+    let client = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(30))
+        .timeout(REPLAY_TIMEOUT)
+        .build()
+        .map_err(|e| format!("初始化回放 HTTP 客户端失败: {}", e))?;
+
+    Ok(join_all(
+        plan.targets
+            .iter()
+            .map(|target| replay_one(&client, target, &plan.body)),
+    )
+    .await)
+}
+
+/// 只构造各供应商的上游请求而不发送，用于检查改写规则的效果。
+pub fn preview(plan: &ReplayPlan) -> Vec<ReplayPreview> {
+    let client = reqwest::Client::new();
+    plan.targets
+        .iter()
+        .map(|target| {
+            let mut preview = ReplayPreview {
+                provider_id: target.id.clone(),
+                provider_name: target.name.clone(),
+                url: None,
+                headers: BTreeMap::new(),
+                body: Value::Null,
+                error: None,
+            };
+            let request = match target.build_request(&client, &plan.body) {
+                Ok(request) => request,
+                Err(error) => {
+                    preview.error = Some(error);
+                    return preview;
+                }
+            };
+
+            let headers = request
+                .headers()
+                .iter()
+                .map(|(name, value)| (name.to_string(), Value::String(value.to_str().unwrap_or_default().to_string())))
+                .collect::<serde_json::Map<_, _>>();
+            let body = request
+                .body()
+                .and_then(|body| body.as_bytes())
+                .and_then(|bytes| serde_json::from_slice::<Value>(bytes).ok())
+                .unwrap_or(Value::Null);
+            let mut redacted = json!({ "url": request.url().as_str(), "headers": headers, "body": body });
+            target.redactor().redact(&mut redacted);
+
+            preview.url = redacted["url"].as_str().map(str::to_string);
+            preview.headers = serde_json::from_value(redacted["headers"].take()).unwrap_or_default();
+            preview.body = redacted["body"].take();
+            preview
+        })
+        .collect()
+}
+
+/// 读取粘贴的请求体或请求文件；会读文件，不要在持有配置锁时调用。
+pub fn load_body(request: &ReplayRequest) -> Result<ReplayBody, String> {
+    let text = match (request.body.as_deref(), request.file_path.as_deref()) {
+        (Some(body), _) if !body.trim().is_empty() => body.to_string(),
+        (_, Some(path)) if !path.trim().is_empty() => {
+            std::fs::read_to_string(path.trim()).map_err(|e| format!("读取请求文件失败: {}", e))?
+        }
+        _ => return Err("请提供要回放的请求体".to_string()),
+    };
+
+    let body: Value = serde_json::from_str(&text).map_err(|e| format!("请求体不是有效的 JSON: {}", e))?;
+    if !body.is_object() {
+        return Err("请求体必须是 JSON 对象".to_string());
+    }
+    let format = request.format.unwrap_or_else(|| detect_format(&body));
+    Ok(ReplayBody { body, format })
+}
+
+fn detect_format(body: &Value) -> ReplayFormat {
+    if body.get("input").is_some() {
+        ReplayFormat::Responses
+    } else {
+        ReplayFormat::Anthropic
+    }
+}
+
+/// 按格式查找并复制目标供应商；在持有配置锁时调用，只做查找和克隆。
+pub fn resolve_targets(
+    config: &AppConfig,
+    format: ReplayFormat,
+    provider_ids: &[String],
+) -> Result<Vec<ReplayTarget>, String> {
+    let provider_ids = if provider_ids.is_empty() {
+        let current = match format {
+            ReplayFormat::Anthropic => config
+                .api_gateway
+                .target_provider_id
+                .clone()
+                .unwrap_or_else(|| config.current.clone()),
+            ReplayFormat::Responses => config
+                .codex_gateway
+                .target_provider_id
+                .clone()
+                .unwrap_or_else(|| config.current_codex.clone()),
+        };
+        vec![current]
+    } else {
+        provider_ids.to_vec()
+    };
+
+    provider_ids
+        .iter()
+        .map(|provider_id| {
+            let target = match format {
+                ReplayFormat::Anthropic => config.providers.get(provider_id).cloned().map(ReplayTarget::Claude),
+                ReplayFormat::Responses => config
+                    .codex_providers
+                    .get(provider_id)
+                    .cloned()
+                    .map(|provider| ReplayTarget::Codex(Box::new(provider))),
+            };
+            target.ok_or_else(|| format!("供应商不存在或格式不匹配: {}", provider_id))
+        })
+        .collect()
+}
+
+async fn replay_one(client: &reqwest::Client, target: &ResolvedTarget, body: &Value) -> ReplayResult {
+    let mut result = ReplayResult {
+        provider_id: target.id.clone(),
+        provider_name: target.name.clone(),
+        model: None,
+        status: None,
+        latency_ms: 0,
+        usage: ReplayUsage::default(),
+        output_text: String::new(),
+        tool_calls: Vec::new(),
+        response: Value::Null,
+        error: None,
+    };
+
+    let request = match target.build_request(client, body) {
+        Ok(request) => request,
+        Err(error) => {
+            result.error = Some(error);
+            return result;
+        }
+    };
+    result.model = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .and_then(|bytes| serde_json::from_slice::<Value>(bytes).ok())
+        .and_then(|body| body.get("model").and_then(Value::as_str).map(str::to_string));
+
+    let started_at = Instant::now();
+    let response = client.execute(request).await;
+    let response = match response {
+        Ok(response) => response,
+        Err(error) => {
+            result.latency_ms = started_at.elapsed().as_millis() as u64;
+            result.error = Some(format!("请求上游失败: {}", error));
+            return result;
+        }
+    };
+    let status = response.status();
+    result.status = Some(status.as_u16());
+    let text = response.text().await.unwrap_or_default();
+    result.latency_ms = started_at.elapsed().as_millis() as u64;
+
+    let Ok(upstream) = serde_json::from_str::<Value>(&text) else {
+        result.error = Some(format!("上游响应不是有效的 JSON: status={}", status));
+        result.response = Value::String(text);
+        return result;
+    };
+    if !status.is_success() {
+        result.error = Some(format!("上游返回错误: status={}", status));
+        result.response = upstream;
+        return result;
+    }
+
+    // 能发出请求说明目标已解析成功
+    match &target.target {
+        Ok(ReplayTarget::Codex(provider)) => {
+            let converted = codex_gateway::convert_replay_response(provider, &upstream);
+            summarize_responses(&converted, &mut result);
+            result.response = converted;
+        }
+        _ => {
+            summarize_anthropic(&upstream, &mut result);
+            result.response = upstream;
+        }
+    }
+    result
+}
+
+fn summarize_anthropic(response: &Value, result: &mut ReplayResult) {
+    for block in response.get("content").and_then(Value::as_array).into_iter().flatten() {
+        match block.get("type").and_then(Value::as_str) {
+            Some("text") => result
+                .output_text
+                .push_str(block.get("text").and_then(Value::as_str).unwrap_or_default()),
+            Some("tool_use") => result.tool_calls.push(json!({
+                "name": block.get("name"),
+                "arguments": block.get("input"),
+            })),
+            _ => {}
+        }
+    }
+    result.usage = usage_from(response.get("usage"));
+}
+
+fn summarize_responses(response: &Value, result: &mut ReplayResult) {
+    for item in response.get("output").and_then(Value::as_array).into_iter().flatten() {
+        match item.get("type").and_then(Value::as_str) {
+            Some("message") => {
+                for content in item.get("content").and_then(Value::as_array).into_iter().flatten() {
+                    result
+                        .output_text
+                        .push_str(content.get("text").and_then(Value::as_str).unwrap_or_default());
+                }
+            }
+            Some("function_call") => result.tool_calls.push(json!({
+                "name": item.get("name"),
+                "arguments": item.get("arguments"),
+            })),
+            _ => {}
+        }
+    }
+    result.usage = usage_from(response.get("usage"));
+}
+
+fn usage_from(usage: Option<&Value>) -> ReplayUsage {
+    let read = |key: &str| {
+        usage
+            .and_then(|usage| usage.get(key))
+            .and_then(Value::as_u64)
+            .unwrap_or_default()
+    };
+    ReplayUsage {
+        input_tokens: read("input_tokens"),
+        output_tokens: read("output_tokens"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{CodexProvider, CodexProviderConfig, Provider};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    #[test]
+    fn resolves_current_route_by_detected_format() {
+        let claude = Provider {
+            id: "relay".to_string(),
+            name: "Relay".to_string(),
+            settings_config: json!({ "env": { "ANTHROPIC_BASE_URL": "https://relay.example.com" } }),
+            website_url: None,
+            category: None,
+            created_at: None,
+        };
+        let codex = CodexProvider {
+            id: "deepseek".to_string(),
+            name: "DeepSeek".to_string(),
+            codex_config: CodexProviderConfig {
+                provider_name: "deepseek".to_string(),
+                upstream_url: "https://api.deepseek.com/v1".to_string(),
+                model_name: "deepseek-chat".to_string(),
+                api_key: "sk-test".to_string(),
+                ..Default::default()
+            },
+            website_url: None,
+            category: None,
+            created_at: None,
+        };
+        let config = AppConfig {
+            providers: HashMap::from([(claude.id.clone(), claude)]),
+            codex_providers: HashMap::from([(codex.id.clone(), codex)]),
+            current: "relay".to_string(),
+            current_codex: "deepseek".to_string(),
+            ..Default::default()
+        };
+
+        let responses_body = json!({ "model": "gpt-5", "input": "hi" });
+        assert_eq!(detect_format(&responses_body), ReplayFormat::Responses);
+        let request = ReplayRequest {
+            body: Some(responses_body.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(load_body(&request).unwrap().format(), ReplayFormat::Responses);
+        let targets = resolve_targets(&config, ReplayFormat::Responses, &[]).unwrap();
+        assert_eq!(targets[0].id(), "deepseek");
+
+        let targets = resolve_targets(&config, ReplayFormat::Anthropic, &[]).unwrap();
+        assert_eq!(targets[0].name(), "Relay");
+        assert!(resolve_targets(&config, ReplayFormat::Anthropic, &["deepseek".to_string()]).is_err());
+    }
+
+    #[test]
+    fn summarizes_text_tool_calls_and_usage() {
+        let mut result = ReplayResult {
+            provider_id: "relay".to_string(),
+            provider_name: "Relay".to_string(),
+            model: None,
+            status: Some(200),
+            latency_ms: 0,
+            usage: ReplayUsage::default(),
+            output_text: String::new(),
+            tool_calls: Vec::new(),
+            response: Value::Null,
+            error: None,
+        };
+        summarize_anthropic(
+            &json!({
+                "content": [
+                    { "type": "text", "text": "读取文件" },
+                    { "type": "tool_use", "name": "read_file", "input": { "path": "a.rs" } }
+                ],
+                "usage": { "input_tokens": 12, "output_tokens": 7 }
+            }),
+            &mut result,
+        );
+
+        assert_eq!(result.output_text, "读取文件");
+        assert_eq!(result.tool_calls[0]["name"], json!("read_file"));
+        assert_eq!(result.usage, ReplayUsage { input_tokens: 12, output_tokens: 7 });
+    }
+
+    #[test]
+    fn preview_applies_transform_rules_and_redacts_credentials() {
+        let claude = Provider {
+            id: "relay".to_string(),
+            name: "Relay".to_string(),
+            settings_config: json!({
+                "env": {
+                    "ANTHROPIC_BASE_URL": "https://relay.example.com",
+                    "ANTHROPIC_AUTH_TOKEN": "sk-relay-secret",
+                    "ANTHROPIC_MODEL": "relay-model"
+                },
+                "apiGateway": {
+                    "transform": {
+                        "remove": ["thinking", "metadata"],
+                        "maxTokens": 4096,
+                        "headers": { "x-relay-tier": "pro" }
+                    }
+                }
+            }),
+            website_url: None,
+            category: None,
+            created_at: None,
+        };
+        let plan = ReplayPlan {
+            body: json!({
+                "model": "claude-sonnet",
+                "max_tokens": 32000,
+                "thinking": { "type": "enabled", "budget_tokens": 1024 },
+                "metadata": { "user_id": "u" },
+                "messages": [{ "role": "user", "content": "hi" }]
+            }),
+            targets: vec![ResolvedTarget::new(&ReplayTarget::Claude(claude))],
+        };
+
+        let preview = &preview(&plan)[0];
+
+        assert_eq!(preview.error, None);
+        assert_eq!(preview.url.as_deref(), Some("https://relay.example.com/v1/messages"));
+        assert_eq!(preview.body["model"], json!("relay-model"));
+        assert_eq!(preview.body["max_tokens"], json!(4096));
+        assert!(preview.body.get("thinking").is_none() && preview.body.get("metadata").is_none());
+        assert_eq!(preview.headers["x-relay-tier"], "pro");
+        assert!(preview.headers.values().all(|value| !value.contains("sk-relay-secret")));
+    }
+
+    #[test]
+    fn preview_reports_malformed_transform_rules() {
+        let claude = Provider {
+            id: "relay".to_string(),
+            name: "Relay".to_string(),
+            settings_config: json!({
+                "env": { "ANTHROPIC_BASE_URL": "https://relay.example.com" },
+                "apiGateway": { "transform": { "remove": "thinking" } }
+            }),
+            website_url: None,
+            category: None,
+            created_at: None,
+        };
+        let plan = ReplayPlan {
+            body: json!({ "model": "claude-sonnet", "messages": [] }),
+            targets: vec![ResolvedTarget::new(&ReplayTarget::Claude(claude))],
+        };
+
+        let preview = &preview(&plan)[0];
+
+        assert!(preview.error.as_deref().is_some_and(|error| error.contains("apiGateway.transform")));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn preview_resolves_cmd_secret_once_and_redacts_it() {
+        let counter = std::env::temp_dir().join(format!("switch-cc-replay-cmd-{}", uuid::Uuid::new_v4().simple()));
+        let codex = CodexProvider {
+            id: "relay".to_string(),
+            name: "Relay".to_string(),
+            codex_config: CodexProviderConfig {
+                provider_name: "relay".to_string(),
+                upstream_url: "https://relay.example.com/v1".to_string(),
+                model_name: "relay-chat".to_string(),
+                api_key: format!("cmd:echo run >> '{}'; printf sk-cmd-secret", counter.display()),
+                ..Default::default()
+            },
+            website_url: None,
+            category: None,
+            created_at: None,
+        };
+        let body = load_body(&ReplayRequest {
+            body: Some(json!({ "model": "gpt-5", "input": "hi" }).to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let plan = prepare(body, vec![ReplayTarget::Codex(Box::new(codex))]).await.unwrap();
+        let preview = &preview(&plan)[0];
+
+        assert_eq!(preview.error, None);
+        assert!(preview.headers.values().all(|value| !value.contains("sk-cmd-secret")));
+        assert_eq!(std::fs::read_to_string(&counter).unwrap().lines().count(), 1);
+        std::fs::remove_file(&counter).unwrap();
+    }
+}
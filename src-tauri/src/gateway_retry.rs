@@ -0,0 +1,202 @@
+use crate::gateway_rate_limit::parse_retry_after;
+use reqwest::header::RETRY_AFTER;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// 上游瞬时失败的重试策略，保存在 Gateway 配置中。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// 首次请求之外的最大重试次数，0 表示不重试
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 8_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 计算第 `attempt` 次重试（从 0 开始）前的等待时间。
+    ///
+    /// 指数退避并叠加随机抖动；上游给出 `retry-after` 时至少等待该时长，
+    /// 超过 `max_backoff_ms` 则放弃重试，直接把上游响应返回给客户端。
+    fn next_delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        let max_backoff = Duration::from_millis(self.max_backoff_ms);
+        let exponential = self
+            .initial_backoff_ms
+            .saturating_mul(1u64 << attempt.min(16))
+            .min(self.max_backoff_ms);
+        let jittered = Duration::from_millis(exponential / 2 + jitter_millis(exponential / 2));
+
+        match retry_after {
+            Some(retry_after) if retry_after > max_backoff => None,
+            Some(retry_after) => Some(retry_after.max(jittered)),
+            None => Some(jittered),
+        }
+    }
+}
+
+/// Codex Gateway 读取上游 SSE 的超时与断流续传策略。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StreamPolicy {
+    /// 上游连接建立后等待首个 SSE 事件的秒数
+    pub first_byte_timeout_secs: u64,
+    /// 两个 SSE 事件之间允许的最长间隔秒数
+    pub idle_timeout_secs: u64,
+    /// 上游中途断流后重新发起请求的次数，0 表示不续传
+    pub max_resumes: u32,
+}
+
+impl Default for StreamPolicy {
+    fn default() -> Self {
+        Self {
+            first_byte_timeout_secs: 120,
+            idle_timeout_secs: 300,
+            max_resumes: 0,
+        }
+    }
+}
+
+impl StreamPolicy {
+    /// 尚未收到任何事件时使用首包超时，之后使用空闲超时。
+    pub fn read_timeout(&self, received_first_event: bool) -> Duration {
+        let secs = if received_first_event {
+            self.idle_timeout_secs
+        } else {
+            self.first_byte_timeout_secs
+        };
+        Duration::from_secs(secs.max(1))
+    }
+}
+
+/// 一次重试的记录，用于写入 Gateway 日志。
+#[derive(Debug, Clone)]
+pub struct RetryAttempt {
+    /// 即将发起的重试序号（从 1 开始）
+    pub attempt: u32,
+    pub max_retries: u32,
+    pub delay: Duration,
+    pub reason: String,
+    /// 触发重试的上游状态码，连接失败时为空
+    pub status: Option<u16>,
+}
+
+/// 上游状态码是否属于可重试的瞬时失败。
+pub fn is_retryable_status(status: u16) -> bool {
+    matches!(status, 408 | 429 | 500 | 502 | 503 | 529)
+}
+
+/// 发送上游请求，在连接失败或可重试状态码时按策略重试。
+///
+/// 重试只发生在拿到上游响应体之前，因此不会向下游重复输出内容。
+/// `build_request` 每次重试都会被调用以重新构造请求；`skip_retry` 对某个响应返回 true 时
+/// 直接返回该响应，不再退避重试（例如交给调用方换用其他密钥）。
+pub async fn send_with_retry<B, L, S>(
+    policy: &RetryPolicy,
+    mut build_request: B,
+    mut on_retry: L,
+    mut skip_retry: S,
+) -> Result<reqwest::Response, reqwest::Error>
+where
+    B: FnMut() -> reqwest::RequestBuilder,
+    L: FnMut(&RetryAttempt),
+    S: FnMut(&reqwest::Response) -> bool,
+{
+    let mut attempt = 0;
+
+    loop {
+        let result = build_request().send().await;
+        if attempt >= policy.max_retries {
+            return result;
+        }
+
+        let (reason, retry_after, status) = match &result {
+            Ok(response) if skip_retry(response) => return result,
+            Ok(response) if is_retryable_status(response.status().as_u16()) => {
+                let retry_after = response
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| parse_retry_after(value, chrono::Utc::now()));
+                (
+                    format!("status={}", response.status()),
+                    retry_after,
+                    Some(response.status().as_u16()),
+                )
+            }
+            Ok(_) => return result,
+            Err(error) if error.is_connect() => (format!("连接失败: {}", error), None, None),
+            Err(_) => return result,
+        };
+
+        let Some(delay) = policy.next_delay(attempt, retry_after) else {
+            return result;
+        };
+
+        attempt += 1;
+        on_retry(&RetryAttempt {
+            attempt,
+            max_retries: policy.max_retries,
+            delay,
+            reason,
+            status,
+        });
+        drop(result);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+fn jitter_millis(upper: u64) -> u64 {
+    if upper == 0 {
+        return 0;
+    }
+    (Uuid::new_v4().as_u128() as u64) % (upper + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retryable_statuses_cover_overload_and_gateway_errors() {
+        for status in [408, 429, 500, 502, 503, 529] {
+            assert!(is_retryable_status(status), "{status} should be retryable");
+        }
+        for status in [200, 400, 401, 404, 501] {
+            assert!(!is_retryable_status(status), "{status} should not be retryable");
+        }
+    }
+
+    #[test]
+    fn next_delay_grows_exponentially_with_jitter_and_honours_retry_after() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            initial_backoff_ms: 400,
+            max_backoff_ms: 1_000,
+        };
+
+        for _ in 0..32 {
+            let first = policy.next_delay(0, None).unwrap();
+            assert!(first >= Duration::from_millis(200) && first <= Duration::from_millis(400));
+
+            let capped = policy.next_delay(4, None).unwrap();
+            assert!(capped >= Duration::from_millis(500) && capped <= Duration::from_millis(1_000));
+        }
+
+        assert_eq!(
+            policy.next_delay(0, Some(Duration::from_millis(900))),
+            Some(Duration::from_millis(900))
+        );
+        assert_eq!(policy.next_delay(0, Some(Duration::from_secs(30))), None);
+    }
+}
@@ -0,0 +1,293 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// 上游对结构化输出的支持程度。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StructuredOutputMode {
+    /// 原生支持 `response_format: json_schema`
+    #[default]
+    JsonSchema,
+    /// 只支持 `json_object`，Schema 写入系统提示词并在本地校验结果
+    JsonObject,
+    /// 不支持 `response_format`，只靠系统提示词并在本地校验结果
+    Prompt,
+}
+
+impl StructuredOutputMode {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// 一次请求要求的 JSON Schema 输出。
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructuredOutput {
+    pub name: String,
+    pub schema: Value,
+    pub strict: bool,
+    pub description: Option<String>,
+}
+
+impl StructuredOutput {
+    /// 解析 Responses `text.format`：`{type: "json_schema", name, schema, strict}`。
+    pub fn from_responses_format(format: &Value) -> Option<Self> {
+        if format.get("type").and_then(Value::as_str) != Some("json_schema") {
+            return None;
+        }
+        Self::from_fields(format)
+    }
+
+    /// 解析 Chat `response_format`：`{type: "json_schema", json_schema: {name, schema, strict}}`。
+    pub fn from_chat_format(format: &Value) -> Option<Self> {
+        if format.get("type").and_then(Value::as_str) != Some("json_schema") {
+            return None;
+        }
+        Self::from_fields(format.get("json_schema")?)
+    }
+
+    fn from_fields(fields: &Value) -> Option<Self> {
+        Some(Self {
+            name: fields
+                .get("name")
+                .and_then(Value::as_str)
+                .unwrap_or("response")
+                .to_string(),
+            schema: fields.get("schema")?.clone(),
+            strict: fields.get("strict").and_then(Value::as_bool).unwrap_or(false),
+            description: fields
+                .get("description")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+        })
+    }
+
+    pub fn to_chat_format(&self) -> Value {
+        json!({ "type": "json_schema", "json_schema": Value::Object(self.fields()) })
+    }
+
+    fn fields(&self) -> Map<String, Value> {
+        let mut fields = Map::new();
+        fields.insert("name".to_string(), Value::String(self.name.clone()));
+        if let Some(description) = &self.description {
+            fields.insert("description".to_string(), Value::String(description.clone()));
+        }
+        fields.insert("schema".to_string(), self.schema.clone());
+        fields.insert("strict".to_string(), Value::Bool(self.strict));
+        fields
+    }
+
+    /// 按上游能力写入 Chat 请求的 `response_format`，必要时把 Schema 追加到系统提示词。
+    pub fn apply_to_chat_request(&self, mode: StructuredOutputMode, request: &mut Map<String, Value>) {
+        match mode {
+            StructuredOutputMode::JsonSchema => {
+                request.insert("response_format".to_string(), self.to_chat_format());
+                return;
+            }
+            StructuredOutputMode::JsonObject => {
+                request.insert("response_format".to_string(), json!({ "type": "json_object" }));
+            }
+            StructuredOutputMode::Prompt => {
+                request.remove("response_format");
+            }
+        }
+
+        let Some(messages) = request.get_mut("messages").and_then(Value::as_array_mut) else {
+            return;
+        };
+        let prompt = self.prompt();
+        let system_content = messages
+            .first_mut()
+            .filter(|message| message.get("role").and_then(Value::as_str) == Some("system"))
+            .and_then(|message| message.get_mut("content"))
+            .filter(|content| content.is_string());
+        match system_content {
+            Some(content) => {
+                let text = content.as_str().unwrap_or_default();
+                *content = Value::String(format!("{text}\n\n{prompt}"));
+            }
+            None => messages.insert(0, json!({ "role": "system", "content": prompt })),
+        }
+    }
+
+    fn prompt(&self) -> String {
+        let schema = serde_json::to_string_pretty(&self.schema).unwrap_or_default();
+        let description = self
+            .description
+            .as_deref()
+            .map(|description| format!("{description}\n"))
+            .unwrap_or_default();
+        format!(
+            "Respond with a single JSON value named \"{}\" that conforms to the following JSON Schema. Output JSON only, without Markdown code fences or extra text.\n{}{}",
+            self.name, description, schema
+        )
+    }
+
+    /// 校验模型输出是否为符合 Schema 的 JSON。
+    pub fn validate(&self, text: &str) -> Result<(), String> {
+        let value = parse_json_output(text).ok_or_else(|| "输出不是合法的 JSON".to_string())?;
+        validate_value(&value, &self.schema, "$")
+    }
+}
+
+/// Responses `text.format` 转为 Chat `response_format`；`text` 格式不需要传给上游。
+pub fn responses_format_to_chat(format: &Value) -> Option<Value> {
+    match format.get("type").and_then(Value::as_str)? {
+        "json_schema" => StructuredOutput::from_responses_format(format).map(|output| output.to_chat_format()),
+        "text" => None,
+        _ => Some(format.clone()),
+    }
+}
+
+/// 解析模型输出的 JSON，容忍首尾空白和 Markdown 代码块。
+fn parse_json_output(text: &str) -> Option<Value> {
+    let text = text.trim();
+    if let Ok(value) = serde_json::from_str(text) {
+        return Some(value);
+    }
+    let fenced = text.strip_prefix("```")?.strip_suffix("```")?;
+    let fenced = fenced.strip_prefix("json").unwrap_or(fenced);
+    serde_json::from_str(fenced.trim()).ok()
+}
+
+/// JSON Schema 的常用子集：type、enum、const、anyOf/oneOf、properties、required、additionalProperties、items。
+fn validate_value(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    let Some(schema) = schema.as_object() else {
+        return Ok(());
+    };
+
+    if let Some(variants) = schema
+        .get("anyOf")
+        .or_else(|| schema.get("oneOf"))
+        .and_then(Value::as_array)
+    {
+        if !variants.iter().any(|variant| validate_value(value, variant, path).is_ok()) {
+            return Err(format!("{path} 不匹配任何候选 Schema"));
+        }
+    }
+    if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+        if !options.contains(value) {
+            return Err(format!("{path} 不在 enum 允许的取值中"));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            return Err(format!("{path} 不等于 const 指定的值"));
+        }
+    }
+    if let Some(types) = schema.get("type") {
+        let matches = match types {
+            Value::String(kind) => matches_type(value, kind),
+            Value::Array(kinds) => kinds
+                .iter()
+                .filter_map(Value::as_str)
+                .any(|kind| matches_type(value, kind)),
+            _ => true,
+        };
+        if !matches {
+            return Err(format!("{path} 类型应为 {types}"));
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for key in schema.get("required").and_then(Value::as_array).into_iter().flatten() {
+            if let Some(key) = key.as_str().filter(|key| !object.contains_key(*key)) {
+                return Err(format!("{path} 缺少必填字段 {key}"));
+            }
+        }
+        for (key, item) in object {
+            let item_path = format!("{path}.{key}");
+            match properties.and_then(|properties| properties.get(key)) {
+                Some(property) => validate_value(item, property, &item_path)?,
+                None => match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => return Err(format!("{path} 不允许额外字段 {key}")),
+                    Some(additional) => validate_value(item, additional, &item_path)?,
+                    None => {}
+                },
+            }
+        }
+    }
+
+    if let (Some(items), Some(item_schema)) = (value.as_array(), schema.get("items")) {
+        for (index, item) in items.iter().enumerate() {
+            validate_value(item, item_schema, &format!("{path}[{index}]"))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn matches_type(value: &Value, kind: &str) -> bool {
+    match kind {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|number| number.fract() == 0.0),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weather_format() -> Value {
+        json!({
+            "type": "json_schema",
+            "name": "weather",
+            "strict": true,
+            "schema": {
+                "type": "object",
+                "properties": {
+                    "city": { "type": "string" },
+                    "unit": { "type": "string", "enum": ["c", "f"] },
+                    "temps": { "type": "array", "items": { "type": "integer" } }
+                },
+                "required": ["city", "unit", "temps"],
+                "additionalProperties": false
+            }
+        })
+    }
+
+    #[test]
+    fn json_schema_format_round_trips_between_protocols() {
+        let chat = responses_format_to_chat(&weather_format()).unwrap();
+        assert_eq!(chat["type"], json!("json_schema"));
+        assert_eq!(chat["json_schema"]["name"], json!("weather"));
+        assert_eq!(chat["json_schema"]["strict"], json!(true));
+        assert_eq!(chat["json_schema"]["schema"], weather_format()["schema"]);
+
+        assert_eq!(
+            StructuredOutput::from_chat_format(&chat),
+            StructuredOutput::from_responses_format(&weather_format())
+        );
+        assert_eq!(responses_format_to_chat(&json!({ "type": "text" })), None);
+    }
+
+    #[test]
+    fn json_object_fallback_injects_schema_and_validates_output() {
+        let output = StructuredOutput::from_responses_format(&weather_format()).unwrap();
+        let mut request = json!({ "messages": [{ "role": "user", "content": "weather?" }] })
+            .as_object()
+            .cloned()
+            .unwrap();
+
+        output.apply_to_chat_request(StructuredOutputMode::JsonObject, &mut request);
+
+        assert_eq!(request["response_format"], json!({ "type": "json_object" }));
+        assert_eq!(request["messages"][0]["role"], json!("system"));
+        assert!(request["messages"][0]["content"].as_str().unwrap().contains("\"enum\""));
+        assert!(output
+            .validate("```json\n{\"city\": \"Paris\", \"unit\": \"c\", \"temps\": [12, 14]}\n```")
+            .is_ok());
+        assert!(output
+            .validate(r#"{"city": "Paris", "unit": "k", "temps": []}"#)
+            .unwrap_err()
+            .contains("$.unit"));
+        assert!(output.validate(r#"{"city": "Paris", "temps": [1.5]}"#).is_err());
+    }
+}
@@ -0,0 +1,234 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// `maxTokens` 限制的输出 token 字段（Anthropic、Chat Completions、Responses）
+const MAX_TOKENS_KEYS: &[&str] = &["max_tokens", "max_completion_tokens", "max_output_tokens"];
+
+/// 单个供应商的请求改写规则，全部为空时不改写。
+///
+/// 路径以 `.` 分隔，`*` 匹配数组的每个元素或对象的每个值，例如 `messages.*.content.*.cache_control`。
+/// 按 rename、remove、set、maxTokens、stripCacheControl 的顺序执行。
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransformRules {
+    /// 路径 -> 同一层级下的新字段名
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rename: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove: Vec<String>,
+    /// 路径 -> 固定值，缺失的中间对象会自动创建
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub set: BTreeMap<String, Value>,
+    /// 输出 token 上限，超出时截到该值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u64>,
+    /// 附加到上游请求的固定请求头
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// 删除请求体中所有 `cache_control` 字段
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strip_cache_control: bool,
+}
+
+impl TransformRules {
+    /// 从 `apiGateway.transform` 配置读取规则，缺失时视为不改写，格式错误时返回错误。
+    pub fn from_value(value: Option<&Value>) -> Result<Self, String> {
+        match value {
+            Some(value) => serde_json::from_value(value.clone())
+                .map_err(|e| format!("apiGateway.transform 格式错误: {}", e)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// 就地改写 JSON 请求体。
+    pub fn apply(&self, body: &mut Value) {
+        for (path, new_name) in &self.rename {
+            rename_path(body, &split_path(path), new_name);
+        }
+        for path in &self.remove {
+            remove_path(body, &split_path(path));
+        }
+        for (path, value) in &self.set {
+            set_path(body, &split_path(path), value);
+        }
+        if let (Some(limit), Some(object)) = (self.max_tokens, body.as_object_mut()) {
+            for key in MAX_TOKENS_KEYS {
+                if let Some(tokens) = object.get_mut(*key) {
+                    if tokens.as_u64().is_some_and(|tokens| tokens > limit) {
+                        *tokens = Value::from(limit);
+                    }
+                }
+            }
+        }
+        if self.strip_cache_control {
+            strip_key(body, "cache_control");
+        }
+    }
+
+    /// 改写序列化后的请求体；不是 JSON 或无需改写时原样返回。
+    pub fn apply_bytes(&self, body: Vec<u8>) -> Vec<u8> {
+        if self.is_empty() {
+            return body;
+        }
+        let Ok(mut json_body) = serde_json::from_slice::<Value>(&body) else {
+            return body;
+        };
+        self.apply(&mut json_body);
+        serde_json::to_vec(&json_body).unwrap_or(body)
+    }
+
+    pub fn apply_headers(&self, mut request_builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        for (name, value) in &self.headers {
+            request_builder = request_builder.header(name, value);
+        }
+        request_builder
+    }
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    path.split('.').filter(|segment| !segment.is_empty()).collect()
+}
+
+/// 依次返回 `segment` 在当前节点下匹配到的子节点。
+fn children_mut<'a>(value: &'a mut Value, segment: &str) -> Vec<&'a mut Value> {
+    match (value, segment) {
+        (Value::Array(items), "*") => items.iter_mut().collect(),
+        (Value::Object(object), "*") => object.values_mut().collect(),
+        (Value::Array(items), index) => index
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| items.get_mut(index))
+            .into_iter()
+            .collect(),
+        (Value::Object(object), key) => object.get_mut(key).into_iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn remove_path(value: &mut Value, segments: &[&str]) {
+    match segments {
+        [] => {}
+        [last] => match (value, *last) {
+            (Value::Object(object), "*") => object.clear(),
+            (Value::Object(object), key) => {
+                object.remove(key);
+            }
+            _ => {}
+        },
+        [segment, rest @ ..] => {
+            for child in children_mut(value, segment) {
+                remove_path(child, rest);
+            }
+        }
+    }
+}
+
+fn rename_path(value: &mut Value, segments: &[&str], new_name: &str) {
+    match segments {
+        [] => {}
+        [last] => {
+            if let Some(object) = value.as_object_mut() {
+                if let Some(moved) = object.remove(*last) {
+                    object.insert(new_name.to_string(), moved);
+                }
+            }
+        }
+        [segment, rest @ ..] => {
+            for child in children_mut(value, segment) {
+                rename_path(child, rest, new_name);
+            }
+        }
+    }
+}
+
+fn set_path(value: &mut Value, segments: &[&str], new_value: &Value) {
+    match segments {
+        [] => {}
+        [last] => {
+            if let Some(object) = value.as_object_mut() {
+                object.insert(last.to_string(), new_value.clone());
+            }
+        }
+        [segment, rest @ ..] => {
+            if *segment != "*" {
+                if let Some(object) = value.as_object_mut() {
+                    object
+                        .entry(segment.to_string())
+                        .or_insert_with(|| Value::Object(Default::default()));
+                }
+            }
+            for child in children_mut(value, segment) {
+                set_path(child, rest, new_value);
+            }
+        }
+    }
+}
+
+fn strip_key(value: &mut Value, key: &str) {
+    match value {
+        Value::Object(object) => {
+            object.remove(key);
+            object.values_mut().for_each(|item| strip_key(item, key));
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| strip_key(item, key)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn apply_runs_rules_in_declared_order() {
+        let rules = TransformRules::from_value(Some(&json!({
+            "rename": { "tools.*.input_schema": "parameters" },
+            "remove": ["thinking", "metadata", "messages.*.content.*.citations"],
+            "set": { "temperature": 0.5, "extra_body.top_k": 20 },
+            "maxTokens": 8192,
+            "stripCacheControl": true
+        })))
+        .unwrap();
+        let mut body = json!({
+            "max_tokens": 32000,
+            "thinking": { "type": "enabled" },
+            "metadata": { "user_id": "u" },
+            "system": [{ "type": "text", "text": "s", "cache_control": { "type": "ephemeral" } }],
+            "messages": [{ "role": "user", "content": [{ "type": "text", "text": "hi", "citations": [] }] }],
+            "tools": [{ "name": "read", "input_schema": { "type": "object" } }]
+        });
+
+        rules.apply(&mut body);
+
+        assert_eq!(
+            body,
+            json!({
+                "max_tokens": 8192,
+                "system": [{ "type": "text", "text": "s" }],
+                "messages": [{ "role": "user", "content": [{ "type": "text", "text": "hi" }] }],
+                "tools": [{ "name": "read", "parameters": { "type": "object" } }],
+                "temperature": 0.5,
+                "extra_body": { "top_k": 20 }
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_rules_are_reported() {
+        let error = TransformRules::from_value(Some(&json!({ "remove": "not-a-list" }))).unwrap_err();
+        assert!(error.contains("apiGateway.transform"));
+    }
+
+    #[test]
+    fn empty_rules_leave_non_json_body_untouched() {
+        let rules = TransformRules::from_value(None).unwrap();
+        assert!(rules.is_empty());
+        assert_eq!(rules.apply_bytes(b"plain".to_vec()), b"plain".to_vec());
+    }
+}
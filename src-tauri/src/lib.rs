@@ -1,25 +1,63 @@
+// 关闭 app 特性只构建独立 Gateway 时，桌面应用专用的代码不会被引用
+#![cfg_attr(not(feature = "app"), allow(dead_code))]
+
 mod api_gateway;
 mod codex_config;
 mod codex_gateway;
+mod codex_tools;
+#[cfg(feature = "app")]
 mod commands;
 mod config;
+mod gateway_capture;
+#[cfg(test)]
+mod gateway_conformance_tests;
+mod gateway_context;
+mod gateway_defaults;
+mod gateway_daemon;
+mod gateway_health;
+mod gateway_host;
+#[cfg(test)]
+mod gateway_integration_tests;
+mod gateway_keys;
+mod gateway_log;
+mod gateway_metrics;
+mod gateway_rate_limit;
+mod gateway_reasoning;
+mod gateway_replay;
+mod gateway_retry;
+mod gateway_structured;
+mod gateway_transform;
+#[cfg(feature = "app")]
 mod menubar;
+#[cfg(test)]
+mod mock_upstream;
 mod provider;
+mod secret_ref;
+mod secret_vault;
 mod settings;
 mod store;
+mod token_estimate;
 
+#[cfg(feature = "app")]
+use gateway_host::{GatewayConfigProvider, GatewayEventSink, GatewayHost};
+#[cfg(feature = "app")]
+use gateway_log::{GatewayKind, GatewayLogConfig};
+#[cfg(feature = "app")]
 use store::AppState;
+#[cfg(feature = "app")]
 use tauri::{
     menu::{CheckMenuItem, Menu, MenuBuilder, MenuItem},
     tray::{TrayIconBuilder, TrayIconEvent},
     Emitter, Manager,
 };
+#[cfg(feature = "app")]
 use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut};
 
-#[cfg(target_os = "macos")]
+#[cfg(all(feature = "app", target_os = "macos"))]
 use tauri::RunEvent;
 
 /// 创建动态托盘菜单
+#[cfg(feature = "app")]
 pub fn create_tray_menu(
     app: &tauri::AppHandle,
     app_state: &AppState,
@@ -43,7 +81,7 @@ pub fn create_tray_menu(
             let item = CheckMenuItem::with_id(
                 app,
                 format!("provider_{}", id),
-                &provider.name,
+                tray_provider_label(app_state, id, &provider.name),
                 true,
                 is_current,
                 None::<&str>,
@@ -64,6 +102,24 @@ pub fn create_tray_menu(
         menu_builder = menu_builder.item(&empty_hint);
     }
 
+    // Codex 供应商
+    if !config.codex_providers.is_empty() {
+        menu_builder = menu_builder.separator();
+        for (id, provider) in &config.codex_providers {
+            let is_current = config.current_codex == *id;
+            let item = CheckMenuItem::with_id(
+                app,
+                format!("codex_provider_{}", id),
+                format!("Codex: {}", tray_provider_label(app_state, id, &provider.name)),
+                true,
+                is_current,
+                None::<&str>,
+            )
+            .map_err(|e| format!("创建 Codex 菜单项失败: {}", e))?;
+            menu_builder = menu_builder.item(&item);
+        }
+    }
+
     // 分隔符和退出菜单
     let quit_item = MenuItem::with_id(app, "quit", "退出", true, None::<&str>)
         .map_err(|e| format!("创建退出菜单失败: {}", e))?;
@@ -75,7 +131,74 @@ pub fn create_tray_menu(
         .map_err(|e| format!("构建菜单失败: {}", e))
 }
 
+/// 供应商异常或熔断时在名称后显示状态标记
+#[cfg(feature = "app")]
+fn tray_provider_label(app_state: &AppState, provider_id: &str, name: &str) -> String {
+    match app_state
+        .gateway_health
+        .snapshot(provider_id)
+        .and_then(|snapshot| snapshot.tray_badge())
+    {
+        Some(badge) => format!("{} {}", name, badge),
+        None => name.to_string(),
+    }
+}
+
+/// 重新构建托盘菜单（供应商切换或健康状态变化后调用）
+#[cfg(feature = "app")]
+pub fn refresh_tray_menu(app: &tauri::AppHandle) {
+    let Some(app_state) = app.try_state::<AppState>() else {
+        return;
+    };
+
+    if let Ok(new_menu) = create_tray_menu(app, app_state.inner()) {
+        if let Some(tray) = app.tray_by_id("main") {
+            if let Err(e) = tray.set_menu(Some(new_menu)) {
+                log::error!("更新托盘菜单失败: {}", e);
+            }
+        }
+    }
+}
+
+/// Tauri 应用作为 Gateway 宿主：实时日志转发到前端日志窗口，供应商健康状态变化时刷新托盘菜单。
+#[cfg(feature = "app")]
+struct TauriSink {
+    app_handle: tauri::AppHandle,
+}
+
+#[cfg(feature = "app")]
+impl GatewayEventSink for TauriSink {
+    fn emit_log(&self, gateway: GatewayKind, level: &str, message: &str) {
+        let event = match gateway {
+            GatewayKind::Api => "api-gateway-log",
+            GatewayKind::Codex => "codex-gateway-log",
+        };
+        let payload = serde_json::json!({
+            "timestamp": chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            "level": level,
+            "message": message,
+        });
+        let _ = self.app_handle.emit(event, payload);
+    }
+
+    fn health_changed(&self) {
+        refresh_tray_menu(&self.app_handle);
+    }
+}
+
+#[cfg(feature = "app")]
+impl GatewayConfigProvider for TauriSink {
+    fn disk_logging(&self, gateway: GatewayKind) -> Option<GatewayLogConfig> {
+        self.app_handle.state::<AppState>().config.disk_logging(gateway)
+    }
+
+    fn capture_enabled(&self, gateway: GatewayKind) -> bool {
+        self.app_handle.state::<AppState>().config.capture_enabled(gateway)
+    }
+}
+
 /// 处理托盘菜单事件
+#[cfg(feature = "app")]
 fn handle_tray_menu_event(app: &tauri::AppHandle, event_id: &str) {
     log::info!("处理托盘菜单事件: {}", event_id);
 
@@ -95,6 +218,18 @@ fn handle_tray_menu_event(app: &tauri::AppHandle, event_id: &str) {
             log::info!("退出应用");
             app.exit(0);
         }
+        id if id.starts_with("codex_provider_") => {
+            let provider_id = id.strip_prefix("codex_provider_").unwrap();
+            log::info!("切换到 Codex 供应商: {}", provider_id);
+
+            let app_handle = app.clone();
+            let provider_id = provider_id.to_string();
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = switch_codex_provider_internal(&app_handle, provider_id).await {
+                    log::error!("切换 Codex 供应商失败: {}", e);
+                }
+            });
+        }
         id if id.starts_with("provider_") => {
             let provider_id = id.strip_prefix("provider_").unwrap();
             log::info!("切换到供应商: {}", provider_id);
@@ -115,6 +250,7 @@ fn handle_tray_menu_event(app: &tauri::AppHandle, event_id: &str) {
 }
 
 /// 内部切换供应商函数
+#[cfg(feature = "app")]
 async fn switch_provider_internal(
     app: &tauri::AppHandle,
     provider_id: String,
@@ -129,7 +265,20 @@ async fn switch_provider_internal(
     Ok(())
 }
 
+/// 内部切换 Codex 供应商函数
+#[cfg(feature = "app")]
+async fn switch_codex_provider_internal(
+    app: &tauri::AppHandle,
+    provider_id: String,
+) -> Result<(), String> {
+    if let Some(app_state) = app.try_state::<AppState>() {
+        commands::switch_codex_provider(app.clone(), app_state.clone().into(), provider_id).await?;
+    }
+    Ok(())
+}
+
 /// 更新托盘菜单的Tauri命令
+#[cfg(feature = "app")]
 #[tauri::command]
 async fn update_tray_menu(
     app: tauri::AppHandle,
@@ -145,6 +294,12 @@ async fn update_tray_menu(
     Ok(false)
 }
 
+/// 独立 Gateway 守护进程入口，见 `src/bin/switch-cc-gateway.rs`
+pub fn run_gateway_daemon() -> Result<(), String> {
+    gateway_daemon::run(std::env::args().skip(1))
+}
+
+#[cfg(feature = "app")]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     let mut builder = tauri::Builder::default();
@@ -204,7 +359,10 @@ pub fn run() {
 
             // 初始化应用状态
             let app_state = AppState::new();
-            app_state.set_app_handle(app.handle().clone());
+            let sink = std::sync::Arc::new(TauriSink {
+                app_handle: app.handle().clone(),
+            });
+            app_state.set_gateway_host(GatewayHost::new(sink.clone(), sink, app_state.gateway_log.clone()));
 
             // 初始化配置文件
             {
@@ -215,13 +373,20 @@ pub fn run() {
             // 保存配置
             let _ = app_state.save();
 
+            // 按保留策略清理过期的 Gateway 磁盘日志
+            if let (Ok(log_dir), Ok(config)) = (gateway_log::log_directory(), app_state.config.lock()) {
+                if let Err(error) = gateway_log::prune(&log_dir, &config.gateway_log, std::time::SystemTime::now()) {
+                    log::warn!("清理 Gateway 磁盘日志失败: {}", error);
+                }
+            }
+
             app.manage(app_state);
 
             {
                 let app_handle = app.handle().clone();
                 tauri::async_runtime::spawn(async move {
                     if let Some(app_state) = app_handle.try_state::<AppState>() {
-                        let (gateway_config, current_provider, gateway_provider) = {
+                        let (gateway_config, current_provider, gateway_provider, all_providers) = {
                             let config = match app_state.config.lock() {
                                 Ok(config) => config,
                                 Err(error) => {
@@ -230,14 +395,9 @@ pub fn run() {
                                 }
                             };
                             let current_provider = config.providers.get(&config.current).cloned();
-                            let gateway_provider = config
-                                .api_gateway
-                                .target_provider_id
-                                .as_ref()
-                                .and_then(|provider_id| config.providers.get(provider_id))
-                                .cloned()
-                                .or_else(|| current_provider.clone());
-                            (config.api_gateway.clone(), current_provider, gateway_provider)
+                            let gateway_provider = config.api_gateway_provider().cloned();
+                            let all_providers = config.providers.values().cloned().collect::<Vec<_>>();
+                            (config.api_gateway.clone(), current_provider, gateway_provider, all_providers)
                         };
 
                         if gateway_config.enabled {
@@ -255,7 +415,9 @@ pub fn run() {
                         }
 
                         if let Some(provider) = current_provider {
-                            if let Err(error) = config::merge_claude_config(&provider.settings_config) {
+                            let gateway_port = gateway_config.enabled.then_some(gateway_config.port);
+                            let settings = api_gateway::claude_settings(&provider, &all_providers, gateway_port);
+                            if let Err(error) = settings.and_then(|settings| config::merge_claude_config(&settings)) {
                                 log::error!("同步当前供应商 Claude 配置失败: {}", error);
                             }
                         }
@@ -272,13 +434,7 @@ pub fn run() {
                                 .codex_providers
                                 .get(&config.current_codex)
                                 .cloned();
-                            let codex_gateway_provider = config
-                                .codex_gateway
-                                .target_provider_id
-                                .as_ref()
-                                .and_then(|provider_id| config.codex_providers.get(provider_id))
-                                .cloned()
-                                .or_else(|| current_codex_provider.clone());
+                            let codex_gateway_provider = config.codex_gateway_provider().cloned();
                             (
                                 config.codex_gateway.clone(),
                                 current_codex_provider,
@@ -368,6 +524,7 @@ pub fn run() {
             commands::get_providers,
             commands::get_current_provider,
             commands::get_current_codex_provider,
+            commands::get_missing_secrets,
             commands::add_provider,
             commands::update_provider,
             commands::delete_provider,
@@ -394,10 +551,18 @@ pub fn run() {
             commands::launch_claude_with_provider,
             commands::get_api_gateway_status,
             commands::set_api_gateway_enabled,
+            commands::set_api_gateway_disk_logging_enabled,
+            commands::set_api_gateway_capture_enabled,
             commands::get_codex_gateway_status,
             commands::set_codex_gateway_enabled,
             commands::set_codex_gateway_disk_logging_enabled,
+            commands::set_codex_gateway_capture_enabled,
             commands::install_codex_gateway_provider,
+            commands::query_gateway_logs,
+            commands::list_gateway_captures,
+            commands::export_gateway_capture,
+            commands::replay_gateway_request,
+            commands::preview_gateway_request,
             update_tray_menu,
         ]);
 
@@ -0,0 +1,277 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    routing::any,
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// 模拟上游按顺序返回的一次响应。
+#[derive(Debug, Clone)]
+pub enum MockResponse {
+    Json { status: u16, body: Value },
+    /// 原样返回的响应体，用于构造非 JSON 的异常响应
+    Raw { status: u16, content_type: String, body: String },
+    /// 逐块写出的 SSE 响应，每块之前等待 `delay`
+    Sse { chunks: Vec<String>, delay: Duration },
+    /// 等待 `delay` 后再返回内层响应
+    Delayed { delay: Duration, response: Box<MockResponse> },
+}
+
+impl MockResponse {
+    pub fn json(body: Value) -> Self {
+        Self::Json { status: 200, body }
+    }
+
+    pub fn error(status: u16, body: Value) -> Self {
+        Self::Json { status, body }
+    }
+
+    pub fn raw(status: u16, content_type: &str, body: &str) -> Self {
+        Self::Raw {
+            status,
+            content_type: content_type.to_string(),
+            body: body.to_string(),
+        }
+    }
+
+    /// 把每个 JSON 事件编码为 `data:` 行，结尾附加 `[DONE]`。
+    pub fn sse_data(events: impl IntoIterator<Item = Value>) -> Self {
+        let mut chunks = events
+            .into_iter()
+            .map(|event| format!("data: {}\n\n", event))
+            .collect::<Vec<_>>();
+        chunks.push("data: [DONE]\n\n".to_string());
+        Self::Sse {
+            chunks,
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// 按 Anthropic 格式编码带事件名的 SSE。
+    pub fn sse_events(events: impl IntoIterator<Item = (&'static str, Value)>) -> Self {
+        Self::Sse {
+            chunks: events
+                .into_iter()
+                .map(|(event, data)| format!("event: {}\ndata: {}\n\n", event, data))
+                .collect(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// 原样写出的 SSE 分块，可用于构造格式错误的流。
+    pub fn sse_raw(chunks: impl IntoIterator<Item = &'static str>) -> Self {
+        Self::Sse {
+            chunks: chunks.into_iter().map(str::to_string).collect(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// 录制的 SSE 原文，按空行切分为逐个事件写出。
+    pub fn sse_transcript(transcript: &str) -> Self {
+        let normalized = transcript.replace("\r\n", "\n");
+        Self::Sse {
+            chunks: normalized
+                .split_inclusive("\n\n")
+                .filter(|chunk| !chunk.trim().is_empty())
+                .map(str::to_string)
+                .collect(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    pub fn with_chunk_delay(mut self, chunk_delay: Duration) -> Self {
+        if let Self::Sse { delay, .. } = &mut self {
+            *delay = chunk_delay;
+        }
+        self
+    }
+
+    pub fn delayed(self, delay: Duration) -> Self {
+        Self::Delayed {
+            delay,
+            response: Box::new(self),
+        }
+    }
+
+    fn into_response(self) -> futures_util::future::BoxFuture<'static, Response> {
+        Box::pin(async move {
+            match self {
+                Self::Json { status, body } => (status_code(status), Json(body)).into_response(),
+                Self::Raw {
+                    status,
+                    content_type,
+                    body,
+                } => (status_code(status), [("content-type", content_type)], body).into_response(),
+                Self::Sse { chunks, delay } => {
+                    let stream = async_stream::stream! {
+                        for chunk in chunks {
+                            if !delay.is_zero() {
+                                tokio::time::sleep(delay).await;
+                            }
+                            yield Ok::<_, std::io::Error>(Bytes::from(chunk));
+                        }
+                    };
+                    Response::builder()
+                        .status(StatusCode::OK)
+                        .header("content-type", "text/event-stream")
+                        .body(Body::from_stream(stream))
+                        .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+                }
+                Self::Delayed { delay, response } => {
+                    tokio::time::sleep(delay).await;
+                    response.into_response().await
+                }
+            }
+        })
+    }
+}
+
+/// 模拟上游收到的一次请求。
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub path: String,
+    pub query: Option<String>,
+    pub headers: HeaderMap,
+    /// 请求体不是 JSON 时为 `Value::Null`
+    pub body: Value,
+}
+
+#[derive(Clone, Default)]
+struct MockState {
+    responses: Arc<Mutex<VecDeque<MockResponse>>>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+/// 监听临时端口的离线上游，按脚本顺序返回响应并记录收到的请求。
+pub struct MockUpstream {
+    pub base_url: String,
+    state: MockState,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+}
+
+impl MockUpstream {
+    pub async fn start(responses: impl IntoIterator<Item = MockResponse>) -> Self {
+        let state = MockState {
+            responses: Arc::new(Mutex::new(responses.into_iter().collect())),
+            requests: Arc::default(),
+        };
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+            .await
+            .expect("绑定模拟上游端口失败");
+        let base_url = format!("http://{}", listener.local_addr().expect("读取模拟上游地址失败"));
+        let app = Router::new()
+            .fallback(any(handle_request))
+            .with_state(state.clone());
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, app)
+                .with_graceful_shutdown(async {
+                    let _ = shutdown_rx.await;
+                })
+                .await;
+        });
+
+        Self {
+            base_url,
+            state,
+            shutdown_tx: Some(shutdown_tx),
+        }
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.requests.lock().map(|requests| requests.clone()).unwrap_or_default()
+    }
+}
+
+impl Drop for MockUpstream {
+    fn drop(&mut self) {
+        if let Some(shutdown_tx) = self.shutdown_tx.take() {
+            let _ = shutdown_tx.send(());
+        }
+    }
+}
+
+async fn handle_request(
+    State(state): State<MockState>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if let Ok(mut requests) = state.requests.lock() {
+        requests.push(RecordedRequest {
+            method,
+            path: uri.path().to_string(),
+            query: uri.query().map(str::to_string),
+            headers,
+            body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+        });
+    }
+
+    let next = state.responses.lock().ok().and_then(|mut responses| responses.pop_front());
+    match next {
+        Some(response) => response.into_response().await,
+        None => MockResponse::error(500, json!({ "error": "模拟上游没有剩余的脚本响应" }))
+            .into_response()
+            .await,
+    }
+}
+
+fn status_code(status: u16) -> StatusCode {
+    StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// 返回一个当前没有进程监听的本地地址，用于模拟连接被拒绝。
+pub async fn unreachable_base_url() -> String {
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .expect("绑定临时端口失败");
+    let address = listener.local_addr().expect("读取临时端口失败");
+    drop(listener);
+    format!("http://{}", address)
+}
+
+/// Anthropic `/v1/messages` 非流式响应。
+pub fn anthropic_message(model: &str, text: &str) -> Value {
+    json!({
+        "id": "msg_mock",
+        "type": "message",
+        "role": "assistant",
+        "model": model,
+        "content": [{ "type": "text", "text": text }],
+        "stop_reason": "end_turn",
+        "usage": { "input_tokens": 12, "output_tokens": 5 }
+    })
+}
+
+/// OpenAI Chat Completions 非流式响应。
+pub fn chat_completion(model: &str, text: &str) -> Value {
+    json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion",
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": text },
+            "finish_reason": "stop"
+        }],
+        "usage": { "prompt_tokens": 12, "completion_tokens": 5, "total_tokens": 17 }
+    })
+}
+
+/// OpenAI Chat Completions 流式分块。
+pub fn chat_chunk(delta: Value, finish_reason: Option<&str>) -> Value {
+    json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion.chunk",
+        "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }]
+    })
+}
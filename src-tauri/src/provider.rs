@@ -1,3 +1,10 @@
+use crate::gateway_context::ContextWindow;
+use crate::gateway_defaults::RequestDefaults;
+use crate::gateway_keys::KeyPoolConfig;
+use crate::gateway_rate_limit::RateLimitConfig;
+use crate::gateway_reasoning::ReasoningMapping;
+use crate::gateway_structured::StructuredOutputMode;
+use crate::gateway_transform::TransformRules;
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
 
@@ -14,7 +21,23 @@ impl Default for ProviderType {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// 覆盖适配器默认的多模态输入能力，未设置的项沿用适配器默认值。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputCapabilities {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub images: Option<bool>,
+    /// 是否接受 Chat Completions 的 `file` 内容块（如 PDF）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<bool>,
+}
+
+impl InputCapabilities {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CodexProviderConfig {
     #[serde(rename = "providerName")]
     pub provider_name: String,
@@ -22,8 +45,29 @@ pub struct CodexProviderConfig {
     pub upstream_url: String,
     #[serde(rename = "apiKey")]
     pub api_key: String,
+    /// 与 `apiKey` 轮换使用的其他密钥
+    #[serde(rename = "keyPool", default, skip_serializing_if = "KeyPoolConfig::is_empty")]
+    pub key_pool: KeyPoolConfig,
     #[serde(rename = "modelName")]
     pub model_name: String,
+    #[serde(rename = "rateLimit", default, skip_serializing_if = "RateLimitConfig::is_empty")]
+    pub rate_limit: RateLimitConfig,
+    /// 系统提示词和默认参数
+    #[serde(default, skip_serializing_if = "RequestDefaults::is_empty")]
+    pub defaults: RequestDefaults,
+    /// 推理强度映射
+    #[serde(default, skip_serializing_if = "ReasoningMapping::is_empty")]
+    pub reasoning: ReasoningMapping,
+    /// 上游对 `json_schema` 结构化输出的支持程度
+    #[serde(rename = "structuredOutput", default, skip_serializing_if = "StructuredOutputMode::is_default")]
+    pub structured_output: StructuredOutputMode,
+    #[serde(default, skip_serializing_if = "InputCapabilities::is_empty")]
+    pub capabilities: InputCapabilities,
+    /// 模型上下文长度及超出时的处理方式
+    #[serde(rename = "contextWindow", default, skip_serializing_if = "ContextWindow::is_empty")]
+    pub context_window: ContextWindow,
+    #[serde(default, skip_serializing_if = "TransformRules::is_empty")]
+    pub transform: TransformRules,
 }
 
 impl CodexProviderConfig {
@@ -455,6 +499,7 @@ mod tests {
                 upstream_url: "https://token-plan-sgp.xiaomimimo.com/v1".to_string(),
                 api_key: "tp-test".to_string(),
                 model_name: "mimo-v2.5-pro".to_string(),
+                ..Default::default()
             },
             website_url: None,
             category: None,
@@ -476,6 +521,7 @@ mod tests {
                 upstream_url: "https://token-plan-sgp.xiaomimimo.com/v1".to_string(),
                 api_key: "tp-test".to_string(),
                 model_name: "mimo-v2.5-pro".to_string(),
+                ..Default::default()
             }),
             website_url: None,
             category: None,
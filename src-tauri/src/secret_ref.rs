@@ -0,0 +1,179 @@
+use crate::provider::{CodexProvider, Provider};
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+const ENV_PREFIX: &str = "env:";
+const FILE_PREFIX: &str = "file:";
+const CMD_PREFIX: &str = "cmd:";
+
+/// 值是 `env:` / `file:` / `cmd:` 形式的密钥引用。
+pub fn is_secret_ref(value: &str) -> bool {
+    value.starts_with(ENV_PREFIX) || value.starts_with(CMD_PREFIX) || file_ref_path(value).is_some()
+}
+
+/// `file:` 引用的路径，只接受 `~/` 开头或绝对路径；`file://` 形式的 URL 不视为引用。
+fn file_ref_path(value: &str) -> Option<&str> {
+    let path = value.strip_prefix(FILE_PREFIX)?.trim();
+    if path.starts_with("//") {
+        return None;
+    }
+    (path.starts_with("~/") || std::path::Path::new(path).is_absolute()).then_some(path)
+}
+
+/// 解析密钥引用，普通值原样返回。
+///
+/// - `env:NAME` 读取进程环境变量
+/// - `file:~/.secrets/key`、`file:/etc/relay/key` 读取文件内容（去掉结尾空白）
+/// - `cmd:pass show relay/work` 通过 shell 执行命令，取标准输出
+pub fn resolve(value: &str) -> Result<String, String> {
+    if let Some(name) = value.strip_prefix(ENV_PREFIX) {
+        let name = name.trim();
+        return std::env::var(name)
+            .ok()
+            .filter(|secret| !secret.is_empty())
+            .ok_or_else(|| format!("环境变量 {} 未设置或为空", name));
+    }
+    if let Some(path) = file_ref_path(value) {
+        let path = expand_home(path)?;
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("读取密钥文件 {} 失败: {}", path.display(), e))?;
+        return non_empty(content.trim_end(), || format!("密钥文件 {} 为空", path.display()));
+    }
+    if let Some(command) = value.strip_prefix(CMD_PREFIX) {
+        return run_command(command.trim());
+    }
+    Ok(value.to_string())
+}
+
+fn non_empty(secret: &str, error: impl FnOnce() -> String) -> Result<String, String> {
+    if secret.is_empty() {
+        Err(error())
+    } else {
+        Ok(secret.to_string())
+    }
+}
+
+fn expand_home(path: &str) -> Result<PathBuf, String> {
+    match path.strip_prefix("~/") {
+        Some(rest) => Ok(dirs::home_dir().ok_or("无法获取用户目录")?.join(rest)),
+        None => Ok(PathBuf::from(path)),
+    }
+}
+
+fn run_command(command: &str) -> Result<String, String> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output()
+    } else {
+        Command::new("sh").args(["-c", command]).output()
+    }
+    .map_err(|e| format!("执行密钥命令 `{}` 失败: {}", command, e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "密钥命令 `{}` 执行失败（{}）: {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    non_empty(stdout.trim_end(), || format!("密钥命令 `{}` 没有输出", command))
+}
+
+fn resolve_in_place(value: &mut Value) -> Result<(), String> {
+    if let Some(text) = value.as_str().filter(|text| is_secret_ref(text)) {
+        *value = Value::String(resolve(text)?);
+    }
+    Ok(())
+}
+
+/// 解析 Claude 配置中 `env` 的各项值、`apiGateway.auth`/`authHeaders` 的 `value`
+/// 以及 `apiGateway.keyPool.keys` 的 `key`。
+pub fn resolve_claude_settings(settings: &Value) -> Result<Value, String> {
+    let mut settings = settings.clone();
+    if let Some(env) = settings.get_mut("env").and_then(Value::as_object_mut) {
+        for (name, value) in env.iter_mut() {
+            resolve_in_place(value).map_err(|e| format!("{}: {}", name, e))?;
+        }
+    }
+    for field in ["auth", "authHeaders"] {
+        let entries = settings
+            .get_mut("apiGateway")
+            .and_then(|gateway| gateway.get_mut(field))
+            .and_then(Value::as_array_mut);
+        for entry in entries.into_iter().flatten() {
+            if let Some(value) = entry.get_mut("value") {
+                resolve_in_place(value).map_err(|e| format!("apiGateway.{}: {}", field, e))?;
+            }
+        }
+    }
+    let pool_keys = settings
+        .get_mut("apiGateway")
+        .and_then(|gateway| gateway.get_mut("keyPool"))
+        .and_then(|pool| pool.get_mut("keys"))
+        .and_then(Value::as_array_mut);
+    for entry in pool_keys.into_iter().flatten() {
+        if let Some(value) = entry.get_mut("key") {
+            resolve_in_place(value).map_err(|e| format!("apiGateway.keyPool: {}", e))?;
+        }
+    }
+    Ok(settings)
+}
+
+pub fn resolve_claude_provider(provider: &Provider) -> Result<Provider, String> {
+    let mut provider = provider.clone();
+    provider.settings_config = resolve_claude_settings(&provider.settings_config)
+        .map_err(|e| format!("解析供应商 {} 的密钥失败: {}", provider.name, e))?;
+    Ok(provider)
+}
+
+pub fn resolve_codex_provider(provider: &CodexProvider) -> Result<CodexProvider, String> {
+    let mut provider = provider.clone();
+    let name = provider.name.clone();
+    let config = &mut provider.codex_config;
+    for api_key in std::iter::once(&mut config.api_key).chain(config.key_pool.keys.iter_mut().map(|key| &mut key.key)) {
+        *api_key = resolve(api_key.trim()).map_err(|e| format!("解析供应商 {} 的密钥失败: {}", name, e))?;
+    }
+    Ok(provider)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn resolves_env_and_file_references() {
+        std::env::set_var("SWITCH_CC_TEST_SECRET_REF", "sk-from-env");
+        let path = std::env::temp_dir().join(format!("switch-cc-secret-{}", uuid::Uuid::new_v4().simple()));
+        fs::write(&path, "sk-from-file\n").unwrap();
+
+        let settings = json!({
+            "env": {
+                "ANTHROPIC_AUTH_TOKEN": "env:SWITCH_CC_TEST_SECRET_REF",
+                "ANTHROPIC_BASE_URL": "https://relay.example.com"
+            },
+            "apiGateway": { "auth": [{ "name": "x-token", "value": format!("file:{}", path.display()) }] }
+        });
+        let resolved = resolve_claude_settings(&settings).unwrap();
+
+        assert_eq!(resolved["env"]["ANTHROPIC_AUTH_TOKEN"], json!("sk-from-env"));
+        assert_eq!(resolved["env"]["ANTHROPIC_BASE_URL"], json!("https://relay.example.com"));
+        assert_eq!(resolved["apiGateway"]["auth"][0]["value"], json!("sk-from-file"));
+        assert!(resolve("env:SWITCH_CC_TEST_MISSING_SECRET").unwrap_err().contains("未设置"));
+        for url in ["file:///Users/me/relay.sock", "file://host/share/key"] {
+            assert!(!is_secret_ref(url));
+            assert_eq!(resolve(url).unwrap(), url);
+        }
+        let _ = fs::remove_file(path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolves_command_references() {
+        assert_eq!(resolve("cmd:printf 'sk-from-cmd\\n'").unwrap(), "sk-from-cmd");
+        assert!(resolve("cmd:echo oops >&2; exit 3").unwrap_err().contains("oops"));
+    }
+}
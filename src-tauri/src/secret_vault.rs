@@ -0,0 +1,484 @@
+use crate::secret_ref;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// 配置中指向保险库条目的引用前缀
+pub const VAULT_REF_PREFIX: &str = "vault:";
+/// 设置后用口令派生密钥，否则使用本机密钥文件
+pub const PASSPHRASE_ENV: &str = "SWITCH_CC_VAULT_PASSPHRASE";
+const VAULT_FILE: &str = "secrets.vault";
+const KEY_FILE: &str = "vault.key";
+/// 本机密钥所在目录名，位于本地数据目录下，与会被同步/备份的配置目录分开
+const KEY_DIR: &str = "switch-cc-keys";
+const VAULT_VERSION: u32 = 1;
+const PBKDF2_ITERATIONS: u32 = 600_000;
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const VAULT_AAD: &[u8] = b"switch-cc-vault-v1";
+
+/// 保险库加密密钥的来源。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum VaultKdf {
+    /// 本地数据目录下权限为 0600 的随机密钥文件
+    KeyFile,
+    /// PBKDF2-HMAC-SHA256 从用户口令派生
+    Passphrase,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VaultFile {
+    version: u32,
+    kdf: VaultKdf,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    salt: String,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    iterations: u32,
+    nonce: String,
+    ciphertext: String,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+/// 口令派生较慢，同一进程内按盐值缓存派生结果
+fn derived_keys() -> &'static Mutex<HashMap<String, [u8; KEY_LEN]>> {
+    static KEYS: OnceLock<Mutex<HashMap<String, [u8; KEY_LEN]>>> = OnceLock::new();
+    KEYS.get_or_init(Mutex::default)
+}
+
+/// 与 `config.json` 同目录的加密密钥库，保存引用 → 明文密钥。
+pub struct SecretVault {
+    dir: PathBuf,
+    /// 本机密钥文件所在目录
+    key_dir: PathBuf,
+}
+
+impl SecretVault {
+    pub fn for_config(config_path: &Path) -> Self {
+        let dir = config_path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Self {
+            key_dir: machine_key_dir().unwrap_or_else(|| dir.clone()),
+            dir,
+        }
+    }
+
+    fn vault_path(&self) -> PathBuf {
+        self.dir.join(VAULT_FILE)
+    }
+
+    /// 解密全部条目，保险库不存在时返回空表。
+    pub fn read(&self) -> Result<BTreeMap<String, String>, String> {
+        let path = self.vault_path();
+        if !path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let file = read_vault_file(&path)?;
+        if file.version != VAULT_VERSION {
+            return Err(format!("不支持的保险库版本: {}", file.version));
+        }
+        let key = self.key(&file)?;
+        let nonce = decode_base64(&file.nonce, "nonce")?;
+        let nonce = Nonce::try_assume_unique_for_key(&nonce).map_err(|_| "保险库 nonce 长度无效".to_string())?;
+        let mut in_out = decode_base64(&file.ciphertext, "ciphertext")?;
+        let plaintext = key
+            .open_in_place(nonce, Aad::from(VAULT_AAD), &mut in_out)
+            .map_err(|_| "保险库解密失败：密钥或口令不正确，或文件已损坏".to_string())?;
+        serde_json::from_slice(plaintext).map_err(|e| format!("解析保险库内容失败: {}", e))
+    }
+
+    /// 加密写入全部条目；设置了口令环境变量时改用口令加密。
+    pub fn write(&self, secrets: &BTreeMap<String, String>) -> Result<(), String> {
+        let rng = SystemRandom::new();
+        let mut file = VaultFile {
+            version: VAULT_VERSION,
+            kdf: VaultKdf::KeyFile,
+            salt: String::new(),
+            iterations: 0,
+            nonce: String::new(),
+            ciphertext: String::new(),
+        };
+        if std::env::var(PASSPHRASE_ENV).is_ok_and(|value| !value.is_empty()) {
+            // 沿用已有的盐值，避免每次保存都重新派生密钥
+            let existing_salt = read_vault_file(&self.vault_path())
+                .ok()
+                .filter(|existing| existing.kdf == VaultKdf::Passphrase && existing.iterations == PBKDF2_ITERATIONS)
+                .map(|existing| existing.salt);
+            file.kdf = VaultKdf::Passphrase;
+            file.iterations = PBKDF2_ITERATIONS;
+            file.salt = match existing_salt {
+                Some(salt) => salt,
+                None => STANDARD.encode(random_bytes::<SALT_LEN>(&rng)?),
+            };
+        }
+
+        let key = self.key(&file)?;
+        let nonce = random_bytes::<NONCE_LEN>(&rng)?;
+        let mut in_out = serde_json::to_vec(secrets).map_err(|e| format!("序列化保险库内容失败: {}", e))?;
+        key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(VAULT_AAD), &mut in_out)
+            .map_err(|_| "保险库加密失败".to_string())?;
+        file.nonce = STANDARD.encode(nonce);
+        file.ciphertext = STANDARD.encode(in_out);
+
+        let content = serde_json::to_string_pretty(&file).map_err(|e| format!("序列化保险库失败: {}", e))?;
+        write_private_file(&self.vault_path(), content.as_bytes())
+    }
+
+    fn key(&self, file: &VaultFile) -> Result<LessSafeKey, String> {
+        let bytes = match file.kdf {
+            VaultKdf::KeyFile => self.key_file_bytes()?,
+            VaultKdf::Passphrase => derive_passphrase_key(file)?,
+        };
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &bytes).map_err(|_| "保险库密钥无效".to_string())?;
+        Ok(LessSafeKey::new(key))
+    }
+
+    /// 读取本机密钥文件，不存在时生成。
+    ///
+    /// 旧版本把密钥文件放在配置目录，首次读取时迁移到本地数据目录并删除旧文件。
+    fn key_file_bytes(&self) -> Result<[u8; KEY_LEN], String> {
+        let path = self.key_dir.join(KEY_FILE);
+        if !path.exists() {
+            create_private_dir(&self.key_dir)?;
+            let legacy_path = self.dir.join(KEY_FILE);
+            if legacy_path != path && legacy_path.exists() {
+                let content =
+                    fs::read(&legacy_path).map_err(|e| format!("读取旧的保险库密钥文件失败: {}", e))?;
+                write_private_file(&path, &content)?;
+                if let Err(error) = fs::remove_file(&legacy_path) {
+                    log::warn!("删除配置目录中的旧保险库密钥文件失败: {}", error);
+                }
+            } else {
+                let key = random_bytes::<KEY_LEN>(&SystemRandom::new())?;
+                write_private_file(&path, STANDARD.encode(key).as_bytes())?;
+            }
+        }
+        let content = fs::read_to_string(&path).map_err(|e| format!("读取保险库密钥文件失败: {}", e))?;
+        decode_base64(content.trim(), "密钥文件")?
+            .try_into()
+            .map_err(|_| "保险库密钥文件长度无效".to_string())
+    }
+}
+
+/// 本机密钥目录；测试使用临时目录，避免写入真实的用户数据目录
+fn machine_key_dir() -> Option<PathBuf> {
+    if cfg!(test) {
+        return Some(std::env::temp_dir().join(KEY_DIR));
+    }
+    dirs::data_local_dir().map(|dir| dir.join(KEY_DIR))
+}
+
+fn read_vault_file(path: &Path) -> Result<VaultFile, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("读取保险库失败: {}", e))?;
+    serde_json::from_str(&content).map_err(|e| format!("解析保险库失败: {}", e))
+}
+
+fn derive_passphrase_key(file: &VaultFile) -> Result<[u8; KEY_LEN], String> {
+    let passphrase = std::env::var(PASSPHRASE_ENV)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| format!("保险库使用口令加密，请设置环境变量 {}", PASSPHRASE_ENV))?;
+    let mut cache = derived_keys().lock().map_err(|e| format!("获取锁失败: {}", e))?;
+    if let Some(key) = cache.get(&file.salt) {
+        return Ok(*key);
+    }
+    let iterations = NonZeroU32::new(file.iterations).ok_or("保险库迭代次数无效")?;
+    let salt = decode_base64(&file.salt, "salt")?;
+    let mut key = [0u8; KEY_LEN];
+    ring::pbkdf2::derive(ring::pbkdf2::PBKDF2_HMAC_SHA256, iterations, &salt, passphrase.as_bytes(), &mut key);
+    cache.insert(file.salt.clone(), key);
+    Ok(key)
+}
+
+fn random_bytes<const N: usize>(rng: &SystemRandom) -> Result<[u8; N], String> {
+    let mut bytes = [0u8; N];
+    rng.fill(&mut bytes).map_err(|_| "生成随机数失败".to_string())?;
+    Ok(bytes)
+}
+
+fn decode_base64(value: &str, field: &str) -> Result<Vec<u8>, String> {
+    STANDARD
+        .decode(value)
+        .map_err(|e| format!("保险库 {} 不是有效的 base64: {}", field, e))
+}
+
+/// 原子写入仅当前用户可读写（0600）的文件。
+pub fn write_private_file(path: &Path, content: &[u8]) -> Result<(), String> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).map_err(|e| format!("创建目录失败: {}", e))?;
+    }
+    let temp_path = path.with_extension("tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&temp_path)
+        .map_err(|e| format!("写入临时文件失败: {}", e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        // 临时文件可能是之前残留的，mode 只在创建时生效
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("设置文件权限失败: {}", e))?;
+    }
+    std::io::Write::write_all(&mut file, content).map_err(|e| format!("写入临时文件失败: {}", e))?;
+    drop(file);
+    fs::rename(&temp_path, path).map_err(|e| format!("重命名文件失败: {}", e))
+}
+
+/// 创建仅当前用户可访问（0700）的目录，已存在时同样收紧权限。
+pub fn create_private_dir(dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("创建目录失败: {}", e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+            .map_err(|e| format!("设置目录权限失败: {}", e))?;
+    }
+    Ok(())
+}
+
+/// 环境变量名看起来是凭据（`ANTHROPIC_AUTH_TOKEN`、`*_API_KEY` 等）。
+pub fn is_secret_env_name(name: &str) -> bool {
+    let upper = name.to_uppercase();
+    ["TOKEN", "KEY", "SECRET", "PASSWORD"]
+        .iter()
+        .any(|marker| upper.contains(marker))
+}
+
+/// 非空、且不是保险库引用或 `env:`/`file:`/`cmd:` 引用的明文换成引用，明文放入 `secrets`。
+fn take_secret(value: &mut Value, reference: String, secrets: &mut BTreeMap<String, String>) {
+    let Some(secret) = value
+        .as_str()
+        .filter(|secret| !secret.is_empty() && !secret.starts_with(VAULT_REF_PREFIX) && !secret_ref::is_secret_ref(secret))
+    else {
+        return;
+    };
+    secrets.insert(reference.clone(), secret.to_string());
+    *value = Value::String(format!("{}{}", VAULT_REF_PREFIX, reference));
+}
+
+/// 把序列化后的 `AppConfig` 中的密钥替换为 `vault:` 引用，返回取出的 引用 → 明文。
+///
+/// 覆盖 Claude 供应商 `env` 中的凭据变量、`apiGateway.auth`/`authHeaders` 的 `value`、
+/// Codex 供应商的 `apiKey`，以及两者 `keyPool` 中的密钥。
+pub fn extract_secrets(config: &mut Value) -> BTreeMap<String, String> {
+    let mut secrets = BTreeMap::new();
+    if let Some(providers) = config.get_mut("providers").and_then(Value::as_object_mut) {
+        for (id, provider) in providers.iter_mut() {
+            let Some(settings) = provider.get_mut("settingsConfig") else {
+                continue;
+            };
+            if let Some(env) = settings.get_mut("env").and_then(Value::as_object_mut) {
+                for (name, value) in env.iter_mut().filter(|(name, _)| is_secret_env_name(name)) {
+                    take_secret(value, format!("providers/{}/env/{}", id, name), &mut secrets);
+                }
+            }
+            for field in ["auth", "authHeaders"] {
+                let entries = settings
+                    .get_mut("apiGateway")
+                    .and_then(|gateway| gateway.get_mut(field))
+                    .and_then(Value::as_array_mut);
+                for (index, entry) in entries.into_iter().flatten().enumerate() {
+                    if let Some(value) = entry.get_mut("value") {
+                        take_secret(value, format!("providers/{}/apiGateway/{}/{}", id, field, index), &mut secrets);
+                    }
+                }
+            }
+            take_key_pool_secrets(
+                settings.pointer_mut("/apiGateway/keyPool"),
+                &format!("providers/{}/apiGateway/keyPool", id),
+                &mut secrets,
+            );
+        }
+    }
+    if let Some(providers) = config.get_mut("codex_providers").and_then(Value::as_object_mut) {
+        for (id, provider) in providers.iter_mut() {
+            if let Some(api_key) = provider.pointer_mut("/codexConfig/apiKey") {
+                take_secret(api_key, format!("codex_providers/{}/apiKey", id), &mut secrets);
+            }
+            take_key_pool_secrets(
+                provider.pointer_mut("/codexConfig/keyPool"),
+                &format!("codex_providers/{}/keyPool", id),
+                &mut secrets,
+            );
+        }
+    }
+    secrets
+}
+
+fn take_key_pool_secrets(pool: Option<&mut Value>, reference: &str, secrets: &mut BTreeMap<String, String>) {
+    let keys = pool
+        .and_then(|pool| pool.get_mut("keys"))
+        .and_then(Value::as_array_mut);
+    for (index, entry) in keys.into_iter().flatten().enumerate() {
+        if let Some(key) = entry.get_mut("key") {
+            take_secret(key, format!("{}/{}", reference, index), secrets);
+        }
+    }
+}
+
+/// 把所有 `vault:` 引用替换回明文；保险库中缺失的引用保留原文（下次保存时不会被空值覆盖）并返回其名称。
+pub fn inject_secrets(config: &mut Value, secrets: &BTreeMap<String, String>) -> Vec<String> {
+    let mut missing = Vec::new();
+    inject_into(config, secrets, &mut missing);
+    missing
+}
+
+fn inject_into(value: &mut Value, secrets: &BTreeMap<String, String>, missing: &mut Vec<String>) {
+    match value {
+        Value::String(text) => {
+            if let Some(reference) = text.strip_prefix(VAULT_REF_PREFIX) {
+                match secrets.get(reference) {
+                    Some(secret) => *text = secret.clone(),
+                    None => missing.push(reference.to_string()),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| inject_into(item, secrets, missing)),
+        Value::Object(object) => object.values_mut().for_each(|item| inject_into(item, secrets, missing)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample_config() -> Value {
+        json!({
+            "providers": {
+                "relay": {
+                    "id": "relay",
+                    "settingsConfig": {
+                        "env": {
+                            "ANTHROPIC_AUTH_TOKEN": "sk-ant-secret",
+                            "ANTHROPIC_BASE_URL": "https://relay.example.com",
+                            "CUSTOM_API_KEY": ""
+                        },
+                        "apiGateway": { "auth": [{ "type": "header", "name": "x-token", "value": "tok-secret" }] }
+                    }
+                }
+            },
+            "codex_providers": {
+                "deepseek": {
+                    "codexConfig": {
+                        "apiKey": "sk-ds-secret",
+                        "upstreamUrl": "https://api.deepseek.com/v1",
+                        "keyPool": { "keys": [{ "key": "sk-ds-second-secret", "label": "second" }] }
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn extract_and_inject_round_trip_secrets() {
+        let original = sample_config();
+        let mut config = original.clone();
+
+        let secrets = extract_secrets(&mut config);
+
+        assert_eq!(secrets.len(), 4);
+        assert_eq!(secrets["codex_providers/deepseek/keyPool/0"], "sk-ds-second-secret");
+        let text = config.to_string();
+        assert!(!text.contains("secret"));
+        assert_eq!(
+            config["providers"]["relay"]["settingsConfig"]["env"]["ANTHROPIC_AUTH_TOKEN"],
+            json!("vault:providers/relay/env/ANTHROPIC_AUTH_TOKEN")
+        );
+        assert_eq!(
+            config["providers"]["relay"]["settingsConfig"]["env"]["ANTHROPIC_BASE_URL"],
+            json!("https://relay.example.com")
+        );
+        assert!(extract_secrets(&mut config.clone()).is_empty());
+
+        assert!(inject_secrets(&mut config, &secrets).is_empty());
+        assert_eq!(config, original);
+    }
+
+    #[test]
+    fn missing_vault_entry_keeps_reference() {
+        let mut config = sample_config();
+        let mut secrets = extract_secrets(&mut config);
+        secrets.remove("codex_providers/deepseek/apiKey");
+
+        let missing = inject_secrets(&mut config, &secrets);
+
+        assert_eq!(missing, vec!["codex_providers/deepseek/apiKey".to_string()]);
+        assert_eq!(
+            config["codex_providers"]["deepseek"]["codexConfig"]["apiKey"],
+            json!("vault:codex_providers/deepseek/apiKey")
+        );
+        let mut saved = config.clone();
+        assert_eq!(extract_secrets(&mut saved).len(), 3);
+        assert_eq!(
+            saved["codex_providers"]["deepseek"]["codexConfig"]["apiKey"],
+            json!("vault:codex_providers/deepseek/apiKey")
+        );
+    }
+
+    #[test]
+    fn vault_encrypts_with_private_key_file() {
+        let dir = std::env::temp_dir().join(format!("switch-cc-vault-{}", uuid::Uuid::new_v4().simple()));
+        let vault = SecretVault {
+            dir: dir.join("config"),
+            key_dir: dir.join("keys"),
+        };
+        let secrets = BTreeMap::from([("codex_providers/a/apiKey".to_string(), "sk-very-secret".to_string())]);
+
+        vault.write(&secrets).unwrap();
+
+        assert_eq!(vault.read().unwrap(), secrets);
+        assert!(!fs::read_to_string(vault.vault_path()).unwrap().contains("sk-very-secret"));
+        assert!(!vault.dir.join(KEY_FILE).exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            for path in [vault.key_dir.join(KEY_FILE), vault.vault_path()] {
+                let mode = fs::metadata(path).unwrap().permissions().mode();
+                assert_eq!(mode & 0o777, 0o600);
+            }
+        }
+
+        fs::write(vault.key_dir.join(KEY_FILE), STANDARD.encode([7u8; KEY_LEN])).unwrap();
+        assert!(vault.read().unwrap_err().contains("解密失败"));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn legacy_key_file_moves_out_of_config_dir() {
+        let dir = std::env::temp_dir().join(format!("switch-cc-vault-{}", uuid::Uuid::new_v4().simple()));
+        let legacy = SecretVault {
+            dir: dir.join("config"),
+            key_dir: dir.join("config"),
+        };
+        let secrets = BTreeMap::from([("codex_providers/a/apiKey".to_string(), "sk-very-secret".to_string())]);
+        legacy.write(&secrets).unwrap();
+
+        let vault = SecretVault {
+            dir: dir.join("config"),
+            key_dir: dir.join("keys"),
+        };
+
+        assert_eq!(vault.read().unwrap(), secrets);
+        assert!(vault.key_dir.join(KEY_FILE).exists());
+        assert!(!vault.dir.join(KEY_FILE).exists());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
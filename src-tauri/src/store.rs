@@ -1,9 +1,14 @@
 use crate::config;
+use crate::gateway_health::{CircuitBreakerConfig, HealthRegistry};
+use crate::gateway_keys::KeyPoolRegistry;
+use crate::gateway_host::GatewayHost;
+use crate::gateway_log::{GatewayLogConfig, GatewayLogStore};
+use crate::gateway_retry::{RetryPolicy, StreamPolicy};
 use crate::provider::CodexProvider;
 use crate::provider::Provider;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::{Mutex, OnceLock};
+use std::sync::{Arc, Mutex, OnceLock};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AppMode {
@@ -17,6 +22,14 @@ pub struct ApiGatewayConfig {
     pub port: u16,
     #[serde(default)]
     pub target_provider_id: Option<String>,
+    #[serde(default)]
+    pub disk_logging_enabled: bool,
+    #[serde(default)]
+    pub capture_enabled: bool,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
 }
 
 impl Default for ApiGatewayConfig {
@@ -25,6 +38,10 @@ impl Default for ApiGatewayConfig {
             enabled: false,
             port: 3456,
             target_provider_id: None,
+            disk_logging_enabled: false,
+            capture_enabled: false,
+            retry: RetryPolicy::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
         }
     }
 }
@@ -37,6 +54,14 @@ pub struct CodexGatewayConfig {
     pub target_provider_id: Option<String>,
     #[serde(default)]
     pub disk_logging_enabled: bool,
+    #[serde(default)]
+    pub capture_enabled: bool,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    #[serde(default)]
+    pub stream: StreamPolicy,
 }
 
 impl Default for CodexGatewayConfig {
@@ -46,6 +71,10 @@ impl Default for CodexGatewayConfig {
             port: 7373,
             target_provider_id: None,
             disk_logging_enabled: false,
+            capture_enabled: false,
+            retry: RetryPolicy::default(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            stream: StreamPolicy::default(),
         }
     }
 }
@@ -63,6 +92,8 @@ pub struct AppConfig {
     pub api_gateway: ApiGatewayConfig,
     #[serde(default)]
     pub codex_gateway: CodexGatewayConfig,
+    #[serde(default)]
+    pub gateway_log: GatewayLogConfig,
 }
 
 impl Default for AppConfig {
@@ -75,6 +106,7 @@ impl Default for AppConfig {
             app_mode: AppMode::Main,
             api_gateway: ApiGatewayConfig::default(),
             codex_gateway: CodexGatewayConfig::default(),
+            gateway_log: GatewayLogConfig::default(),
         }
     }
 }
@@ -107,34 +139,63 @@ impl AppConfig {
             self.current_codex = self.codex_providers.keys().next().cloned().unwrap_or_default();
         }
     }
+
+    /// API Gateway 的转发目标：优先使用固定的目标供应商，否则使用当前供应商。
+    pub fn api_gateway_provider(&self) -> Option<&Provider> {
+        self.api_gateway
+            .target_provider_id
+            .as_ref()
+            .and_then(|provider_id| self.providers.get(provider_id))
+            .or_else(|| self.providers.get(&self.current))
+    }
+
+    /// Codex Gateway 的转发目标，规则同 `api_gateway_provider`。
+    pub fn codex_gateway_provider(&self) -> Option<&CodexProvider> {
+        self.codex_gateway
+            .target_provider_id
+            .as_ref()
+            .and_then(|provider_id| self.codex_providers.get(provider_id))
+            .or_else(|| self.codex_providers.get(&self.current_codex))
+    }
 }
 
 pub struct AppState {
     pub config: Mutex<AppConfig>,
     pub api_gateway_runtime: Mutex<crate::api_gateway::ApiGatewayRuntime>,
     pub codex_gateway_runtime: Mutex<crate::codex_gateway::CodexGatewayRuntime>,
-    app_handle: OnceLock<tauri::AppHandle>,
+    pub gateway_health: Arc<HealthRegistry>,
+    pub gateway_keys: Arc<KeyPoolRegistry>,
+    pub gateway_log: Arc<GatewayLogStore>,
+    /// 启动时保险库中找不到的密钥引用，由前端提示用户重新填写
+    pub missing_secrets: Vec<String>,
+    gateway_host: OnceLock<GatewayHost>,
 }
 
 impl AppState {
     pub fn new() -> Self {
-        let config = config::load_config().unwrap_or_default();
+        let (config, missing_secrets) = config::load_config().unwrap_or_default();
         Self {
             config: Mutex::new(config),
             api_gateway_runtime: Mutex::new(crate::api_gateway::ApiGatewayRuntime::default()),
             codex_gateway_runtime: Mutex::new(crate::codex_gateway::CodexGatewayRuntime::default()),
-            app_handle: OnceLock::new(),
+            gateway_health: Arc::new(HealthRegistry::default()),
+            gateway_keys: Arc::new(KeyPoolRegistry::default()),
+            gateway_log: Arc::new(GatewayLogStore::default()),
+            missing_secrets,
+            gateway_host: OnceLock::new(),
         }
     }
 
-    pub fn set_app_handle(&self, app_handle: tauri::AppHandle) {
-        let _ = self.app_handle.set(app_handle);
+    /// 由宿主（桌面应用）在启动时注入，Gateway 日志与配置读取都经由它完成。
+    pub fn set_gateway_host(&self, host: GatewayHost) {
+        let _ = self.gateway_host.set(host);
     }
 
-    pub fn app_handle(&self) -> Result<&tauri::AppHandle, String> {
-        self.app_handle
+    pub fn gateway_host(&self) -> Result<GatewayHost, String> {
+        self.gateway_host
             .get()
-            .ok_or_else(|| "应用句柄尚未初始化".to_string())
+            .cloned()
+            .ok_or_else(|| "Gateway 宿主尚未初始化".to_string())
     }
 
     pub fn save(&self) -> Result<(), String> {
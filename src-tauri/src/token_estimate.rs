@@ -0,0 +1,197 @@
+use serde_json::Value;
+
+/// 粗略估算文本 token 数：ASCII 约 4 个字符 1 token，CJK 等非 ASCII 字符按 1 字符 1 token 计。
+pub fn estimate_text_tokens(text: &str) -> u64 {
+    let mut ascii_chars = 0u64;
+    let mut wide_chars = 0u64;
+
+    for ch in text.chars() {
+        if ch.is_ascii() {
+            ascii_chars += 1;
+        } else {
+            wide_chars += 1;
+        }
+    }
+
+    ascii_chars.div_ceil(4) + wide_chars
+}
+
+/// 估算 JSON 值中所有字符串内容的 token 数（键名不计入）。
+pub fn estimate_json_tokens(value: &Value) -> u64 {
+    match value {
+        Value::String(text) => estimate_text_tokens(text),
+        Value::Array(items) => items.iter().map(estimate_json_tokens).sum(),
+        Value::Object(object) => object.values().map(estimate_json_tokens).sum(),
+        _ => 0,
+    }
+}
+
+/// 估算请求体 token 数；非 JSON 请求体按原始字节长度估算。
+pub fn estimate_body_tokens(body: &[u8]) -> u64 {
+    if body.is_empty() {
+        return 0;
+    }
+
+    match serde_json::from_slice::<Value>(body) {
+        Ok(value) => estimate_json_tokens(&value),
+        Err(_) => (body.len() as u64).div_ceil(4),
+    }
+}
+
+/// 每条消息的角色、分隔符等固定开销
+const MESSAGE_OVERHEAD_TOKENS: u64 = 4;
+/// 图片、文档等附件块的固定估值
+const ATTACHMENT_TOKENS: u64 = 1_500;
+
+/// 按 Anthropic `count_tokens` 的口径估算输入 token 数：system、messages 和 tools 定义。
+pub fn estimate_anthropic_input_tokens(body: &Value) -> u64 {
+    let system_tokens = body.get("system").map(estimate_content_tokens).unwrap_or(0);
+    let message_tokens = body
+        .get("messages")
+        .and_then(Value::as_array)
+        .map(|messages| {
+            messages
+                .iter()
+                .map(|message| MESSAGE_OVERHEAD_TOKENS + message.get("content").map(estimate_content_tokens).unwrap_or(0))
+                .sum()
+        })
+        .unwrap_or(0);
+    let tool_tokens = body
+        .get("tools")
+        .and_then(Value::as_array)
+        .map(|tools| tools.iter().map(estimate_tool_tokens).sum())
+        .unwrap_or(0);
+
+    system_tokens + message_tokens + tool_tokens
+}
+
+/// 估算 Chat Completions 请求的输入 token 数：所有 messages 加上 tools 定义。
+pub fn estimate_chat_input_tokens(request: &Value) -> u64 {
+    let message_tokens: u64 = request
+        .get("messages")
+        .and_then(Value::as_array)
+        .map(|messages| messages.iter().map(estimate_chat_message_tokens).sum())
+        .unwrap_or(0);
+    let tool_tokens = request
+        .get("tools")
+        .and_then(Value::as_array)
+        .map(|tools| estimate_chat_tools_tokens(tools))
+        .unwrap_or(0);
+
+    message_tokens + tool_tokens
+}
+
+/// Chat `tools` 定义按序列化后的 JSON 估算。
+pub fn estimate_chat_tools_tokens(tools: &[Value]) -> u64 {
+    tools.iter().map(estimate_serialized_tokens).sum()
+}
+
+/// 估算单条 Chat message：正文、附件和工具调用参数。
+pub fn estimate_chat_message_tokens(message: &Value) -> u64 {
+    let content_tokens = match message.get("content") {
+        Some(Value::String(text)) => estimate_text_tokens(text),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .map(|part| match part.get("type").and_then(Value::as_str) {
+                Some("text") => part.get("text").map(estimate_json_tokens).unwrap_or(0),
+                Some("image_url") | Some("input_audio") | Some("file") => ATTACHMENT_TOKENS,
+                _ => estimate_json_tokens(part),
+            })
+            .sum(),
+        _ => 0,
+    };
+    let tool_call_tokens: u64 = message
+        .get("tool_calls")
+        .and_then(Value::as_array)
+        .map(|tool_calls| {
+            tool_calls
+                .iter()
+                .filter_map(|tool_call| tool_call.get("function"))
+                .map(estimate_json_tokens)
+                .sum()
+        })
+        .unwrap_or(0);
+
+    MESSAGE_OVERHEAD_TOKENS + content_tokens + tool_call_tokens
+}
+
+fn estimate_content_tokens(content: &Value) -> u64 {
+    match content {
+        Value::String(text) => estimate_text_tokens(text),
+        Value::Array(blocks) => blocks.iter().map(estimate_block_tokens).sum(),
+        _ => 0,
+    }
+}
+
+fn estimate_block_tokens(block: &Value) -> u64 {
+    let field_tokens = |key: &str| block.get(key).map(estimate_json_tokens).unwrap_or(0);
+    match block.get("type").and_then(Value::as_str) {
+        Some("text") => field_tokens("text"),
+        Some("thinking") => field_tokens("thinking"),
+        Some("image") | Some("document") => ATTACHMENT_TOKENS,
+        // 工具参数按序列化后的 JSON 计，键名和标点同样占用 token
+        Some("tool_use") => field_tokens("name") + block.get("input").map(estimate_serialized_tokens).unwrap_or(0),
+        Some("tool_result") => block.get("content").map(estimate_content_tokens).unwrap_or(0),
+        _ => estimate_json_tokens(block),
+    }
+}
+
+fn estimate_tool_tokens(tool: &Value) -> u64 {
+    let field_tokens = |key: &str| tool.get(key).map(estimate_json_tokens).unwrap_or(0);
+    field_tokens("name")
+        + field_tokens("description")
+        + tool.get("input_schema").map(estimate_serialized_tokens).unwrap_or(0)
+}
+
+fn estimate_serialized_tokens(value: &Value) -> u64 {
+    estimate_text_tokens(&value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn estimate_text_tokens_counts_ascii_and_cjk_differently() {
+        assert_eq!(estimate_text_tokens(""), 0);
+        assert_eq!(estimate_text_tokens("abcd"), 1);
+        assert_eq!(estimate_text_tokens("abcde"), 2);
+        assert_eq!(estimate_text_tokens("你好"), 2);
+    }
+
+    #[test]
+    fn estimate_body_tokens_ignores_json_keys() {
+        let body = serde_json::to_vec(&json!({
+            "model": "abcd",
+            "messages": [{"role": "user", "content": "abcdabcd"}]
+        }))
+        .unwrap();
+
+        assert_eq!(estimate_body_tokens(&body), 1 + 1 + 2);
+    }
+
+    #[test]
+    fn estimate_anthropic_input_tokens_covers_system_messages_and_tools() {
+        let body = json!({
+            "model": "claude-sonnet",
+            "system": [{ "type": "text", "text": "abcdabcd" }],
+            "messages": [
+                { "role": "user", "content": "abcd" },
+                { "role": "assistant", "content": [
+                    { "type": "tool_use", "id": "toolu_1", "name": "read", "input": {} }
+                ] },
+                { "role": "user", "content": [
+                    { "type": "tool_result", "tool_use_id": "toolu_1", "content": "你好" },
+                    { "type": "image", "source": { "type": "base64", "data": "AAAA" } }
+                ] }
+            ],
+            "tools": [{ "name": "read", "description": "abcd", "input_schema": {} }]
+        });
+
+        let system = 2;
+        let messages = (MESSAGE_OVERHEAD_TOKENS + 1) + (MESSAGE_OVERHEAD_TOKENS + 1 + 1) + (MESSAGE_OVERHEAD_TOKENS + 2 + ATTACHMENT_TOKENS);
+        let tools = 1 + 1 + 1;
+        assert_eq!(estimate_anthropic_input_tokens(&body), system + messages + tools);
+    }
+}
@@ -1,6 +1,13 @@
 import { useEffect, useMemo, useRef, useState } from "react";
-import { ChevronDown, ChevronRight, Search, Waypoints } from "lucide-react";
-import { ApiGatewayLogEntry } from "../../types";
+import { ChevronDown, ChevronRight, Download, Search, Waypoints } from "lucide-react";
+import {
+  ApiGatewayLogEntry,
+  GatewayCaptureSummary,
+  GatewayKind,
+  GatewayLogRecord,
+} from "../../types";
+import { api } from "../../lib/tauri-api";
+import { extractErrorMessage } from "../../utils/errorUtils";
 import {
   Dialog,
   DialogContent,
@@ -27,6 +34,44 @@ interface ApiGatewayLogModalProps {
   diskLoggingDisabled?: boolean;
   diskLoggingLabel?: string;
   diskLoggingDescription?: string;
+  historyGateway?: GatewayKind;
+  captureEnabled?: boolean;
+  onCaptureChange?: (enabled: boolean) => void;
+  captureDisabled?: boolean;
+}
+
+type LogView = "live" | "history" | "captures";
+
+const VIEW_LABELS: Record<LogView, string> = {
+  live: "实时",
+  history: "磁盘历史",
+  captures: "抓包",
+};
+
+const HISTORY_PAGE_SIZE = 200;
+
+function formatHistoryEntry(record: GatewayLogRecord): ApiGatewayLogEntry {
+  const timestamp = new Date(record.timestamp);
+  const details =
+    record.kind === "request"
+      ? [
+          record.requestId,
+          record.providerId && `provider=${record.providerId}`,
+          record.model && `model=${record.model}`,
+          record.errorClass && `error=${record.errorClass}`,
+          typeof record.responseBytes === "number" && `bytes=${record.responseBytes}`,
+          typeof record.inputTokens === "number" && `input_tokens=${record.inputTokens}`,
+          typeof record.outputTokens === "number" && `output_tokens=${record.outputTokens}`,
+        ].filter(Boolean)
+      : [];
+
+  return {
+    timestamp: Number.isNaN(timestamp.getTime())
+      ? record.timestamp
+      : timestamp.toLocaleString(),
+    level: record.level,
+    message: details.length > 0 ? `${record.message}\n${details.join(" ")}` : record.message,
+  };
 }
 
 function ApiGatewayLogModal({
@@ -44,14 +89,112 @@ function ApiGatewayLogModal({
   diskLoggingDisabled = false,
   diskLoggingLabel = "将日志写入本地磁盘",
   diskLoggingDescription,
+  historyGateway,
+  captureEnabled,
+  onCaptureChange,
+  captureDisabled = false,
 }: ApiGatewayLogModalProps) {
   const [searchTerm, setSearchTerm] = useState("");
+  const [view, setView] = useState<LogView>("live");
+  const [historyLogs, setHistoryLogs] = useState<ApiGatewayLogEntry[]>([]);
+  const [historyHasMore, setHistoryHasMore] = useState(false);
+  const [isHistoryLoading, setIsHistoryLoading] = useState(false);
+  const [historyError, setHistoryError] = useState("");
+  const [captures, setCaptures] = useState<GatewayCaptureSummary[]>([]);
+  const [exportMessage, setExportMessage] = useState("");
   const [isDetailsExpanded, setIsDetailsExpanded] = useState(false);
   const scrollRef = useRef<HTMLDivElement>(null);
   const detailLines = localBaseUrl ? [`本地地址 ${localBaseUrl}`, ...details] : ["实时日志流", ...details];
   const diskLoggingToggleId = "gateway-disk-logging";
+  const captureToggleId = "gateway-capture";
+
+  const loadHistory = async (reset: boolean) => {
+    if (!historyGateway) {
+      return;
+    }
+
+    try {
+      setIsHistoryLoading(true);
+      setHistoryError("");
+      const page = await api.queryGatewayLogs({
+        gateway: historyGateway,
+        search: searchTerm.trim() || undefined,
+        offset: reset ? 0 : historyLogs.length,
+        limit: HISTORY_PAGE_SIZE,
+      });
+      const entries = page.entries.map(formatHistoryEntry);
+      setHistoryLogs((current) => (reset ? entries : [...current, ...entries]));
+      setHistoryHasMore(page.hasMore);
+    } catch (error) {
+      setHistoryError(extractErrorMessage(error));
+    } finally {
+      setIsHistoryLoading(false);
+    }
+  };
+
+  const loadCaptures = async () => {
+    try {
+      setIsHistoryLoading(true);
+      setHistoryError("");
+      setCaptures(await api.listGatewayCaptures(historyGateway));
+    } catch (error) {
+      setHistoryError(extractErrorMessage(error));
+    } finally {
+      setIsHistoryLoading(false);
+    }
+  };
+
+  const handleExportCapture = async (requestId: string) => {
+    try {
+      setHistoryError("");
+      const targetDir = await api.pickDirectory();
+      if (!targetDir) {
+        return;
+      }
+      const path = await api.exportGatewayCapture(requestId, targetDir);
+      setExportMessage(`已导出到 ${path}`);
+    } catch (error) {
+      setHistoryError(extractErrorMessage(error));
+    }
+  };
+
+  useEffect(() => {
+    if (isOpen && view === "captures") {
+      setExportMessage("");
+      void loadCaptures();
+    }
+  }, [isOpen, view, historyGateway]);
+
+  useEffect(() => {
+    if (!isOpen || view !== "history") {
+      return;
+    }
+
+    // 搜索词变化后重新从第一页查询
+    const timer = window.setTimeout(() => void loadHistory(true), 300);
+    return () => window.clearTimeout(timer);
+  }, [isOpen, view, searchTerm, historyGateway]);
+
+  const filteredCaptures = useMemo(() => {
+    const keyword = searchTerm.trim().toLowerCase();
+    if (!keyword) {
+      return captures;
+    }
+
+    return captures.filter((capture) => {
+      const haystack = [capture.requestId, capture.providerId, capture.model, capture.error]
+        .filter(Boolean)
+        .join(" ")
+        .toLowerCase();
+      return haystack.includes(keyword);
+    });
+  }, [captures, searchTerm]);
 
   const filteredLogs = useMemo(() => {
+    if (view === "history") {
+      return historyLogs;
+    }
+
     const keyword = searchTerm.trim().toLowerCase();
     if (!keyword) {
       return logs;
@@ -61,10 +204,10 @@ function ApiGatewayLogModal({
       const haystack = `${entry.timestamp} ${entry.level} ${entry.message}`.toLowerCase();
       return haystack.includes(keyword);
     });
-  }, [logs, searchTerm]);
+  }, [logs, searchTerm, view, historyLogs]);
 
   useEffect(() => {
-    if (!isOpen || !scrollRef.current) {
+    if (!isOpen || !scrollRef.current || view !== "live") {
       return;
     }
 
@@ -142,7 +285,44 @@ function ApiGatewayLogModal({
             </div>
           )}
 
+          {typeof captureEnabled === "boolean" && onCaptureChange && (
+            <div className="rounded-base border-2 border-border bg-secondary-background px-4 py-3">
+              <div className="flex items-start gap-3">
+                <Checkbox
+                  id={captureToggleId}
+                  checked={captureEnabled}
+                  disabled={captureDisabled}
+                  onCheckedChange={(checked) => onCaptureChange(checked === true)}
+                />
+                <div className="space-y-1">
+                  <Label htmlFor={captureToggleId}>抓包保存完整请求与响应</Label>
+                  <p className="text-xs text-foreground opacity-70">
+                    记录入站请求、转换后的上游请求、上游原始响应和下游事件，保存在 ~/.switchcc/captures，API Key 等鉴权信息会被脱敏。仅在排查问题时开启。
+                  </p>
+                </div>
+              </div>
+            </div>
+          )}
+
           <div className="flex items-center gap-4">
+            {historyGateway && (
+              <div className="flex shrink-0 overflow-hidden rounded-base border-2 border-border text-sm">
+                {(["live", "history", "captures"] as const).map((option) => (
+                  <button
+                    key={option}
+                    type="button"
+                    onClick={() => setView(option)}
+                    className={`px-3 py-1.5 font-medium transition-colors ${
+                      view === option
+                        ? "bg-main text-main-foreground"
+                        : "bg-secondary-background text-foreground hover:bg-background"
+                    }`}
+                  >
+                    {VIEW_LABELS[option]}
+                  </button>
+                ))}
+              </div>
+            )}
             <div className="relative flex-1">
               <Search
                 size={16}
@@ -152,20 +332,86 @@ function ApiGatewayLogModal({
                 type="text"
                 value={searchTerm}
                 onChange={(event) => setSearchTerm(event.target.value)}
-                placeholder="搜索日志内容、级别或时间"
+                placeholder={
+                  view === "live"
+                    ? "搜索日志内容、级别或时间"
+                    : "搜索消息、请求 ID、供应商或模型"
+                }
                 className="pl-9"
               />
             </div>
-            <Badge variant="neutral">{filteredLogs.length} / {logs.length}</Badge>
+            <Badge variant="neutral">
+              {view === "captures"
+                ? `${filteredCaptures.length} / ${captures.length}`
+                : view === "history"
+                  ? `${historyLogs.length}${historyHasMore ? "+" : ""}`
+                  : `${filteredLogs.length} / ${logs.length}`}
+            </Badge>
           </div>
 
+          {view !== "live" && historyError && (
+            <div className="rounded-base border-2 border-border bg-red-100 px-4 py-2 text-sm text-red-700 dark:bg-red-950 dark:text-red-300">
+              {historyError}
+            </div>
+          )}
+
+          {view === "captures" && exportMessage && (
+            <div className="rounded-base border-2 border-border bg-secondary-background px-4 py-2 text-sm text-foreground">
+              {exportMessage}
+            </div>
+          )}
+
           <div
             ref={scrollRef}
             className="min-h-0 flex-1 overflow-y-auto rounded-base border-2 border-border bg-secondary-background p-3"
           >
-            {filteredLogs.length === 0 ? (
+            {view === "captures" ? (
+              filteredCaptures.length === 0 ? (
+                <div className="flex h-full items-center justify-center text-sm text-foreground opacity-60">
+                  {isHistoryLoading ? "正在加载..." : "没有抓包记录"}
+                </div>
+              ) : (
+                <div className="space-y-2 font-mono text-xs leading-5 text-foreground">
+                  {filteredCaptures.map((capture) => (
+                    <div
+                      key={capture.requestId}
+                      className="flex items-center justify-between gap-3 rounded-base border border-border/60 bg-background px-3 py-2"
+                    >
+                      <div className="min-w-0 space-y-1">
+                        <div className="flex items-center gap-2 opacity-70">
+                          <span>{new Date(capture.startedAt).toLocaleString()}</span>
+                          {typeof capture.status === "number" && (
+                            <Badge variant={capture.status >= 400 ? "destructive" : "neutral"}>
+                              {capture.status}
+                            </Badge>
+                          )}
+                        </div>
+                        <div className="break-all">
+                          {[
+                            capture.requestId,
+                            capture.providerId && `provider=${capture.providerId}`,
+                            capture.model && `model=${capture.model}`,
+                            capture.error && `error=${capture.error}`,
+                          ]
+                            .filter(Boolean)
+                            .join(" ")}
+                        </div>
+                      </div>
+                      <button
+                        type="button"
+                        onClick={() => void handleExportCapture(capture.requestId)}
+                        className="flex shrink-0 items-center gap-1 rounded-base border-2 border-border bg-secondary-background px-2 py-1 font-sans text-xs font-medium transition-colors hover:bg-background"
+                      >
+                        <Download size={14} />
+                        导出
+                      </button>
+                    </div>
+                  ))}
+                </div>
+              )
+            ) : filteredLogs.length === 0 ? (
               <div className="flex h-full items-center justify-center text-sm text-foreground opacity-60">
-                没有匹配的日志
+                {view === "history" && isHistoryLoading ? "正在加载..." : "没有匹配的日志"}
               </div>
             ) : (
               <div className="space-y-2 font-mono text-xs leading-5 text-foreground">
@@ -187,6 +433,16 @@ function ApiGatewayLogModal({
                     </div>
                   </div>
                 ))}
+                {view === "history" && historyHasMore && (
+                  <button
+                    type="button"
+                    onClick={() => void loadHistory(false)}
+                    disabled={isHistoryLoading}
+                    className="w-full rounded-base border-2 border-border bg-background px-3 py-2 font-sans text-sm font-medium transition-colors hover:bg-secondary-background disabled:cursor-not-allowed disabled:opacity-50"
+                  >
+                    {isHistoryLoading ? "正在加载..." : "加载更早的日志"}
+                  </button>
+                )}
               </div>
             )}
           </div>
@@ -87,6 +87,7 @@ function EditProviderModal({
             providerType: "codex",
             websiteUrl: websiteUrl.trim() || undefined,
             codexConfig: {
+              ...provider.codexConfig,
               providerName: providerName.trim(),
               upstreamUrl: upstreamUrl.trim(),
               apiKey: apiKey.trim(),
@@ -0,0 +1,225 @@
+import { useMemo, useState } from "react";
+import { GitCompare } from "lucide-react";
+import { Provider, ReplayFormat, ReplayPreview, ReplayResult, getProviderType } from "../../types";
+import { api } from "../../lib/tauri-api";
+import { extractErrorMessage } from "../../utils/errorUtils";
+import { Dialog, DialogContent, DialogHeader, DialogTitle } from "../ui/dialog";
+import { Badge } from "../ui/badge";
+import { Button } from "../ui/button";
+import { Checkbox } from "../ui/checkbox";
+import { Label } from "../ui/label";
+import { Textarea } from "../ui/textarea";
+
+interface GatewayReplayModalProps {
+  isOpen: boolean;
+  onClose: () => void;
+  providers: Record<string, Provider>;
+  currentClaudeProviderId: string;
+  currentCodexProviderId: string;
+}
+
+// 请求体含 input 字段时视为 Responses 请求，与后端识别规则一致
+function detectFormat(body: string): ReplayFormat {
+  try {
+    return "input" in JSON.parse(body) ? "responses" : "anthropic";
+  } catch {
+    return "anthropic";
+  }
+}
+
+function GatewayReplayModal({
+  isOpen,
+  onClose,
+  providers,
+  currentClaudeProviderId,
+  currentCodexProviderId,
+}: GatewayReplayModalProps) {
+  const [body, setBody] = useState("");
+  const [selectedIds, setSelectedIds] = useState<string[]>([]);
+  const [results, setResults] = useState<ReplayResult[]>([]);
+  const [previews, setPreviews] = useState<ReplayPreview[]>([]);
+  const [isRunning, setIsRunning] = useState(false);
+  const [error, setError] = useState("");
+
+  const format = detectFormat(body);
+  const candidates = useMemo(
+    () =>
+      Object.values(providers).filter(
+        (provider) =>
+          getProviderType(provider) === (format === "responses" ? "codex" : "claude"),
+      ),
+    [providers, format],
+  );
+  const currentId = format === "responses" ? currentCodexProviderId : currentClaudeProviderId;
+  const effectiveIds = selectedIds.filter((id) => candidates.some((provider) => provider.id === id));
+
+  const handleLoadFile = async (file: File | undefined) => {
+    if (file) {
+      setBody(await file.text());
+    }
+  };
+
+  const toggleProvider = (providerId: string, checked: boolean) => {
+    setSelectedIds((current) =>
+      checked ? [...current, providerId] : current.filter((id) => id !== providerId),
+    );
+  };
+
+  const handleReplay = async () => {
+    try {
+      setIsRunning(true);
+      setError("");
+      setPreviews([]);
+      setResults(
+        await api.replayGatewayRequest({ format, body, providerIds: effectiveIds }),
+      );
+    } catch (replayError) {
+      setError(extractErrorMessage(replayError));
+    } finally {
+      setIsRunning(false);
+    }
+  };
+
+  // 只查看改写规则处理后的上游请求，不发送
+  const handlePreview = async () => {
+    try {
+      setError("");
+      setResults([]);
+      setPreviews(
+        await api.previewGatewayRequest({ format, body, providerIds: effectiveIds }),
+      );
+    } catch (previewError) {
+      setError(extractErrorMessage(previewError));
+    }
+  };
+
+  if (!isOpen) {
+    return null;
+  }
+
+  return (
+    <Dialog open={isOpen} onOpenChange={onClose}>
+      <DialogContent className="flex max-h-[92vh] max-w-5xl flex-col overflow-hidden">
+        <DialogHeader className="shrink-0">
+          <div className="flex items-center gap-2">
+            <GitCompare size={18} />
+            <DialogTitle>请求回放对比</DialogTitle>
+          </div>
+        </DialogHeader>
+
+        <div className="min-h-0 flex-1 space-y-4 overflow-y-auto">
+          <div className="space-y-2">
+            <div className="flex items-center justify-between gap-3">
+              <Label>
+                请求体（Anthropic /v1/messages 或 Responses）
+                <Badge variant="neutral" className="ml-2">
+                  {format === "responses" ? "Responses" : "Anthropic"}
+                </Badge>
+              </Label>
+              <input
+                type="file"
+                accept=".json,application/json"
+                onChange={(event) => void handleLoadFile(event.target.files?.[0])}
+                className="text-xs"
+              />
+            </div>
+            <Textarea
+              value={body}
+              onChange={(event) => setBody(event.target.value)}
+              placeholder='{"model": "...", "messages": [...]}'
+              className="h-48 font-mono text-xs"
+            />
+          </div>
+
+          <div className="space-y-2">
+            <Label>回放到供应商（不选则使用当前路由）</Label>
+            <div className="flex flex-wrap gap-3">
+              {candidates.map((provider) => (
+                <label key={provider.id} className="flex items-center gap-2 text-sm">
+                  <Checkbox
+                    checked={effectiveIds.includes(provider.id)}
+                    onCheckedChange={(checked) => toggleProvider(provider.id, checked === true)}
+                  />
+                  {provider.name}
+                  {provider.id === currentId && <Badge variant="neutral">当前</Badge>}
+                </label>
+              ))}
+            </div>
+          </div>
+
+          <div className="flex gap-2">
+            <Button onClick={handleReplay} disabled={isRunning || !body.trim()}>
+              {isRunning ? "回放中..." : "开始回放"}
+            </Button>
+            <Button variant="neutral" onClick={handlePreview} disabled={isRunning || !body.trim()}>
+              预览改写
+            </Button>
+          </div>
+
+          {error && (
+            <div className="rounded-base border-2 border-border bg-red-100 px-4 py-2 text-sm text-red-700 dark:bg-red-950 dark:text-red-300">
+              {error}
+            </div>
+          )}
+
+          {previews.length > 0 && (
+            <div className="grid gap-3" style={{ gridTemplateColumns: `repeat(${previews.length}, minmax(0, 1fr))` }}>
+              {previews.map((preview) => (
+                <div
+                  key={preview.providerId}
+                  className="min-w-0 space-y-2 rounded-base border-2 border-border bg-secondary-background p-3 text-xs"
+                >
+                  <span className="block truncate text-sm font-medium">{preview.providerName}</span>
+                  {preview.url && <div className="break-all opacity-70">POST {preview.url}</div>}
+                  {preview.error && <div className="text-red-600 dark:text-red-400">{preview.error}</div>}
+                  <pre className="max-h-32 overflow-y-auto whitespace-pre-wrap break-all rounded-base border border-border/60 bg-background p-2 font-mono">
+                    {Object.entries(preview.headers)
+                      .map(([name, value]) => `${name}: ${value}`)
+                      .join("\n")}
+                  </pre>
+                  <pre className="max-h-96 overflow-y-auto whitespace-pre-wrap break-all rounded-base border border-border/60 bg-background p-2 font-mono">
+                    {JSON.stringify(preview.body, null, 2)}
+                  </pre>
+                </div>
+              ))}
+            </div>
+          )}
+
+          {results.length > 0 && (
+            <div className="grid gap-3" style={{ gridTemplateColumns: `repeat(${results.length}, minmax(0, 1fr))` }}>
+              {results.map((result) => (
+                <div
+                  key={result.providerId}
+                  className="min-w-0 space-y-2 rounded-base border-2 border-border bg-secondary-background p-3 text-xs"
+                >
+                  <div className="flex items-center justify-between gap-2">
+                    <span className="truncate text-sm font-medium">{result.providerName}</span>
+                    {typeof result.status === "number" && (
+                      <Badge variant={result.error ? "destructive" : "neutral"}>{result.status}</Badge>
+                    )}
+                  </div>
+                  <div className="opacity-70">
+                    {result.model ?? "-"} · {result.latencyMs}ms · 输入 {result.usage.inputTokens} / 输出{" "}
+                    {result.usage.outputTokens} tokens
+                  </div>
+                  {result.error && <div className="text-red-600 dark:text-red-400">{result.error}</div>}
+                  <pre className="max-h-64 overflow-y-auto whitespace-pre-wrap break-all rounded-base border border-border/60 bg-background p-2 font-mono">
+                    {result.outputText ||
+                      (result.toolCalls.length === 0 ? JSON.stringify(result.response, null, 2) : "")}
+                  </pre>
+                  {result.toolCalls.length > 0 && (
+                    <pre className="max-h-48 overflow-y-auto whitespace-pre-wrap break-all rounded-base border border-border/60 bg-background p-2 font-mono">
+                      {JSON.stringify(result.toolCalls, null, 2)}
+                    </pre>
+                  )}
+                </div>
+              ))}
+            </div>
+          )}
+        </div>
+      </DialogContent>
+    </Dialog>
+  );
+}
+
+export default GatewayReplayModal;
@@ -4,6 +4,7 @@ import {
   ApiGatewayStatus,
   CodexGatewayStatus,
   Provider,
+  RequestDefaults,
   getProviderType,
   isClaudeProvider,
 } from "../../types";
@@ -15,14 +16,26 @@ import SettingsModal from "./SettingsModal";
 import ClaudeConfigModal from "./ClaudeConfigModal";
 import ConfigSyncModal from "./ConfigSyncModal";
 import ApiGatewayLogModal from "./ApiGatewayLogModal";
+import GatewayReplayModal from "./GatewayReplayModal";
 import { UpdateBadge } from "./UpdateBadge";
-import { Plus, Settings, Moon, Sun, Eye, Cloud, Waypoints, Bot } from "lucide-react";
+import { Plus, Settings, Moon, Sun, Eye, Cloud, Waypoints, Bot, GitCompare } from "lucide-react";
 import { Button } from "../ui/button";
 import { Checkbox } from "../ui/checkbox";
 import { useDarkMode } from "../../hooks/useDarkMode";
 import { extractErrorMessage } from "../../utils/errorUtils";
 import { api } from "../../lib/tauri-api";
 
+function formatRequestDefaults(defaults?: RequestDefaults): string {
+  if (!defaults) return "";
+  const parts = [
+    defaults.systemPrompt?.trim() && "系统提示词",
+    typeof defaults.temperature === "number" && `temperature=${defaults.temperature}`,
+    typeof defaults.maxOutputTokens === "number" && `max_tokens=${defaults.maxOutputTokens}`,
+    defaults.reasoningEffort && `reasoning=${defaults.reasoningEffort}`,
+  ].filter(Boolean);
+  return parts.length > 0 ? `默认参数 ${parts.join(" ")}` : "";
+}
+
 function MainWindow() {
   const { isDarkMode, toggleDarkMode } = useDarkMode();
   const [providers, setProviders] = useState<Record<string, Provider>>({});
@@ -48,13 +61,17 @@ function MainWindow() {
   const [showSyncModal, setShowSyncModal] = useState(false);
   const [isApiGatewayLogOpen, setIsApiGatewayLogOpen] = useState(false);
   const [isCodexGatewayLogOpen, setIsCodexGatewayLogOpen] = useState(false);
+  const [isReplayOpen, setIsReplayOpen] = useState(false);
   const [apiGatewayStatus, setApiGatewayStatus] = useState<ApiGatewayStatus | null>(null);
   const [codexGatewayStatus, setCodexGatewayStatus] = useState<CodexGatewayStatus | null>(null);
   const [apiGatewayLogs, setApiGatewayLogs] = useState<ApiGatewayLogEntry[]>([]);
   const [codexGatewayLogs, setCodexGatewayLogs] = useState<ApiGatewayLogEntry[]>([]);
   const [isApiGatewayPending, setIsApiGatewayPending] = useState(false);
   const [isCodexGatewayPending, setIsCodexGatewayPending] = useState(false);
+  const [isApiGatewayDiskLoggingPending, setIsApiGatewayDiskLoggingPending] = useState(false);
   const [isCodexGatewayDiskLoggingPending, setIsCodexGatewayDiskLoggingPending] = useState(false);
+  const [isApiGatewayCapturePending, setIsApiGatewayCapturePending] = useState(false);
+  const [isCodexGatewayCapturePending, setIsCodexGatewayCapturePending] = useState(false);
   const timeoutRef = useRef<ReturnType<typeof setTimeout> | null>(null);
 
   // 设置通知的辅助函数
@@ -88,6 +105,26 @@ function MainWindow() {
     loadProviders();
   }, []);
 
+  // 保险库中缺失的密钥保留为 vault: 引用，提示用户重新填写
+  useEffect(() => {
+    const checkMissingSecrets = async () => {
+      try {
+        const missing = await api.getMissingSecrets();
+        if (missing.length > 0) {
+          showNotification(
+            `保险库中缺少 ${missing.length} 个密钥，请重新填写：${missing.join("、")}`,
+            "error",
+            8000,
+          );
+        }
+      } catch (error) {
+        console.error("获取缺失密钥失败:", error);
+      }
+    };
+
+    checkMissingSecrets();
+  }, []);
+
   useEffect(() => {
     let unlisten: (() => void) | null = null;
 
@@ -392,6 +429,27 @@ function MainWindow() {
     }
   };
 
+  const handleToggleApiGatewayDiskLogging = async (checked: boolean) => {
+    try {
+      setIsApiGatewayDiskLoggingPending(true);
+      const status = await api.setApiGatewayDiskLoggingEnabled(checked);
+      setApiGatewayStatus(status);
+      showNotification(
+        checked
+          ? `已开启 API Gateway 日志落盘，目录 ${status.logDirectory}`
+          : "已关闭 API Gateway 日志落盘",
+        "success",
+        2500,
+      );
+    } catch (error) {
+      console.error("切换 API Gateway 日志落盘失败:", error);
+      const errorMessage = extractErrorMessage(error);
+      showNotification(`日志落盘设置失败：${errorMessage}`, "error");
+    } finally {
+      setIsApiGatewayDiskLoggingPending(false);
+    }
+  };
+
   const handleToggleCodexGatewayDiskLogging = async (checked: boolean) => {
     try {
       setIsCodexGatewayDiskLoggingPending(true);
@@ -413,6 +471,44 @@ function MainWindow() {
     }
   };
 
+  const handleToggleApiGatewayCapture = async (checked: boolean) => {
+    try {
+      setIsApiGatewayCapturePending(true);
+      const status = await api.setApiGatewayCaptureEnabled(checked);
+      setApiGatewayStatus(status);
+      showNotification(
+        checked ? "已开启 API Gateway 抓包" : "已关闭 API Gateway 抓包",
+        "success",
+        2500,
+      );
+    } catch (error) {
+      console.error("切换 API Gateway 抓包失败:", error);
+      const errorMessage = extractErrorMessage(error);
+      showNotification(`抓包设置失败：${errorMessage}`, "error");
+    } finally {
+      setIsApiGatewayCapturePending(false);
+    }
+  };
+
+  const handleToggleCodexGatewayCapture = async (checked: boolean) => {
+    try {
+      setIsCodexGatewayCapturePending(true);
+      const status = await api.setCodexGatewayCaptureEnabled(checked);
+      setCodexGatewayStatus(status);
+      showNotification(
+        checked ? "已开启 Codex Gateway 抓包" : "已关闭 Codex Gateway 抓包",
+        "success",
+        2500,
+      );
+    } catch (error) {
+      console.error("切换 Codex Gateway 抓包失败:", error);
+      const errorMessage = extractErrorMessage(error);
+      showNotification(`抓包设置失败：${errorMessage}`, "error");
+    } finally {
+      setIsCodexGatewayCapturePending(false);
+    }
+  };
+
   // 自动从 live 导入一条默认供应商（仅首次初始化时）
   const handleAutoImportDefault = async () => {
     try {
@@ -499,6 +595,14 @@ function MainWindow() {
             >
               <Cloud size={18} />
             </Button>
+            <Button
+              onClick={() => setIsReplayOpen(true)}
+              variant="neutral"
+              size="icon"
+              title="请求回放对比"
+            >
+              <GitCompare size={18} />
+            </Button>
             <Button
               onClick={() => setIsSettingsOpen(true)}
               variant="neutral"
@@ -661,10 +765,19 @@ function MainWindow() {
                   apiGatewayStatus.targetBaseUrl
                     ? `上游地址 ${apiGatewayStatus.targetBaseUrl}`
                     : "",
+                  formatRequestDefaults(apiGatewayStatus.requestDefaults),
                 ].filter(Boolean)
               : []
           }
           logs={apiGatewayLogs}
+          historyGateway="api"
+          diskLoggingEnabled={apiGatewayStatus?.diskLoggingEnabled ?? false}
+          onDiskLoggingChange={handleToggleApiGatewayDiskLogging}
+          diskLoggingDisabled={isApiGatewayDiskLoggingPending}
+          diskLoggingDescription={`开启后会将转发日志和请求汇总写入 ${apiGatewayStatus?.logDirectory ?? "~/.switchcc/logs"}，可在“磁盘历史”中检索`}
+          captureEnabled={apiGatewayStatus?.captureEnabled ?? false}
+          onCaptureChange={handleToggleApiGatewayCapture}
+          captureDisabled={isApiGatewayCapturePending}
         />
       )}
 
@@ -684,6 +797,7 @@ function MainWindow() {
             codexGatewayStatus?.targetBaseUrl
               ? `上游地址 ${codexGatewayStatus.targetBaseUrl}`
               : "",
+            formatRequestDefaults(codexGatewayStatus?.requestDefaults),
             codexGatewayStatus?.logDirectory
               ? `本地日志目录 ${codexGatewayStatus.logDirectory}${codexGatewayStatus.diskLoggingEnabled ? "" : " (已关闭)"}`
               : "",
@@ -697,6 +811,7 @@ function MainWindow() {
               : "",
           ].filter(Boolean)}
           logs={codexGatewayLogs}
+          historyGateway="codex"
           actionLabel="Add Local Gateway"
           onAction={handleInstallCodexGateway}
           actionDisabled={codexProviderCount === 0}
@@ -708,6 +823,19 @@ function MainWindow() {
               ? `已开启后，新的转发日志会追加写入 ${codexGatewayStatus.logDirectory}`
               : `默认关闭。开启后会将所有转发日志写入 ${codexGatewayStatus?.logDirectory ?? "~/.switchcc/logs"}`
           }
+          captureEnabled={codexGatewayStatus?.captureEnabled ?? false}
+          onCaptureChange={handleToggleCodexGatewayCapture}
+          captureDisabled={isCodexGatewayCapturePending}
+        />
+      )}
+
+      {isReplayOpen && (
+        <GatewayReplayModal
+          isOpen={isReplayOpen}
+          onClose={() => setIsReplayOpen(false)}
+          providers={providers}
+          currentClaudeProviderId={apiGatewayStatus?.targetProviderId ?? currentProviderId}
+          currentCodexProviderId={codexGatewayStatus?.targetProviderId ?? currentCodexProviderId}
         />
       )}
 
@@ -7,6 +7,13 @@ import {
   ApiGatewayStatus,
   ApiGatewayLogEntry,
   CodexGatewayStatus,
+  GatewayCaptureSummary,
+  GatewayKind,
+  GatewayLogPage,
+  GatewayLogQuery,
+  ReplayRequest,
+  ReplayPreview,
+  ReplayResult,
 } from "../types";
 
 export class TauriAPI {
@@ -25,6 +32,11 @@ export class TauriAPI {
     return await invoke("get_current_codex_provider");
   }
 
+  // 获取启动时保险库中缺失的密钥引用
+  async getMissingSecrets(): Promise<string[]> {
+    return await invoke("get_missing_secrets");
+  }
+
   // 添加供应商
   async addProvider(provider: Provider): Promise<void> {
     return await invoke("add_provider", { provider });
@@ -162,6 +174,16 @@ export class TauriAPI {
     return await invoke("set_api_gateway_enabled", { enabled });
   }
 
+  async setApiGatewayDiskLoggingEnabled(
+    enabled: boolean,
+  ): Promise<ApiGatewayStatus> {
+    return await invoke("set_api_gateway_disk_logging_enabled", { enabled });
+  }
+
+  async setApiGatewayCaptureEnabled(enabled: boolean): Promise<ApiGatewayStatus> {
+    return await invoke("set_api_gateway_capture_enabled", { enabled });
+  }
+
   // 获取 Codex Gateway 状态
   async getCodexGatewayStatus(): Promise<CodexGatewayStatus> {
     return await invoke("get_codex_gateway_status");
@@ -178,11 +200,42 @@ export class TauriAPI {
     return await invoke("set_codex_gateway_disk_logging_enabled", { enabled });
   }
 
+  async setCodexGatewayCaptureEnabled(
+    enabled: boolean,
+  ): Promise<CodexGatewayStatus> {
+    return await invoke("set_codex_gateway_capture_enabled", { enabled });
+  }
+
   // 将本地 Codex Gateway 写入 ~/.codex/config.toml
   async installCodexGatewayProvider(): Promise<CodexGatewayStatus> {
     return await invoke("install_codex_gateway_provider");
   }
 
+  // 分页查询磁盘上的 Gateway 结构化日志（按时间倒序）
+  async queryGatewayLogs(query: GatewayLogQuery): Promise<GatewayLogPage> {
+    return await invoke("query_gateway_logs", { query });
+  }
+
+  // 列出已保存的抓包（按时间倒序）
+  async listGatewayCaptures(gateway?: GatewayKind): Promise<GatewayCaptureSummary[]> {
+    return await invoke("list_gateway_captures", { gateway });
+  }
+
+  // 将同一请求体回放到多个供应商，返回并排对比结果
+  async replayGatewayRequest(request: ReplayRequest): Promise<ReplayResult[]> {
+    return await invoke("replay_gateway_request", { request });
+  }
+
+  // 预览回放请求经改写规则处理后的上游请求，不实际发送
+  async previewGatewayRequest(request: ReplayRequest): Promise<ReplayPreview[]> {
+    return await invoke("preview_gateway_request", { request });
+  }
+
+  // 导出单个抓包到指定目录，返回导出文件路径
+  async exportGatewayCapture(requestId: string, targetDir: string): Promise<string> {
+    return await invoke("export_gateway_capture", { requestId, targetDir });
+  }
+
   // 监听供应商切换事件
   async onProviderSwitched(callback: (data: { providerId: string }) => void) {
     return await listen("provider-switched", (event) => {
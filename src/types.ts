@@ -1,10 +1,85 @@
 export type ProviderType = "claude" | "codex";
 
+export interface RateLimitConfig {
+  requestsPerMinute?: number;
+  tokensPerMinute?: number;
+  maxConcurrentStreams?: number;
+  maxQueueWaitMs?: number; // 超出限额时最多排队等待的毫秒数
+}
+
+// 供应商请求改写规则；路径以 . 分隔，* 匹配数组元素或对象的每个值
+export interface TransformRules {
+  rename?: Record<string, string>; // 路径 -> 同一层级下的新字段名
+  remove?: string[];
+  set?: Record<string, unknown>;
+  maxTokens?: number; // 输出 token 上限
+  headers?: Record<string, string>;
+  stripCacheControl?: boolean;
+}
+
+// 供应商级系统提示词和默认参数；系统提示词总是前置，其余参数只在客户端未指定时补上
+export interface RequestDefaults {
+  systemPrompt?: string;
+  temperature?: number;
+  maxOutputTokens?: number;
+  reasoningEffort?: "minimal" | "low" | "medium" | "high";
+}
+
+// 推理参数格式：不发送 / reasoning_effort / enable_thinking / thinking.type / Anthropic 思考预算
+export type ReasoningStyle = "drop" | "effort" | "enableThinking" | "thinkingType" | "anthropicBudget";
+
+// 供应商推理强度映射；未设置 style 时按上游适配器推断
+export interface ReasoningMapping {
+  style?: ReasoningStyle;
+  effortMap?: Record<string, string>; // 推理强度改名，如 minimal -> low
+  budgets?: Record<string, number>; // 推理强度 -> 思考预算 token
+}
+
+// 上游对 json_schema 结构化输出的支持：原生 / 仅 json_object / 仅靠提示词
+export type StructuredOutputMode = "jsonSchema" | "jsonObject" | "prompt";
+
+// 覆盖适配器默认的多模态输入能力
+export interface InputCapabilities {
+  images?: boolean;
+  files?: boolean; // 是否接受 Chat Completions 的 file 内容块（如 PDF）
+}
+
+export type KeyRotation = "failover" | "roundRobin";
+
+export interface PooledKey {
+  key: string;
+  label?: string;
+  disabled?: boolean;
+}
+
+// 与主密钥轮换使用的其他密钥；Claude 供应商写在 settingsConfig.apiGateway.keyPool
+export interface KeyPoolConfig {
+  keys: PooledKey[];
+  rotation?: KeyRotation;
+  cooldownSecs?: number; // 被限流（429）或额度不足（402）后暂停使用的秒数
+}
+
+// 请求超出上下文窗口时：报错 / 截断最早的工具输出 / 丢弃最早的对话
+export type OverflowPolicy = "error" | "trimToolOutputs" | "dropOldest";
+
+export interface ContextWindow {
+  length?: number; // 模型上下文长度（token）
+  overflow?: OverflowPolicy;
+}
+
 export interface CodexProviderConfig {
   providerName: string;
   upstreamUrl: string;
   apiKey: string;
   modelName: string;
+  rateLimit?: RateLimitConfig;
+  defaults?: RequestDefaults;
+  reasoning?: ReasoningMapping;
+  structuredOutput?: StructuredOutputMode;
+  capabilities?: InputCapabilities;
+  contextWindow?: ContextWindow;
+  transform?: TransformRules;
+  keyPool?: KeyPoolConfig;
 }
 
 interface ProviderBase {
@@ -47,6 +122,32 @@ export interface AppConfig {
   current: string;
 }
 
+export interface ProviderHealthSnapshot {
+  status: "healthy" | "degraded" | "unhealthy" | "unknown";
+  circuitState: "closed" | "open" | "half_open";
+  sampleCount: number;
+  errorRate: number;
+  consecutiveFailures: number;
+  p50LatencyMs?: number;
+  p95LatencyMs?: number;
+  totalRequests: number;
+  totalFailures: number;
+  lastError?: string;
+  lastFailureAt?: string;
+  openRemainingSecs?: number; // 熔断剩余秒数
+}
+
+export interface KeySnapshot {
+  label: string;
+  status: "active" | "cooling" | "disabled";
+  totalRequests: number;
+  totalFailures: number;
+  lastStatus?: number;
+  lastError?: string;
+  lastUsedAt?: string;
+  cooldownRemainingSecs?: number; // 暂停使用的剩余秒数
+}
+
 export interface ApiGatewayStatus {
   enabled: boolean;
   running: boolean;
@@ -55,6 +156,12 @@ export interface ApiGatewayStatus {
   targetProviderId?: string;
   targetProviderName?: string;
   targetBaseUrl?: string;
+  diskLoggingEnabled: boolean;
+  logDirectory: string;
+  captureEnabled: boolean; // 是否抓包保存完整请求/响应（已脱敏）
+  providerHealth: Record<string, ProviderHealthSnapshot>;
+  providerKeys: Record<string, KeySnapshot[]>; // 密钥池中每个密钥的使用情况
+  requestDefaults?: RequestDefaults; // 当前生效的默认参数
 }
 
 export interface ApiGatewayLogEntry {
@@ -63,6 +170,89 @@ export interface ApiGatewayLogEntry {
   message: string;
 }
 
+export type GatewayKind = "api" | "codex";
+
+export interface GatewayLogRecord {
+  timestamp: string; // RFC3339
+  gateway: GatewayKind;
+  kind: "event" | "request";
+  level: string;
+  message: string;
+  requestId?: string;
+  providerId?: string;
+  model?: string;
+  method?: string;
+  path?: string;
+  status?: number;
+  latencyMs?: number;
+  durationMs?: number;
+  requestBytes?: number;
+  responseBytes?: number;
+  inputTokens?: number;
+  outputTokens?: number;
+  errorClass?: string;
+}
+
+export interface GatewayLogQuery {
+  gateway?: GatewayKind;
+  kind?: "event" | "request";
+  providerId?: string;
+  level?: string;
+  errorsOnly?: boolean;
+  search?: string;
+  since?: string;
+  until?: string;
+  offset?: number;
+  limit?: number;
+}
+
+export interface GatewayLogPage {
+  entries: GatewayLogRecord[];
+  hasMore: boolean;
+}
+
+export interface GatewayCaptureSummary {
+  requestId: string;
+  gateway: GatewayKind;
+  startedAt: string; // RFC3339
+  providerId?: string;
+  model?: string;
+  status?: number;
+  error?: string;
+}
+
+export type ReplayFormat = "anthropic" | "responses";
+
+export interface ReplayRequest {
+  format?: ReplayFormat; // 不传时根据请求体自动识别
+  body?: string;
+  filePath?: string;
+  providerIds: string[]; // 为空时回放到当前路由的目标供应商
+}
+
+export interface ReplayResult {
+  providerId: string;
+  providerName: string;
+  model?: string;
+  status?: number;
+  latencyMs: number;
+  usage: { inputTokens: number; outputTokens: number };
+  outputText: string;
+  toolCalls: { name?: string; arguments?: unknown }[];
+  response: unknown;
+  error?: string;
+}
+
+// 回放前的改写预览，鉴权信息已脱敏
+export interface ReplayPreview {
+  providerId: string;
+  providerName: string;
+  url?: string;
+  headers: Record<string, string>;
+  body: unknown;
+  error?: string;
+}
+
 export interface CodexGatewayStatus {
   enabled: boolean;
   running: boolean;
@@ -71,6 +261,7 @@ export interface CodexGatewayStatus {
   healthUrl: string;
   diskLoggingEnabled: boolean;
   logDirectory: string;
+  captureEnabled: boolean; // 是否抓包保存完整请求/响应（已脱敏）
   targetProviderId?: string;
   targetProviderName?: string;
   targetBaseUrl?: string;
@@ -78,6 +269,9 @@ export interface CodexGatewayStatus {
   codexConfigPath: string;
   installedInCodexConfig: boolean;
   providerKey: string;
+  providerHealth: Record<string, ProviderHealthSnapshot>;
+  providerKeys: Record<string, KeySnapshot[]>; // 密钥池中每个密钥的使用情况
+  requestDefaults?: RequestDefaults; // 当前生效的默认参数
 }
 
 // 应用设置类型